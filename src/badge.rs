@@ -0,0 +1,141 @@
+use std::{fmt, fs, path::PathBuf, process::Command};
+
+use anyhow::{ensure, Context};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    manager::ScriptStatus,
+    settings::{system_mode, Settings},
+    tray_handle::TrayData,
+};
+
+const XATTR_NAME: &str = "user.backup-monitor.freshness";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freshness {
+    Fresh,
+    Stale,
+    Failed,
+}
+
+impl fmt::Display for Freshness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Freshness::Fresh => write!(f, "fresh"),
+            Freshness::Stale => write!(f, "stale"),
+            Freshness::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+// failures take precedence over overdue backups
+fn freshness(tray_data: &TrayData) -> Freshness {
+    let failed = tray_data
+        .scripts
+        .iter()
+        .flatten()
+        .any(|(_, _, status)| *status == ScriptStatus::Failed);
+    if failed {
+        Freshness::Failed
+    } else if tray_data
+        .overdue
+        .as_ref()
+        .is_some_and(|overdue| !overdue.is_empty())
+    {
+        Freshness::Stale
+    } else {
+        Freshness::Fresh
+    }
+}
+
+// e.g. `stale 52`, the age in hours of the newest backup is left out if there is no backup yet
+pub fn badge(tray_data: &TrayData, settings: &Settings, now: DateTime<Utc>) -> String {
+    let freshness = freshness(tray_data);
+    let last_backup = settings
+        .scripts
+        .iter()
+        .filter_map(|script| script.last_backup)
+        .max();
+
+    match last_backup {
+        Some(last_backup) => format!("{freshness} {}", (now - last_backup).num_hours().max(0)),
+        None => freshness.to_string(),
+    }
+}
+
+fn badge_path() -> anyhow::Result<PathBuf> {
+    let dir = if system_mode() {
+        PathBuf::from("/var/lib/backup-monitor")
+    } else {
+        dirs::state_dir()
+            .context("state dir not found")?
+            .join("backup-monitor")
+    };
+    Ok(dir.join("freshness"))
+}
+
+// written to a temporary file first, so readers never see a partial badge
+pub fn write_badge(badge: &str, xattr: bool) -> anyhow::Result<()> {
+    let path = badge_path()?;
+    let dir = path.parent().context("badge path has no parent")?;
+    fs::create_dir_all(dir)?;
+
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, format!("{badge}\n"))?;
+    fs::rename(&temp_path, &path)?;
+
+    if xattr && !system_mode() {
+        let home = dirs::home_dir().context("home dir not found")?;
+        let output = Command::new("setfattr")
+            .args(["--name", XATTR_NAME, "--value", badge])
+            .arg(&home)
+            .output()
+            .context("failed to run setfattr")?;
+        ensure!(
+            output.status.success(),
+            "setfattr failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::Clock, settings::Script};
+    use chrono::Duration;
+    use fake::{Fake, Faker};
+    use indoc::indoc;
+
+    #[test]
+    fn badges() {
+        let now = Faker.fake::<Clock>().now();
+        let mut tray_data = TrayData {
+            scripts: Some(vec![("Backup".to_string(), None, ScriptStatus::Ok)]),
+            overdue: Some(Vec::new()),
+            ..Default::default()
+        };
+        let mut settings = Settings::default();
+
+        assert_eq!(badge(&tray_data, &settings, now), "fresh");
+
+        settings.scripts = vec![Script {
+            last_backup: Some(now - Duration::minutes(52 * 60 + 30)),
+            ..serde_yaml_ng::from_str(indoc! {"
+                name: Backup
+                backup-script: '#!/bin/bash'
+                interval: 1day
+            "})
+            .unwrap()
+        }];
+        assert_eq!(badge(&tray_data, &settings, now), "fresh 52");
+
+        tray_data.overdue = Some(vec!["Backup".to_string()]);
+        assert_eq!(badge(&tray_data, &settings, now), "stale 52");
+
+        tray_data.scripts = Some(vec![("Backup".to_string(), None, ScriptStatus::Failed)]);
+        assert_eq!(badge(&tray_data, &settings, now), "failed 52");
+    }
+}
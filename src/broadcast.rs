@@ -0,0 +1,64 @@
+use std::{env::current_exe, process::Command, thread};
+
+use nix::unistd::getuid;
+use notify_rust::{Notification, Timeout, Urgency};
+use zbus::blocking::Connection;
+
+use crate::{logind::graphical_users, notification, settings::system_mode};
+
+// shows a notification in the graphical sessions of all users by running `backup-monitor notify`
+// as each of them, which needs root privileges
+pub fn broadcast(app_name: &str, summary: &str, body: &str) {
+    let users = match Connection::system().and_then(|connection| graphical_users(&connection)) {
+        Ok(users) => users,
+        Err(error) => {
+            log::warn!("failed to list the graphical sessions: {error}");
+            return;
+        }
+    };
+    let helper = match current_exe() {
+        Ok(helper) => helper,
+        Err(error) => {
+            log::warn!("failed to find the notification helper: {error}");
+            return;
+        }
+    };
+
+    for (uid, user) in users {
+        // the user of the backup monitor has already been notified
+        if !system_mode() && uid == getuid().as_raw() {
+            continue;
+        }
+
+        let mut command = Command::new("runuser");
+        command
+            .args(["-u", &user, "--", "env"])
+            .arg(format!(
+                "DBUS_SESSION_BUS_ADDRESS=unix:path=/run/user/{uid}/bus"
+            ))
+            .arg(&helper)
+            // the summary and body may start with `-`
+            .args(["notify", "--", app_name, summary, body]);
+
+        // the notification server may take a moment to answer
+        thread::spawn(move || match command.status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => log::warn!("failed to notify {user}: helper exited with {status}"),
+            Err(error) => log::warn!("failed to notify {user}: {error}"),
+        });
+    }
+}
+
+// the helper, run in the session of a user
+pub fn notify(app_name: &str, summary: &str, body: &str) -> anyhow::Result<()> {
+    notification::show(
+        Notification::new()
+            .appname(app_name)
+            .summary(summary)
+            .body(body)
+            .icon("dialog-error")
+            .urgency(Urgency::Critical)
+            .timeout(Timeout::Never),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context};
+use chrono::Weekday;
+
+// a systemd calendar expression (see `man systemd.time`), e.g. `Mon..Fri *-*-* 18:00` or `daily`,
+// as bit sets of the matching values; years, seconds other than 0 and time zones aren't supported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calendar {
+    pub minutes: u64,
+    pub hours: u64,
+    pub days_of_month: u64,
+    pub months: u64,
+    // sunday is 0
+    pub days_of_week: u64,
+}
+
+impl FromStr for Calendar {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = match expression.trim().to_lowercase().as_str() {
+            "minutely" => "*-*-* *:*:00",
+            "hourly" => "*-*-* *:00:00",
+            "daily" => "*-*-* 00:00:00",
+            "weekly" => "Mon *-*-* 00:00:00",
+            "monthly" => "*-*-01 00:00:00",
+            "yearly" | "annually" => "*-01-01 00:00:00",
+            "quarterly" => "*-01,04,07,10-01 00:00:00",
+            "semiannually" => "*-01,07-01 00:00:00",
+            _ => expression,
+        };
+
+        let mut tokens = expression.split_whitespace().peekable();
+        ensure!(tokens.peek().is_some(), "the expression is empty");
+
+        let days_of_week = match tokens.next_if(|token| token.starts_with(char::is_alphabetic)) {
+            Some(token) => parse_weekdays(token).context("invalid day of week")?,
+            None => (1 << 7) - 1,
+        };
+
+        let (months, days_of_month) = match tokens.next_if(|token| token.contains('-')) {
+            Some(token) => parse_date(token)?,
+            None => ((1 << 13) - 2, (1 << 32) - 2),
+        };
+
+        let (hours, minutes) = match tokens.next_if(|token| token.contains(':')) {
+            Some(token) => parse_time(token)?,
+            None => (1, 1),
+        };
+
+        if let Some(token) = tokens.next() {
+            if token.starts_with(char::is_alphabetic) {
+                bail!("time zones aren't supported (`{token}`)");
+            }
+            bail!("unexpected `{token}`, expected `[weekdays] [date] [time]`");
+        }
+
+        Ok(Calendar {
+            minutes,
+            hours,
+            days_of_month,
+            months,
+            days_of_week,
+        })
+    }
+}
+
+// e.g. `Mon..Fri` or `Sat,Sun`, ranges may wrap around the end of the week
+fn parse_weekdays(field: &str) -> anyhow::Result<u64> {
+    let weekday = |value: &str| -> anyhow::Result<u32> {
+        let weekday = value
+            .parse::<Weekday>()
+            .map_err(|_| anyhow::anyhow!("`{value}` is not a weekday"))?;
+        Ok(weekday.num_days_from_sunday())
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (start, end) = match part.split_once("..").or_else(|| part.split_once('-')) {
+            Some((start, end)) => (weekday(start)?, weekday(end)?),
+            None => (weekday(part)?, weekday(part)?),
+        };
+        let mut day = start;
+        loop {
+            set |= 1 << day;
+            if day == end {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+
+    Ok(set)
+}
+
+// `[year-]month-day`, only `*` is supported for the year
+fn parse_date(field: &str) -> anyhow::Result<(u64, u64)> {
+    let parts = field.split('-').collect::<Vec<_>>();
+    let (month, day) = match parts[..] {
+        ["*", month, day] => (month, day),
+        [_, _, _] => bail!("years aren't supported in `{field}`, use `*`"),
+        [month, day] => (month, day),
+        _ => bail!("`{field}` is not a date, expected `*-month-day`"),
+    };
+
+    Ok((
+        parse_values(month, 1, 12).context("invalid month")?,
+        parse_values(day, 1, 31).context("invalid day")?,
+    ))
+}
+
+// `hour:minute[:second]`, the second must be 0
+fn parse_time(field: &str) -> anyhow::Result<(u64, u64)> {
+    let parts = field.split(':').collect::<Vec<_>>();
+    let (hour, minute) = match parts[..] {
+        [hour, minute] => (hour, minute),
+        [hour, minute, second] => {
+            ensure!(
+                parse_values(second, 0, 59).context("invalid second")? == 1,
+                "seconds aren't supported in `{field}`, use `00`"
+            );
+            (hour, minute)
+        }
+        _ => bail!("`{field}` is not a time, expected `hour:minute`"),
+    };
+
+    Ok((
+        parse_values(hour, 0, 23).context("invalid hour")?,
+        parse_values(minute, 0, 59).context("invalid minute")?,
+    ))
+}
+
+// a comma separated list of `*`, values and ranges (`..`) with optional steps, e.g. `1..5` or
+// `0/15`
+fn parse_values(field: &str, min: u32, max: u32) -> anyhow::Result<u64> {
+    let value = |value: &str| -> anyhow::Result<u32> {
+        let value = value
+            .parse::<u32>()
+            .with_context(|| format!("`{value}` is not a number"))?;
+        ensure!(
+            (min..=max).contains(&value),
+            "{value} is not between {min} and {max}"
+        );
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .with_context(|| format!("`{step}` is not a number"))?;
+                ensure!(step > 0, "the step must not be 0");
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (start, end) = match range.split_once("..") {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `0/15` repeats until the end
+            None if step.is_some() => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        ensure!(start <= end, "`{range}` is an empty range");
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn set(values: impl IntoIterator<Item = u32>) -> u64 {
+        values.into_iter().map(|value| 1_u64 << value).sum()
+    }
+
+    #[rstest]
+    #[case("Mon..Fri *-*-* 18:00", set([0]), set([18]), set(1..=31), set(1..=12), set(1..=5))]
+    #[case("Sat,Sun 10:30", set([30]), set([10]), set(1..=31), set(1..=12), set([0, 6]))]
+    #[case("Fri..Mon 08:00:00", set([0]), set([8]), set(1..=31), set(1..=12), set([5, 6, 0, 1]))]
+    #[case("monday-wednesday", set([0]), set([0]), set(1..=31), set(1..=12), set(1..=3))]
+    #[case("*-*-01 03:15", set([15]), set([3]), set([1]), set(1..=12), set(0..7))]
+    #[case("*-02-28", set([0]), set([0]), set([28]), set([2]), set(0..7))]
+    #[case("06-01", set([0]), set([0]), set([1]), set([6]), set(0..7))]
+    #[case("*-1,7-1 *:0/20", set([0, 20, 40]), set(0..=23), set([1]), set([1, 7]), set(0..7))]
+    #[case("*-*-1..7/2 8..9:00", set([0]), set([8, 9]), set([1, 3, 5, 7]), set(1..=12), set(0..7))]
+    #[case("*:*", set(0..=59), set(0..=23), set(1..=31), set(1..=12), set(0..7))]
+    #[case("daily", set([0]), set([0]), set(1..=31), set(1..=12), set(0..7))]
+    #[case("Hourly", set([0]), set(0..=23), set(1..=31), set(1..=12), set(0..7))]
+    #[case("weekly", set([0]), set([0]), set(1..=31), set(1..=12), set([1]))]
+    #[case("monthly", set([0]), set([0]), set([1]), set(1..=12), set(0..7))]
+    #[case("quarterly", set([0]), set([0]), set([1]), set([1, 4, 7, 10]), set(0..7))]
+    #[case("annually", set([0]), set([0]), set([1]), set([1]), set(0..7))]
+    fn parse_calendar(
+        #[case] expression: &str,
+        #[case] minutes: u64,
+        #[case] hours: u64,
+        #[case] days_of_month: u64,
+        #[case] months: u64,
+        #[case] days_of_week: u64,
+    ) {
+        assert_eq!(
+            expression.parse::<Calendar>().unwrap(),
+            Calendar {
+                minutes,
+                hours,
+                days_of_month,
+                months,
+                days_of_week,
+            }
+        );
+    }
+
+    #[rstest]
+    #[case("", "the expression is empty")]
+    #[case("Mon..Funday", "invalid day of week: `Funday` is not a weekday")]
+    #[case("2024-*-* 18:00", "years aren't supported in `2024-*-*`")]
+    #[case("*-13-01", "invalid month: 13 is not between 1 and 12")]
+    #[case("*-*-* 24:00", "invalid hour: 24 is not between 0 and 23")]
+    #[case("*-*-* 18:00:30", "seconds aren't supported in `18:00:30`")]
+    #[case("*-*-* 18", "unexpected `18`")]
+    #[case("*-*-* 18:00 Europe/Berlin", "time zones aren't supported")]
+    #[case("*-*-* *:0/0", "invalid minute: the step must not be 0")]
+    #[case("*-*-5..1", "invalid day: `5..1` is an empty range")]
+    fn invalid_calendar(#[case] expression: &str, #[case] error: &str) {
+        let message = format!("{:#}", expression.parse::<Calendar>().unwrap_err());
+        assert!(message.starts_with(error), "{expression}: {message}");
+    }
+}
@@ -0,0 +1,99 @@
+// metadata of incremental or differential backups, reported by the backup script in
+// `$BM_CHAIN_FILE`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chain {
+    // id of the full backup the increments are based on
+    pub base: String,
+    // 0 for a full backup
+    pub increment: u32,
+}
+
+// lines like `base 2024-10-01` and `increment 3`, unknown lines are ignored
+pub fn parse_chain(content: &str) -> Option<Chain> {
+    let mut base = None;
+    let mut increment = None;
+    for line in content.lines() {
+        match line.trim().split_once(char::is_whitespace) {
+            Some(("base", value)) => base = Some(value.trim().to_string()),
+            Some(("increment", value)) => increment = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+
+    Some(Chain {
+        base: base.filter(|base| !base.is_empty())?,
+        increment: increment?,
+    })
+}
+
+// `known_base` is the base of the last full backup that has been reported
+pub fn chain_warnings(
+    chain: &Chain,
+    known_base: Option<&str>,
+    max_length: Option<u32>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if chain.increment > 0 {
+        if let Some(known_base) = known_base.filter(|known_base| *known_base != chain.base) {
+            warnings.push(format!(
+                "The base `{}` of the backup chain is missing (the last full backup was \
+                 `{known_base}`), run a new full backup",
+                chain.base
+            ));
+        }
+    }
+
+    if let Some(max_length) = max_length.filter(|max_length| chain.increment > *max_length) {
+        warnings.push(format!(
+            "The backup chain has {} increments (more than {max_length}), run a new full backup",
+            chain.increment
+        ));
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_chain("base 2024-10-01T22:00\nincrement 3\n"),
+            Some(Chain {
+                base: "2024-10-01T22:00".to_string(),
+                increment: 3,
+            })
+        );
+        assert_eq!(parse_chain("increment 3\n"), None);
+        assert_eq!(parse_chain("base full-1\nincrement three\n"), None);
+        assert_eq!(parse_chain(""), None);
+    }
+
+    #[test]
+    fn warnings() {
+        let chain = |base: &str, increment| Chain {
+            base: base.to_string(),
+            increment,
+        };
+
+        assert!(chain_warnings(&chain("full-2", 0), Some("full-1"), Some(7)).is_empty());
+        assert!(chain_warnings(&chain("full-1", 7), Some("full-1"), Some(7)).is_empty());
+        // nothing to compare with, e.g. after an upgrade
+        assert!(chain_warnings(&chain("full-1", 3), None, None).is_empty());
+
+        assert_eq!(
+            chain_warnings(&chain("full-1", 8), Some("full-1"), Some(7)),
+            vec!["The backup chain has 8 increments (more than 7), run a new full backup"]
+        );
+        assert_eq!(
+            chain_warnings(&chain("full-1", 3), Some("full-2"), None),
+            vec![
+                "The base `full-1` of the backup chain is missing (the last full backup was \
+                 `full-2`), run a new full backup"
+            ]
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use std::{sync::mpsc::RecvTimeoutError, time::Duration};
+
+use crate::{
+    control::{self, Request, Response},
+    event,
+    settings::Settings,
+    tray::Tray,
+    tray_handle::{ChangeFilter, TrayData, TrayHandle},
+    Event,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+// shows the status of the system-wide daemon in the system tray
+pub fn client_loop() -> anyhow::Result<()> {
+    let socket_path = control::socket_path(true)?;
+
+    let (tx, rx) = event::channel();
+    let service = ksni::TrayService::new(Tray::remote(&Settings::default(), tx));
+    let handle = ChangeFilter::new(service.handle());
+    service.spawn();
+
+    loop {
+        match control::request(&socket_path, &Request::Status) {
+            Ok(Response::Status(status)) => TrayHandle::update(&handle, status.into_tray_data()),
+            Ok(response) => log::warn!("unexpected response {response:?}"),
+            Err(error) => {
+                log::warn!("failed to get status: {error}");
+
+                TrayHandle::update(
+                    &handle,
+                    TrayData {
+                        status: Some(ksni::Status::NeedsAttention),
+                        tooltip: Some("The system-wide backup daemon is not running".to_string()),
+                        scripts: Some(Vec::new()),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Event::ManualRun(name, _, _)) => {
+                log::info!("requesting to run script {name}");
+
+                match control::request(&socket_path, &Request::Run(name)) {
+                    Ok(Response::Ok) => {}
+                    Ok(response) => log::warn!("failed to run script: {response:?}"),
+                    Err(error) => log::warn!("failed to run script: {error}"),
+                }
+            }
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context};
+
+// wl-copy on Wayland, xclip or xsel on X11
+fn clipboard_commands() -> Vec<Vec<&'static str>> {
+    let mut commands = Vec::new();
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        commands.push(vec!["wl-copy"]);
+    }
+    commands.push(vec!["xclip", "-selection", "clipboard"]);
+    commands.push(vec!["xsel", "--clipboard", "--input"]);
+    commands
+}
+
+// the first available clipboard tool is used
+pub fn copy(text: &str) -> anyhow::Result<()> {
+    for command in clipboard_commands() {
+        let Ok(mut child) = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+
+        child
+            .stdin
+            .take()
+            .context("stdin not captured")?
+            .write_all(text.as_bytes())?;
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{} failed with {status}", command[0]);
+        }
+        return Ok(());
+    }
+
+    bail!("no clipboard tool found, install wl-clipboard, xclip or xsel")
+}
@@ -31,6 +31,16 @@ impl Clock {
         Clock(Some(id))
     }
 
+    // moves a fake clock to the given time, has no effect on the real clock
+    pub fn set_time<Tz: TimeZone>(&self, time: DateTime<Tz>) {
+        if let Some(id) = self.0 {
+            FAKE_CLOCKS
+                .lock()
+                .unwrap()
+                .insert(id, FakeClock::Static(time.with_timezone(&Utc)));
+        }
+    }
+
     pub fn now(&self) -> DateTime<Utc> {
         self.0.map_or_else(Utc::now, |id| {
             match FAKE_CLOCKS.lock().unwrap().get_mut(&id).unwrap() {
@@ -80,6 +90,16 @@ mod tests {
         assert_eq!(clock.now(), time);
     }
 
+    #[test]
+    fn set_static_clock() {
+        let time = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let clock = Clock::with_time(time);
+
+        clock.set_time(time + Duration::days(1));
+
+        assert_eq!(clock.now(), time + Duration::days(1));
+    }
+
     #[test]
     fn fake_clock() {
         let clock = Faker.fake::<Clock>();
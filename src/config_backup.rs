@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    history::history_file_path,
+    settings::{include_dir_path, include_files, settings_file_path, ConfigBackup, Settings},
+    state::state_file_path,
+    targets::resolve_mount_path_now,
+};
+
+// the directory the configuration is copied to on the backup targets
+const TARGET_DIR_NAME: &str = ".backup-monitor";
+
+// the directories the configuration is copied to, the mount paths of the scripts are skipped
+// while they aren't mounted
+pub fn destinations(settings: &Settings, config_backup: &ConfigBackup) -> Vec<PathBuf> {
+    if let Some(path) = &config_backup.path {
+        return vec![path.clone()];
+    }
+
+    let mut destinations = Vec::new();
+    for path in settings
+        .scripts
+        .iter()
+        .filter(|script| script.enabled)
+        .flat_map(|script| &script.mount_paths)
+        .filter_map(|path| resolve_mount_path_now(path))
+    {
+        let destination = path.join(TARGET_DIR_NAME);
+        if is_mount_point(&path) && !destinations.contains(&destination) {
+            destinations.push(destination);
+        }
+    }
+    destinations
+}
+
+// a directory on another device than its parent
+fn is_mount_point(path: &Path) -> bool {
+    let parent = path.parent().unwrap_or(path);
+    match (fs::metadata(path), fs::metadata(parent)) {
+        (Ok(metadata), Ok(parent_metadata)) => {
+            metadata.is_dir() && metadata.dev() != parent_metadata.dev()
+        }
+        _ => false,
+    }
+}
+
+// copies the settings file with its include directory, the state and the history
+pub fn copy_config(destination: &Path) -> anyhow::Result<()> {
+    let settings_path = settings_file_path()?;
+    let include_dir = include_dir_path(&settings_path);
+
+    let mut files = vec![(settings_path.clone(), PathBuf::new())];
+    for file in include_files(&include_dir)? {
+        files.push((
+            file,
+            PathBuf::from(include_dir.file_name().unwrap_or_default()),
+        ));
+    }
+    files.push((state_file_path()?, PathBuf::new()));
+    files.push((history_file_path()?, PathBuf::new()));
+
+    for (file, dir) in files {
+        // e.g. no state has been saved yet
+        if !file.exists() {
+            continue;
+        }
+        let dir = destination.join(dir);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create `{}`", dir.display()))?;
+        let file_name = file.file_name().context("file has no name")?;
+        fs::copy(&file, dir.join(file_name))
+            .with_context(|| format!("failed to copy `{}`", file.display()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Script;
+
+    #[test]
+    fn mount_points() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(is_mount_point(Path::new("/proc")));
+        assert!(!is_mount_point(dir.path()));
+        assert!(!is_mount_point(&dir.path().join("missing")));
+    }
+
+    #[test]
+    fn destination_dirs() {
+        let mut settings = Settings::default();
+        settings.scripts.push(
+            serde_yaml_ng::from_str::<Script>(
+                "{name: Backup, backup-script: '#!/bin/sh', mount-paths: [/proc]}",
+            )
+            .unwrap(),
+        );
+
+        let config_backup = ConfigBackup {
+            path: None,
+            interval: Default::default(),
+        };
+        assert_eq!(
+            destinations(&settings, &config_backup),
+            vec![PathBuf::from("/proc/.backup-monitor")]
+        );
+
+        let config_backup = ConfigBackup {
+            path: Some(PathBuf::from("/srv/config")),
+            interval: Default::default(),
+        };
+        assert_eq!(
+            destinations(&settings, &config_backup),
+            vec![PathBuf::from("/srv/config")]
+        );
+    }
+}
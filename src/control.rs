@@ -0,0 +1,526 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use chrono::Utc;
+use nix::{
+    sys::socket::{getsockopt, sockopt::PeerCredentials},
+    unistd::{Uid, User},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::EventSender,
+    history::Trigger,
+    manager::ScriptStatus,
+    metrics::{self, Metrics},
+    run_result::ScriptResult,
+    settings::Settings,
+    tray::Tray,
+    tray_handle::{parse_status, status_name, TrayData, TrayHandle},
+    Event,
+};
+
+// the main loop doesn't handle events while a backup script is running
+const EXPLAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    Run(String),
+    // replies once the script has finished
+    RunAndWait(String),
+    Explain(String),
+    Metrics,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Response {
+    Status(Status),
+    Explanation(String),
+    Metrics(Metrics),
+    RunResult(Vec<ScriptResult>),
+    Ok,
+    Error(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Status {
+    pub status: String,
+    pub tooltip: String,
+    pub scripts: Vec<ScriptInfo>,
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScriptInfo {
+    pub name: String,
+    pub icon_name: Option<String>,
+    #[serde(default)]
+    pub status: ScriptStatus,
+    // whether the requesting user may run the script
+    pub permitted: bool,
+}
+
+impl Status {
+    pub fn into_tray_data(self) -> TrayData {
+        TrayData {
+            status: parse_status(&self.status),
+            tooltip: Some(self.tooltip),
+            scripts: Some(
+                self.scripts
+                    .into_iter()
+                    .filter(|script| script.permitted)
+                    .map(|script| (script.name, script.icon_name, script.status))
+                    .collect(),
+            ),
+            paused: Some(self.paused),
+            profiles: None,
+            overdue: None,
+        }
+    }
+}
+
+pub fn socket_path(system: bool) -> anyhow::Result<PathBuf> {
+    if system {
+        Ok(PathBuf::from("/run/backup-monitor/control.sock"))
+    } else {
+        let runtime_dir = dirs::runtime_dir().context("runtime dir not found")?;
+        Ok(runtime_dir.join("backup-monitor").join("control.sock"))
+    }
+}
+
+// forwards updates to the system tray (if any) and keeps a copy for control clients
+pub struct StatusPublisher<H> {
+    handle: Option<H>,
+    status: Arc<ArcSwap<Status>>,
+}
+
+impl<H> StatusPublisher<H> {
+    pub fn new(handle: Option<H>, status: Arc<ArcSwap<Status>>) -> Self {
+        StatusPublisher { handle, status }
+    }
+}
+
+impl<H: TrayHandle<Tray>> TrayHandle<Tray> for StatusPublisher<H> {
+    fn update(&self, data: TrayData) {
+        let mut status = Status::clone(&self.status.load());
+        if let Some(tray_status) = data.status {
+            status.status = status_name(tray_status).to_string();
+        }
+        if let Some(tooltip) = &data.tooltip {
+            status.tooltip.clone_from(tooltip);
+        }
+        if let Some(scripts) = &data.scripts {
+            status.scripts = scripts
+                .iter()
+                .map(|(name, icon_name, status)| ScriptInfo {
+                    name: name.clone(),
+                    icon_name: icon_name.clone(),
+                    status: *status,
+                    permitted: false,
+                })
+                .collect();
+        }
+        if let Some(paused) = data.paused {
+            status.paused = paused;
+        }
+        self.status.store(Arc::new(status));
+
+        if let Some(handle) = &self.handle {
+            handle.update(data);
+        }
+    }
+}
+
+pub fn serve(
+    path: &Path,
+    system: bool,
+    status: Arc<ArcSwap<Status>>,
+    settings: Arc<ArcSwap<Settings>>,
+    tx: EventSender,
+) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+        let mode = if system { 0o755 } else { 0o700 };
+        fs::set_permissions(dir, fs::Permissions::from_mode(mode))?;
+    }
+
+    // remove stale socket of a previous instance
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    if system {
+        // every user may connect, permissions are checked per request
+        fs::set_permissions(path, fs::Permissions::from_mode(0o666))?;
+    }
+
+    log::debug!("listening on `{}`", path.display());
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                log::warn!("failed to accept control client: {error}");
+                continue;
+            }
+        };
+        let status = status.clone();
+        let settings = settings.clone();
+        let tx = tx.clone();
+        thread::spawn(move || {
+            if let Err(error) = handle_client(stream, &status, &settings, &tx) {
+                log::warn!("control client failed: {error}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_client(
+    stream: UnixStream,
+    status: &ArcSwap<Status>,
+    settings: &ArcSwap<Settings>,
+    tx: &EventSender,
+) -> anyhow::Result<()> {
+    let uid = Uid::from_raw(getsockopt(&stream, PeerCredentials)?.uid());
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let response = match serde_json::from_str::<Request>(&line?) {
+            Ok(request) => handle_request(request, uid, status, &settings.load(), tx),
+            Err(error) => Response::Error(format!("invalid request: {error}")),
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn handle_request(
+    request: Request,
+    uid: Uid,
+    status: &ArcSwap<Status>,
+    settings: &Settings,
+    tx: &EventSender,
+) -> Response {
+    match request {
+        Request::Status => {
+            let mut status = Status::clone(&status.load());
+            for script in &mut status.scripts {
+                script.permitted = permitted(settings, &script.name, uid);
+            }
+            Response::Status(status)
+        }
+        Request::Run(name) | Request::RunAndWait(name)
+            if !settings.scripts.iter().any(|script| script.name == name) =>
+        {
+            Response::Error(format!("unknown script `{name}`"))
+        }
+        Request::Run(name) | Request::RunAndWait(name) if !permitted(settings, &name, uid) => {
+            Response::Error(format!("not permitted to run `{name}`"))
+        }
+        Request::Run(name) => {
+            log::info!("user {uid} requested to run `{name}`");
+
+            let _ = tx.send(Event::ManualRun(name, control_trigger(uid), None));
+            Response::Ok
+        }
+        Request::RunAndWait(name) => {
+            log::info!("user {uid} requested to run `{name}` and wait for it");
+
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let _ = tx.send(Event::ManualRun(name, control_trigger(uid), Some(reply_tx)));
+            // backups may take hours, so there is no timeout
+            match reply_rx.recv() {
+                Ok(outcomes) => Response::RunResult(
+                    outcomes
+                        .iter()
+                        .map(|(name, outcome)| ScriptResult::new(name, outcome))
+                        .collect(),
+                ),
+                Err(_) => Response::Error("backup monitor has stopped".to_string()),
+            }
+        }
+        Request::Explain(name) => {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let _ = tx.send(Event::Explain(name.clone(), reply_tx));
+            match reply_rx.recv_timeout(EXPLAIN_TIMEOUT) {
+                Ok(Some(explanation)) => Response::Explanation(explanation),
+                Ok(None) => Response::Error(format!("unknown script `{name}`")),
+                Err(_) => Response::Error("backup monitor is busy, try again later".to_string()),
+            }
+        }
+        Request::Metrics => Response::Metrics(metrics::snapshot()),
+    }
+}
+
+fn control_trigger(uid: Uid) -> Trigger {
+    Trigger::Control {
+        uid: uid.as_raw(),
+        user: User::from_uid(uid).ok().flatten().map(|user| user.name),
+    }
+}
+
+// root and the owner of the daemon may run every script, other users need to be listed in
+// `allowed-users`
+fn permitted(settings: &Settings, name: &str, uid: Uid) -> bool {
+    if uid.is_root() || uid == Uid::current() {
+        return true;
+    }
+
+    let Ok(Some(user)) = User::from_uid(uid) else {
+        return false;
+    };
+
+    settings
+        .scripts
+        .iter()
+        .find(|script| script.name == name)
+        .is_some_and(|script| script.allowed_users.contains(&user.name))
+}
+
+fn status(socket_path: &Path) -> anyhow::Result<Status> {
+    match request(socket_path, &Request::Status)? {
+        Response::Status(status) => Ok(status),
+        _ => anyhow::bail!("unexpected response to status request"),
+    }
+}
+
+pub fn print_status(system: bool, json: bool) -> anyhow::Result<()> {
+    let socket_path = socket_path(system)?;
+
+    let status = status(&socket_path)?;
+    let Response::Metrics(metrics) = request(&socket_path, &Request::Metrics)? else {
+        anyhow::bail!("unexpected response to metrics request");
+    };
+
+    if json {
+        let value = serde_json::json!({ "status": status, "metrics": metrics });
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        println!("{}\n", status.tooltip);
+        println!("{}", metrics.summary(Utc::now()));
+    }
+
+    Ok(())
+}
+
+// one script per line, the name and its status separated by a tab
+pub fn list(system: bool) -> anyhow::Result<()> {
+    for script in status(&socket_path(system)?)?.scripts {
+        println!("{}\t{}", script.name, script.status.name());
+    }
+
+    Ok(())
+}
+
+// the script runs in the background, see `status` for the result
+pub fn run(script: &str, system: bool) -> anyhow::Result<()> {
+    match request(&socket_path(system)?, &Request::Run(script.to_string()))? {
+        Response::Ok => Ok(()),
+        Response::Error(error) => anyhow::bail!(error),
+        response => anyhow::bail!("unexpected response {response:?}"),
+    }
+}
+
+// the error of the response (e.g. an unknown script) is returned separately from errors talking to
+// the backup monitor
+pub fn run_and_wait(
+    script: &str,
+    system: bool,
+) -> anyhow::Result<Result<Vec<ScriptResult>, String>> {
+    match request(
+        &socket_path(system)?,
+        &Request::RunAndWait(script.to_string()),
+    )? {
+        Response::RunResult(results) => Ok(Ok(results)),
+        Response::Error(error) => Ok(Err(error)),
+        response => anyhow::bail!("unexpected response {response:?}"),
+    }
+}
+
+pub fn explain(script: &str, system: bool) -> anyhow::Result<()> {
+    match request(&socket_path(system)?, &Request::Explain(script.to_string()))? {
+        Response::Explanation(explanation) => {
+            println!("{explanation}");
+            Ok(())
+        }
+        Response::Error(error) => anyhow::bail!(error),
+        response => anyhow::bail!("unexpected response {response:?}"),
+    }
+}
+
+pub fn request(path: &Path, request: &Request) -> anyhow::Result<Response> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("failed to connect to `{}`", path.display()))?;
+
+    serde_json::to_writer(&mut stream, request)?;
+    stream.write_all(b"\n")?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event, manager::RunOutcome, run_result::Outcome};
+
+    #[test]
+    fn serialize_requests() {
+        assert_eq!(
+            serde_json::to_string(&Request::Status).unwrap(),
+            r#""status""#
+        );
+        assert_eq!(
+            serde_json::to_string(&Request::Run("Backup".to_string())).unwrap(),
+            r#"{"run":"Backup"}"#
+        );
+    }
+
+    #[test]
+    fn publish_status() {
+        let status = Arc::new(ArcSwap::from_pointee(Status::default()));
+        let publisher = StatusPublisher::<ksni::Handle<Tray>>::new(None, status.clone());
+
+        publisher.update(TrayData {
+            status: Some(ksni::Status::NeedsAttention),
+            tooltip: Some("Backup:\nNever backed up before".to_string()),
+            scripts: Some(vec![("Backup".to_string(), None, ScriptStatus::Overdue)]),
+            ..Default::default()
+        });
+        publisher.update(TrayData {
+            tooltip: Some("Backup:\nRunning".to_string()),
+            ..Default::default()
+        });
+
+        let settings = Settings::default();
+        let (tx, _rx) = event::channel();
+        let response = handle_request(Request::Status, Uid::current(), &status, &settings, &tx);
+
+        assert_eq!(
+            response,
+            Response::Status(Status {
+                status: "needsattention".to_string(),
+                tooltip: "Backup:\nRunning".to_string(),
+                scripts: vec![ScriptInfo {
+                    name: "Backup".to_string(),
+                    icon_name: None,
+                    status: ScriptStatus::Overdue,
+                    permitted: true,
+                }],
+                paused: false,
+            })
+        );
+    }
+
+    #[test]
+    fn run_unknown_script() {
+        let status = ArcSwap::from_pointee(Status::default());
+        let settings = Settings::default();
+        let (tx, rx) = event::channel();
+
+        let response = handle_request(
+            Request::Run("Backup".to_string()),
+            Uid::current(),
+            &status,
+            &settings,
+            &tx,
+        );
+
+        assert_eq!(
+            response,
+            Response::Error("unknown script `Backup`".to_string())
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn run_and_wait() {
+        let status = ArcSwap::from_pointee(Status::default());
+        let settings = serde_yaml_ng::from_str::<Settings>(indoc::indoc! {"
+            scripts:
+            - name: Backup
+              backup-script: '#!/bin/bash'
+              interval: 1day
+        "})
+        .unwrap();
+        let (tx, rx) = event::channel();
+
+        let main_loop = thread::spawn(move || {
+            let Ok(Event::ManualRun(name, _, Some(reply))) = rx.recv() else {
+                panic!("expected manual run event with reply");
+            };
+            reply
+                .send(vec![(name, RunOutcome::Failed("exit code 2".to_string()))])
+                .unwrap();
+        });
+
+        let response = handle_request(
+            Request::RunAndWait("Backup".to_string()),
+            Uid::current(),
+            &status,
+            &settings,
+            &tx,
+        );
+        main_loop.join().unwrap();
+
+        assert_eq!(
+            response,
+            Response::RunResult(vec![ScriptResult {
+                name: "Backup".to_string(),
+                outcome: Outcome::Failed,
+                message: Some("exit code 2".to_string()),
+            }])
+        );
+    }
+
+    #[test]
+    fn explain_script() {
+        let status = ArcSwap::from_pointee(Status::default());
+        let settings = Settings::default();
+        let (tx, rx) = event::channel();
+
+        let main_loop = thread::spawn(move || {
+            let Ok(Event::Explain(name, reply)) = rx.recv() else {
+                panic!("expected explain event");
+            };
+            reply
+                .send(Some(format!("{name}:\nBlocked by: nothing")))
+                .unwrap();
+        });
+
+        let response = handle_request(
+            Request::Explain("Backup".to_string()),
+            Uid::current(),
+            &status,
+            &settings,
+            &tx,
+        );
+        main_loop.join().unwrap();
+
+        assert_eq!(
+            response,
+            Response::Explanation("Backup:\nBlocked by: nothing".to_string())
+        );
+    }
+}
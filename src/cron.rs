@@ -0,0 +1,300 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{ensure, Context};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::Calendar;
+
+// occurrences are searched this far ahead, e.g. for `0 0 29 2 *`
+const MAX_DAYS: i64 = 8 * 366;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// a cron expression with the fields minute, hour, day of month, month and day of week, e.g.
+// `0 22 * * *` or `0 9 * * mon`, or a systemd calendar expression, e.g. `Mon..Fri 18:00`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    expression: String,
+    // bit sets of the matching values
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    // sunday is 0
+    days_of_week: u64,
+    // like cron, a day matches either field if both are restricted
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl Cron {
+    // the first occurrence after `ts` in the time zone `tz`, occurrences skipped by daylight saving
+    // time are left out
+    pub fn next_after<Tz: TimeZone>(&self, ts: DateTime<Utc>, tz: &Tz) -> Option<DateTime<Utc>> {
+        let local = ts.with_timezone(tz).naive_local();
+        let start = local.date();
+
+        for day in 0..MAX_DAYS {
+            let date = start + Duration::days(day);
+            if !self.matches_date(date) {
+                continue;
+            }
+
+            for hour in bits(self.hours) {
+                for minute in bits(self.minutes) {
+                    let Some(candidate) = date.and_hms_opt(hour, minute, 0) else {
+                        continue;
+                    };
+                    if candidate <= local {
+                        continue;
+                    }
+                    if let Some(next) = tz.from_local_datetime(&candidate).earliest() {
+                        return Some(next.with_timezone(&Utc));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !has(self.months, date.month()) {
+            return false;
+        }
+
+        let day_of_month = has(self.days_of_month, date.day());
+        let day_of_week = has(self.days_of_week, date.weekday().num_days_from_sunday());
+        match (self.days_of_month_restricted, self.days_of_week_restricted) {
+            (true, true) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            let calendar = expression.parse::<Calendar>().context(
+                "expected a cron expression (minute, hour, day of month, month, day of week) or \
+                 a calendar expression",
+            )?;
+            return Ok(Cron {
+                expression: expression.to_string(),
+                minutes: calendar.minutes,
+                hours: calendar.hours,
+                days_of_month: calendar.days_of_month,
+                months: calendar.months,
+                days_of_week: calendar.days_of_week,
+                // the weekdays and the date must both match
+                days_of_month_restricted: false,
+                days_of_week_restricted: true,
+            });
+        };
+
+        let mut weekdays =
+            parse_field(days_of_week, 0, 7, &WEEKDAYS).context("invalid day of week")?;
+        // 7 is sunday too
+        if has(weekdays, 7) {
+            weekdays |= 1;
+        }
+
+        Ok(Cron {
+            expression: expression.to_string(),
+            minutes: parse_field(minutes, 0, 59, &[]).context("invalid minute")?,
+            hours: parse_field(hours, 0, 23, &[]).context("invalid hour")?,
+            days_of_month: parse_field(days_of_month, 1, 31, &[])
+                .context("invalid day of month")?,
+            months: parse_field(months, 1, 12, &MONTHS).context("invalid month")?,
+            days_of_week: weekdays,
+            days_of_month_restricted: days_of_month != "*",
+            days_of_week_restricted: days_of_week != "*",
+        })
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = anyhow::Error;
+
+    fn try_from(expression: String) -> Result<Self, Self::Error> {
+        expression.parse()
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> Self {
+        cron.expression
+    }
+}
+
+// a comma separated list of `*`, values and ranges with optional steps, e.g. `1-5` or `*/15`;
+// names (e.g. `mon` or `jan`) start at `min`
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<u64> {
+    let value = |value: &str| -> anyhow::Result<u32> {
+        let value = match names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(value))
+        {
+            Some(index) => index as u32 + min,
+            None => value
+                .parse()
+                .with_context(|| format!("`{value}` is not a number"))?,
+        };
+        ensure!(
+            (min..=max).contains(&value),
+            "{value} is not between {min} and {max}"
+        );
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .with_context(|| format!("`{step}` is not a number"))?;
+                ensure!(step > 0, "the step must not be 0");
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/10` runs from 5 to the end
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        ensure!(start <= end, "`{range}` is an empty range");
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+fn bits(set: u64) -> impl Iterator<Item = u32> {
+    (0..64).filter(move |value| has(set, *value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().to_utc()
+    }
+
+    #[test]
+    fn next_occurrences() {
+        // a wednesday
+        let now = ts("2024-10-23T21:30:00Z");
+
+        let daily = "0 22 * * *".parse::<Cron>().unwrap();
+        assert_eq!(
+            daily.next_after(now, &Utc),
+            Some(ts("2024-10-23T22:00:00Z"))
+        );
+        assert_eq!(
+            daily.next_after(ts("2024-10-23T22:00:00Z"), &Utc),
+            Some(ts("2024-10-24T22:00:00Z"))
+        );
+
+        let mondays = "0 9 * * mon".parse::<Cron>().unwrap();
+        assert_eq!(
+            mondays.next_after(now, &Utc),
+            Some(ts("2024-10-28T09:00:00Z"))
+        );
+
+        let quarter_hours = "*/15 8-17 * * 1-5".parse::<Cron>().unwrap();
+        assert_eq!(
+            quarter_hours.next_after(ts("2024-10-25T17:50:00Z"), &Utc),
+            Some(ts("2024-10-28T08:00:00Z"))
+        );
+
+        // the day of month or the day of week
+        let either = "0 0 1 * sun".parse::<Cron>().unwrap();
+        assert_eq!(
+            either.next_after(now, &Utc),
+            Some(ts("2024-10-27T00:00:00Z"))
+        );
+
+        let leap_day = "0 12 29 feb *".parse::<Cron>().unwrap();
+        assert_eq!(
+            leap_day.next_after(now, &Utc),
+            Some(ts("2028-02-29T12:00:00Z"))
+        );
+
+        let sunday = "30 6 * * 7".parse::<Cron>().unwrap();
+        assert_eq!(
+            sunday.next_after(now, &Utc),
+            Some(ts("2024-10-27T06:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_calendar_occurrences() {
+        // a friday
+        let now = ts("2024-10-25T19:00:00Z");
+
+        let evenings = "Mon..Fri *-*-* 18:00".parse::<Cron>().unwrap();
+        assert_eq!(
+            evenings.next_after(now, &Utc),
+            Some(ts("2024-10-28T18:00:00Z"))
+        );
+        assert_eq!(evenings.to_string(), "Mon..Fri *-*-* 18:00");
+
+        // unlike cron, the weekday and the day of month must both match
+        let friday_13th = "Fri *-*-13".parse::<Cron>().unwrap();
+        assert_eq!(
+            friday_13th.next_after(now, &Utc),
+            Some(ts("2024-12-13T00:00:00Z"))
+        );
+
+        let daily = "daily".parse::<Cron>().unwrap();
+        assert_eq!(
+            daily.next_after(now, &Utc),
+            Some(ts("2024-10-26T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for (expression, error) in [
+            ("0 22 * *", "expected a cron expression"),
+            ("60 22 * * *", "invalid minute: 60 is not between 0 and 59"),
+            (
+                "0 22 * * funday",
+                "invalid day of week: `funday` is not a number",
+            ),
+            (
+                "0 22 * * 5-1",
+                "invalid day of week: `5-1` is an empty range",
+            ),
+            ("*/0 22 * * *", "invalid minute: the step must not be 0"),
+        ] {
+            let message = format!("{:#}", expression.parse::<Cron>().unwrap_err());
+            assert!(message.starts_with(error), "{expression}: {message}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use zbus::{
+    blocking::{connection, Connection},
+    fdo,
+    object_server::SignalContext,
+};
+
+use crate::{control::Status, event::EventSender, history::Trigger, settings::Settings, Event};
+
+const BUS_NAME: &str = "org.backup_monitor";
+const OBJECT_PATH: &str = "/org/backup_monitor";
+
+struct BackupMonitor {
+    status: Arc<ArcSwap<Status>>,
+    settings: Arc<ArcSwap<Settings>>,
+    tx: EventSender,
+}
+
+#[zbus::interface(name = "org.backup_monitor")]
+impl BackupMonitor {
+    fn run_script(&self, name: String) -> fdo::Result<()> {
+        if !self
+            .settings
+            .load()
+            .scripts
+            .iter()
+            .any(|script| script.name == name)
+        {
+            return Err(fdo::Error::InvalidArgs(format!("unknown script `{name}`")));
+        }
+
+        log::info!("requested to run `{name}` over D-Bus");
+
+        let _ = self.tx.send(Event::ManualRun(name, Trigger::DBus, None));
+        Ok(())
+    }
+
+    // JSON, like the status sent to clients of the control socket
+    fn get_status(&self) -> fdo::Result<String> {
+        serde_json::to_string(&**self.status.load())
+            .map_err(|error| fdo::Error::Failed(error.to_string()))
+    }
+
+    fn list_scripts(&self) -> Vec<String> {
+        self.settings
+            .load()
+            .scripts
+            .iter()
+            .map(|script| script.name.clone())
+            .collect()
+    }
+
+    #[zbus(signal)]
+    async fn backup_started(context: &SignalContext<'_>, name: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn backup_finished(
+        context: &SignalContext<'_>,
+        name: &str,
+        success: bool,
+    ) -> zbus::Result<()>;
+
+    // `ok`, `overdue`, `failed`, `running` or `disabled`, like `backup-monitor list`
+    #[zbus(signal)]
+    async fn state_changed(
+        context: &SignalContext<'_>,
+        name: &str,
+        state: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn reminder_raised(context: &SignalContext<'_>, names: Vec<String>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn settings_reloaded(context: &SignalContext<'_>) -> zbus::Result<()>;
+}
+
+// lets other tools (scripts, desktop widgets, ...) drive the backup monitor on the session bus
+#[derive(Clone)]
+pub struct DBusService(Connection);
+
+impl DBusService {
+    pub fn serve(
+        status: Arc<ArcSwap<Status>>,
+        settings: Arc<ArcSwap<Settings>>,
+        tx: EventSender,
+    ) -> zbus::Result<DBusService> {
+        let interface = BackupMonitor {
+            status,
+            settings,
+            tx,
+        };
+        let connection = connection::Builder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, interface)?
+            .build()?;
+        Ok(DBusService(connection))
+    }
+
+    pub fn backup_started(&self, name: &str) {
+        let result = SignalContext::new(self.0.inner(), OBJECT_PATH)
+            .and_then(|context| zbus::block_on(BackupMonitor::backup_started(&context, name)));
+        if let Err(error) = result {
+            log::warn!("failed to emit BackupStarted: {error}");
+        }
+    }
+
+    pub fn backup_finished(&self, name: &str, success: bool) {
+        let result = SignalContext::new(self.0.inner(), OBJECT_PATH).and_then(|context| {
+            zbus::block_on(BackupMonitor::backup_finished(&context, name, success))
+        });
+        if let Err(error) = result {
+            log::warn!("failed to emit BackupFinished: {error}");
+        }
+    }
+
+    pub fn state_changed(&self, name: &str, state: &str) {
+        let result = SignalContext::new(self.0.inner(), OBJECT_PATH).and_then(|context| {
+            zbus::block_on(BackupMonitor::state_changed(&context, name, state))
+        });
+        if let Err(error) = result {
+            log::warn!("failed to emit StateChanged: {error}");
+        }
+    }
+
+    pub fn reminder_raised(&self, names: Vec<String>) {
+        let result = SignalContext::new(self.0.inner(), OBJECT_PATH)
+            .and_then(|context| zbus::block_on(BackupMonitor::reminder_raised(&context, names)));
+        if let Err(error) = result {
+            log::warn!("failed to emit ReminderRaised: {error}");
+        }
+    }
+
+    pub fn settings_reloaded(&self) {
+        let result = SignalContext::new(self.0.inner(), OBJECT_PATH)
+            .and_then(|context| zbus::block_on(BackupMonitor::settings_reloaded(&context)));
+        if let Err(error) = result {
+            log::warn!("failed to emit SettingsReloaded: {error}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fs::{self, File},
+    path::Path,
+    sync::Arc,
+};
+
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Duration, Local, Utc};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::Clock,
+    control::{self, Request, Response, Status},
+    logind,
+    main_loop::{analyze, WakeupReason},
+    manager::Manager,
+    script_manager::{Decision, ScriptManager},
+    settings::Settings,
+};
+
+const REPLAY_DURATION: Duration = Duration::days(14);
+
+// protects against scheduling bugs that would never advance the fake clock
+const MAX_REPLAY_STEPS: usize = 100_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DebugBundle {
+    pub created: DateTime<Utc>,
+
+    pub mounts: String,
+
+    pub active_user: Option<String>,
+
+    pub settings: Settings,
+
+    // status of the running instance, if any
+    pub status: Option<Status>,
+}
+
+pub fn record(path: &Path, system: bool) -> anyhow::Result<()> {
+    let status = match control::request(&control::socket_path(system)?, &Request::Status) {
+        Ok(Response::Status(status)) => Some(status),
+        Ok(_) | Err(_) => {
+            log::warn!("backup monitor is not running, recording without status");
+            None
+        }
+    };
+
+    let active_user = zbus::blocking::Connection::system()
+        .and_then(|connection| logind::active_user(&connection))
+        .unwrap_or_else(|error| {
+            log::warn!("failed to get active user: {error}");
+            None
+        });
+
+    let bundle = DebugBundle {
+        created: Utc::now(),
+        mounts: fs::read_to_string("/proc/mounts")?,
+        active_user,
+        settings: Settings::load()?,
+        status,
+    };
+
+    serde_yaml_ng::to_writer(File::create(path)?, &bundle)?;
+
+    println!("debug bundle saved to `{}`", path.display());
+
+    Ok(())
+}
+
+// drives the scheduler against the bundle with a fake clock, scripts are not run but assumed to be
+// successful
+pub fn replay(path: &Path) -> anyhow::Result<()> {
+    let bundle = serde_yaml_ng::from_reader::<_, DebugBundle>(File::open(path)?)?;
+
+    let clock = Clock::with_time(bundle.created);
+    let settings = Arc::new(ArcSwap::from_pointee(bundle.settings));
+    let mut manager = ScriptManager::new(clock, settings.clone(), &bundle.mounts);
+    manager.set_active_user(bundle.active_user);
+
+    if let Some(status) = &bundle.status {
+        println!("recorded status:\n{}\n", status.tooltip);
+    }
+
+    println!("{}:\n{}\n", format_time(clock.now()), manager.tooltip());
+
+    let end = bundle.created + REPLAY_DURATION;
+    let mut last_reminder = None;
+
+    for _ in 0..MAX_REPLAY_STEPS {
+        let now = clock.now();
+
+        let (_, show_reminder, next_wakeup) = analyze(
+            now,
+            &mut manager,
+            &mut last_reminder,
+            &settings.load(),
+            false,
+        )?;
+
+        if show_reminder {
+            println!(
+                "{}  remind about {}",
+                format_time(now),
+                manager.overdue().iter().map(|item| &item.name).join(", ")
+            );
+        }
+
+        let Some((next_wakeup, reason)) = next_wakeup.filter(|(ts, _)| *ts <= end) else {
+            break;
+        };
+
+        clock.set_time(next_wakeup.max(now));
+
+        if reason == WakeupReason::RunScripts {
+            for (name, decision) in manager.dry_run() {
+                let description = match decision {
+                    Decision::Run => "run".to_string(),
+                    Decision::WaitForUser(user) => format!("wait for {user} to be active"),
+                    Decision::WaitForPower(requirement) => format!("wait for {requirement}"),
+                    Decision::WaitForIdle => "wait for the user to be idle".to_string(),
+                    Decision::WaitForPaths(paths) => format!(
+                        "wait for {} to be mounted",
+                        paths.iter().map(|path| path.display()).join(", ")
+                    ),
+                };
+                println!("{}  {name}: {description}", format_time(clock.now()));
+            }
+        }
+    }
+
+    println!("\n{}:\n{}", format_time(clock.now()), manager.tooltip());
+
+    Ok(())
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
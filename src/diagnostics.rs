@@ -0,0 +1,85 @@
+use std::{
+    env,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use chrono::Utc;
+use notify_rust::{Notification, Timeout};
+
+use crate::{
+    auto_launch, history::history_file_path, metrics, notification, settings::settings_file_path,
+};
+
+// the information needed when filing an issue
+pub fn report() -> String {
+    let path = |path: anyhow::Result<PathBuf>| match path {
+        Ok(path) => path.display().to_string(),
+        Err(error) => format!("unknown ({error})"),
+    };
+    let autostart = match auto_launch().and_then(|auto_launch| Ok(auto_launch.is_enabled()?)) {
+        Ok(true) => "enabled".to_string(),
+        Ok(false) => "disabled".to_string(),
+        Err(error) => format!("unknown ({error})"),
+    };
+
+    [
+        format!(
+            "{} {} ({})",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_HOMEPAGE")
+        ),
+        format!("Settings: {}", path(settings_file_path())),
+        format!("History: {}", path(history_file_path())),
+        format!("Autostart: {autostart}"),
+        metrics::snapshot().summary(Utc::now()),
+    ]
+    .join("\n")
+}
+
+pub fn show(title: &str, icon_name: &str) -> anyhow::Result<()> {
+    let report = report();
+
+    let Some(handle) = notification::show(
+        Notification::new()
+            .appname(title)
+            .summary("About / Diagnostics")
+            .body(&report)
+            .icon(icon_name)
+            .action("copy", "Copy report")
+            .timeout(Timeout::Milliseconds(30_000)),
+    )?
+    else {
+        return Ok(());
+    };
+
+    handle.wait_for_action(|action| {
+        if action == "copy" {
+            if let Err(error) = copy_to_clipboard(&report) {
+                log::warn!("failed to copy diagnostics report: {error}");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn copy_to_clipboard(text: &str) -> anyhow::Result<()> {
+    let mut command = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        Command::new("wl-copy")
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard"]);
+        command
+    };
+
+    let mut child = command.stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    child.wait()?;
+
+    Ok(())
+}
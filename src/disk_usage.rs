@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use nix::sys::statvfs::statvfs;
+
+// the trend needs a few samples to not be thrown off by a single large backup
+const MIN_SAMPLES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub used: u64,
+    // available to unprivileged users
+    pub free: u64,
+}
+
+pub fn disk_usage(path: &Path) -> nix::Result<DiskUsage> {
+    let stat = statvfs(path)?;
+    let block_size = stat.fragment_size() as u64;
+    Ok(DiskUsage {
+        used: (stat.blocks() as u64 - stat.blocks_free() as u64) * block_size,
+        free: stat.blocks_available() as u64 * block_size,
+    })
+}
+
+// when the used space reaches the capacity if it keeps growing like in the samples of used space,
+// by a least squares fit
+pub fn projected_full(samples: &[(DateTime<Utc>, u64)], capacity: u64) -> Option<DateTime<Utc>> {
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    let start = samples.iter().map(|(time, _)| *time).min()?;
+    let points = samples
+        .iter()
+        .map(|(time, used)| ((*time - start).num_seconds() as f64, *used as f64))
+        .collect::<Vec<_>>();
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let variance = points
+        .iter()
+        .map(|(x, _)| (x - mean_x).powi(2))
+        .sum::<f64>();
+    let covariance = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+    if variance == 0.0 {
+        return None;
+    }
+
+    // bytes per second
+    let slope = covariance / variance;
+    if slope <= 0.0 {
+        return None;
+    }
+
+    let full = mean_x + (capacity as f64 - mean_y) / slope;
+    start.checked_add_signed(Duration::try_seconds(full.round() as i64)?)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    let gib = bytes as f64 / (1024.0 * 1024.0 * 1024.0);
+    if gib >= 1.0 {
+        format!("{gib:.1} GiB")
+    } else {
+        format!("{} MiB", bytes / (1024 * 1024))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn projection() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        // 10 GiB per day
+        let samples = (0..5)
+            .map(|day| (start + Duration::days(day), (100 + 10 * day as u64) * GIB))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            projected_full(&samples, 500 * GIB),
+            Some(start + Duration::days(40))
+        );
+        assert_eq!(projected_full(&samples[..2], 500 * GIB), None);
+
+        // pruned backups
+        let shrinking = samples
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(index, (_, used))| (start + Duration::days(index as i64), *used))
+            .collect::<Vec<_>>();
+        assert_eq!(projected_full(&shrinking, 500 * GIB), None);
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(format_bytes(512 * 1024 * 1024), "512 MiB");
+        assert_eq!(format_bytes(380 * GIB + GIB / 2), "380.5 GiB");
+    }
+}
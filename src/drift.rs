@@ -0,0 +1,164 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+    process::Command,
+};
+
+use anyhow::{ensure, Context};
+use serde_yaml_ng::Value;
+
+use crate::settings::DriftCheck;
+
+// the settings which differ from the reference, e.g. `title` or `scripts: Backup`
+pub fn check_drift(drift_check: &DriftCheck, settings_path: &Path) -> anyhow::Result<Vec<String>> {
+    let live = fs::read_to_string(settings_path)?;
+    let reference = match &drift_check.revision {
+        Some(revision) => committed_file(&drift_check.reference, revision)?,
+        None => fs::read_to_string(&drift_check.reference)
+            .with_context(|| format!("failed to read `{}`", drift_check.reference.display()))?,
+    };
+
+    Ok(differences(
+        &serde_yaml_ng::from_str(&live)?,
+        &serde_yaml_ng::from_str(&reference).context("failed to parse the reference settings")?,
+    ))
+}
+
+fn committed_file(path: &Path, revision: &str) -> anyhow::Result<String> {
+    let dir = path.parent().context("reference has no parent directory")?;
+    let file_name = path.file_name().context("reference has no file name")?;
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        .arg(format!("{revision}:./{}", file_name.to_string_lossy()))
+        .output()
+        .context("failed to run git")?;
+    ensure!(
+        output.status.success(),
+        "git show failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+// compares the top level settings, lists of named items (scripts, timers, ...) by name, so
+// reordering them doesn't count as a difference
+fn differences(live: &Value, reference: &Value) -> Vec<String> {
+    let keys = mapping_keys(live)
+        .chain(mapping_keys(reference))
+        .collect::<BTreeSet<_>>();
+
+    let mut differences = Vec::new();
+    for key in keys {
+        let (live, reference) = (live.get(key), reference.get(key));
+        if live == reference {
+            continue;
+        }
+
+        match (named_items(live), named_items(reference)) {
+            (Some(live), Some(reference)) => {
+                let names = live.keys().chain(reference.keys()).collect::<BTreeSet<_>>();
+                differences.extend(
+                    names
+                        .into_iter()
+                        .filter(|name| live.get(*name) != reference.get(*name))
+                        .map(|name| format!("{key}: {name}")),
+                );
+            }
+            _ => differences.push(key.to_string()),
+        }
+    }
+
+    differences
+}
+
+fn mapping_keys(value: &Value) -> impl Iterator<Item = &str> {
+    value
+        .as_mapping()
+        .into_iter()
+        .flat_map(|mapping| mapping.keys())
+        .filter_map(Value::as_str)
+}
+
+fn named_items(value: Option<&Value>) -> Option<BTreeMap<&str, &Value>> {
+    value?
+        .as_sequence()?
+        .iter()
+        .map(|item| Some((item.get("name")?.as_str()?, item)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use std::time::Duration;
+
+    #[test]
+    fn drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings_path = dir.path().join("backup-monitor.yaml");
+        let reference = dir.path().join("reference.yaml");
+
+        fs::write(
+            &settings_path,
+            indoc! {"
+                # edited locally
+                title: Backups
+                scripts:
+                - name: Photos
+                  backup-script: '#!/bin/bash'
+                  interval: 1day
+                - name: Documents
+                  backup-script: '#!/bin/bash'
+                  interval: 1day
+            "},
+        )
+        .unwrap();
+        fs::write(
+            &reference,
+            indoc! {"
+                scripts:
+                - name: Documents
+                  backup-script: '#!/bin/bash'
+                  interval: 1day
+                - name: Photos
+                  backup-script: '#!/bin/bash'
+                  interval: 1week
+                - name: Music
+                  backup-script: '#!/bin/bash'
+                  interval: 1week
+            "},
+        )
+        .unwrap();
+
+        let drift_check = DriftCheck {
+            reference: reference.clone(),
+            revision: None,
+            interval: Duration::from_secs(60),
+        };
+        assert_eq!(
+            check_drift(&drift_check, &settings_path).unwrap(),
+            vec!["scripts: Music", "scripts: Photos", "title"]
+        );
+
+        // comments, formatting and order don't matter
+        fs::write(
+            &reference,
+            indoc! {"
+                title: Backups
+                scripts:
+                - {name: Documents, backup-script: '#!/bin/bash', interval: 1day}
+                - {name: Photos, backup-script: '#!/bin/bash', interval: 1day}
+            "},
+        )
+        .unwrap();
+        assert!(check_drift(&drift_check, &settings_path)
+            .unwrap()
+            .is_empty());
+    }
+}
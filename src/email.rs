@@ -0,0 +1,96 @@
+use std::thread;
+
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport,
+    Transport,
+};
+use nix::unistd::gethostname;
+
+use crate::settings::Email;
+
+fn failure_email(
+    email: &Email,
+    script_name: &str,
+    failures: u32,
+    message: &str,
+) -> anyhow::Result<Message> {
+    let hostname = gethostname()
+        .map(|hostname| hostname.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let body = format!(
+        "{script_name} on {hostname} has failed {failures} times in a row.\n\n\
+         Last error: {message}\n"
+    );
+
+    Ok(Message::builder()
+        .from(email.from.parse::<Mailbox>()?)
+        .to(email.to.parse::<Mailbox>()?)
+        .subject(format!("{script_name} keeps failing on {hostname}"))
+        .body(body)?)
+}
+
+// sent in the background, so an unreachable server doesn't hold up the backups
+pub fn send_failure_email(email: &Email, script_name: &str, failures: u32, message: &str) {
+    let mail = match failure_email(email, script_name, failures, message) {
+        Ok(mail) => mail,
+        Err(error) => {
+            log::warn!("failed to create failure email: {error:#}");
+            return;
+        }
+    };
+
+    let email = email.clone();
+    thread::spawn(move || {
+        let result = SmtpTransport::starttls_relay(&email.smtp_server).map(|builder| {
+            let builder = match email.port {
+                Some(port) => builder.port(port),
+                None => builder,
+            };
+            match (&email.username, &email.password) {
+                (Some(username), Some(password)) => builder
+                    .credentials(Credentials::new(username.clone(), password.clone()))
+                    .build(),
+                _ => builder.build(),
+            }
+        });
+
+        match result.and_then(|transport| transport.send(&mail)) {
+            Ok(_) => log::info!("sent failure email to {}", email.to),
+            Err(error) => log::warn!("failed to send failure email: {error}"),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_emails() {
+        let email = Email {
+            smtp_server: "smtp.example.com".to_string(),
+            port: None,
+            username: None,
+            password: None,
+            from: "backup@example.com".to_string(),
+            to: "alice@example.com".to_string(),
+            after_failures: 3,
+        };
+
+        let mail = failure_email(&email, "Backup", 3, "Backup failed with exit code 2").unwrap();
+        let mail = String::from_utf8(mail.formatted()).unwrap();
+
+        assert!(mail.contains("From: backup@example.com"));
+        assert!(mail.contains("To: alice@example.com"));
+        assert!(mail.contains("Subject: Backup keeps failing on "));
+        assert!(mail.contains("has failed 3 times in a row"));
+        assert!(mail.contains("Last error: Backup failed with exit code 2"));
+
+        let invalid = Email {
+            to: "not an address".to_string(),
+            ..email
+        };
+        assert!(failure_email(&invalid, "Backup", 3, "failed").is_err());
+    }
+}
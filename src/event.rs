@@ -1,14 +1,71 @@
+#[cfg(test)]
+use std::cell::RefCell;
 use std::{
-    cell::RefCell,
-    sync::mpsc::{Receiver, RecvTimeoutError},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, SendError, Sender},
     time::Duration,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use chrono::{DateTime, Utc};
+
+use crate::{
+    history::Trigger, manager::RunOutcome, metrics, network::Location, script_manager,
+    upower::Power,
+};
+
+#[derive(Debug, Clone)]
 pub enum Event {
     MountsChanged(String),
     SettingsChanged,
-    ManualRun(String),
+    // the outcomes are sent back through the channel if any, e.g. for `run --wait`
+    ManualRun(String, Trigger, Option<Sender<Vec<(String, RunOutcome)>>>),
+    Pause(Duration),
+    Resume,
+    // `None` uses all backup scripts
+    SwitchProfile(Option<String>),
+    // the network the computer is connected to, for profiles which are activated automatically
+    LocationChanged(Location),
+    ActiveUserChanged(Option<String>),
+    // the screen of the user's session has been locked or unlocked
+    LockedChanged(bool),
+    // since when the user has been idle, `None` while the user is active
+    IdleChanged(Option<DateTime<Utc>>),
+    // the computer woke up from suspend or hibernation, the timeout of the main loop doesn't
+    // include the time asleep
+    Resumed,
+    // the computer has been plugged in or unplugged or the battery level has changed
+    PowerChanged(Power),
+    // a file system has been connected, see `auto-mount`
+    DeviceAdded,
+    Cancel(String),
+    // hides the failure of the script, see `clear-failures-after`
+    DismissFailure(String),
+    // the label of a command of the tray menu
+    TrayCommand(String),
+    // the explanation is sent back through the channel
+    Explain(String, Sender<Option<String>>),
+}
+
+// counts sent events to keep track of the backlog of the main loop
+#[derive(Debug, Clone)]
+pub struct EventSender(Sender<Event>);
+
+impl EventSender {
+    pub fn send(&self, event: Event) -> Result<(), SendError<Event>> {
+        // the main loop is blocked while a script runs, so it can't receive the event in time
+        if let Event::Cancel(name) = &event {
+            script_manager::request_cancel(name);
+        }
+
+        metrics::event_sent();
+        self.0
+            .send(event)
+            .inspect_err(|_| metrics::event_received())
+    }
+}
+
+pub fn channel() -> (EventSender, Receiver<Event>) {
+    let (tx, rx) = mpsc::channel();
+    (EventSender(tx), rx)
 }
 
 pub trait ReceiveEvent {
@@ -25,19 +82,25 @@ impl EventReceiver {
 
 impl ReceiveEvent for EventReceiver {
     fn recv_timeout(&self, timeout: Option<Duration>) -> Result<Event, RecvTimeoutError> {
-        match timeout {
+        let result = match timeout {
             Some(t) => self.0.recv_timeout(t),
             None => self.0.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        if result.is_ok() {
+            metrics::event_received();
         }
+        result
     }
 }
 
+#[cfg(test)]
 #[derive(Debug, Default)]
 pub struct MockEventReceiver {
     pub results: RefCell<Vec<Result<Event, RecvTimeoutError>>>,
     pub recv_timeout: RefCell<Vec<Option<Duration>>>,
 }
 
+#[cfg(test)]
 impl ReceiveEvent for MockEventReceiver {
     fn recv_timeout(&self, timeout: Option<Duration>) -> Result<Event, RecvTimeoutError> {
         self.recv_timeout.borrow_mut().push(timeout);
@@ -0,0 +1,149 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Context};
+
+use crate::{
+    settings::{settings_file_path, system_mode, Settings},
+    state::state_file_path,
+};
+
+static COMMIT_LOCK: Mutex<()> = Mutex::new(());
+
+fn repo_dir() -> anyhow::Result<PathBuf> {
+    if system_mode() {
+        Ok(PathBuf::from("/etc/backup-monitor"))
+    } else {
+        let config_dir = dirs::config_dir().context("config dir not found")?;
+        Ok(config_dir.join("backup-monitor"))
+    }
+}
+
+// commits copies of the settings and state files if enabled, nothing is committed if they didn't
+// change
+pub fn commit(settings: &Settings, message: &str) {
+    if !settings.git_history {
+        return;
+    }
+
+    if let Err(error) = commit_settings(message) {
+        log::warn!("failed to commit settings: {error:#}");
+    }
+}
+
+fn commit_settings(message: &str) -> anyhow::Result<bool> {
+    let files = [
+        (settings_file_path()?, "backup-monitor.yaml"),
+        (state_file_path()?, "state.yaml"),
+    ];
+    commit_files(&repo_dir()?, &files, message)
+}
+
+fn commit_files(repo: &Path, files: &[(PathBuf, &str)], message: &str) -> anyhow::Result<bool> {
+    let _lock = COMMIT_LOCK.lock().unwrap();
+
+    if !repo.join(".git").exists() {
+        fs::create_dir_all(repo)?;
+        git(repo, &["init", "--quiet"])?;
+    }
+
+    for (source, name) in files {
+        let target = repo.join(name);
+        // the state file doesn't exist before the first run
+        if source.exists() && *source != target {
+            fs::copy(source, &target)?;
+        }
+        if target.exists() {
+            git(repo, &["add", "--", name])?;
+        }
+    }
+
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["diff", "--cached", "--quiet"])
+        .status()
+        .context("failed to run git")?;
+    if status.success() {
+        return Ok(false);
+    }
+
+    // doesn't depend on the git config of the user
+    git(
+        repo,
+        &[
+            "-c",
+            "user.name=Backup Monitor",
+            "-c",
+            "user.email=backup-monitor@localhost",
+            "-c",
+            "commit.gpgsign=false",
+            "commit",
+            "--quiet",
+            "--message",
+            message,
+        ],
+    )?;
+    log::debug!("committed settings: {message}");
+
+    Ok(true)
+}
+
+fn git(repo: &Path, args: &[&str]) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+    ensure!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.first().unwrap_or(&""),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("backup-monitor");
+        let settings_path = dir.path().join("backup-monitor.yaml");
+        let files = [
+            (settings_path.clone(), "backup-monitor.yaml"),
+            (dir.path().join("state.yaml"), "state.yaml"),
+        ];
+
+        fs::write(&settings_path, "title: Backups\n").unwrap();
+        assert!(commit_files(&repo, &files, "backup monitor started").unwrap());
+        // unchanged
+        assert!(!commit_files(&repo, &files, "backup monitor started").unwrap());
+
+        fs::write(&settings_path, "title: Edited\n").unwrap();
+        assert!(commit_files(&repo, &files, "settings reloaded after an edit").unwrap());
+
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args(["log", "--format=%s"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "settings reloaded after an edit\nbackup monitor started\n"
+        );
+        assert_eq!(
+            fs::read_to_string(repo.join("backup-monitor.yaml")).unwrap(),
+            "title: Edited\n"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{disk_usage::format_bytes, settings::system_mode};
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+// who or what initiated an action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trigger {
+    Schedule,
+    Tray,
+    Notification,
+    // a client of the control socket, e.g. the command line or the tray of the system-wide daemon
+    Control { uid: u32, user: Option<String> },
+    // a client of the D-Bus interface on the session bus
+    DBus,
+    // the user accepted the warnings of the pre-flight checks in a notification
+    RunAnyway,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
+pub enum Action {
+    Run {
+        success: bool,
+        message: String,
+        // missing in entries recorded before runs had ids
+        #[serde(default)]
+        run_id: Option<String>,
+    },
+    Pause {
+        until: DateTime<Utc>,
+    },
+    Resume,
+    // of the file system of a mount path after a run
+    DiskUsage {
+        path: PathBuf,
+        used: u64,
+        free: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    pub script: Option<String>,
+    pub action: Action,
+    pub trigger: Trigger,
+}
+
+impl Trigger {
+    fn describe(&self) -> String {
+        match self {
+            Trigger::Schedule => "schedule".to_string(),
+            Trigger::Tray => "tray".to_string(),
+            Trigger::Notification => "notification".to_string(),
+            Trigger::Control {
+                uid,
+                user: Some(user),
+            } => format!("{user} (uid {uid})"),
+            Trigger::Control { uid, user: None } => format!("uid {uid}"),
+            Trigger::DBus => "D-Bus".to_string(),
+            Trigger::RunAnyway => "run anyway".to_string(),
+        }
+    }
+}
+
+impl Entry {
+    fn message(&self) -> String {
+        let action = match &self.action {
+            Action::Run { message, .. } => message.clone(),
+            Action::Pause { until } => format!("backups paused until {until}"),
+            Action::Resume => "backups resumed".to_string(),
+            Action::DiskUsage { path, used, free } => format!(
+                "`{}` has {} used and {} free",
+                path.display(),
+                format_bytes(*used),
+                format_bytes(*free)
+            ),
+        };
+        format!("{action} (initiated by {})", self.trigger.describe())
+    }
+}
+
+pub fn history_file_path() -> anyhow::Result<PathBuf> {
+    if system_mode() {
+        Ok(PathBuf::from("/var/lib/backup-monitor/history.jsonl"))
+    } else {
+        let state_dir = dirs::state_dir().context("state dir not found")?;
+        Ok(state_dir.join("backup-monitor").join("history.jsonl"))
+    }
+}
+
+// failing to record history must not keep backups from running
+pub fn record(entry: Entry, journal: bool) {
+    if let Err(error) = append(&entry) {
+        log::warn!("failed to record history: {error}");
+    }
+
+    if journal {
+        if let Err(error) = log_to_journal(&entry) {
+            log::warn!("failed to log to journal: {error}");
+        }
+    }
+}
+
+// unreadable entries, e.g. of newer versions, are skipped
+pub fn read() -> anyhow::Result<Vec<Entry>> {
+    let path = history_file_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+fn append(entry: &Entry) -> anyhow::Result<()> {
+    let path = history_file_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+
+    Ok(())
+}
+
+// native journal protocol, see https://systemd.io/JOURNAL_NATIVE_PROTOCOL/
+fn log_to_journal(entry: &Entry) -> anyhow::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to(journal_fields(entry).as_bytes(), JOURNAL_SOCKET)?;
+    Ok(())
+}
+
+fn journal_fields(entry: &Entry) -> String {
+    let mut fields = vec![
+        ("MESSAGE", entry.message()),
+        ("PRIORITY", "5".to_string()),
+        ("SYSLOG_IDENTIFIER", env!("CARGO_PKG_NAME").to_string()),
+        ("BACKUP_MONITOR_TRIGGER", entry.trigger.describe()),
+    ];
+    if let Some(script) = &entry.script {
+        fields.push(("BACKUP_MONITOR_SCRIPT", script.clone()));
+    }
+    if let Action::Run {
+        run_id: Some(run_id),
+        ..
+    } = &entry.action
+    {
+        fields.push(("BACKUP_MONITOR_RUN_ID", run_id.clone()));
+    }
+
+    fields
+        .into_iter()
+        // multi-line values would need the binary format
+        .map(|(key, value)| format!("{key}={}\n", value.replace('\n', " ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_entry() {
+        let entry = Entry {
+            time: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            script: Some("Backup".to_string()),
+            action: Action::Run {
+                success: false,
+                message: "Backup failed with exit code 1".to_string(),
+                run_id: Some("0b5c1a4e-7d2f-4c89-9a61-3f0e8b7d2c15".to_string()),
+            },
+            trigger: Trigger::Control {
+                uid: 1000,
+                user: Some("alice".to_string()),
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"time":"2023-11-14T22:13:20Z","script":"Backup","action":{"run":{"success":false,"message":"Backup failed with exit code 1","run-id":"0b5c1a4e-7d2f-4c89-9a61-3f0e8b7d2c15"}},"trigger":{"control":{"uid":1000,"user":"alice"}}}"#
+        );
+        assert_eq!(
+            journal_fields(&entry),
+            "MESSAGE=Backup failed with exit code 1 (initiated by alice (uid 1000))\n\
+             PRIORITY=5\n\
+             SYSLOG_IDENTIFIER=backup-monitor\n\
+             BACKUP_MONITOR_TRIGGER=alice (uid 1000)\n\
+             BACKUP_MONITOR_SCRIPT=Backup\n\
+             BACKUP_MONITOR_RUN_ID=0b5c1a4e-7d2f-4c89-9a61-3f0e8b7d2c15\n"
+        );
+
+        // recorded before runs had ids
+        let entry = serde_json::from_str::<Entry>(
+            r#"{"time":"2023-11-14T22:13:20Z","script":"Backup","action":{"run":{"success":true,"message":"Backup finished"}},"trigger":"schedule"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            entry.action,
+            Action::Run {
+                success: true,
+                message: "Backup finished".to_string(),
+                run_id: None,
+            }
+        );
+    }
+}
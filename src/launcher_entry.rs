@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+
+use zbus::{blocking::Connection, zvariant::Value};
+
+// docks and taskbars match the progress to the launcher of `backup-monitor.desktop`
+const APP_URI: &str = "application://backup-monitor.desktop";
+const OBJECT_PATH: &str = "/com/canonical/unity/launcherentry/backup_monitor";
+const INTERFACE: &str = "com.canonical.Unity.LauncherEntry";
+
+// shows the progress of running scripts on the taskbar entry (Plasma, Dash to Dock, Plank, ...)
+pub struct LauncherEntry(Connection);
+
+impl LauncherEntry {
+    pub fn connect() -> zbus::Result<LauncherEntry> {
+        Ok(LauncherEntry(Connection::session()?))
+    }
+
+    // `progress` ranges from 0 to 1, `None` hides the progress bar
+    pub fn set_progress(&self, progress: Option<f64>) {
+        let properties = HashMap::from([
+            ("progress", Value::from(progress.unwrap_or(0.0))),
+            ("progress-visible", Value::from(progress.is_some())),
+        ]);
+
+        if let Err(error) = self.0.emit_signal(
+            None::<&str>,
+            OBJECT_PATH,
+            INTERFACE,
+            "Update",
+            &(APP_URI, properties),
+        ) {
+            log::warn!("failed to update launcher entry: {error}");
+        }
+    }
+}
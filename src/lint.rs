@@ -0,0 +1,340 @@
+use std::{collections::HashSet, env, path::Path, time::Duration};
+
+use crate::{settings::Script, targets::Selector};
+
+// shorter intervals run the script (almost) back-to-back
+const MIN_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+const KEYWORDS: &[&str] = &[
+    "!", "[[", "]]", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for",
+    "function", "if", "in", "select", "then", "time", "until", "while",
+];
+
+const BUILTINS: &[&str] = &[
+    ".",
+    ":",
+    "[",
+    "alias",
+    "bg",
+    "bind",
+    "break",
+    "builtin",
+    "caller",
+    "cd",
+    "command",
+    "compgen",
+    "complete",
+    "compopt",
+    "continue",
+    "declare",
+    "dirs",
+    "disown",
+    "echo",
+    "enable",
+    "eval",
+    "exec",
+    "exit",
+    "export",
+    "false",
+    "fc",
+    "fg",
+    "getopts",
+    "hash",
+    "help",
+    "history",
+    "jobs",
+    "kill",
+    "let",
+    "local",
+    "logout",
+    "mapfile",
+    "popd",
+    "printf",
+    "pushd",
+    "pwd",
+    "read",
+    "readarray",
+    "readonly",
+    "return",
+    "set",
+    "shift",
+    "shopt",
+    "source",
+    "suspend",
+    "test",
+    "times",
+    "trap",
+    "true",
+    "type",
+    "typeset",
+    "ulimit",
+    "umask",
+    "unalias",
+    "unset",
+    "wait",
+];
+
+// finds mistakes which would only show up when the script is run, the checks are heuristics and
+// may miss problems
+pub fn lint(script: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if !script.starts_with("#!") {
+        warnings.push("missing shebang (e.g. `#!/bin/bash`)".to_string());
+    }
+
+    if script.contains("\r\n") {
+        warnings.push("uses Windows (CRLF) line endings".to_string());
+    }
+
+    if script.contains("\"~/") || script.contains("'~/") {
+        warnings.push("`~` is not expanded inside quotes, use `$HOME` instead".to_string());
+    }
+
+    let mut missing = Vec::new();
+    for command in commands(script) {
+        if !missing.contains(&command) && !in_path(&command) {
+            missing.push(command);
+        }
+    }
+    for command in missing {
+        warnings.push(format!("command `{command}` not found in PATH"));
+    }
+
+    warnings
+}
+
+// the first word of each simple command, without functions defined in the script
+// suspicious schedules, unless the script ignores schedule warnings
+pub fn lint_schedule(script: &Script) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if script.ignore_schedule_warnings {
+        return warnings;
+    }
+
+    // the interval isn't used with a schedule
+    let Some(interval) = script.interval.filter(|_| script.schedule.is_none()) else {
+        return warnings;
+    };
+
+    if interval.is_zero() {
+        warnings.push("`interval` is zero, the script runs back-to-back".to_string());
+    } else if interval < MIN_INTERVAL {
+        warnings.push(format!(
+            "`interval` of {} is very short, the script runs almost back-to-back",
+            humantime::format_duration(interval)
+        ));
+    }
+
+    if let Some(reminder) = script.reminder.filter(|reminder| *reminder < interval) {
+        warnings.push(format!(
+            "`reminder` of {} is shorter than `interval` of {}, reminders are shown before the \
+             backup is due",
+            humantime::format_duration(reminder),
+            humantime::format_duration(interval)
+        ));
+    }
+
+    warnings
+}
+
+// mount paths which can never be mounted, the paths themselves may be missing while the backup
+// disk is unplugged
+pub fn lint_mount_paths(script: &Script) -> Vec<String> {
+    script
+        .mount_paths
+        .iter()
+        .filter_map(|path| {
+            if Selector::parse(path).is_some() {
+                None
+            } else if !path.is_absolute() {
+                Some(format!("mount path `{}` isn't absolute", path.display()))
+            } else if path.parent().is_some_and(|parent| !parent.exists()) {
+                Some(format!(
+                    "the parent directory of mount path `{}` doesn't exist",
+                    path.display()
+                ))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn commands(script: &str) -> Vec<String> {
+    let mut functions = HashSet::new();
+    let mut commands = Vec::new();
+    let mut heredoc_end: Option<String> = None;
+    let mut continued = false;
+
+    for line in script.lines() {
+        let line = line.trim();
+
+        if let Some(end) = &heredoc_end {
+            if line == end {
+                heredoc_end = None;
+            }
+            continue;
+        }
+
+        let was_continued = continued;
+        continued = line.ends_with('\\');
+        if was_continued || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some((_, rest)) = line.split_once("<<") {
+            let end = rest
+                .trim_start_matches(['-', '~'])
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .trim_matches(['"', '\'']);
+            if !end.is_empty() {
+                heredoc_end = Some(end.to_string());
+            }
+        }
+
+        let mut words = line.split_whitespace().skip_while(|word| {
+            KEYWORDS.contains(word) || (word.contains('=') && !word.starts_with('='))
+        });
+        let Some(word) = words.next() else {
+            continue;
+        };
+
+        if let Some(name) = word.strip_suffix("()") {
+            functions.insert(name.to_string());
+            continue;
+        }
+        if words.next() == Some("()") {
+            functions.insert(word.to_string());
+            continue;
+        }
+
+        // skip everything which isn't a plain command name
+        let word = word.trim_end_matches(';');
+        if word.is_empty()
+            || BUILTINS.contains(&word)
+            || KEYWORDS.contains(&word)
+            || word.contains([
+                '/', '$', '`', '(', ')', '"', '\'', '{', '}', '<', '>', '|', '&',
+            ])
+        {
+            continue;
+        }
+
+        commands.push(word.to_string());
+    }
+
+    commands.retain(|command| !functions.contains(command));
+    commands
+}
+
+fn in_path(command: &str) -> bool {
+    env::var_os("PATH").is_some_and(|path| {
+        env::split_paths(&path).any(|dir| Path::new(&dir).join(command).is_file())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn lint_script() {
+        let script = indoc! {r#"
+            set -e
+            log() {
+                echo "$1"
+            }
+            VAR=1 backup-monitor-missing-command --dest "~/backup" \
+                definitely-not-a-continuation
+            if true; then
+                log "done"
+            fi
+            cat << EOF
+            not-a-command
+            EOF
+        "#};
+
+        assert_eq!(
+            lint(script),
+            vec![
+                "missing shebang (e.g. `#!/bin/bash`)",
+                "`~` is not expanded inside quotes, use `$HOME` instead",
+                "command `backup-monitor-missing-command` not found in PATH",
+            ]
+        );
+        assert_eq!(
+            lint("#!/bin/bash\r\necho done\r\n"),
+            vec!["uses Windows (CRLF) line endings"]
+        );
+        assert!(lint("#!/bin/bash\necho done\n").is_empty());
+    }
+
+    #[test]
+    fn lint_schedules() {
+        let script = |interval: u64, reminder: Option<u64>| {
+            let mut script = serde_yaml_ng::from_str::<Script>(indoc! {"
+                name: Backup
+                backup-script: '#!/bin/bash'
+                interval: 1day
+            "})
+            .unwrap();
+            script.interval = Some(Duration::from_secs(interval));
+            script.reminder = reminder.map(Duration::from_secs);
+            script
+        };
+
+        assert_eq!(
+            lint_schedule(&script(0, None)),
+            vec!["`interval` is zero, the script runs back-to-back"]
+        );
+        assert_eq!(
+            lint_schedule(&script(60, Some(3600))),
+            vec!["`interval` of 1m is very short, the script runs almost back-to-back"]
+        );
+        assert_eq!(
+            lint_schedule(&script(7200, Some(3600))),
+            vec![
+                "`reminder` of 1h is shorter than `interval` of 2h, reminders are shown before \
+                 the backup is due"
+            ]
+        );
+        assert!(lint_schedule(&script(3600, Some(7200))).is_empty());
+
+        let mut ignored = script(0, Some(0));
+        ignored.ignore_schedule_warnings = true;
+        assert!(lint_schedule(&ignored).is_empty());
+    }
+
+    #[test]
+    fn lint_mount_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut script = serde_yaml_ng::from_str::<Script>(indoc! {"
+            name: Backup
+            backup-script: '#!/bin/bash'
+            interval: 1day
+        "})
+        .unwrap();
+        script.mount_paths = vec![
+            // unplugged
+            dir.path().join("backup"),
+            dir.path().join("missing").join("backup"),
+            "mnt/backup".into(),
+        ];
+
+        assert_eq!(
+            lint_mount_paths(&script),
+            vec![
+                format!(
+                    "the parent directory of mount path `{}` doesn't exist",
+                    dir.path().join("missing").join("backup").display()
+                ),
+                "mount path `mnt/backup` isn't absolute".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use chrono::{DateTime, Utc};
+use zbus::{
+    blocking::Connection,
+    proxy,
+    zvariant::{OwnedFd, OwnedObjectPath},
+};
+
+use crate::{event::EventSender, Event};
+
+// id, uid, user name, seat and path of a session
+type SessionEntry = (String, u32, String, String, OwnedObjectPath);
+
+// what, who, why, mode, uid and pid of an inhibitor lock
+type InhibitorEntry = (String, String, String, String, u32, u32);
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn list_sessions(&self) -> zbus::Result<Vec<SessionEntry>>;
+
+    fn suspend(&self, interactive: bool) -> zbus::Result<()>;
+
+    fn list_inhibitors(&self) -> zbus::Result<Vec<InhibitorEntry>>;
+
+    // the lock is held until the file descriptor is closed
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    // `start` is `true` before going to sleep and `false` after waking up
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+
+    // `start` is `false` if the shutdown has been cancelled
+    #[zbus(signal)]
+    fn prepare_for_shutdown(&self, start: bool) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Seat",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/seat/seat0"
+)]
+trait Seat {
+    #[zbus(property)]
+    fn active_session(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+trait Session {
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn active(&self) -> zbus::Result<bool>;
+
+    // set by the desktop environment (or e.g. `swayidle`) when the user is away
+    #[zbus(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+
+    // microseconds since the epoch
+    #[zbus(property)]
+    fn idle_since_hint(&self) -> zbus::Result<u64>;
+
+    // e.g. `x11`, `wayland` or `tty`
+    #[zbus(property, name = "Type")]
+    fn type_(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.User",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/user/self"
+)]
+trait User {
+    // the graphical session of the user
+    #[zbus(property)]
+    fn display(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+// name of the user owning the active session on the main seat
+pub fn active_user(connection: &Connection) -> zbus::Result<Option<String>> {
+    let seat = SeatProxyBlocking::new(connection)?;
+    let (_, path) = seat.active_session()?;
+
+    // the path is `/` if there is no active session
+    if path.as_str() == "/" {
+        return Ok(None);
+    }
+
+    let session = SessionProxyBlocking::builder(connection)
+        .path(path)?
+        .build()?;
+    Ok(Some(session.name()?))
+}
+
+// uid and name of the users with a graphical session which isn't closing
+pub fn graphical_users(connection: &Connection) -> zbus::Result<Vec<(u32, String)>> {
+    let mut users = Vec::new();
+    for (_, uid, user, _, path) in ManagerProxyBlocking::new(connection)?.list_sessions()? {
+        let session = SessionProxyBlocking::builder(connection)
+            .path(path)?
+            .build()?;
+        if !matches!(session.type_()?.as_str(), "x11" | "wayland")
+            || session.state()? == "closing"
+            || users.iter().any(|(other, _)| *other == uid)
+        {
+            continue;
+        }
+        users.push((uid, user));
+    }
+    Ok(users)
+}
+
+pub fn suspend() -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    ManagerProxyBlocking::new(&connection)?.suspend(false)
+}
+
+// why suspending now would disturb someone, `None` if nobody is using the computer and nothing
+// else keeps it awake
+pub fn suspend_blocker() -> zbus::Result<Option<String>> {
+    let connection = Connection::system()?;
+    let manager = ManagerProxyBlocking::new(&connection)?;
+
+    for (_, _, user, _, path) in manager.list_sessions()? {
+        let session = SessionProxyBlocking::builder(&connection)
+            .path(path)?
+            .build()?;
+        if session.active()? && !session.idle_hint()? && session.state()? != "closing" {
+            return Ok(Some(format!("{user} is active")));
+        }
+    }
+
+    for (what, who, why, mode, _, _) in manager.list_inhibitors()? {
+        if mode == "block" && what.split(':').any(|what| what == "sleep") {
+            return Ok(Some(format!("{who} keeps the computer awake ({why})")));
+        }
+    }
+
+    Ok(None)
+}
+
+// keeps the computer from going to sleep until the returned lock is dropped
+pub fn inhibit_sleep(who: &str, why: &str) -> zbus::Result<OwnedFd> {
+    let connection = Connection::system()?;
+    ManagerProxyBlocking::new(&connection)?.inhibit("sleep", who, why, "block")
+}
+
+pub fn watch_active_user(tx: &EventSender) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let seat = SeatProxyBlocking::new(&connection)?;
+
+    let _ = tx.send(Event::ActiveUserChanged(active_user(&connection)?));
+
+    for _ in seat.receive_active_session_changed() {
+        let user = active_user(&connection)?;
+
+        log::debug!("active user has changed to {user:?}");
+
+        let _ = tx.send(Event::ActiveUserChanged(user));
+    }
+
+    Ok(())
+}
+
+pub fn watch_sleep(tx: &EventSender) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let manager = ManagerProxyBlocking::new(&connection)?;
+
+    for signal in manager.receive_prepare_for_sleep()? {
+        if signal.args()?.start {
+            log::debug!("going to sleep");
+            continue;
+        }
+
+        log::debug!("resumed from sleep");
+
+        let _ = tx.send(Event::Resumed);
+    }
+
+    Ok(())
+}
+
+// holds a delay lock, so that `before_shutdown` can let the running scripts finish before the
+// system shuts down
+pub fn watch_shutdown(who: &str, before_shutdown: impl Fn()) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let manager = ManagerProxyBlocking::new(&connection)?;
+    let signals = manager.receive_prepare_for_shutdown()?;
+
+    let why = "Waiting for running backups to finish";
+    let mut lock = Some(manager.inhibit("shutdown", who, why, "delay")?);
+
+    for signal in signals {
+        if !signal.args()?.start {
+            log::debug!("shutdown has been cancelled");
+            lock = Some(manager.inhibit("shutdown", who, why, "delay")?);
+            continue;
+        }
+
+        log::debug!("shutting down");
+
+        before_shutdown();
+        drop(lock.take());
+    }
+
+    Ok(())
+}
+
+// the backup monitor may run outside of the session (e.g. as a systemd user service), so the
+// graphical session of the user is watched, `None` if there is none
+fn display_session(connection: &Connection) -> zbus::Result<Option<SessionProxyBlocking<'_>>> {
+    let (_, path) = UserProxyBlocking::new(connection)?.display()?;
+    if path.as_str() == "/" {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        SessionProxyBlocking::builder(connection)
+            .path(path)?
+            .build()?,
+    ))
+}
+
+pub fn watch_locked(tx: &EventSender) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let Some(session) = display_session(&connection)? else {
+        log::debug!("no graphical session to watch the screen lock of");
+        return Ok(());
+    };
+
+    let _ = tx.send(Event::LockedChanged(session.locked_hint()?));
+
+    for _ in session.receive_locked_hint_changed() {
+        let locked = session.locked_hint()?;
+
+        log::debug!("screen lock has changed to {locked}");
+
+        let _ = tx.send(Event::LockedChanged(locked));
+    }
+
+    Ok(())
+}
+
+// since when the user has been idle, `None` while the user is active
+fn idle_since(session: &SessionProxyBlocking) -> zbus::Result<Option<DateTime<Utc>>> {
+    if !session.idle_hint()? {
+        return Ok(None);
+    }
+    Ok(DateTime::from_timestamp_micros(
+        session.idle_since_hint()? as i64
+    ))
+}
+
+pub fn watch_idle(tx: &EventSender) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let Some(session) = display_session(&connection)? else {
+        log::debug!("no graphical session to watch the idle hint of");
+        return Ok(());
+    };
+
+    let _ = tx.send(Event::IdleChanged(idle_since(&session)?));
+
+    for _ in session.receive_idle_hint_changed() {
+        let since = idle_since(&session)?;
+
+        log::debug!("idle hint has changed to {since:?}");
+
+        let _ = tx.send(Event::IdleChanged(since));
+    }
+
+    Ok(())
+}
@@ -1,43 +1,191 @@
 use std::{
-    env::current_exe,
-    fs::File,
+    env::{self, current_exe},
+    fmt::Display,
+    fs::{self, File},
     io::{self, Read, Seek},
     os::unix::prelude::AsRawFd,
-    sync::{
-        mpsc::{self, Sender},
-        Arc,
-    },
+    path::PathBuf,
+    sync::Arc,
     thread,
+    time::Duration as StdDuration,
 };
 
 use arc_swap::ArcSwap;
-use auto_launch::AutoLaunchBuilder;
+use auto_launch::{AutoLaunch, AutoLaunchBuilder};
 use chrono::Duration;
+use clap::{Parser, Subcommand};
 use clock::Clock;
+use control::{Status, StatusPublisher};
+use dbus_service::DBusService;
 use env_logger::Env;
-use event::{Event, EventReceiver};
-use main_loop::main_loop;
+use event::{Event, EventReceiver, EventSender};
+use main_loop::{main_loop, Autostart};
 use mio::{unix::SourceFd, Events, Interest, Poll, Token};
 use notify::Watcher;
 
+mod badge;
+mod broadcast;
+mod calendar;
+mod chain;
+mod client;
+mod clipboard;
 mod clock;
+mod config_backup;
+mod control;
+mod cron;
+mod dbus_service;
+mod debug_bundle;
+mod diagnostics;
+mod disk_usage;
+mod drift;
+mod email;
 mod event;
+mod git_history;
+mod history;
+mod launcher_entry;
+mod lint;
+mod logind;
 mod main_loop;
 mod manager;
+mod metrics;
+#[cfg(test)]
 mod mock_manager;
+#[cfg(test)]
+mod mock_tray_host;
+mod mqtt;
+mod network;
+mod notification;
+mod oneshot;
+mod power;
+mod privileged;
 mod round_duration;
+mod rtc_wake;
+mod run_result;
 mod script_manager;
 mod settings;
+mod snapshot_manager;
+mod state;
+mod status_output;
+mod success;
+mod systemd;
+mod targets;
+mod template;
+mod time_window;
+mod timer_manager;
 mod tray;
 mod tray_handle;
+mod udisks;
+mod upower;
+mod webhook;
 
-use settings::{settings_file_path, Settings};
+use metrics::MountWatcher;
+use settings::{include_dir_path, set_system_mode, settings_file_path, Settings};
 use tray::Tray;
+use tray_handle::ChangeFilter;
 
 pub const RETRY_INTERVAL: Duration = Duration::hours(1);
-pub const REMINDER_INTERVAL: Duration = Duration::hours(4);
+pub const NO_BACKUP_ALERT_INTERVAL: Duration = Duration::days(1);
+const WATCHER_RESTART_DELAY: StdDuration = StdDuration::from_secs(60);
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Run as system-wide daemon using the settings in `/etc/backup-monitor`
+    #[arg(long, conflicts_with = "system_client")]
+    system: bool,
+
+    /// Show the status of the system-wide daemon in the system tray
+    #[arg(long)]
+    system_client: bool,
+
+    /// Replay a debug bundle with a fake clock instead of running backups
+    #[arg(long, value_name = "BUNDLE")]
+    replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Save mounts, settings and status into a debug bundle
+    DebugBundle { path: PathBuf },
+
+    /// Explain how the next run of a backup script is scheduled
+    Explain { script: String },
+
+    /// Show the status and health of the running instance
+    Status {
+        /// Print the status and health as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List the backup scripts of the running instance and their status
+    List,
+
+    /// Ask the running instance to run a backup script now
+    Run {
+        script: String,
+
+        /// Wait until the script has finished and exit with its result
+        #[arg(long)]
+        wait: bool,
+
+        /// Print the result as JSON
+        #[arg(long, requires = "wait")]
+        json: bool,
+    },
+
+    /// List the backup disks the backup scripts have used, connected ones first
+    Targets,
+
+    /// Validate the settings and lint the backup scripts
+    #[command(alias = "check")]
+    CheckConfig {
+        /// Settings file to check instead of the one in use
+        path: Option<PathBuf>,
+
+        /// Exit with code 1 if there are warnings
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+
+    /// Show when each backup script runs next and what blocks it, without running anything
+    Next {
+        /// Print the next runs as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run the due backup scripts once and exit with their result
+    Oneshot {
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show a notification, used by the system-wide daemon to notify all users
+    #[command(hide = true)]
+    Notify {
+        app_name: String,
+        summary: String,
+        body: String,
+    },
+}
 
 fn main() -> anyhow::Result<()> {
+    // the output goes to the output of the privileged script, without the log of the backup monitor
+    let args = env::args_os().collect::<Vec<_>>();
+    if args
+        .get(1)
+        .is_some_and(|arg| arg == privileged::EXEC_PRIVILEGED)
+    {
+        return privileged::exec_script(&args[2..]);
+    }
+
+    let args = Args::parse();
+
     env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
         .format_timestamp(None)
         .init();
@@ -49,32 +197,217 @@ fn main() -> anyhow::Result<()> {
         env!("CARGO_PKG_HOMEPAGE")
     );
 
+    if args.system {
+        set_system_mode();
+    }
+
+    match &args.command {
+        Some(Command::DebugBundle { path }) => return debug_bundle::record(path, args.system),
+        Some(Command::Explain { script }) => return control::explain(script, args.system),
+        Some(Command::Status { json }) => return control::print_status(args.system, *json),
+        Some(Command::List) => return control::list(args.system),
+        Some(Command::Targets) => return targets::print_inventory(),
+        Some(Command::Run {
+            script,
+            wait: false,
+            ..
+        }) => return control::run(script, args.system),
+        Some(Command::Run { script, json, .. }) => {
+            match control::run_and_wait(script, args.system) {
+                Ok(Ok(results)) => run_result::exit_with_results(&results, true, *json),
+                Ok(Err(error)) => run_result::exit_with_error(
+                    &anyhow::anyhow!(error),
+                    run_result::CONFIG_ERROR,
+                    *json,
+                ),
+                Err(error) => run_result::exit_with_error(&error, run_result::ERROR, *json),
+            }
+        }
+        Some(Command::CheckConfig {
+            path,
+            deny_warnings,
+        }) => {
+            if !settings::check_config(path.as_deref(), *deny_warnings)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Next { json }) => {
+            return oneshot::print_next_runs(Settings::load()?, *json);
+        }
+        Some(Command::Oneshot { json }) => {
+            let settings = match Settings::load() {
+                Ok(settings) => settings,
+                Err(error) => run_result::exit_with_error(&error, run_result::CONFIG_ERROR, *json),
+            };
+            match oneshot::oneshot(settings) {
+                Ok(results) => run_result::exit_with_results(&results, false, *json),
+                Err(error) => run_result::exit_with_error(&error, run_result::ERROR, *json),
+            }
+        }
+        Some(Command::Notify {
+            app_name,
+            summary,
+            body,
+        }) => return broadcast::notify(app_name, summary, body),
+        None => {}
+    }
+
+    if let Some(bundle) = &args.replay {
+        return debug_bundle::replay(bundle);
+    }
+
+    if args.system_client {
+        return client::client_loop();
+    }
+
+    metrics::start();
+
+    // a catch-up run at login may have started first, its scripts would be run again otherwise
+    oneshot::wait_for_oneshot()?;
+
     let settings = Settings::load()?;
 
-    let (tx, rx) = mpsc::channel::<Event>();
+    let (tx, rx) = event::channel();
     let rx = EventReceiver::new(rx);
 
-    let tx_tray = tx.clone();
-    let service = ksni::TrayService::new(Tray::new(&settings, tx_tray));
-    let handle = service.handle();
-    service.spawn();
+    // the system-wide daemon reports to the trays of the users through the control socket
+    let tray_handle = if args.system {
+        None
+    } else {
+        let tx_tray = tx.clone();
+        let service = ksni::TrayService::new(Tray::new(&settings, tx_tray));
+        let handle = service.handle();
+        service.spawn();
+        Some(ChangeFilter::new(handle))
+    };
+
+    let status = Arc::new(ArcSwap::from_pointee(Status::default()));
+    let handle = StatusPublisher::new(tray_handle, status.clone());
+    let settings = Arc::new(ArcSwap::from_pointee(settings));
+
+    // serve status and commands to control clients
+    let socket_path = control::socket_path(args.system)?;
+    let tx_control = tx.clone();
+    let settings_control = settings.clone();
+    let status_dbus = status.clone();
+    thread::spawn(move || {
+        if let Err(error) = control::serve(
+            &socket_path,
+            args.system,
+            status,
+            settings_control,
+            tx_control,
+        ) {
+            log::warn!("failed to serve control socket: {error}");
+        }
+    });
+
+    // serve metrics for prometheus
+    if let Some(address) = settings.load().metrics_address.clone() {
+        thread::spawn(move || {
+            if let Err(error) = metrics::serve(&address) {
+                log::warn!("failed to serve metrics: {error}");
+            }
+        });
+    }
 
     // watch for mounts
-    let mut file = File::open("/proc/mounts").unwrap();
-    let mut mounts = String::new();
-    let _ = file.read_to_string(&mut mounts);
+    let mounts = fs::read_to_string("/proc/mounts")?;
     let tx_mounts = tx.clone();
-    thread::spawn(|| poll_mounts(file, tx_mounts));
+    let mount_poll_interval = settings.load().mount_poll_interval;
+    spawn_watcher("mount", move || {
+        poll_mounts(&tx_mounts, mount_poll_interval)
+    });
+
+    // watch for changes of the active session
+    let tx_session = tx.clone();
+    spawn_watcher("session", move || logind::watch_active_user(&tx_session));
+
+    // watch for resuming from sleep, the wakeups of the main loop are late after sleeping
+    let tx_sleep = tx.clone();
+    spawn_watcher("sleep", move || logind::watch_sleep(&tx_sleep));
+
+    // let running scripts finish before shutting down
+    let shutdown_settings = settings.clone();
+    spawn_watcher("shutdown", move || {
+        let settings = shutdown_settings.load();
+        logind::watch_shutdown(&settings.title, || {
+            script_manager::finish_before_shutdown(&shutdown_settings.load())
+        })
+    });
+
+    // watch the power supply for scripts which wait for AC power or a charged battery
+    if settings
+        .load()
+        .scripts
+        .iter()
+        .any(|script| script.require_ac_power || script.min_battery_percent.is_some())
+    {
+        let tx_power = tx.clone();
+        spawn_watcher("power", move || upower::watch_power(&tx_power));
+    }
+
+    // watch for drives being plugged in for scripts which mount them
+    if settings
+        .load()
+        .scripts
+        .iter()
+        .any(|script| script.auto_mount.is_some())
+    {
+        let tx_devices = tx.clone();
+        spawn_watcher("device", move || udisks::watch_devices(&tx_devices));
+    }
+
+    // watch the screen lock, reminders are deferred while the screen is locked
+    if !args.system {
+        let tx_lock = tx.clone();
+        spawn_watcher("screen lock", move || logind::watch_locked(&tx_lock));
+    }
+
+    // watch the idle hint for scripts which wait until the user is away
+    if !args.system
+        && settings
+            .load()
+            .scripts
+            .iter()
+            .any(|script| script.only_when_idle.is_some())
+    {
+        let tx_idle = tx.clone();
+        spawn_watcher("idle", move || logind::watch_idle(&tx_idle));
+    }
 
-    // watch for changes to settings file
+    // watch the network for profiles which are activated automatically
+    if settings
+        .load()
+        .profiles
+        .iter()
+        .any(|profile| profile.auto_activate.is_some())
+    {
+        let tx_network = tx.clone();
+        spawn_watcher("network", move || network::watch_location(&tx_network));
+    }
+
+    // watch for changes to settings file and the include directory, where files are also added,
+    // removed and renamed
+    let settings_file_path = settings_file_path()?;
+    let include_dir_path = include_dir_path(&settings_file_path);
+    let include_dir = include_dir_path.clone();
     let tx_settings = tx.clone();
     let mut watcher =
         notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| match res {
             Ok(event) => {
-                if matches!(
-                    event.kind,
-                    notify::event::EventKind::Modify(notify::event::ModifyKind::Data(_))
-                ) {
+                let changed = match event.kind {
+                    notify::event::EventKind::Modify(notify::event::ModifyKind::Data(_)) => true,
+                    notify::event::EventKind::Modify(notify::event::ModifyKind::Name(_))
+                    | notify::event::EventKind::Create(_)
+                    | notify::event::EventKind::Remove(_) => event
+                        .paths
+                        .iter()
+                        .any(|path| path.starts_with(&include_dir)),
+                    _ => false,
+                };
+                if changed {
                     log::debug!("settings have changed");
 
                     let _ = tx_settings.send(Event::SettingsChanged);
@@ -83,41 +416,140 @@ fn main() -> anyhow::Result<()> {
             Err(e) => eprintln!("watch error: {e:?}"),
         })?;
 
-    let settings_file_path = settings_file_path()?;
     watcher.watch(&settings_file_path, notify::RecursiveMode::NonRecursive)?;
+    match fs::create_dir_all(&include_dir_path) {
+        Ok(()) => watcher.watch(&include_dir_path, notify::RecursiveMode::NonRecursive)?,
+        Err(error) => log::warn!(
+            "failed to create `{}`, changes to it are not noticed: {error}",
+            include_dir_path.display()
+        ),
+    }
 
     // autostart
+    let autostart = if args.system {
+        None
+    } else {
+        Some(Autostart {
+            tray: auto_launch()?,
+            catch_up: catch_up_launch()?,
+        })
+    };
+
+    // the system-wide daemon is controlled through the control socket
+    let dbus_service = if args.system {
+        None
+    } else {
+        match DBusService::serve(status_dbus, settings.clone(), tx.clone()) {
+            Ok(dbus_service) => Some(dbus_service),
+            Err(error) => {
+                log::warn!("failed to serve D-Bus interface: {error}");
+                None
+            }
+        }
+    };
+
+    let clock = Clock::new();
+
+    main_loop(
+        clock,
+        settings,
+        mounts,
+        rx,
+        tx,
+        handle,
+        autostart,
+        dbus_service,
+    )
+}
+
+pub fn auto_launch() -> anyhow::Result<AutoLaunch> {
     let current_exe = current_exe()?;
-    let autolaunch = AutoLaunchBuilder::new()
+    Ok(AutoLaunchBuilder::new()
         .set_app_name("backup-monitor")
         .set_app_path(&current_exe.display().to_string())
-        .build()?;
+        .build()?)
+}
 
-    let clock = Clock::new();
-    let settings = Arc::new(ArcSwap::from_pointee(settings));
+// runs the overdue scripts once at login, independent of the tray
+pub fn catch_up_launch() -> anyhow::Result<AutoLaunch> {
+    let current_exe = current_exe()?;
+    Ok(AutoLaunchBuilder::new()
+        .set_app_name("backup-monitor-catch-up")
+        .set_app_path(&current_exe.display().to_string())
+        .set_args(&["oneshot"])
+        .build()?)
+}
+
+pub fn sync_autolaunch(autolaunch: &AutoLaunch, enabled: bool, name: &str) -> anyhow::Result<()> {
+    if autolaunch.is_enabled()? != enabled {
+        if enabled {
+            log::info!("enabling {name}");
+            autolaunch.enable()?;
+        } else {
+            log::info!("disabling {name}");
+            autolaunch.disable()?;
+        }
+    }
+    Ok(())
+}
+
+// restarts the watcher after it has failed
+fn spawn_watcher<E: Display>(
+    name: &'static str,
+    watch: impl Fn() -> Result<(), E> + Send + 'static,
+) {
+    thread::spawn(move || loop {
+        match watch() {
+            Ok(()) => break,
+            Err(error) => log::warn!("{name} watcher failed: {error}"),
+        }
 
-    main_loop(clock, settings, mounts, rx, handle, autolaunch)
+        thread::sleep(WATCHER_RESTART_DELAY);
+
+        log::debug!("restarting {name} watcher");
+        metrics::watcher_restarted();
+    });
 }
 
-fn poll_mounts(mut file: File, tx: Sender<Event>) -> io::Result<()> {
+// `/proc/mounts` can't be watched in some environments (e.g. containers, WSL), the watcher falls
+// back to comparing it periodically then
+fn poll_mounts(tx: &EventSender, interval: StdDuration) -> io::Result<()> {
+    let mut file = File::open("/proc/mounts")?;
+    let mut last_mounts = String::new();
+    let _ = file.read_to_string(&mut last_mounts);
+
     let mut poll = Poll::new()?;
     let mut events = Events::with_capacity(1024);
 
-    poll.registry().register(
+    // no timeout while changes are signalled, so the thread only wakes up for them
+    let mut timeout = None;
+    match poll.registry().register(
         &mut SourceFd(&file.as_raw_fd()),
         Token(0),
         Interest::READABLE,
-    )?;
+    ) {
+        Ok(()) => metrics::set_mount_watcher(MountWatcher::Events),
+        Err(error) => {
+            log::warn!("failed to watch `/proc/mounts`, falling back to polling: {error}");
+            metrics::set_mount_watcher(MountWatcher::Polling);
+            timeout = Some(interval);
+        }
+    }
 
     loop {
-        poll.poll(&mut events, None)?;
-
-        log::debug!("mounts have changed");
+        poll.poll(&mut events, timeout)?;
 
         let mut mounts = String::new();
         let _ = file.rewind();
         let _ = file.read_to_string(&mut mounts);
 
+        if mounts == last_mounts {
+            continue;
+        }
+
+        log::debug!("mounts have changed");
+
+        last_mounts.clone_from(&mounts);
         let _ = tx.send(Event::MountsChanged(mounts));
     }
 }
@@ -1,26 +1,54 @@
 use std::{
+    collections::HashMap,
     fmt,
     sync::{mpsc::RecvTimeoutError, Arc},
+    thread,
 };
 
 use arc_swap::ArcSwap;
 use auto_launch::AutoLaunch;
 use chrono::{DateTime, Local, Utc};
-use notify_rust::{Notification, Timeout};
+use itertools::Itertools;
+use notify_rust::{Notification, Timeout, Urgency as NotificationUrgency};
 
 use crate::{
+    badge,
     clock::Clock,
-    event::ReceiveEvent,
-    manager::Manager,
-    script_manager::ScriptManager,
-    settings::Settings,
+    config_backup,
+    dbus_service::DBusService,
+    drift,
+    event::{EventSender, ReceiveEvent},
+    git_history,
+    history::{self, Action, Entry, Trigger},
+    launcher_entry::LauncherEntry,
+    logind,
+    manager::{Manager, Overdue, RunOutcome},
+    metrics,
+    mqtt::{self, MqttPublisher},
+    notification, power, rtc_wake,
+    script_manager::{run_action, ScriptManager},
+    settings::{
+        settings_file_path, system_mode, update_settings, DriftCheck, Script, Settings, Urgency,
+    },
+    snapshot_manager::SnapshotManager,
+    state::State,
+    status_output, sync_autolaunch,
+    template::{format_overdue, render, script_values},
+    timer_manager::TimerManager,
     tray::Tray,
-    tray_handle::{TrayData, TrayHandle},
-    Event, REMINDER_INTERVAL,
+    tray_handle::{MapTooltip, TrayData, TrayHandle},
+    Event, NO_BACKUP_ALERT_INTERVAL,
 };
 
+// consecutive wakeups which are already due before the main loop counts as busy
+const BUSY_LOOP_THRESHOLD: u32 = 10;
+// minimum time between wakeups of a busy main loop
+const BUSY_LOOP_DELAY: chrono::Duration = chrono::Duration::seconds(5);
+// resuming this close to the wake alarm counts as woken up by it
+const WAKE_ALARM_TOLERANCE: chrono::Duration = chrono::Duration::minutes(2);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum WakeupReason {
+pub enum WakeupReason {
     RunScripts,
     ShowReminder,
     UpdateUi,
@@ -40,74 +68,749 @@ impl fmt::Display for WakeupReason {
     }
 }
 
+// protects against bugs in the scheduling which compute the next wakeup in the past over and
+// over again
+#[derive(Debug, Default)]
+struct BusyLoopGuard {
+    immediate_wakeups: u32,
+}
+
+impl BusyLoopGuard {
+    // delays the wakeup if the main loop is busy, returns whether it has just become busy
+    fn check(
+        &mut self,
+        now: DateTime<Utc>,
+        next_wakeup: &mut Option<(DateTime<Utc>, WakeupReason)>,
+    ) -> bool {
+        let Some((ts, reason)) = *next_wakeup else {
+            self.immediate_wakeups = 0;
+            return false;
+        };
+        if ts > now {
+            self.immediate_wakeups = 0;
+            return false;
+        }
+
+        self.immediate_wakeups += 1;
+        if self.immediate_wakeups < BUSY_LOOP_THRESHOLD {
+            return false;
+        }
+
+        *next_wakeup = Some((now + BUSY_LOOP_DELAY, reason));
+        self.immediate_wakeups == BUSY_LOOP_THRESHOLD
+    }
+}
+
+// the autostart entries of the tray and of the catch-up run at login
+pub struct Autostart {
+    pub tray: AutoLaunch,
+    pub catch_up: AutoLaunch,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn main_loop(
     clock: Clock,
     settings: Arc<ArcSwap<Settings>>,
     mounts: String,
     rx: impl ReceiveEvent,
+    tx: EventSender,
     handle: impl TrayHandle<Tray>,
-    autolaunch: AutoLaunch,
+    autostart: Option<Autostart>,
+    dbus_service: Option<DBusService>,
 ) -> anyhow::Result<()> {
-    let mut manager = ScriptManager::new(clock, settings.clone(), &mounts);
+    let mut script_manager = ScriptManager::new(clock, settings.clone(), &mounts);
+    if !system_mode() {
+        match LauncherEntry::connect() {
+            Ok(launcher_entry) => script_manager.set_launcher_entry(launcher_entry),
+            Err(error) => log::warn!("failed to connect to session bus: {error}"),
+        }
+    }
+    if let Some(dbus_service) = &dbus_service {
+        script_manager.set_dbus_service(dbus_service.clone());
+    }
+    script_manager.set_event_sender(tx.clone());
+    if let Some(delay) = settings.load().startup_delay {
+        script_manager.delay_startup(delay);
+    }
+    match State::load() {
+        Ok(Some(state)) => script_manager.restore_state(&state),
+        Ok(None) => {}
+        Err(error) => log::warn!("failed to load state: {error:#}"),
+    }
+    let mut manager = (
+        script_manager,
+        (
+            TimerManager::new(clock, settings.clone()),
+            SnapshotManager::new(clock, settings.clone()),
+        ),
+    );
+
+    // includes edits made while the backup monitor wasn't running
+    git_history::commit(&settings.load(), "backup monitor started");
 
     let mut last_reminder = None;
+    let mut last_no_backup_alert = None;
+    let started = monitored_since(&settings, clock.now());
+    let mut busy_loop_guard = BusyLoopGuard::default();
+    let mut locked = false;
+    let mut reminder_deferred = false;
+    let mut last_badge = None;
+    let mut last_status_line = None;
+    let mut mqtt_publisher = MqttPublisher::default();
+    let mut last_drift_check = None;
+    // by destination
+    let mut last_config_backups = HashMap::new();
+    let mut wake_alarm = None;
+    let mut reload_error = None;
+    let mut last_script_statuses = HashMap::new();
 
     loop {
-        if autolaunch.is_enabled()? != settings.load().autostart {
-            if autolaunch.is_enabled()? {
-                log::info!("disabling autostart");
-                autolaunch.disable()?;
-            } else {
-                log::info!("enabling autostart");
-                autolaunch.enable()?;
-            }
+        if let Some(autostart) = &autostart {
+            sync_autolaunch(&autostart.tray, settings.load().autostart, "autostart")?;
+            sync_autolaunch(
+                &autostart.catch_up,
+                settings.load().catch_up_on_login,
+                "catch-up on login",
+            )?;
         }
 
-        let (tray_data, show_reminder, next_wakeup) = analyze(
-            clock.now(),
+        manager.0.close_stale_notifications();
+
+        let now = clock.now();
+
+        let (mut tray_data, show_reminder, mut next_wakeup) = analyze(
+            now,
             &mut manager,
             &mut last_reminder,
             &settings.load(),
+            power::on_battery(),
         )?;
 
+        // until the settings file parses again
+        if reload_error.is_some() {
+            tray_data.status = Some(ksni::Status::NeedsAttention);
+            tray_data.tooltip = Some(with_reload_error(
+                reload_error.as_deref(),
+                tray_data.tooltip.unwrap_or_default(),
+            ));
+        }
+
+        let badge = badge::badge(&tray_data, &settings.load(), now);
+        if last_badge.as_ref() != Some(&badge) {
+            if let Err(error) = badge::write_badge(&badge, settings.load().freshness_xattr) {
+                log::warn!("failed to write freshness badge: {error:#}");
+            }
+            last_badge = Some(badge);
+        }
+
+        // switching `rtc-wake` off clears the alarm which has been set
+        let next_alarm = next_wake_alarm(settings.load().rtc_wake, &manager, now);
+        if next_alarm != wake_alarm {
+            log::debug!("setting the wake alarm to {next_alarm:?}");
+            match rtc_wake::set_wake_alarm(next_alarm) {
+                Ok(()) => wake_alarm = next_alarm,
+                // tried again on the next iteration
+                Err(error) => log::warn!("failed to set the wake alarm: {error:#}"),
+            }
+        }
+
+        if let Some(output) = &settings.load().status_output {
+            match status_output::status_line(&tray_data, &settings.load(), |name| {
+                manager.0.next_backup_of(name)
+            }) {
+                Ok(line) if last_status_line.as_ref() != Some(&line) => {
+                    if let Err(error) = status_output::write_status_line(output, &line) {
+                        log::warn!("failed to write status to {}: {error:#}", output.display());
+                    }
+                    last_status_line = Some(line);
+                }
+                Ok(_) => {}
+                Err(error) => log::warn!("failed to serialize status: {error:#}"),
+            }
+        }
+
+        if let Some(mqtt) = &settings.load().mqtt {
+            let reports = mqtt::script_reports(&tray_data, &settings.load(), |name| {
+                manager.0.failures(name)
+            });
+            mqtt_publisher.update(mqtt, &reports);
+        }
+
+        if let Some(dbus_service) = &dbus_service {
+            for (name, _, status) in tray_data.scripts.iter().flatten() {
+                if last_script_statuses.insert(name.clone(), *status) != Some(*status) {
+                    dbus_service.state_changed(name, status.name());
+                }
+            }
+        }
+
         handle.update(tray_data);
 
         if show_reminder {
-            let settings = settings.load();
-            Notification::new()
-                .appname(&settings.title)
-                .summary("Backup out of date")
-                .body("Make sure to run backups regularly")
-                .icon(&settings.icon_name)
-                .timeout(Timeout::Milliseconds(10_000))
-                .show()?;
+            let overdue = manager.overdue();
+            if let Some(dbus_service) = &dbus_service {
+                dbus_service
+                    .reminder_raised(overdue.iter().map(|item| item.name.clone()).collect());
+            }
+            if locked {
+                match &settings.load().locked_reminder_command {
+                    Some(command) => {
+                        run_locked_reminder_command(command, &settings.load(), &overdue)
+                    }
+                    None => {
+                        log::debug!("deferring reminder until the screen is unlocked");
+                        reminder_deferred = true;
+                    }
+                }
+            } else {
+                let script_names = overdue
+                    .iter()
+                    .map(|item| item.name.clone())
+                    .collect::<Vec<_>>();
+                if let Some(id) = show_reminder_notification(&settings.load(), overdue, tx.clone())?
+                {
+                    manager.0.replace_notifications(&script_names, id);
+                }
+            }
+        }
+
+        let mut next_alert = next_no_backup_alert(&settings.load(), started, last_no_backup_alert);
+        if next_alert.is_some_and(|ts| ts <= now) {
+            show_no_backup_alert(
+                &settings.load(),
+                now - last_activity(&settings.load(), started),
+            )?;
+            last_no_backup_alert = Some(now);
+            next_alert = next_no_backup_alert(&settings.load(), started, last_no_backup_alert);
+        }
+        if let Some(next_alert) = next_alert {
+            if next_wakeup.is_none_or(|(ts, _)| ts > next_alert) {
+                next_wakeup = Some((next_alert, WakeupReason::ShowReminder));
+            }
+        }
+
+        if let Some(drift_check) = &settings.load().drift_check {
+            let mut next_check = last_drift_check.map_or(now, |last| last + drift_check.interval);
+            if next_check <= now {
+                check_drift(&settings.load(), drift_check);
+                last_drift_check = Some(now);
+                next_check = now + drift_check.interval;
+            }
+            if next_wakeup.is_none_or(|(ts, _)| ts > next_check) {
+                next_wakeup = Some((next_check, WakeupReason::ShowReminder));
+            }
+        }
+
+        if let Some(config_backup) = &settings.load().config_backup {
+            for destination in config_backup::destinations(&settings.load(), config_backup) {
+                let mut next_copy = last_config_backups
+                    .get(&destination)
+                    .map_or(now, |last| *last + config_backup.interval);
+                if next_copy <= now {
+                    match config_backup::copy_config(&destination) {
+                        Ok(()) => log::debug!("copied the settings to `{}`", destination.display()),
+                        Err(error) => log::warn!("failed to copy the settings: {error:#}"),
+                    }
+                    // a failed copy is retried after the interval as well, not on every wakeup
+                    last_config_backups.insert(destination, now);
+                    next_copy = now + config_backup.interval;
+                }
+                if next_wakeup.is_none_or(|(ts, _)| ts > next_copy) {
+                    next_wakeup = Some((next_copy, WakeupReason::ShowReminder));
+                }
+            }
+        }
+
+        if busy_loop_guard.check(now, &mut next_wakeup) {
+            show_busy_loop_alert(&settings.load(), next_wakeup);
         }
 
         let event = wait(next_wakeup, &clock, &rx)?;
+        if event.is_none() {
+            metrics::woke_up();
+        }
+
+        if let Some(Event::LockedChanged(locked_hint)) = event {
+            locked = locked_hint;
+            // shows the deferred reminder right away
+            if !locked && reminder_deferred {
+                last_reminder = None;
+                reminder_deferred = false;
+            }
+        }
+
+        // the scripts run while the event is handled
+        let suspend_again = suspend_again(&event, &settings.load(), wake_alarm, clock.now());
+
+        handle_event(
+            event,
+            next_wakeup,
+            &clock,
+            &mut manager,
+            EventContext {
+                settings: &settings,
+                handle: &handle,
+                reload_error: &mut reload_error,
+                dbus_service: dbus_service.as_ref(),
+            },
+        )?;
+
+        if suspend_again {
+            match logind::suspend_blocker() {
+                Ok(None) => {
+                    log::info!("suspending again after the backups");
+                    if let Err(error) = logind::suspend() {
+                        log::warn!("failed to suspend: {error}");
+                    }
+                }
+                Ok(Some(blocker)) => log::info!("not suspending again, {blocker}"),
+                Err(error) => log::warn!("failed to check whether to suspend: {error}"),
+            }
+        }
+    }
+}
+
+fn find_script<'a>(settings: &'a Settings, name: &str) -> Option<&'a Script> {
+    settings.scripts.iter().find(|script| script.name == name)
+}
+
+// summary and body of a reminder
+fn reminder_message(settings: &Settings, overdue: &[Overdue]) -> (String, String) {
+    let reminders = &settings.reminder_notifications;
+    let script_summary = match overdue {
+        [item] => find_script(settings, &item.name)
+            .and_then(|script| Some((script, script.reminder_summary.as_ref()?, item.overdue))),
+        _ => None,
+    };
+    let summary = match (script_summary, &reminders.summary) {
+        (Some((script, template, overdue)), _) => render(template, &script_values(script, overdue)),
+        (None, Some(template)) => render(
+            template,
+            &[
+                ("names", overdue.iter().map(|item| &item.name).join(", ")),
+                ("count", overdue.len().to_string()),
+            ],
+        ),
+        (None, None) => match overdue {
+            // the backups can't run, reminding of them wouldn't help
+            items if items.iter().all(|item| item.target_missing_for.is_some()) => format!(
+                "Your backup disk hasn't been connected in {}",
+                format_overdue(
+                    items
+                        .iter()
+                        .filter_map(|item| item.target_missing_for)
+                        .min()
+                        .unwrap_or_default()
+                )
+            ),
+            items if items.iter().all(|item| !item.missing_paths.is_empty()) => {
+                "Plug in your backup disk".to_string()
+            }
+            [item] => format!("{} backup out of date", item.name),
+            _ => "Backups out of date".to_string(),
+        },
+    };
+    let body = overdue
+        .iter()
+        .map(|item| {
+            if let Some(missing_for) = item.target_missing_for {
+                let paths = find_script(settings, &item.name)
+                    .map(|script| &script.mount_paths[..])
+                    .unwrap_or_default();
+                return format!(
+                    "{}: {} hasn't been connected in {}",
+                    item.name,
+                    paths
+                        .iter()
+                        .map(|path| format!("\"{}\"", path.display()))
+                        .join(", "),
+                    format_overdue(missing_for)
+                );
+            }
+
+            let template = find_script(settings, &item.name).and_then(|script| {
+                let template = script
+                    .reminder_message
+                    .as_ref()
+                    .or(reminders.message.as_ref())?;
+                Some((script, template))
+            });
+            let mut line = match (template, item.overdue) {
+                (Some((script, template)), overdue) => {
+                    render(template, &script_values(script, overdue))
+                }
+                (None, Some(overdue)) => {
+                    format!("{}: {} overdue", item.name, format_overdue(overdue))
+                }
+                (None, None) => format!("{}: never backed up", item.name),
+            };
+            if !item.missing_paths.is_empty() {
+                line.push_str(&format!(
+                    ", plug in {}",
+                    item.missing_paths
+                        .iter()
+                        .map(|path| format!("\"{}\"", path.display()))
+                        .join(", ")
+                ));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (summary, body)
+}
+
+// the most urgent of the overdue backups
+fn reminder_urgency(settings: &Settings, overdue: &[Overdue]) -> Urgency {
+    let default = settings.reminder_notifications.urgency;
+    overdue
+        .iter()
+        .map(|item| {
+            find_script(settings, &item.name)
+                .and_then(|script| script.reminder_urgency)
+                .unwrap_or(default)
+        })
+        .max()
+        .unwrap_or(default)
+}
+
+// the shortest repetition of the overdue backups
+fn reminder_repeat(settings: &Settings, overdue: &[Overdue]) -> chrono::Duration {
+    let default = settings.reminder_notifications.repeat;
+    let repeat = overdue
+        .iter()
+        .map(|item| {
+            find_script(settings, &item.name)
+                .and_then(|script| script.reminder_repeat)
+                .unwrap_or(default)
+        })
+        .min()
+        .unwrap_or(default);
+    chrono::Duration::from_std(repeat).unwrap_or(chrono::Duration::max_value())
+}
+
+fn show_reminder_notification(
+    settings: &Settings,
+    overdue: Vec<Overdue>,
+    tx: EventSender,
+) -> anyhow::Result<Option<u32>> {
+    let (summary, body) = reminder_message(settings, &overdue);
+
+    let mut notification = Notification::new();
+    notification
+        .appname(&settings.title)
+        .summary(&summary)
+        .body(&body)
+        .icon(&settings.icon_name)
+        .urgency(match reminder_urgency(settings, &overdue) {
+            Urgency::Low => NotificationUrgency::Low,
+            Urgency::Normal => NotificationUrgency::Normal,
+            Urgency::Critical => NotificationUrgency::Critical,
+        })
+        .timeout(Timeout::Milliseconds(10_000));
+    // systemd timers and snapshots are only monitored
+    for item in &overdue {
+        if settings
+            .scripts
+            .iter()
+            .any(|script| script.name == item.name)
+        {
+            notification.action(&item.name, &format!("Run {} now", item.name));
+        }
+    }
+    let Some(notification_handle) = notification::show(&notification)? else {
+        return Ok(None);
+    };
+    let id = notification_handle.id();
+
+    // wait for actions in the background to keep the main loop responsive
+    thread::spawn(move || {
+        notification_handle.wait_for_action(|action| {
+            if let Some(item) = overdue.iter().find(|item| item.name == action) {
+                let _ = tx.send(Event::ManualRun(
+                    item.name.clone(),
+                    Trigger::Notification,
+                    None,
+                ));
+            }
+        });
+    });
+
+    Ok(Some(id))
+}
+
+// the reminder is passed in `$BM_SUMMARY` and `$BM_BODY`
+fn run_locked_reminder_command(command: &str, settings: &Settings, overdue: &[Overdue]) {
+    let (summary, body) = reminder_message(settings, overdue);
+
+    log::info!("sending reminder while the screen is locked");
+
+    // runs in the background to keep the main loop responsive
+    match std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("BM_SUMMARY", summary)
+        .env("BM_BODY", body)
+        .spawn()
+    {
+        Ok(mut child) => {
+            thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => {
+                    log::warn!("locked reminder command failed with {status}");
+                }
+                Ok(_) => {}
+                Err(error) => log::warn!("locked reminder command failed: {error}"),
+            });
+        }
+        Err(error) => log::warn!("failed to run locked reminder command: {error}"),
+    }
+}
+
+// shown even if reminders are disabled, as a safety net against misconfigured scripts
+fn show_no_backup_alert(settings: &Settings, since: chrono::Duration) -> anyhow::Result<()> {
+    let summary = format!("No backups have run in {}", format_overdue(since));
+
+    log::warn!("{summary}");
+
+    notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary(&summary)
+            .body(
+                "Run `backup-monitor explain <script>` to see what is blocking the backup scripts.",
+            )
+            .icon(&settings.icon_name)
+            .timeout(Timeout::Milliseconds(10_000)),
+    )?;
+
+    Ok(())
+}
+
+// the previous settings are kept, which may be surprising after editing them
+fn show_reload_failure(settings: &Settings, error: &anyhow::Error) {
+    let _ = notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary("Failed to reload settings")
+            .body(&format!(
+                "{error:#}\nThe previous settings are used until the error is fixed."
+            ))
+            .icon(&settings.icon_name)
+            .timeout(Timeout::Milliseconds(10_000)),
+    );
+}
+
+fn check_drift(settings: &Settings, drift_check: &DriftCheck) {
+    let differences =
+        match settings_file_path().and_then(|path| drift::check_drift(drift_check, &path)) {
+            Ok(differences) => differences,
+            Err(error) => {
+                log::warn!("failed to compare the settings with the reference: {error:#}");
+                return;
+            }
+        };
+    if differences.is_empty() {
+        log::debug!("settings match the reference");
+        return;
+    }
+
+    let differences = differences.join(", ");
+    log::warn!("settings differ from the reference: {differences}");
+
+    // the warning is logged anyway
+    let _ = notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary("Settings differ from the reference")
+            .body(&format!(
+                "Changed: {differences}\nReference: {}",
+                drift_check.reference.display()
+            ))
+            .icon(&settings.icon_name),
+    );
+}
+
+fn show_busy_loop_alert(settings: &Settings, next_wakeup: Option<(DateTime<Utc>, WakeupReason)>) {
+    let reason = next_wakeup.map_or("none".to_string(), |(_, reason)| reason.to_string());
+    log::warn!("the next wakeup ({reason}) is due over and over again, slowing down the main loop");
+
+    metrics::busy_loop_detected();
+
+    // the warning is logged anyway
+    let _ = notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary("Backup Monitor is busy")
+            .body(&format!(
+                "The next wakeup ({reason}) is due over and over again. Please report this bug."
+            ))
+            .icon(&settings.icon_name)
+            .timeout(Timeout::Milliseconds(10_000)),
+    );
+}
 
-        handle_event(event, next_wakeup, &settings, &mut manager, &handle)?;
+// the first start of the backup monitor, recorded so that restarts don't delay the no backup alert
+fn monitored_since(settings: &ArcSwap<Settings>, now: DateTime<Utc>) -> DateTime<Utc> {
+    if let Some(since) = settings.load().monitored_since {
+        return since;
     }
+
+    update_settings(settings, "monitoring started", |settings| {
+        settings.monitored_since = Some(now)
+    });
+    now
+}
+
+// time of the last backup of any script, or the first start of the backup monitor
+fn last_activity(settings: &Settings, started: DateTime<Utc>) -> DateTime<Utc> {
+    settings
+        .scripts
+        .iter()
+        .filter(|script| script.enabled)
+        .filter_map(|script| script.last_backup)
+        .max()
+        .unwrap_or(started)
+}
+
+fn next_no_backup_alert(
+    settings: &Settings,
+    started: DateTime<Utc>,
+    last_alert: Option<DateTime<Utc>>,
+) -> Option<DateTime<Utc>> {
+    let period = settings.no_backup_alert?;
+    if !settings.scripts.iter().any(|script| script.enabled) {
+        return None;
+    }
+
+    let next_alert = last_activity(settings, started) + period;
+    Some(last_alert.map_or(next_alert, |last| {
+        next_alert.max(last + NO_BACKUP_ALERT_INTERVAL)
+    }))
+}
+
+// the state of the main loop an event is handled with
+struct EventContext<'a, H> {
+    settings: &'a Arc<ArcSwap<Settings>>,
+    handle: &'a H,
+    // set while the settings file doesn't parse
+    reload_error: &'a mut Option<String>,
+    dbus_service: Option<&'a DBusService>,
 }
 
 fn handle_event(
     event: Option<Event>,
     next_wakeup: Option<(DateTime<Utc>, WakeupReason)>,
-    settings: &Arc<ArcSwap<Settings>>,
+    clock: &Clock,
     manager: &mut impl Manager,
-    handle: &impl TrayHandle<Tray>,
+    context: EventContext<'_, impl TrayHandle<Tray>>,
 ) -> anyhow::Result<()> {
+    let EventContext {
+        settings,
+        handle,
+        reload_error,
+        dbus_service,
+    } = context;
+    // for the tooltip updates of running scripts
+    let shown_reload_error = reload_error.clone();
+    let handle = &MapTooltip::new(handle, |tooltip| {
+        with_reload_error(shown_reload_error.as_deref(), tooltip)
+    });
     match event {
         Some(Event::SettingsChanged) => {
+            if !Settings::changed_externally().unwrap_or(true) {
+                log::trace!("ignoring change of settings written by backup monitor");
+                return Ok(());
+            }
+
             log::info!("reloading settings");
 
-            let loaded_settings = Settings::load()?;
+            // keep the previous settings while the file is broken
+            match Settings::load() {
+                Ok(loaded_settings) => {
+                    metrics::settings_reloaded(None);
+                    git_history::commit(&loaded_settings, "settings reloaded after an edit");
+                    settings.store(Arc::new(loaded_settings));
+                    *reload_error = None;
+                    if let Some(dbus_service) = dbus_service {
+                        dbus_service.settings_reloaded();
+                    }
+                }
+                Err(error) => {
+                    log::error!("failed to reload settings: {error:#}");
+                    metrics::settings_reloaded(Some(format!("{error:#}")));
+                    show_reload_failure(&settings.load(), &error);
+                    *reload_error = Some(format!("{error:#}"));
+                }
+            }
+        }
+        Some(Event::Pause(duration)) => {
+            let paused_until = clock.now() + duration;
+
+            log::info!(
+                "pausing backups until {}",
+                paused_until.with_timezone(&Local)
+            );
+
+            let message = format!("backups paused until {paused_until}");
+            update_settings(settings, &message, |settings| {
+                settings.paused_until = Some(paused_until);
+            });
 
-            settings.store(Arc::new(loaded_settings));
+            history::record(
+                Entry {
+                    time: clock.now(),
+                    script: None,
+                    action: Action::Pause {
+                        until: paused_until,
+                    },
+                    trigger: Trigger::Tray,
+                },
+                settings.load().audit_journal,
+            );
+        }
+        Some(Event::Resume) => {
+            log::info!("resuming backups");
+
+            update_settings(settings, "backups resumed", |settings| {
+                settings.paused_until = None
+            });
+
+            history::record(
+                Entry {
+                    time: clock.now(),
+                    script: None,
+                    action: Action::Resume,
+                    trigger: Trigger::Tray,
+                },
+                settings.load().audit_journal,
+            );
+
+            run(manager, None, &Trigger::Schedule, handle)?;
+        }
+        Some(Event::SwitchProfile(profile)) => {
+            switch_profile(settings, profile);
+            run(manager, None, &Trigger::Schedule, handle)?;
+        }
+        Some(Event::LocationChanged(location)) => {
+            let Some(profile) = settings.load().auto_profile(&location) else {
+                return Ok(());
+            };
+            if profile != settings.load().active_profile {
+                switch_profile(settings, profile);
+                run(manager, None, &Trigger::Schedule, handle)?;
+            }
         }
-        Some(Event::ManualRun(name)) => {
+        Some(Event::ManualRun(name, trigger, reply)) => {
             log::info!("running script {name}");
 
-            manager.run(Some(&name), handle)?;
+            let outcomes = run(manager, Some(&name), &trigger, handle)?;
+            if outcomes.is_empty() {
+                log::warn!("script {name} wasn't run");
+            }
+            if let Some(reply) = reply {
+                let _ = reply.send(outcomes);
+            }
         }
         Some(Event::MountsChanged(mounts)) => {
             log::info!("reloading mounts");
@@ -116,18 +819,163 @@ fn handle_event(
 
             log::info!("running scripts");
 
-            manager.run(None, handle)?;
+            run(manager, None, &Trigger::Schedule, handle)?;
+        }
+        Some(Event::Explain(name, reply)) => {
+            let explanation = manager.explain(&name).map(|explanation| {
+                let next_wakeup = match next_wakeup {
+                    Some((ts, reason)) => format!(
+                        "{} ({reason})",
+                        ts.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+                    ),
+                    None => "none".to_string(),
+                };
+                format!("{explanation}\nNext wakeup: {next_wakeup}")
+            });
+
+            let _ = reply.send(explanation);
+        }
+        // handled by the running script, see `EventSender::send`
+        Some(Event::Cancel(_)) => {}
+        Some(Event::DismissFailure(script_name)) => manager.dismiss_failure(&script_name),
+        Some(Event::TrayCommand(label)) => {
+            let settings = settings.load_full();
+            let Some(command) = settings
+                .tray_commands
+                .iter()
+                .find(|command| command.label == label)
+                .cloned()
+            else {
+                log::warn!("tray command `{label}` has been removed from the settings");
+                return Ok(());
+            };
+
+            log::info!("running tray command `{label}`");
+
+            // the result is shown in a notification, which doesn't block the main loop
+            thread::spawn(move || {
+                if let Err(error) =
+                    run_action(&settings, &command.label, &command.script(&settings))
+                {
+                    log::warn!("failed to run tray command `{}`: {error:#}", command.label);
+                }
+            });
+        }
+        // handled by `main_loop`
+        Some(Event::LockedChanged(_)) => {}
+        Some(Event::Resumed) => {
+            log::info!("resumed from sleep");
+
+            log::info!("running scripts");
+
+            run(manager, None, &Trigger::Schedule, handle)?;
+        }
+        Some(Event::IdleChanged(idle_since)) => {
+            log::info!("idle hint has changed");
+
+            manager.set_idle_since(idle_since);
+
+            log::info!("running scripts");
+
+            run(manager, None, &Trigger::Schedule, handle)?;
+        }
+        Some(Event::DeviceAdded) => {
+            log::info!("device has been added");
+
+            log::info!("running scripts");
+
+            run(manager, None, &Trigger::Schedule, handle)?;
+        }
+        Some(Event::PowerChanged(power)) => {
+            log::info!("power has changed");
+
+            manager.set_power(Some(power));
+
+            log::info!("running scripts");
+
+            run(manager, None, &Trigger::Schedule, handle)?;
+        }
+        Some(Event::ActiveUserChanged(user)) => {
+            log::info!("active user has changed");
+
+            manager.set_active_user(user);
+
+            log::info!("running scripts");
+
+            run(manager, None, &Trigger::Schedule, handle)?;
         }
         None if next_wakeup.is_none_or(|(_, reason)| reason == WakeupReason::RunScripts) => {
             log::info!("running scripts");
 
-            manager.run(None, handle)?;
+            run(manager, None, &Trigger::Schedule, handle)?;
         }
         None => {}
     }
     Ok(())
 }
 
+// after the backups the wake alarm has woken the computer up for
+fn suspend_again(
+    event: &Option<Event>,
+    settings: &Settings,
+    wake_alarm: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    matches!(event, Some(Event::Resumed))
+        && settings.rtc_wake
+        && settings.suspend_after_wake
+        && wake_alarm.is_some_and(|alarm| (now - alarm).abs() <= WAKE_ALARM_TOLERANCE)
+}
+
+// overdue scripts run right away, there is nothing to wake up for
+fn next_wake_alarm(
+    rtc_wake: bool,
+    manager: &impl Manager,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    manager
+        .next_backup()
+        .filter(|next_backup| rtc_wake && *next_backup > now)
+}
+
+fn with_reload_error(reload_error: Option<&str>, tooltip: String) -> String {
+    match reload_error {
+        Some(error) => format!("Invalid settings: {error}\n\n{tooltip}"),
+        None => tooltip,
+    }
+}
+
+fn switch_profile(settings: &ArcSwap<Settings>, profile: Option<String>) {
+    let message = match &profile {
+        Some(profile) => format!("switched to profile {profile}"),
+        None => "switched to all backup scripts".to_string(),
+    };
+    log::info!("{message}");
+
+    update_settings(settings, &message, |settings| {
+        settings.active_profile.clone_from(&profile);
+    });
+
+    // the scripts disabled by the previous profile are restored from the settings file
+    match Settings::load() {
+        Ok(loaded_settings) => settings.store(Arc::new(loaded_settings)),
+        Err(error) => log::error!("failed to reload settings: {error:#}"),
+    }
+}
+
+fn run(
+    manager: &mut impl Manager,
+    script_name: Option<&str>,
+    trigger: &Trigger,
+    handle: &impl TrayHandle<Tray>,
+) -> anyhow::Result<Vec<(String, RunOutcome)>> {
+    let outcomes = manager.run(script_name, trigger, handle)?;
+    for (name, outcome) in &outcomes {
+        log::info!("script {name} {outcome}");
+    }
+    Ok(outcomes)
+}
+
 fn wait(
     next_wakeup: Option<(DateTime<Utc>, WakeupReason)>,
     clock: &Clock,
@@ -159,31 +1007,48 @@ fn wait(
 }
 
 #[allow(clippy::type_complexity)]
-fn analyze(
+pub fn analyze(
     now: DateTime<Utc>,
     manager: &mut impl Manager,
     last_reminder: &mut Option<DateTime<Utc>>,
     settings: &Settings,
+    on_battery: bool,
 ) -> anyhow::Result<(TrayData, bool, Option<(DateTime<Utc>, WakeupReason)>)> {
     let mut show_reminder = false;
 
     let next_backup = manager.next_backup();
     let next_reminder = manager.next_reminder();
-    let next_ui_update = manager.next_ui_update();
 
-    let next_reminder_notification = next_reminder_notification(next_reminder, last_reminder);
+    let accuracy = if on_battery {
+        settings.ui_update_accuracy.on_battery
+    } else {
+        settings.ui_update_accuracy.on_ac
+    };
+    let next_ui_update = match (manager.next_ui_update(), accuracy) {
+        (Some(ts), Some(accuracy)) => Some(round_up(ts, chrono::Duration::from_std(accuracy)?)),
+        (next_ui_update, _) => next_ui_update,
+    };
+
+    let repeat = reminder_repeat(settings, &manager.overdue());
+    let next_reminder_notification =
+        next_reminder_notification(next_reminder, last_reminder, repeat);
+
+    // nothing is scheduled (e.g. all scripts wait for their backup disks), only events wake the
+    // main loop up
+    let next_ui_update =
+        next_ui_update.filter(|_| next_backup.is_some() || next_reminder_notification.is_some());
 
     let next_wakeup = next_wakeup(next_backup, next_reminder_notification, next_ui_update);
 
     if next_reminder_notification.is_some_and(|ts| ts <= now)
-        && last_reminder.map_or(true, |ts| ts <= now - REMINDER_INTERVAL)
+        && last_reminder.is_none_or(|ts| ts <= now - repeat)
     {
         show_reminder = true;
         *last_reminder = Some(now);
     }
 
     let tray_data = TrayData {
-        status: if next_reminder.is_some_and(|ts| ts <= now) {
+        status: if next_reminder.is_some_and(|ts| ts <= now) || manager.low_space() {
             Some(ksni::Status::NeedsAttention)
         } else {
             Some(ksni::Status::Passive)
@@ -193,7 +1058,29 @@ fn analyze(
             settings
                 .scripts
                 .iter()
-                .map(|script| (script.name.clone(), script.icon_name.clone()))
+                .map(|script| {
+                    (
+                        script.name.clone(),
+                        script.icon_name.clone(),
+                        manager.script_status(&script.name),
+                    )
+                })
+                .collect(),
+        ),
+        paused: Some(settings.paused_until(now).is_some()),
+        profiles: Some((
+            settings
+                .profiles
+                .iter()
+                .map(|profile| profile.name.clone())
+                .collect(),
+            settings.active_profile.clone(),
+        )),
+        overdue: Some(
+            manager
+                .overdue()
+                .into_iter()
+                .map(|item| item.name)
                 .collect(),
         ),
     };
@@ -201,18 +1088,20 @@ fn analyze(
     Ok((tray_data, show_reminder, next_wakeup))
 }
 
+// to a multiple of `accuracy`, so the updates of all scripts fall on the same wakeups
+fn round_up(ts: DateTime<Utc>, accuracy: chrono::Duration) -> DateTime<Utc> {
+    let accuracy = accuracy.num_milliseconds().max(1);
+    let millis = (ts.timestamp_millis() + accuracy - 1).div_euclid(accuracy) * accuracy;
+    DateTime::from_timestamp_millis(millis).unwrap_or(ts)
+}
+
 // limit reminder notifications frequency
 fn next_reminder_notification(
     next_reminder: Option<DateTime<Utc>>,
     last_reminder: &Option<DateTime<Utc>>,
+    repeat: chrono::Duration,
 ) -> Option<DateTime<Utc>> {
-    next_reminder.map(|next| {
-        next.max(
-            last_reminder
-                .map(|last| last + REMINDER_INTERVAL)
-                .unwrap_or(next),
-        )
-    })
+    next_reminder.map(|next| next.max(last_reminder.map(|last| last + repeat).unwrap_or(next)))
 }
 
 fn next_wakeup(
@@ -225,7 +1114,7 @@ fn next_wakeup(
     if let Some(next_reminder) = next_reminder_notification {
         if next_wakeup
             .as_ref()
-            .map_or(true, |(ts, _)| *ts > next_reminder)
+            .is_none_or(|(ts, _)| *ts > next_reminder)
         {
             next_wakeup = Some((next_reminder, WakeupReason::ShowReminder));
         }
@@ -234,7 +1123,7 @@ fn next_wakeup(
     if let Some(next_ui_update) = next_ui_update {
         if next_wakeup
             .as_ref()
-            .map_or(true, |(ts, _)| *ts > next_ui_update)
+            .is_none_or(|(ts, _)| *ts > next_ui_update)
         {
             next_wakeup = Some((next_ui_update, WakeupReason::UpdateUi));
         }
@@ -246,10 +1135,11 @@ fn next_wakeup(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mock_manager::MockManager;
+    use crate::{event::MockEventReceiver, mock_manager::MockManager};
     use fake::{Fake, Faker};
+    use indoc::indoc;
     use serde::{Deserialize, Deserializer};
-    use std::{fs::File, time::Duration};
+    use std::{fs::File, path::PathBuf, time::Duration};
 
     pub fn deserialize_wakeup_reason<'de, D>(
         deserializer: D,
@@ -301,6 +1191,7 @@ mod tests {
     #[case("waiting_for_time")]
     #[case("next_ui_update")]
     #[case("next_ui_update_with_blocked_reminder")]
+    #[case("idle")]
     #[case("next_reminder_now")]
     #[case("next_reminder_sleep")]
     #[case("next_reminder_with_last_reminder_blocking")]
@@ -323,8 +1214,14 @@ mod tests {
         };
         let mut last_reminder = test_case.last_reminder.map(|delta| clock.now() - delta);
 
-        let (tray_data, show_reminder, next_wakeup) =
-            analyze(clock.now(), &mut manager, &mut last_reminder, &settings).unwrap();
+        let (tray_data, show_reminder, next_wakeup) = analyze(
+            clock.now(),
+            &mut manager,
+            &mut last_reminder,
+            &settings,
+            false,
+        )
+        .unwrap();
 
         assert_eq!(
             (
@@ -342,4 +1239,244 @@ mod tests {
             "{name}"
         );
     }
+
+    #[test]
+    fn busy_loop_guard() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let mut guard = BusyLoopGuard::default();
+
+        for _ in 1..BUSY_LOOP_THRESHOLD {
+            let mut next_wakeup = Some((now, WakeupReason::RunScripts));
+            assert!(!guard.check(now, &mut next_wakeup));
+            assert_eq!(next_wakeup, Some((now, WakeupReason::RunScripts)));
+        }
+
+        let mut next_wakeup = Some((now, WakeupReason::RunScripts));
+        assert!(guard.check(now, &mut next_wakeup));
+        assert_eq!(
+            next_wakeup,
+            Some((now + BUSY_LOOP_DELAY, WakeupReason::RunScripts))
+        );
+
+        // only reported once
+        let mut next_wakeup = Some((now, WakeupReason::RunScripts));
+        assert!(!guard.check(now, &mut next_wakeup));
+        assert_eq!(
+            next_wakeup,
+            Some((now + BUSY_LOOP_DELAY, WakeupReason::RunScripts))
+        );
+
+        let mut next_wakeup = Some((now + BUSY_LOOP_DELAY, WakeupReason::UpdateUi));
+        assert!(!guard.check(now, &mut next_wakeup));
+        assert_eq!(guard.immediate_wakeups, 0);
+    }
+
+    #[test]
+    fn ui_update_accuracy() {
+        let ts = DateTime::parse_from_rfc3339("2024-10-24T20:18:00.001Z")
+            .unwrap()
+            .to_utc();
+        let five_minutes = chrono::Duration::minutes(5);
+
+        assert_eq!(
+            round_up(ts, five_minutes),
+            DateTime::parse_from_rfc3339("2024-10-24T20:20:00Z")
+                .unwrap()
+                .to_utc()
+        );
+        assert_eq!(
+            round_up(ts + chrono::Duration::minutes(1), five_minutes),
+            round_up(ts, five_minutes)
+        );
+        assert_eq!(round_up(ts, chrono::Duration::zero()), ts);
+    }
+
+    #[test]
+    fn wake_alarm() {
+        let clock = Faker.fake::<Clock>();
+        let next_backup = clock.now() + chrono::Duration::hours(1);
+        let manager = MockManager {
+            next_backup: Some(next_backup),
+            ..Default::default()
+        };
+
+        let alarm = next_wake_alarm(true, &manager, clock.now());
+        assert_eq!(alarm, Some(next_backup));
+        // switched off, the alarm which has been set is cleared
+        assert_ne!(next_wake_alarm(false, &manager, clock.now()), alarm);
+        assert_eq!(next_wake_alarm(false, &manager, clock.now()), None);
+        // overdue
+        assert_eq!(next_wake_alarm(true, &manager, next_backup), None);
+    }
+
+    #[test]
+    fn suspend_after_wake() {
+        let clock = Faker.fake::<Clock>();
+        let manager = MockManager {
+            next_backup: Some(clock.now() + chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        let mut settings = Settings {
+            rtc_wake: true,
+            suspend_after_wake: true,
+            ..Default::default()
+        };
+        let alarm = next_wake_alarm(settings.rtc_wake, &manager, clock.now());
+        let resumed = Some(Event::Resumed);
+
+        // woken up by the alarm
+        let woken_at = clock.now() + chrono::Duration::hours(1) + chrono::Duration::seconds(30);
+        assert!(suspend_again(&resumed, &settings, alarm, woken_at));
+        assert!(!suspend_again(&None, &settings, alarm, woken_at));
+
+        // resumed by the user, outside of the tolerance
+        let resumed_at = clock.now() + chrono::Duration::minutes(30);
+        assert!(!suspend_again(&resumed, &settings, alarm, resumed_at));
+        let late = woken_at + WAKE_ALARM_TOLERANCE;
+        assert!(!suspend_again(&resumed, &settings, alarm, late));
+
+        settings.suspend_after_wake = false;
+        assert!(!suspend_again(&resumed, &settings, alarm, woken_at));
+    }
+
+    #[test]
+    fn wait_for_event() {
+        let clock = Faker.fake::<Clock>();
+        let rx = MockEventReceiver {
+            results: vec![Ok(Event::SettingsChanged), Err(RecvTimeoutError::Timeout)].into(),
+            ..Default::default()
+        };
+
+        let next_wakeup = Some((
+            clock.now() + Duration::from_secs(90),
+            WakeupReason::UpdateUi,
+        ));
+        assert!(matches!(
+            wait(None, &clock, &rx).unwrap(),
+            Some(Event::SettingsChanged)
+        ));
+        assert!(wait(next_wakeup, &clock, &rx).unwrap().is_none());
+
+        assert_eq!(
+            *rx.recv_timeout.borrow(),
+            vec![None, Some(Duration::from_secs(90))]
+        );
+    }
+
+    #[test]
+    fn reminder_templates() {
+        let script = |name: &str| Script {
+            name: name.to_string(),
+            ..serde_yaml_ng::from_str(indoc! {"
+                name: Backup
+                backup-script: '#!/bin/bash'
+                interval: 1day
+            "})
+            .unwrap()
+        };
+        let overdue = |name: &str| Overdue {
+            name: name.to_string(),
+            overdue: None,
+            missing_paths: Vec::new(),
+            target_missing_for: None,
+        };
+        let mut settings = Settings {
+            scripts: vec![script("Home"), script("Photos")],
+            ..Default::default()
+        };
+        settings.reminder_notifications.summary = Some("{count} backups overdue".to_string());
+        settings.reminder_notifications.message = Some("{name}: {overdue}".to_string());
+        settings.scripts[1].reminder_summary = Some("Back up {name}".to_string());
+        settings.scripts[1].reminder_repeat = Some(Duration::from_secs(60 * 60));
+        settings.scripts[1].reminder_urgency = Some(Urgency::Critical);
+
+        let both = [overdue("Home"), overdue("Photos")];
+        assert_eq!(
+            reminder_message(&settings, &both),
+            (
+                "2 backups overdue".to_string(),
+                "Home: never backed up\nPhotos: never backed up".to_string()
+            )
+        );
+        assert_eq!(
+            reminder_message(&settings, &[overdue("Photos")]).0,
+            "Back up Photos"
+        );
+
+        settings.reminder_notifications.summary = None;
+        settings.scripts[0].mount_paths = vec![PathBuf::from("/mnt/backup")];
+        let missing = Overdue {
+            target_missing_for: Some(chrono::Duration::days(15)),
+            ..overdue("Home")
+        };
+        assert_eq!(
+            reminder_message(&settings, &[missing]),
+            (
+                "Your backup disk hasn't been connected in 15 days".to_string(),
+                "Home: \"/mnt/backup\" hasn't been connected in 15 days".to_string()
+            )
+        );
+
+        assert_eq!(reminder_urgency(&settings, &both), Urgency::Critical);
+        assert_eq!(
+            reminder_urgency(&settings, &[overdue("Home")]),
+            Urgency::Normal
+        );
+        assert_eq!(
+            reminder_repeat(&settings, &both),
+            chrono::Duration::hours(1)
+        );
+        assert_eq!(
+            reminder_repeat(&settings, &[overdue("Home")]),
+            chrono::Duration::hours(4)
+        );
+    }
+
+    #[test]
+    fn no_backup_alert() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let script = |last_backup| Script {
+            last_backup,
+            ..serde_yaml_ng::from_str(indoc! {"
+                name: Backup
+                backup-script: '#!/bin/bash'
+                interval: 1day
+            "})
+            .unwrap()
+        };
+        let settings = Settings {
+            scripts: vec![
+                script(Some(now - chrono::Duration::days(20))),
+                script(Some(now - chrono::Duration::days(15))),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            next_no_backup_alert(&settings, now, None),
+            Some(now - chrono::Duration::days(1))
+        );
+        assert_eq!(
+            next_no_backup_alert(&settings, now, Some(now)),
+            Some(now + NO_BACKUP_ALERT_INTERVAL)
+        );
+
+        let settings = Settings {
+            scripts: vec![script(None)],
+            ..settings
+        };
+        assert_eq!(
+            next_no_backup_alert(&settings, now, None),
+            Some(now + chrono::Duration::days(14))
+        );
+
+        let settings = Settings {
+            no_backup_alert: None,
+            ..settings
+        };
+        assert_eq!(next_no_backup_alert(&settings, now, None), None);
+        assert_eq!(next_no_backup_alert(&Settings::default(), now, None), None);
+    }
 }
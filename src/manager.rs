@@ -1,6 +1,88 @@
-use chrono::{DateTime, Utc};
+use std::{fmt, path::PathBuf};
 
-use crate::{tray::Tray, tray_handle::TrayHandle};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    history::Trigger,
+    tray::Tray,
+    tray_handle::{MapTooltip, TrayHandle},
+    upower::Power,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overdue {
+    pub name: String,
+    // `None` if the script has never been backed up
+    pub overdue: Option<Duration>,
+    // only set if the script asks for a "plug in" reminder
+    pub missing_paths: Vec<PathBuf>,
+    // set if the mount paths have been missing for longer than `missing-target-after`, the backup
+    // can't run until they are connected again
+    pub target_missing_for: Option<Duration>,
+}
+
+// shown next to the script in the menu of the tray
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScriptStatus {
+    #[default]
+    Ok,
+    Overdue,
+    Failed,
+    Running,
+    Disabled,
+}
+
+impl ScriptStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            ScriptStatus::Ok => "ok",
+            ScriptStatus::Overdue => "overdue",
+            ScriptStatus::Failed => "failed",
+            ScriptStatus::Running => "running",
+            ScriptStatus::Disabled => "disabled",
+        }
+    }
+}
+
+// what happened to a script during `Manager::run`, scripts which weren't due are left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    Succeeded,
+    Failed(String),
+    WaitingForPaths(Vec<PathBuf>),
+    WaitingForUser(String),
+    WaitingForPower(String),
+    WaitingForIdle(std::time::Duration),
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunOutcome::Succeeded => write!(f, "succeeded"),
+            RunOutcome::Failed(message) => write!(f, "failed: {message}"),
+            RunOutcome::WaitingForPaths(paths) => write!(
+                f,
+                "waiting for {} to be mounted",
+                paths
+                    .iter()
+                    .map(|path| format!("`{}`", path.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            RunOutcome::WaitingForUser(user) => {
+                write!(f, "waiting for `{user}` to be the active user")
+            }
+            RunOutcome::WaitingForPower(requirement) => write!(f, "waiting for {requirement}"),
+            RunOutcome::WaitingForIdle(threshold) => write!(
+                f,
+                "waiting for the user to be idle for {}",
+                humantime::format_duration(*threshold)
+            ),
+        }
+    }
+}
 
 pub trait Manager {
     fn next_backup(&self) -> Option<DateTime<Utc>>;
@@ -11,11 +93,137 @@ pub trait Manager {
 
     fn tooltip(&self) -> String;
 
+    // a mounted backup target has less space free than `low-space-warning`
+    fn low_space(&self) -> bool;
+
+    fn overdue(&self) -> Vec<Overdue>;
+
+    fn script_status(&self, script_name: &str) -> ScriptStatus;
+
+    // describes how the next run of the script is scheduled, `None` for unknown scripts
+    fn explain(&self, script_name: &str) -> Option<String>;
+
     fn set_mounts(&mut self, mounts: &str);
 
+    fn set_active_user(&mut self, user: Option<String>);
+
+    fn set_power(&mut self, power: Option<Power>);
+
+    fn set_idle_since(&mut self, idle_since: Option<DateTime<Utc>>);
+
+    // hides the failure of the script until it fails again
+    fn dismiss_failure(&mut self, script_name: &str);
+
+    fn run<'a>(
+        &'a mut self,
+        script_name: Option<&'a str>,
+        trigger: &Trigger,
+        handle: &impl TrayHandle<Tray>,
+    ) -> anyhow::Result<Vec<(String, RunOutcome)>>;
+}
+
+// combines the managers of different kinds of backups, e.g. scripts and systemd timers
+impl<A: Manager, B: Manager> Manager for (A, B) {
+    fn next_backup(&self) -> Option<DateTime<Utc>> {
+        [self.0.next_backup(), self.1.next_backup()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    fn next_reminder(&self) -> Option<DateTime<Utc>> {
+        [self.0.next_reminder(), self.1.next_reminder()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    fn next_ui_update(&self) -> Option<DateTime<Utc>> {
+        [self.0.next_ui_update(), self.1.next_ui_update()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    fn tooltip(&self) -> String {
+        join_tooltips(self.0.tooltip(), self.1.tooltip())
+    }
+
+    fn low_space(&self) -> bool {
+        self.0.low_space() || self.1.low_space()
+    }
+
+    fn overdue(&self) -> Vec<Overdue> {
+        let mut overdue = self.0.overdue();
+        overdue.extend(self.1.overdue());
+        overdue
+    }
+
+    fn script_status(&self, script_name: &str) -> ScriptStatus {
+        match self.0.script_status(script_name) {
+            ScriptStatus::Ok => self.1.script_status(script_name),
+            status => status,
+        }
+    }
+
+    fn explain(&self, script_name: &str) -> Option<String> {
+        self.0
+            .explain(script_name)
+            .or_else(|| self.1.explain(script_name))
+    }
+
+    fn set_mounts(&mut self, mounts: &str) {
+        self.0.set_mounts(mounts);
+        self.1.set_mounts(mounts);
+    }
+
+    fn set_active_user(&mut self, user: Option<String>) {
+        self.0.set_active_user(user.clone());
+        self.1.set_active_user(user);
+    }
+
+    fn set_power(&mut self, power: Option<Power>) {
+        self.0.set_power(power);
+        self.1.set_power(power);
+    }
+
+    fn set_idle_since(&mut self, idle_since: Option<DateTime<Utc>>) {
+        self.0.set_idle_since(idle_since);
+        self.1.set_idle_since(idle_since);
+    }
+
+    fn dismiss_failure(&mut self, script_name: &str) {
+        self.0.dismiss_failure(script_name);
+        self.1.dismiss_failure(script_name);
+    }
+
     fn run<'a>(
         &'a mut self,
         script_name: Option<&'a str>,
+        trigger: &Trigger,
         handle: &impl TrayHandle<Tray>,
-    ) -> anyhow::Result<()>;
+    ) -> anyhow::Result<Vec<(String, RunOutcome)>> {
+        // the updates of a running backup only have the tooltip of its own manager
+        let other = &self.1;
+        let mut outcomes = self.0.run(
+            script_name,
+            trigger,
+            &MapTooltip::new(handle, |tooltip| join_tooltips(tooltip, other.tooltip())),
+        )?;
+        let other = &self.0;
+        outcomes.extend(self.1.run(
+            script_name,
+            trigger,
+            &MapTooltip::new(handle, |tooltip| join_tooltips(other.tooltip(), tooltip)),
+        )?);
+        Ok(outcomes)
+    }
+}
+
+fn join_tooltips(first: String, second: String) -> String {
+    [first, second]
+        .into_iter()
+        .filter(|tooltip| !tooltip.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
 }
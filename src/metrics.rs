@@ -0,0 +1,331 @@
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+static STARTED: OnceLock<DateTime<Utc>> = OnceLock::new();
+static EVENTS_PROCESSED: AtomicU64 = AtomicU64::new(0);
+static EVENT_BACKLOG: AtomicI64 = AtomicI64::new(0);
+static WATCHER_RESTARTS: AtomicU64 = AtomicU64::new(0);
+static LAST_SETTINGS_RELOAD: Mutex<Option<SettingsReload>> = Mutex::new(None);
+static MOUNT_POLLING: AtomicBool = AtomicBool::new(false);
+static WAKEUPS: AtomicU64 = AtomicU64::new(0);
+static BUSY_LOOPS: AtomicU64 = AtomicU64::new(0);
+
+// how changes of the mounts are detected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MountWatcher {
+    // `/proc/mounts` signals changes
+    #[default]
+    Events,
+    // `/proc/mounts` is compared periodically
+    Polling,
+}
+
+// clients are served one after the other, a stalled client must not block the scrapes for long
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SettingsReload {
+    pub time: DateTime<Utc>,
+    // `None` if the settings have been reloaded successfully
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Metrics {
+    pub started: DateTime<Utc>,
+    pub events_processed: u64,
+    // events which have been sent but not yet received by the main loop
+    pub event_backlog: i64,
+    pub watcher_restarts: u64,
+    pub last_settings_reload: Option<SettingsReload>,
+    #[serde(default)]
+    pub mount_watcher: MountWatcher,
+    // the main loop woke up without an event
+    #[serde(default)]
+    pub wakeups: u64,
+    // the next wakeup was due over and over again
+    #[serde(default)]
+    pub busy_loops: u64,
+}
+
+pub fn start() {
+    let _ = STARTED.set(Utc::now());
+}
+
+pub fn event_sent() {
+    EVENT_BACKLOG.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn event_received() {
+    EVENT_BACKLOG.fetch_sub(1, Ordering::Relaxed);
+    EVENTS_PROCESSED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn watcher_restarted() {
+    WATCHER_RESTARTS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn settings_reloaded(error: Option<String>) {
+    *LAST_SETTINGS_RELOAD.lock().unwrap() = Some(SettingsReload {
+        time: Utc::now(),
+        error,
+    });
+}
+
+pub fn woke_up() {
+    WAKEUPS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn busy_loop_detected() {
+    BUSY_LOOPS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn set_mount_watcher(mount_watcher: MountWatcher) {
+    MOUNT_POLLING.store(mount_watcher == MountWatcher::Polling, Ordering::Relaxed);
+}
+
+pub fn snapshot() -> Metrics {
+    Metrics {
+        started: *STARTED.get_or_init(Utc::now),
+        events_processed: EVENTS_PROCESSED.load(Ordering::Relaxed),
+        event_backlog: EVENT_BACKLOG.load(Ordering::Relaxed),
+        watcher_restarts: WATCHER_RESTARTS.load(Ordering::Relaxed),
+        last_settings_reload: LAST_SETTINGS_RELOAD.lock().unwrap().clone(),
+        mount_watcher: if MOUNT_POLLING.load(Ordering::Relaxed) {
+            MountWatcher::Polling
+        } else {
+            MountWatcher::Events
+        },
+        wakeups: WAKEUPS.load(Ordering::Relaxed),
+        busy_loops: BUSY_LOOPS.load(Ordering::Relaxed),
+    }
+}
+
+impl Metrics {
+    pub fn uptime(&self, now: DateTime<Utc>) -> Duration {
+        (now - self.started.min(now)).to_std().unwrap()
+    }
+
+    pub fn summary(&self, now: DateTime<Utc>) -> String {
+        let uptime = humantime::format_duration(Duration::from_secs(self.uptime(now).as_secs()));
+        let last_settings_reload = match &self.last_settings_reload {
+            Some(reload) => format!(
+                "{} at {}",
+                reload.error.as_deref().unwrap_or("successful"),
+                reload.time.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            ),
+            None => "never".to_string(),
+        };
+        let mount_watcher = match self.mount_watcher {
+            MountWatcher::Events => "events",
+            MountWatcher::Polling => "polling",
+        };
+        let hours = self.uptime(now).as_secs_f64() / 3600.0;
+        let wakeups_per_hour = if hours > 0.0 {
+            self.wakeups as f64 / hours
+        } else {
+            0.0
+        };
+
+        [
+            format!("Uptime: {uptime}"),
+            format!("Events processed: {}", self.events_processed),
+            format!("Event backlog: {}", self.event_backlog),
+            format!("Watcher restarts: {}", self.watcher_restarts),
+            format!("Last settings reload: {last_settings_reload}"),
+            format!("Mount watcher: {mount_watcher}"),
+            format!("Wakeups: {} ({wakeups_per_hour:.1} per hour)", self.wakeups),
+            format!("Busy loops: {}", self.busy_loops),
+        ]
+        .join("\n")
+    }
+
+    // text exposition format, see https://prometheus.io/docs/instrumenting/exposition_formats/
+    pub fn prometheus(&self, now: DateTime<Utc>) -> String {
+        let mut text = String::new();
+
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(text, "# HELP backup_monitor_{name} {help}");
+            let _ = writeln!(text, "# TYPE backup_monitor_{name} {kind}");
+            let _ = writeln!(text, "backup_monitor_{name} {value}");
+        };
+
+        metric(
+            "uptime_seconds",
+            "gauge",
+            "Time since the backup monitor has been started.",
+            self.uptime(now).as_secs().to_string(),
+        );
+        metric(
+            "events_processed_total",
+            "counter",
+            "Events processed by the main loop.",
+            self.events_processed.to_string(),
+        );
+        metric(
+            "event_backlog",
+            "gauge",
+            "Events waiting to be processed by the main loop.",
+            self.event_backlog.to_string(),
+        );
+        metric(
+            "watcher_restarts_total",
+            "counter",
+            "Restarts of failed mount and session watchers.",
+            self.watcher_restarts.to_string(),
+        );
+        metric(
+            "wakeups_total",
+            "counter",
+            "Wakeups of the main loop without an event.",
+            self.wakeups.to_string(),
+        );
+        metric(
+            "busy_loops_total",
+            "counter",
+            "Detections of a main loop whose next wakeup was due over and over again.",
+            self.busy_loops.to_string(),
+        );
+        metric(
+            "mount_watcher_polling",
+            "gauge",
+            "Whether the mounts are polled because `/proc/mounts` doesn't signal changes.",
+            u8::from(self.mount_watcher == MountWatcher::Polling).to_string(),
+        );
+        if let Some(reload) = &self.last_settings_reload {
+            metric(
+                "settings_reload_success",
+                "gauge",
+                "Whether the last reload of the settings was successful.",
+                u8::from(reload.error.is_none()).to_string(),
+            );
+            metric(
+                "settings_reload_timestamp_seconds",
+                "gauge",
+                "Time of the last reload of the settings.",
+                reload.time.timestamp().to_string(),
+            );
+        }
+
+        text
+    }
+}
+
+pub fn serve(address: &str) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(address)?;
+
+    log::debug!("serving metrics on `{address}`");
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                log::warn!("failed to accept metrics client: {error}");
+                continue;
+            }
+        };
+
+        if let Err(error) = handle_client(stream) {
+            log::warn!("metrics client failed: {error}");
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_client(mut stream: TcpStream) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    // every request gets the metrics, no matter the path
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line.trim_end() != "" {
+        line.clear();
+    }
+
+    let body = snapshot().prometheus(Utc::now());
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn prometheus() {
+        let now = Utc::now();
+        let metrics = Metrics {
+            started: now - chrono::Duration::minutes(5),
+            events_processed: 12,
+            event_backlog: 1,
+            watcher_restarts: 0,
+            last_settings_reload: Some(SettingsReload {
+                time: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+                error: Some("script names must be unique".to_string()),
+            }),
+            mount_watcher: MountWatcher::Polling,
+            wakeups: 3,
+            busy_loops: 0,
+        };
+
+        assert_eq!(
+            metrics.prometheus(now),
+            indoc! {"
+                # HELP backup_monitor_uptime_seconds Time since the backup monitor has been started.
+                # TYPE backup_monitor_uptime_seconds gauge
+                backup_monitor_uptime_seconds 300
+                # HELP backup_monitor_events_processed_total Events processed by the main loop.
+                # TYPE backup_monitor_events_processed_total counter
+                backup_monitor_events_processed_total 12
+                # HELP backup_monitor_event_backlog Events waiting to be processed by the main loop.
+                # TYPE backup_monitor_event_backlog gauge
+                backup_monitor_event_backlog 1
+                # HELP backup_monitor_watcher_restarts_total Restarts of failed mount and session watchers.
+                # TYPE backup_monitor_watcher_restarts_total counter
+                backup_monitor_watcher_restarts_total 0
+                # HELP backup_monitor_wakeups_total Wakeups of the main loop without an event.
+                # TYPE backup_monitor_wakeups_total counter
+                backup_monitor_wakeups_total 3
+                # HELP backup_monitor_busy_loops_total Detections of a main loop whose next wakeup was due over and over again.
+                # TYPE backup_monitor_busy_loops_total counter
+                backup_monitor_busy_loops_total 0
+                # HELP backup_monitor_mount_watcher_polling Whether the mounts are polled because `/proc/mounts` doesn't signal changes.
+                # TYPE backup_monitor_mount_watcher_polling gauge
+                backup_monitor_mount_watcher_polling 1
+                # HELP backup_monitor_settings_reload_success Whether the last reload of the settings was successful.
+                # TYPE backup_monitor_settings_reload_success gauge
+                backup_monitor_settings_reload_success 0
+                # HELP backup_monitor_settings_reload_timestamp_seconds Time of the last reload of the settings.
+                # TYPE backup_monitor_settings_reload_timestamp_seconds gauge
+                backup_monitor_settings_reload_timestamp_seconds 1700000000
+            "}
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
 
-use crate::manager::Manager;
+use crate::history::Trigger;
+use crate::manager::{Manager, Overdue, RunOutcome, ScriptStatus};
 use crate::tray::Tray;
-use crate::tray_handle::TrayHandle;
+use crate::tray_handle::{TrayData, TrayHandle};
+use crate::upower::Power;
 
 #[derive(Debug, Default)]
 pub struct MockManager {
@@ -10,6 +12,7 @@ pub struct MockManager {
     pub next_reminder: Option<DateTime<Utc>>,
     pub next_ui_update: Option<DateTime<Utc>>,
     pub tooltip: String,
+    pub overdue: Vec<Overdue>,
     pub run: Vec<Option<String>>,
 }
 
@@ -30,14 +33,44 @@ impl Manager for MockManager {
         self.tooltip.clone()
     }
 
+    fn low_space(&self) -> bool {
+        false
+    }
+
+    fn overdue(&self) -> Vec<Overdue> {
+        self.overdue.clone()
+    }
+
+    fn script_status(&self, _script_name: &str) -> ScriptStatus {
+        ScriptStatus::Ok
+    }
+
+    fn explain(&self, _script_name: &str) -> Option<String> {
+        None
+    }
+
     fn set_mounts(&mut self, _mounts: &str) {}
 
+    fn set_active_user(&mut self, _user: Option<String>) {}
+
+    fn set_power(&mut self, _power: Option<Power>) {}
+
+    fn set_idle_since(&mut self, _idle_since: Option<DateTime<Utc>>) {}
+
+    fn dismiss_failure(&mut self, _script_name: &str) {}
+
     fn run(
         &mut self,
         script_name: Option<&str>,
-        _handle: &impl TrayHandle<Tray>,
-    ) -> anyhow::Result<()> {
+        _trigger: &Trigger,
+        handle: &impl TrayHandle<Tray>,
+    ) -> anyhow::Result<Vec<(String, RunOutcome)>> {
         self.run.push(script_name.map(ToString::to_string));
-        Ok(())
+        // like the update of a running script
+        handle.update(TrayData {
+            tooltip: Some(self.tooltip.clone()),
+            ..Default::default()
+        });
+        Ok(Vec::new())
     }
 }
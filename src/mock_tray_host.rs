@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+
+use ksni::{menu::RadioGroup, MenuItem, Tray as _};
+
+use crate::{
+    tray::Tray,
+    tray_handle::{apply, TrayData, TrayHandle},
+};
+
+// stands in for the StatusNotifierHost of a panel, which renders the menu of the tray and
+// activates its items, without a D-Bus session
+pub struct MockTrayHost {
+    tray: RefCell<Tray>,
+}
+
+impl MockTrayHost {
+    pub fn new(tray: Tray) -> Self {
+        MockTrayHost {
+            tray: RefCell::new(tray),
+        }
+    }
+
+    pub fn status(&self) -> ksni::Status {
+        self.tray.borrow().status()
+    }
+
+    pub fn tool_tip(&self) -> ksni::ToolTip {
+        self.tray.borrow().tool_tip()
+    }
+
+    // a line per item, indented by the depth of its submenu, e.g. `Pause backups >` followed by
+    // `  For 1 day`, disabled items end with `(greyed out)` and the selected radio item is marked
+    // with `(x)`
+    pub fn menu(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        render(&self.tray.borrow().menu(), 0, &mut lines);
+        lines
+    }
+
+    // activates the item at the path of labels, e.g. `["Pause backups", "For 1 day"]`
+    // panics if there is no such item, so the test fails with the menu it has looked at
+    pub fn activate(&self, path: &[&str]) {
+        let menu = self.tray.borrow().menu();
+        let mut items = &menu;
+        let (label, submenus) = path.split_last().expect("the path is empty");
+        for submenu in submenus {
+            items = items
+                .iter()
+                .find_map(|item| match item {
+                    MenuItem::SubMenu(item) if item.label == *submenu => Some(&item.submenu),
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("no submenu {submenu:?} in {:?}", self.menu()));
+        }
+
+        let activated = {
+            let tray = &mut *self.tray.borrow_mut();
+            items.iter().any(|item| match item {
+                MenuItem::Standard(item) if item.label == *label => {
+                    assert!(item.enabled, "{label:?} is greyed out");
+                    (item.activate)(tray);
+                    true
+                }
+                MenuItem::Checkmark(item) if item.label == *label => {
+                    (item.activate)(tray);
+                    true
+                }
+                MenuItem::RadioGroup(RadioGroup {
+                    select, options, ..
+                }) => match options.iter().position(|option| option.label == *label) {
+                    Some(index) => {
+                        select(tray, index);
+                        true
+                    }
+                    None => false,
+                },
+                _ => false,
+            })
+        };
+        assert!(activated, "no item {label:?} in {:?}", self.menu());
+    }
+}
+
+impl TrayHandle<Tray> for MockTrayHost {
+    fn update(&self, data: TrayData) {
+        apply(&mut self.tray.borrow_mut(), data);
+    }
+}
+
+fn render(items: &[MenuItem<Tray>], depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let greyed_out = |enabled: bool| if enabled { "" } else { " (greyed out)" };
+    for item in items {
+        match item {
+            MenuItem::Standard(item) => lines.push(format!(
+                "{indent}{}{}",
+                item.label,
+                greyed_out(item.enabled)
+            )),
+            MenuItem::Separator => lines.push(format!("{indent}---")),
+            MenuItem::Checkmark(item) => lines.push(format!(
+                "{indent}[{}] {}{}",
+                if item.checked { "x" } else { " " },
+                item.label,
+                greyed_out(item.enabled)
+            )),
+            MenuItem::SubMenu(item) => {
+                lines.push(format!("{indent}{} >", item.label));
+                render(&item.submenu, depth + 1, lines);
+            }
+            MenuItem::RadioGroup(group) => {
+                for (index, option) in group.options.iter().enumerate() {
+                    lines.push(format!(
+                        "{indent}({}) {}",
+                        if index == group.selected { "x" } else { " " },
+                        option.label
+                    ));
+                }
+            }
+        }
+    }
+}
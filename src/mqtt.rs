@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+use nix::unistd::gethostname;
+use serde_json::json;
+use tempfile::TempDir;
+
+use crate::{
+    manager::ScriptStatus,
+    settings::{Mqtt, Settings},
+    tray_handle::TrayData,
+};
+
+#[derive(Debug)]
+pub struct ScriptReport {
+    pub name: String,
+    pub status: ScriptStatus,
+    pub last_backup: Option<DateTime<Utc>>,
+    // consecutive failures since the last success
+    pub failures: u32,
+}
+
+pub fn script_reports(
+    tray_data: &TrayData,
+    settings: &Settings,
+    failures: impl Fn(&str) -> u32,
+) -> Vec<ScriptReport> {
+    tray_data
+        .scripts
+        .iter()
+        .flatten()
+        .map(|(name, _, status)| ScriptReport {
+            name: name.clone(),
+            status: *status,
+            last_backup: settings
+                .scripts
+                .iter()
+                .find(|script| script.name == *name)
+                .and_then(|script| script.last_backup),
+            failures: failures(name),
+        })
+        .collect()
+}
+
+// publishes only the messages which changed since the last update, all of them are retained
+#[derive(Debug, Default)]
+pub struct MqttPublisher {
+    // a message is removed again when publishing it fails, so it's retried on the next update
+    published: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl MqttPublisher {
+    pub fn update(&mut self, mqtt: &Mqtt, scripts: &[ScriptReport]) {
+        let hostname = gethostname()
+            .map(|hostname| hostname.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        for (topic, payload) in messages(mqtt, &hostname, scripts) {
+            let mut published = self.published.lock().unwrap();
+            if published.get(&topic) != Some(&payload) {
+                published.insert(topic.clone(), payload.clone());
+                drop(published);
+                publish(mqtt, topic, payload, self.published.clone());
+            }
+        }
+    }
+}
+
+// the states and the Home Assistant discovery configs of the sensors
+fn messages(mqtt: &Mqtt, hostname: &str, scripts: &[ScriptReport]) -> Vec<(String, String)> {
+    let host = slug(hostname);
+    let device = json!({
+        "identifiers": [format!("backup_monitor_{host}")],
+        "name": format!("Backup Monitor ({hostname})"),
+    });
+
+    let mut messages = Vec::new();
+    for script in scripts {
+        let script_slug = slug(&script.name);
+        let state_topic = format!("{}/{host}/{script_slug}/state", mqtt.topic_prefix);
+
+        let sensors = [
+            ("status", "status", json!({})),
+            (
+                "last_backup",
+                "last backup",
+                json!({ "device_class": "timestamp" }),
+            ),
+            (
+                "failures",
+                "failures",
+                json!({ "state_class": "measurement" }),
+            ),
+        ];
+        for (key, label, extra) in sensors {
+            let unique_id = format!("backup_monitor_{host}_{script_slug}_{key}");
+            let mut config = json!({
+                "name": format!("{} {label}", script.name),
+                "unique_id": unique_id,
+                "state_topic": state_topic,
+                "value_template": format!("{{{{ value_json.{key} }}}}"),
+                "device": device,
+            });
+            if let (Some(config), Some(extra)) = (config.as_object_mut(), extra.as_object()) {
+                config.extend(extra.clone());
+            }
+            messages.push((
+                format!("{}/sensor/{unique_id}/config", mqtt.discovery_prefix),
+                config.to_string(),
+            ));
+        }
+
+        let state = json!({
+            "status": script.status,
+            "last_backup": script.last_backup,
+            "failures": script.failures,
+        });
+        messages.push((state_topic, state.to_string()));
+    }
+
+    messages
+}
+
+// e.g. `photos_nas` for `Photos (NAS)`
+fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+// published in the background with `mosquitto_pub`, so an unreachable broker doesn't hold up the
+// main loop
+fn publish(
+    mqtt: &Mqtt,
+    topic: String,
+    payload: String,
+    published: Arc<Mutex<HashMap<String, String>>>,
+) {
+    let forget = move |topic: &str, payload: &str| {
+        let mut published = published.lock().unwrap();
+        if published
+            .get(topic)
+            .is_some_and(|published| published == payload)
+        {
+            published.remove(topic);
+        }
+    };
+
+    let mut command = Command::new("mosquitto_pub");
+    command
+        .args(["--host", &mqtt.host])
+        .args(["--port", &mqtt.port.to_string()])
+        .args(["--retain", "--topic", &topic, "--message", &payload]);
+    if let Some(username) = &mqtt.username {
+        command.args(["--username", username]);
+    }
+    let config_dir = match &mqtt.password {
+        Some(password) => match password_config(password) {
+            Ok(config_dir) => {
+                command.env("XDG_CONFIG_HOME", config_dir.path());
+                Some(config_dir)
+            }
+            Err(error) => {
+                log::warn!("failed to write the mosquitto_pub config: {error}");
+                forget(&topic, &payload);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    thread::spawn(move || {
+        let published = match command.output() {
+            Ok(output) if !output.status.success() => {
+                log::warn!(
+                    "failed to publish `{topic}`: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                false
+            }
+            Ok(_) => {
+                log::trace!("published `{topic}`");
+                true
+            }
+            Err(error) => {
+                log::warn!("failed to run mosquitto_pub: {error}");
+                false
+            }
+        };
+        if !published {
+            forget(&topic, &payload);
+        }
+        drop(config_dir);
+    });
+}
+
+// `mosquitto_pub` reads its default options from `$XDG_CONFIG_HOME/mosquitto_pub`, so the password
+// doesn't show up in its command line. The options of the user's own config file are kept.
+fn password_config(password: &str) -> io::Result<TempDir> {
+    let dir = tempfile::tempdir()?;
+    let mut file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .mode(0o600)
+        .open(dir.path().join("mosquitto_pub"))?;
+    if let Some(config_dir) = dirs::config_dir() {
+        if let Ok(options) = fs::read_to_string(config_dir.join("mosquitto_pub")) {
+            writeln!(file, "{}", options.trim_end())?;
+        }
+    }
+    writeln!(file, "--pw {password}")?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use fake::{Fake, Faker};
+
+    #[test]
+    fn mqtt_messages() {
+        let now = Faker.fake::<Clock>().now();
+        let mqtt = serde_yaml_ng::from_str::<Mqtt>("host: localhost").unwrap();
+        let scripts = [ScriptReport {
+            name: "Photos (NAS)".to_string(),
+            status: ScriptStatus::Failed,
+            last_backup: Some(now),
+            failures: 2,
+        }];
+
+        let messages = messages(&mqtt, "laptop", &scripts);
+        let topics = messages
+            .iter()
+            .map(|(topic, _)| topic.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            topics,
+            vec![
+                "homeassistant/sensor/backup_monitor_laptop_photos_nas_status/config",
+                "homeassistant/sensor/backup_monitor_laptop_photos_nas_last_backup/config",
+                "homeassistant/sensor/backup_monitor_laptop_photos_nas_failures/config",
+                "backup-monitor/laptop/photos_nas/state",
+            ]
+        );
+
+        let config = serde_json::from_str::<serde_json::Value>(&messages[1].1).unwrap();
+        assert_eq!(config["name"], "Photos (NAS) last backup");
+        assert_eq!(config["device_class"], "timestamp");
+        assert_eq!(
+            config["state_topic"],
+            "backup-monitor/laptop/photos_nas/state"
+        );
+        assert_eq!(config["value_template"], "{{ value_json.last_backup }}");
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&messages[3].1).unwrap(),
+            json!({ "status": "failed", "last_backup": now, "failures": 2 })
+        );
+    }
+
+    #[test]
+    fn mqtt_password_config() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let config_dir = password_config("secret").unwrap();
+        let path = config_dir.path().join("mosquitto_pub");
+
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("--pw secret\n"));
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::fs;
+
+use zbus::{blocking::Connection, proxy, zvariant::OwnedObjectPath};
+
+use crate::{event::EventSender, Event};
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager",
+    default_service = "org.freedesktop.NetworkManager",
+    default_path = "/org/freedesktop/NetworkManager"
+)]
+trait NetworkManager {
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn primary_connection(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager.Connection.Active",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait ActiveConnection {
+    #[zbus(property)]
+    fn devices(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager.Device.Wireless",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait Wireless {
+    #[zbus(property)]
+    fn active_access_point(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager.AccessPoint",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait AccessPoint {
+    #[zbus(property)]
+    fn ssid(&self) -> zbus::Result<Vec<u8>>;
+}
+
+// what identifies the network the computer is connected to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub ssid: Option<String>,
+    // lowercase, e.g. `a4:2b:b0:12:34:56`
+    pub gateway_mac: Option<String>,
+    // search domains of the resolver
+    pub domains: Vec<String>,
+}
+
+impl Location {
+    pub fn current(connection: &Connection) -> Location {
+        let ssid = current_ssid(connection).unwrap_or_else(|error| {
+            log::debug!("failed to get the SSID: {error}");
+            None
+        });
+        let gateway_mac = match (
+            fs::read_to_string("/proc/net/route"),
+            fs::read_to_string("/proc/net/arp"),
+        ) {
+            (Ok(route), Ok(arp)) => gateway_mac(&route, &arp),
+            _ => None,
+        };
+        let domains = fs::read_to_string("/etc/resolv.conf")
+            .map(|resolv_conf| domains(&resolv_conf))
+            .unwrap_or_default();
+
+        Location {
+            ssid,
+            gateway_mac,
+            domains,
+        }
+    }
+}
+
+// SSID of the access point of the primary connection, `None` for wired connections
+fn current_ssid(connection: &Connection) -> zbus::Result<Option<String>> {
+    let path = NetworkManagerProxyBlocking::new(connection)?.primary_connection()?;
+    // the path is `/` if there is no connection
+    if path.as_str() == "/" {
+        return Ok(None);
+    }
+
+    let devices = ActiveConnectionProxyBlocking::builder(connection)
+        .path(path)?
+        .build()?
+        .devices()?;
+    for device in devices {
+        let wireless = WirelessProxyBlocking::builder(connection)
+            .path(device)?
+            .build()?;
+        // not a wireless device
+        let Ok(access_point) = wireless.active_access_point() else {
+            continue;
+        };
+        if access_point.as_str() == "/" {
+            continue;
+        }
+
+        let ssid = AccessPointProxyBlocking::builder(connection)
+            .path(access_point)?
+            .build()?
+            .ssid()?;
+        return Ok(Some(String::from_utf8_lossy(&ssid).into_owned()));
+    }
+
+    Ok(None)
+}
+
+// the gateway of the default route in `/proc/net/route` looked up in `/proc/net/arp`
+fn gateway_mac(route: &str, arp: &str) -> Option<String> {
+    let gateway = route.lines().skip(1).find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        match fields[..] {
+            [_, "00000000", gateway, ..] => u32::from_str_radix(gateway, 16).ok(),
+            _ => None,
+        }
+    })?;
+    // the address is in host byte order, i.e. little endian
+    let [a, b, c, d] = gateway.to_le_bytes();
+    let gateway = format!("{a}.{b}.{c}.{d}");
+
+    arp.lines().skip(1).find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        match fields[..] {
+            [ip, _, _, mac, ..] if ip == gateway && mac != "00:00:00:00:00:00" => {
+                Some(mac.to_lowercase())
+            }
+            _ => None,
+        }
+    })
+}
+
+// `domain` and `search` entries of `/etc/resolv.conf`
+fn domains(resolv_conf: &str) -> Vec<String> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("domain" | "search") => Some(fields),
+                _ => None,
+            }
+        })
+        .flatten()
+        .map(|domain| domain.trim_end_matches('.').to_lowercase())
+        .collect()
+}
+
+// the location is determined again whenever the state of NetworkManager changes
+pub fn watch_location(tx: &EventSender) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let network_manager = NetworkManagerProxyBlocking::new(&connection)?;
+
+    let mut last_location = Location::current(&connection);
+    let _ = tx.send(Event::LocationChanged(last_location.clone()));
+
+    for _ in network_manager.receive_state_changed() {
+        let location = Location::current(&connection);
+        if location == last_location {
+            continue;
+        }
+
+        log::debug!("network location has changed to {location:?}");
+
+        let _ = tx.send(Event::LocationChanged(location.clone()));
+        last_location = location;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn gateway() {
+        let route = indoc! {"
+            Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+            wlp2s0\t0000A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+            wlp2s0\t00000000\t0100A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+        "};
+        let arp = indoc! {"
+            IP address       HW type     Flags       HW address            Mask     Device
+            192.168.0.23     0x1         0x2         3c:22:fb:00:11:22     *        wlp2s0
+            192.168.0.1      0x1         0x2         A4:2B:B0:12:34:56     *        wlp2s0
+        "};
+
+        assert_eq!(
+            gateway_mac(route, arp),
+            Some("a4:2b:b0:12:34:56".to_string())
+        );
+        // no default route
+        assert_eq!(
+            gateway_mac(&route.replace("\t00000000\t0100", "\t0000A8C0\t0100"), arp),
+            None
+        );
+    }
+
+    #[test]
+    fn search_domains() {
+        let resolv_conf = indoc! {"
+            # Generated by NetworkManager
+            search fritz.box Corp.example.com.
+            nameserver 192.168.0.1
+        "};
+
+        assert_eq!(
+            domains(resolv_conf),
+            vec!["fritz.box".to_string(), "corp.example.com".to_string()]
+        );
+    }
+}
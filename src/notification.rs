@@ -0,0 +1,18 @@
+use notify_rust::{error::Result, Notification, NotificationHandle};
+
+use crate::settings::system_mode;
+
+// the system-wide daemon has no session to show notifications in, the notification is logged
+// instead and `None` is returned
+pub fn show(notification: &Notification) -> Result<Option<NotificationHandle>> {
+    if system_mode() {
+        if notification.body.is_empty() {
+            log::info!("{}", notification.summary);
+        } else {
+            log::info!("{}: {}", notification.summary, notification.body);
+        }
+        return Ok(None);
+    }
+
+    notification.show().map(Some)
+}
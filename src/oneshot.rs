@@ -0,0 +1,140 @@
+use std::{
+    fs::{self, File},
+    os::unix::net::UnixStream,
+    sync::Arc,
+};
+
+use anyhow::ensure;
+use arc_swap::ArcSwap;
+use chrono::Utc;
+use zbus::blocking::Connection;
+
+use crate::{
+    catch_up_launch,
+    clock::Clock,
+    control,
+    history::Trigger,
+    logind,
+    manager::Manager,
+    run_result::ScriptResult,
+    script_manager::{format_time, ScriptManager},
+    settings::{system_mode, Settings},
+    state::State,
+    sync_autolaunch,
+    tray::Tray,
+    tray_handle::{TrayData, TrayHandle},
+};
+
+// there is no system tray to update
+struct NoTray;
+
+impl TrayHandle<Tray> for NoTray {
+    fn update(&self, _data: TrayData) {}
+}
+
+// held while a oneshot run is in progress
+fn lock_file() -> anyhow::Result<File> {
+    let path = control::socket_path(system_mode())?.with_file_name("oneshot.lock");
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?)
+}
+
+// the backup monitor waits for a oneshot run (e.g. the catch-up at login) to finish before it
+// loads the settings, so the scripts aren't run twice
+pub fn wait_for_oneshot() -> anyhow::Result<()> {
+    let lock = lock_file()?;
+    if lock.try_lock().is_err() {
+        log::info!("waiting for the oneshot run to finish");
+        lock.lock()?;
+    }
+    Ok(())
+}
+
+// a script manager with the state of the last runs, like the running instance would have
+fn manager(settings: Settings) -> anyhow::Result<ScriptManager> {
+    let settings = Arc::new(ArcSwap::from_pointee(settings));
+    let mounts = fs::read_to_string("/proc/mounts")?;
+
+    let mut manager = ScriptManager::new(Clock::new(), settings, &mounts);
+    match State::load() {
+        Ok(Some(state)) => manager.restore_state(&state),
+        Ok(None) => {}
+        Err(error) => log::warn!("failed to load state: {error:#}"),
+    }
+    match Connection::system().and_then(|connection| logind::active_user(&connection)) {
+        Ok(user) => manager.set_active_user(user),
+        Err(error) => log::warn!("failed to get the active user: {error}"),
+    }
+
+    Ok(manager)
+}
+
+// prints when each script runs next and what blocks it, without running anything
+pub fn print_next_runs(settings: Settings, json: bool) -> anyhow::Result<()> {
+    let next_runs = manager(settings)?.next_runs();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&next_runs)?);
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    for next_run in next_runs {
+        let time = match next_run.next_run {
+            Some(ts) if ts <= now => "now".to_string(),
+            Some(ts) => format_time(ts),
+            None => "blocked".to_string(),
+        };
+        println!(
+            "{}\t{time}\t{}",
+            next_run.name,
+            next_run.blocked_by.join("; ")
+        );
+    }
+
+    Ok(())
+}
+
+// runs the due scripts once (e.g. from a systemd timer)
+pub fn oneshot(settings: Settings) -> anyhow::Result<Vec<ScriptResult>> {
+    let lock = lock_file()?;
+    ensure!(
+        lock.try_lock().is_ok(),
+        "another oneshot run is in progress"
+    );
+
+    let socket_path = control::socket_path(system_mode())?;
+    ensure!(
+        UnixStream::connect(&socket_path).is_err(),
+        "backup monitor is already running, use `backup-monitor run <script>` instead"
+    );
+
+    // installed or removed here too, for users who don't start the tray at all
+    if !system_mode() {
+        if let Err(error) = catch_up_launch().and_then(|launch| {
+            sync_autolaunch(&launch, settings.catch_up_on_login, "catch-up on login")
+        }) {
+            log::warn!("failed to update the catch-up autostart entry: {error:#}");
+        }
+    }
+
+    let mut manager = manager(settings)?;
+    let outcomes = manager.run(None, &Trigger::Schedule, &NoTray)?;
+    if outcomes.is_empty() {
+        log::info!("no script is due");
+    }
+    for (name, outcome) in &outcomes {
+        log::info!("script {name} {outcome}");
+    }
+
+    Ok(outcomes
+        .iter()
+        .map(|(name, outcome)| ScriptResult::new(name, outcome))
+        .collect())
+}
@@ -0,0 +1,51 @@
+use std::{fs, path::Path};
+
+// on battery if there are mains power supplies and none of them is online, computers without
+// power supply information are assumed to be on AC
+pub fn on_battery() -> bool {
+    on_battery_in(Path::new("/sys/class/power_supply"))
+}
+
+fn on_battery_in(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+
+    let read = |path: &Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .map_or(String::new(), |content| content.trim().to_string())
+    };
+
+    let mains = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| read(path, "type") == "Mains")
+        .collect::<Vec<_>>();
+
+    !mains.is_empty() && mains.iter().all(|path| read(path, "online") == "0")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn power_supply(dir: &Path, name: &str, kind: &str, online: &str) {
+        let path = dir.join(name);
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("type"), format!("{kind}\n")).unwrap();
+        fs::write(path.join("online"), format!("{online}\n")).unwrap();
+    }
+
+    #[test]
+    fn battery() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!on_battery_in(&dir.path().join("missing")));
+        assert!(!on_battery_in(dir.path()));
+
+        power_supply(dir.path(), "BAT0", "Battery", "1");
+        power_supply(dir.path(), "AC", "Mains", "0");
+        assert!(on_battery_in(dir.path()));
+
+        power_supply(dir.path(), "USB-C", "Mains", "1");
+        assert!(!on_battery_in(dir.path()));
+    }
+}
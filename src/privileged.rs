@@ -0,0 +1,116 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Write},
+    os::unix::{
+        fs::{MetadataExt, OpenOptionsExt},
+        process::{CommandExt, ExitStatusExt},
+    },
+    path::Path,
+    process::{self, Command, Stdio},
+    thread,
+};
+
+use anyhow::{anyhow, bail};
+use nix::{
+    libc,
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+
+// `pkexec backup-monitor exec-privileged <status file> <script>`
+pub const EXEC_PRIVILEGED: &str = "exec-privileged";
+
+// sent on stdin to stop the script, the backup monitor isn't allowed to signal processes of root
+pub const STOP: &str = "stop";
+
+// runs a privileged script as root after `pkexec` has authorized it and exits with its exit code.
+// The exit code is also written to the status file, so it isn't mistaken for an exit code of
+// `pkexec` itself.
+pub fn exec_script(args: &[OsString]) -> anyhow::Result<()> {
+    let [status_file, script] = args else {
+        bail!("usage: backup-monitor {EXEC_PRIVILEGED} <status file> <script>");
+    };
+
+    // checked before the script runs, so nothing runs as root for a caller faking the file
+    let mut status_file = open_status_file(Path::new(status_file))?;
+
+    let mut child = Command::new(script)
+        .stdin(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    let pid = Pid::from_raw(child.id() as i32);
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) if line == STOP => {
+                    // the script runs in its own process group, which includes its child processes
+                    if let Err(error) = killpg(pid, Signal::SIGTERM) {
+                        eprintln!("failed to stop the script: {error}");
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    });
+
+    let status = child.wait()?;
+    // like a shell
+    let code = status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default());
+
+    status_file.write_all(code.to_string().as_bytes())?;
+
+    process::exit(code);
+}
+
+// The file has been created by the backup monitor, root doesn't create files in its run dir. As the
+// path is chosen by the caller, root must neither follow a symlink nor write to a file the caller
+// doesn't own.
+fn open_status_file(path: &Path) -> anyhow::Result<File> {
+    let caller = env::var("PKEXEC_UID")
+        .map_err(|_| anyhow!("{EXEC_PRIVILEGED} must be started by pkexec"))?
+        .parse::<u32>()?;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)?;
+
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.uid() != caller {
+        bail!("{} is not a file of the calling user", path.display());
+    }
+
+    file.set_len(0)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, os::unix::fs::symlink};
+
+    #[test]
+    fn status_file_of_the_caller() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.status");
+        fs::write(&path, "stale").unwrap();
+        let link = dir.path().join("link.status");
+        symlink(&path, &link).unwrap();
+        let owner = fs::metadata(&path).unwrap().uid();
+
+        env::set_var("PKEXEC_UID", owner.to_string());
+        assert!(open_status_file(&path).is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        assert!(open_status_file(&link).is_err());
+        assert!(open_status_file(dir.path()).is_err());
+
+        env::set_var("PKEXEC_UID", (owner + 1).to_string());
+        assert!(open_status_file(&path).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::process::Command;
+
+use anyhow::ensure;
+use chrono::{DateTime, Utc};
+
+// programs the RTC to wake the computer from suspend at `time` with `rtcwake`, which replaces the
+// previous alarm, `None` disables the alarm
+pub fn set_wake_alarm(time: Option<DateTime<Utc>>) -> anyhow::Result<()> {
+    let output = Command::new("rtcwake").args(rtcwake_args(time)).output()?;
+    ensure!(
+        output.status.success(),
+        "rtcwake failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+// `-m no` only sets the alarm, the computer is suspended as usual
+fn rtcwake_args(time: Option<DateTime<Utc>>) -> Vec<String> {
+    match time {
+        Some(time) => vec![
+            "-m".to_string(),
+            "no".to_string(),
+            "-t".to_string(),
+            time.timestamp().to_string(),
+        ],
+        None => vec!["-m".to_string(), "disable".to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args() {
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+        assert_eq!(rtcwake_args(Some(time)), ["-m", "no", "-t", "1700000000"]);
+        assert_eq!(rtcwake_args(None), ["-m", "disable"]);
+    }
+}
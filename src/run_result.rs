@@ -0,0 +1,167 @@
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+use crate::manager::RunOutcome;
+
+// stable exit codes of `run --wait` and `oneshot` for wrapper scripts
+pub const SUCCESS: i32 = 0;
+pub const FAILED: i32 = 1;
+pub const CONFIG_ERROR: i32 = 2;
+// waiting for backup disks or the active user
+pub const BLOCKED: i32 = 3;
+// e.g. the backup monitor isn't running
+pub const ERROR: i32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    Blocked,
+}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Succeeded => "succeeded",
+            Outcome::Failed => "failed",
+            Outcome::Blocked => "blocked",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScriptResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub message: Option<String>,
+}
+
+impl ScriptResult {
+    pub fn new(name: &str, outcome: &RunOutcome) -> ScriptResult {
+        let (outcome, message) = match outcome {
+            RunOutcome::Succeeded => (Outcome::Succeeded, None),
+            RunOutcome::Failed(message) => (Outcome::Failed, Some(message.clone())),
+            RunOutcome::WaitingForPaths(_)
+            | RunOutcome::WaitingForUser(_)
+            | RunOutcome::WaitingForPower(_)
+            | RunOutcome::WaitingForIdle(_) => (Outcome::Blocked, Some(outcome.to_string())),
+        };
+        ScriptResult {
+            name: name.to_string(),
+            outcome,
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RunResult<'a> {
+    exit_code: i32,
+    scripts: &'a [ScriptResult],
+    error: Option<String>,
+}
+
+// failures take precedence over blocked scripts, a requested script which wasn't run (e.g.
+// because it is already running) counts as blocked
+pub fn exit_code(results: &[ScriptResult], requested: bool) -> i32 {
+    let any = |outcome| results.iter().any(|result| result.outcome == outcome);
+    if any(Outcome::Failed) {
+        FAILED
+    } else if any(Outcome::Blocked) || (requested && results.is_empty()) {
+        BLOCKED
+    } else {
+        SUCCESS
+    }
+}
+
+// one line per script, separated by tabs like `list`
+pub fn exit_with_results(results: &[ScriptResult], requested: bool, json: bool) -> ! {
+    let exit_code = exit_code(results, requested);
+    if json {
+        print_json(&RunResult {
+            exit_code,
+            scripts: results,
+            error: None,
+        });
+    } else {
+        for result in results {
+            let outcome = result.outcome.name();
+            match &result.message {
+                Some(message) => println!("{}\t{outcome}\t{message}", result.name),
+                None => println!("{}\t{outcome}", result.name),
+            }
+        }
+    }
+    process::exit(exit_code)
+}
+
+pub fn exit_with_error(error: &anyhow::Error, exit_code: i32, json: bool) -> ! {
+    if json {
+        print_json(&RunResult {
+            exit_code,
+            scripts: &[],
+            error: Some(format!("{error:#}")),
+        });
+    } else {
+        eprintln!("Error: {error:#}");
+    }
+    process::exit(exit_code)
+}
+
+fn print_json(result: &RunResult) {
+    match serde_json::to_string(result) {
+        Ok(json) => println!("{json}"),
+        Err(error) => eprintln!("Error: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn exit_codes() {
+        let succeeded = ScriptResult::new("Home", &RunOutcome::Succeeded);
+        let failed = ScriptResult::new("Photos", &RunOutcome::Failed("exit code 2".to_string()));
+        let blocked = ScriptResult::new(
+            "Music",
+            &RunOutcome::WaitingForPaths(vec![PathBuf::from("/mnt/backup")]),
+        );
+
+        assert_eq!(
+            blocked.message.as_deref(),
+            Some("waiting for `/mnt/backup` to be mounted")
+        );
+
+        assert_eq!(exit_code(&[], false), SUCCESS);
+        assert_eq!(exit_code(&[], true), BLOCKED);
+        assert_eq!(exit_code(std::slice::from_ref(&succeeded), true), SUCCESS);
+        assert_eq!(
+            exit_code(&[succeeded.clone(), blocked.clone()], false),
+            BLOCKED
+        );
+        assert_eq!(exit_code(&[blocked, failed.clone()], false), FAILED);
+
+        assert_eq!(
+            serde_json::to_value(RunResult {
+                exit_code: FAILED,
+                scripts: &[succeeded, failed],
+                error: None,
+            })
+            .unwrap(),
+            serde_json::json!({
+                "exit-code": 1,
+                "scripts": [
+                    { "name": "Home", "outcome": "succeeded", "message": null },
+                    { "name": "Photos", "outcome": "failed", "message": "exit code 2" },
+                ],
+                "error": null,
+            })
+        );
+    }
+}
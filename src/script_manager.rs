@@ -1,29 +1,71 @@
 use std::{
     collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs::{self, File, OpenOptions},
+    hash::{DefaultHasher, Hash, Hasher},
     io::Write,
-    os::unix::fs::PermissionsExt,
-    path::PathBuf,
-    process::Command,
-    sync::Arc,
-    time::Instant,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{OpenOptionsExt, PermissionsExt},
+        process::CommandExt,
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{self as std_time, Instant, SystemTime},
 };
 
+use anyhow::Context;
 use arc_swap::ArcSwap;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, Local, Utc};
 use itertools::Itertools;
-use notify_rust::{Hint, Notification, Timeout};
-use serde::Deserialize;
-use tempfile::NamedTempFile;
+use nix::{
+    sys::{
+        signal::{killpg, Signal},
+        statvfs::{statvfs, FsFlags},
+    },
+    unistd::Pid,
+};
+use notify_rust::{Hint, Notification, NotificationHandle, Timeout};
+use serde::{Deserialize, Serialize};
+use tempfile::{NamedTempFile, TempDir, TempPath};
+use zbus::blocking::Connection;
 
 use crate::tray_handle::TrayHandle;
-use crate::{clock::Clock, manager::Manager};
 use crate::{
+    broadcast::broadcast,
+    chain::{chain_warnings, parse_chain, Chain},
+    clipboard,
+    clock::Clock,
+    dbus_service::DBusService,
+    disk_usage::{disk_usage, format_bytes, projected_full, DiskUsage},
+    email::send_failure_email,
+    event::{Event, EventSender},
+    history::{self, Action, Entry, Trigger},
+    launcher_entry::LauncherEntry,
+    logind::inhibit_sleep,
+    manager::{Manager, Overdue, RunOutcome, ScriptStatus},
+};
+use crate::{
+    notification, privileged,
     round_duration::{round_duration, RoundAccuracy, RoundDirection},
     tray_handle::TrayData,
 };
 use crate::{
-    settings::{Script, Settings},
+    settings::{
+        settings_file_path, system_mode, update_settings, CommitLastBackup, PreflightPolicy,
+        ScheduleFrom, Script, Settings, WebhookEvent,
+    },
+    state::{chain_base, record_chain_base, record_result, record_targets_seen, State},
+    success::unmet_conditions,
+    targets::{device_mounts, record_targets, resolve_mount_path, Selector},
+    template::{render, render_script, script_values, script_variables},
+    time_window::next_allowed,
     tray::Tray,
+    udisks::{request_mount, unmount},
+    upower::Power,
+    webhook::call_webhooks,
     RETRY_INTERVAL,
 };
 
@@ -31,8 +73,163 @@ use crate::{
 enum ScriptState {
     WaitingForTime,
     WaitingForPaths(Vec<PathBuf>),
+    WaitingForUser(String),
+    // what the script is waiting for, e.g. `AC power`
+    WaitingForPower(String),
+    WaitingForIdle,
     Running,
     Failed(DateTime<Utc>, String),
+    // a mount path isn't writable or has less than `min-free-space` free, retried like a failure
+    TargetUnusable(DateTime<Utc>, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Run,
+    WaitForUser(String),
+    WaitForPower(String),
+    WaitForIdle,
+    WaitForPaths(Vec<PathBuf>),
+}
+
+// reported by `backup-monitor next`, e.g. to set a wake alarm from outside
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NextRun {
+    pub name: String,
+    // `None` while the script waits for something other than time, e.g. a mount
+    pub next_run: Option<DateTime<Utc>>,
+    pub blocked_by: Vec<String>,
+}
+
+// names of the scripts which should be cancelled
+static CANCEL_REQUESTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn request_cancel(script_name: &str) {
+    log::info!("cancelling script {script_name}");
+
+    CANCEL_REQUESTS
+        .lock()
+        .unwrap()
+        .push(script_name.to_string());
+}
+
+fn take_cancel_request(script_name: &str) -> bool {
+    let mut requests = CANCEL_REQUESTS.lock().unwrap();
+    let requested = requests.iter().any(|name| name == script_name);
+    requests.retain(|name| name != script_name);
+    requested
+}
+
+// names of the scripts which are running, read when the system shuts down
+static RUNNING_SCRIPTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// registers a running script until it is dropped
+struct RunningScript(String);
+
+impl RunningScript {
+    fn new(script_name: &str) -> Self {
+        RUNNING_SCRIPTS
+            .lock()
+            .unwrap()
+            .push(script_name.to_string());
+        RunningScript(script_name.to_string())
+    }
+}
+
+impl Drop for RunningScript {
+    fn drop(&mut self) {
+        RUNNING_SCRIPTS
+            .lock()
+            .unwrap()
+            .retain(|name| *name != self.0);
+    }
+}
+
+fn running_scripts() -> Vec<String> {
+    RUNNING_SCRIPTS.lock().unwrap().clone()
+}
+
+// gives the running scripts `shutdown-grace-period` to finish, then cancels them and waits a
+// moment for them to exit
+pub fn finish_before_shutdown(settings: &Settings) {
+    let running = running_scripts();
+    if running.is_empty() {
+        return;
+    }
+
+    log::info!(
+        "waiting for {} to finish before shutting down",
+        running.join(", ")
+    );
+
+    let result = notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary("A backup is still finishing")
+            .body(&format!(
+                "{} will be stopped in {} if it hasn't finished by then",
+                running.join(", "),
+                humantime::format_duration(settings.shutdown_grace_period)
+            ))
+            .icon(&settings.icon_name)
+            .timeout(Timeout::Never),
+    );
+    if let Err(error) = result {
+        log::warn!("failed to show notification: {error}");
+    }
+
+    let wait_until = |deadline: Instant| {
+        while !running_scripts().is_empty() && Instant::now() < deadline {
+            thread::sleep(PROGRESS_POLL_INTERVAL);
+        }
+    };
+
+    wait_until(Instant::now() + settings.shutdown_grace_period);
+    for script_name in running_scripts() {
+        request_cancel(&script_name);
+    }
+    wait_until(Instant::now() + SHUTDOWN_STOP_TIMEOUT);
+}
+
+const PROGRESS_POLL_INTERVAL: std_time::Duration = std_time::Duration::from_millis(500);
+
+const NOTIFICATION_UPDATE_INTERVAL: std_time::Duration = std_time::Duration::from_secs(60);
+
+// how long cancelled scripts may take to exit before the system shuts down anyway
+const SHUTDOWN_STOP_TIMEOUT: std_time::Duration = std_time::Duration::from_secs(5);
+
+// distinct from the labels of post backup actions
+const COPY_DETAILS_ACTION: &str = "backup-monitor-copy-details";
+const RUN_ANYWAY_ACTION: &str = "backup-monitor-run-anyway";
+const SKIP_ACTION: &str = "backup-monitor-skip";
+
+// number of logs kept per script
+const LOG_RETENTION: usize = 10;
+
+// disk full warnings are repeated at most this often
+const DISK_FULL_WARNING_INTERVAL: Duration = Duration::days(1);
+
+// the disk usage trend is computed from the samples of this period
+const DISK_USAGE_TREND_PERIOD: Duration = Duration::days(90);
+
+// walking the work dir may take a while, so its size isn't checked on every poll
+const WORK_DIR_CHECK_INTERVAL: std_time::Duration = std_time::Duration::from_secs(10);
+
+// how waiting for a script ended
+#[derive(Debug)]
+enum Wait {
+    Exited(ExitStatus),
+    Cancelled,
+    // the work dir grew larger than its limit, the script has been stopped
+    WorkDirFull,
+}
+
+// reported by scripts by writing lines like `42 copying photos` to `$BM_PROGRESS_FILE`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Progress {
+    percent: u8,
+    stage: Option<String>,
 }
 
 pub struct ScriptManager {
@@ -40,16 +237,281 @@ pub struct ScriptManager {
     settings: Arc<ArcSwap<Settings>>,
     states: HashMap<String, ScriptState>,
     mounts: HashSet<PathBuf>,
+    // autofs mount points, the file system is mounted when the path is accessed
+    automounts: HashSet<PathBuf>,
+    // for mount paths which select a file system by UUID or label
+    device_mounts: HashMap<PathBuf, PathBuf>,
+    active_user: Option<String>,
+    // `None` if UPower isn't watched or doesn't answer
+    power: Option<Power>,
+    // `None` while the user is active
+    idle_since: Option<DateTime<Utc>>,
+    // unknown idleness doesn't block backups, e.g. without a graphical session
+    idle_known: bool,
+    // progress of the running script
+    progress: Option<Progress>,
+    launcher_entry: Option<LauncherEntry>,
+    dbus_service: Option<DBusService>,
+    // to run a script anyway after its pre-flight warnings have been accepted
+    tx: Option<EventSender>,
+    // ids of the shown failure and reminder notifications, by script name
+    notifications: HashMap<String, HashSet<u32>>,
+    // number of consecutive failures since the last success, by script name
+    failures: HashMap<String, u32>,
+    // when the mount paths were last seen mounted, by script name
+    targets_seen: HashMap<String, DateTime<Utc>>,
+    // when the last disk full warning was shown, by mount path
+    disk_full_warnings: HashMap<PathBuf, DateTime<Utc>>,
+    // scheduled runs wait until the startup delay has passed
+    startup_until: Option<DateTime<Utc>>,
+    // when the dismissed failure happened, by script name, so the next failure is shown again
+    dismissed_failures: HashMap<String, DateTime<Utc>>,
 }
 
 impl ScriptManager {
     pub fn new(clock: Clock, settings: Arc<ArcSwap<Settings>>, mounts: &str) -> ScriptManager {
+        let device_mounts = device_mounts(mounts);
+        let (mounts, automounts) = parse_mounts(mounts);
         ScriptManager {
             clock,
             settings,
             states: HashMap::new(),
-            mounts: parse_mounts(mounts),
+            mounts,
+            automounts,
+            device_mounts,
+            active_user: None,
+            power: None,
+            idle_since: None,
+            idle_known: false,
+            progress: None,
+            launcher_entry: None,
+            dbus_service: None,
+            tx: None,
+            notifications: HashMap::new(),
+            failures: HashMap::new(),
+            targets_seen: HashMap::new(),
+            disk_full_warnings: HashMap::new(),
+            startup_until: None,
+            dismissed_failures: HashMap::new(),
+        }
+    }
+
+    pub fn delay_startup(&mut self, delay: std_time::Duration) {
+        self.startup_until = Some(self.clock.now() + delay);
+    }
+
+    // the end of the startup delay plus the jitter of the script, so the overdue scripts don't all
+    // start at once
+    fn startup_at(&self, script: &Script) -> Option<DateTime<Utc>> {
+        self.startup_until
+            .map(|until| until + jitter(script, until))
+    }
+
+    pub fn set_launcher_entry(&mut self, launcher_entry: LauncherEntry) {
+        self.launcher_entry = Some(launcher_entry);
+    }
+
+    // the failures of the previous session, so a series of failures isn't reset by a restart, and
+    // when the mount paths were last seen
+    pub fn restore_state(&mut self, state: &State) {
+        self.failures = state
+            .scripts
+            .iter()
+            .filter(|(_, record)| record.failures > 0)
+            .map(|(name, record)| (name.clone(), record.failures))
+            .collect();
+        self.targets_seen = state
+            .scripts
+            .iter()
+            .filter_map(|(name, record)| Some((name.clone(), record.targets_seen?)))
+            .collect();
+    }
+
+    pub fn set_dbus_service(&mut self, dbus_service: DBusService) {
+        self.dbus_service = Some(dbus_service);
+    }
+
+    pub fn set_event_sender(&mut self, tx: EventSender) {
+        self.tx = Some(tx);
+    }
+
+    // runs the pre-flight checks of the script, returns why the run is skipped if they produced
+    // warnings which weren't accepted
+    fn preflight(&self, settings: &Settings, script: &Script, trigger: &Trigger) -> Option<String> {
+        if script.preflight_checks.is_empty() || *trigger == Trigger::RunAnyway {
+            return None;
+        }
+
+        let dir = match run_dir() {
+            Ok(dir) => dir,
+            Err(error) => return Some(format!("pre-flight checks failed with error: {error}")),
+        };
+        let warnings = run_preflight_checks(settings, script, dir.path(), self.clock.now());
+        if warnings.is_empty() {
+            return None;
+        }
+
+        let policy = match (script.preflight_policy, &self.tx) {
+            // the system-wide daemon has no session to show notifications in
+            (PreflightPolicy::Ask, Some(tx)) if !system_mode() => {
+                if let Err(error) = ask_run_anyway(settings, script, &warnings, tx.clone()) {
+                    log::warn!("failed to show notification: {error}");
+                }
+                PreflightPolicy::Ask
+            }
+            (PreflightPolicy::Ask, _) => PreflightPolicy::Skip,
+            (policy, _) => policy,
+        };
+        if policy == PreflightPolicy::Run {
+            log::info!("running `{}` despite pre-flight warnings", script.name);
+            return None;
+        }
+
+        Some(format!(
+            "{} was skipped because of pre-flight warnings: {}",
+            script.name,
+            warnings.join(", ")
+        ))
+    }
+
+    // the notification supersedes the previous notifications about the scripts
+    pub fn replace_notifications(&mut self, script_names: &[String], id: u32) {
+        for script_name in script_names {
+            self.close_notifications(script_name);
+        }
+        for script_name in script_names {
+            self.notifications
+                .entry(script_name.clone())
+                .or_default()
+                .insert(id);
+        }
+    }
+
+    // closes the notifications about backups which have been removed from the settings
+    pub fn close_stale_notifications(&mut self) {
+        let settings = self.settings.load_full();
+        let removed = self
+            .notifications
+            .keys()
+            .filter(|name| {
+                !settings.scripts.iter().any(|script| &script.name == *name)
+                    && !settings.timers.iter().any(|timer| &timer.name == *name)
+                    && !settings
+                        .snapshots
+                        .iter()
+                        .any(|snapshots| &snapshots.name == *name)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for script_name in removed {
+            self.close_notifications(&script_name);
+        }
+    }
+
+    fn close_notifications(&mut self, script_name: &str) {
+        for id in self.forget_notifications(script_name) {
+            close_notification(id);
+        }
+    }
+
+    // notifications which are also about other scripts are kept
+    fn forget_notifications(&mut self, script_name: &str) -> Vec<u32> {
+        let Some(ids) = self.notifications.remove(script_name) else {
+            return Vec::new();
+        };
+        ids.into_iter()
+            .filter(|id| !self.notifications.values().any(|ids| ids.contains(id)))
+            .collect()
+    }
+
+    fn set_progress(&mut self, progress: Option<Progress>, handle: &impl TrayHandle<Tray>) {
+        if progress == self.progress {
+            return;
+        }
+        self.progress = progress;
+
+        if let Some(launcher_entry) = &self.launcher_entry {
+            launcher_entry.set_progress(
+                self.progress
+                    .as_ref()
+                    .map(|progress| f64::from(progress.percent) / 100.0),
+            );
         }
+
+        handle.update(TrayData {
+            tooltip: Some(self.tooltip()),
+            ..Default::default()
+        });
+    }
+
+    // polls the progress file while waiting for the script to finish, the running notification
+    // shows the elapsed time and is updated every minute
+    // returns `None` if the script was cancelled
+    // `work_dir_limit` is the work dir and its maximum size in bytes
+    #[allow(clippy::too_many_arguments)]
+    fn wait_with_progress(
+        &mut self,
+        script_name: &str,
+        mut child: Child,
+        progress_path: &Path,
+        work_dir_limit: Option<(&Path, u64)>,
+        handle: &impl TrayHandle<Tray>,
+        mut notification_handle: Option<&mut NotificationHandle>,
+        start: Instant,
+    ) -> std::io::Result<Wait> {
+        let mut last_notification_update = start;
+        let mut last_work_dir_check = start;
+        let mut stopped = None;
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            if take_cancel_request(script_name) {
+                match stop_script(&mut child) {
+                    Ok(()) => stopped = Some(Wait::Cancelled),
+                    Err(error) => log::warn!("failed to cancel script {script_name}: {error}"),
+                }
+            }
+
+            if let Some((work_dir, limit)) = work_dir_limit {
+                if stopped.is_none() && last_work_dir_check.elapsed() >= WORK_DIR_CHECK_INTERVAL {
+                    if dir_size(work_dir) > limit {
+                        log::warn!("stopping script {script_name}, its work dir is full");
+                        match stop_script(&mut child) {
+                            Ok(()) => stopped = Some(Wait::WorkDirFull),
+                            Err(error) => {
+                                log::warn!("failed to stop script {script_name}: {error}")
+                            }
+                        }
+                    }
+                    last_work_dir_check = Instant::now();
+                }
+            }
+
+            let progress = std::fs::read_to_string(progress_path)
+                .ok()
+                .and_then(|content| parse_progress(&content));
+            if progress.is_some() {
+                self.set_progress(progress, handle);
+            }
+
+            if let Some(notification_handle) = notification_handle.as_deref_mut() {
+                if last_notification_update.elapsed() >= NOTIFICATION_UPDATE_INTERVAL {
+                    notification_handle
+                        .body(&running_body(start.elapsed(), self.progress.as_ref()));
+                    notification_handle.update();
+                    last_notification_update = Instant::now();
+                }
+            }
+
+            thread::sleep(PROGRESS_POLL_INTERVAL);
+        };
+
+        self.set_progress(None, handle);
+
+        Ok(stopped.unwrap_or(Wait::Exited(status)))
     }
 
     fn script_state(&self, script: &Script) -> ScriptState {
@@ -62,508 +524,3175 @@ impl ScriptManager {
             {
                 ScriptState::WaitingForTime
             }
+            Some(ScriptState::WaitingForUser(_)) if !self.waiting_for_user() => {
+                ScriptState::WaitingForTime
+            }
+            Some(ScriptState::WaitingForPower(_)) if self.waiting_for_power(script).is_none() => {
+                ScriptState::WaitingForTime
+            }
+            Some(ScriptState::WaitingForIdle)
+                if !self.waiting_for_idle(script, self.clock.now()) =>
+            {
+                ScriptState::WaitingForTime
+            }
             Some(state) => state.clone(),
             None => ScriptState::WaitingForTime,
         }
     }
-}
-
-impl Manager for ScriptManager {
-    fn next_backup(&self) -> Option<DateTime<Utc>> {
-        let now = self.clock.now();
-        let settings = self.settings.load();
 
-        settings
-            .scripts
+    // the mounts only contain canonical paths
+    fn missing_paths(&self, script: &Script) -> Vec<PathBuf> {
+        script
+            .mount_paths
             .iter()
-            .filter_map(|script| match self.script_state(script) {
-                ScriptState::WaitingForTime => Some(next_backup(now, script)),
-                ScriptState::WaitingForPaths(_) | ScriptState::Running => None,
-                ScriptState::Failed(ts, _) => Some(ts + RETRY_INTERVAL),
-            })
-            .min()
+            .filter(|path| self.mounted_at(path).is_none())
+            .cloned()
+            .collect()
     }
 
-    fn next_reminder(&self) -> Option<DateTime<Utc>> {
-        let now = self.clock.now();
-        let settings = self.settings.load();
+    // where the mount path is mounted, `None` if it isn't
+    fn mounted_at(&self, path: &Path) -> Option<PathBuf> {
+        mounted_at(path, &self.mounts, &self.device_mounts)
+    }
 
-        settings
-            .scripts
+    // how long the mount paths of the script have been missing if it is longer than
+    // `missing-target-after`, e.g. the backup disk has been left in a drawer
+    fn target_missing_for(
+        &self,
+        settings: &Settings,
+        script: &Script,
+        now: DateTime<Utc>,
+    ) -> Option<Duration> {
+        let after = settings.reminder_notifications.missing_target_after?;
+        if self.missing_paths(script).is_empty() {
+            return None;
+        }
+
+        // the paths were mounted for the last backup
+        let seen = self
+            .targets_seen
+            .get(&script.name)
+            .copied()
+            .or(script.last_backup)?;
+        (seen + after <= now).then(|| now - seen)
+    }
+
+    // the mount watcher notices when the file systems have been mounted. Mounting can take long
+    // (e.g. network shares), so it is triggered in the background to keep the main loop responsive.
+    fn trigger_automounts(&self, paths: &[PathBuf]) {
+        let automounts = paths
             .iter()
-            .filter(|script| self.script_state(script) != ScriptState::Running)
-            .filter_map(|script| next_reminder(now, script))
-            .min()
+            .map(|path| canonical_path(path))
+            .filter(|path| self.automounts.contains(path))
+            .collect::<Vec<_>>();
+        if automounts.is_empty() {
+            return;
+        }
+
+        thread::spawn(move || {
+            for path in automounts {
+                log::debug!("triggering automount of `{}`", path.display());
+                let _ = fs::read_dir(&path);
+            }
+        });
     }
 
-    fn next_ui_update(&self) -> Option<DateTime<Utc>> {
-        let now = self.clock.now();
+    // an unknown active user does not block backups
+    fn waiting_for_user(&self) -> bool {
         let settings = self.settings.load();
-
         settings
-            .scripts
-            .iter()
-            .filter(|script| self.script_state(script) == ScriptState::WaitingForTime)
-            .map(|script| next_ui_update(now, script))
-            .min()
+            .primary_user
+            .as_ref()
+            .zip(self.active_user.as_ref())
+            .is_some_and(|(primary_user, active_user)| primary_user != active_user)
     }
 
-    fn tooltip(&self) -> String {
-        let mut items = Vec::new();
-
-        let settings = self.settings.load();
+    // the unmet power condition of the script, unknown power doesn't block backups
+    fn waiting_for_power(&self, script: &Script) -> Option<String> {
+        let power = self.power?;
+        if !power.on_battery {
+            return None;
+        }
 
-        if settings.scripts.is_empty() {
-            items.push("No backup scripts configured".to_string());
-        } else {
-            for script in &settings.scripts {
-                items.push(format!(
-                    "{}:\n{}",
-                    script.name,
-                    tooltip(
-                        &self.clock,
-                        script,
-                        self.states
-                            .get(&script.name)
-                            .unwrap_or(&ScriptState::WaitingForTime)
-                    )
-                ));
+        if script.require_ac_power {
+            return Some("AC power".to_string());
+        }
+        match (script.min_battery_percent, power.battery_percent) {
+            (Some(min), Some(percent)) if percent < min => {
+                Some(format!("the battery to be charged to {min}%"))
             }
+            _ => None,
         }
+    }
 
-        items.join("\n\n")
+    fn waiting_for_idle(&self, script: &Script, now: DateTime<Utc>) -> bool {
+        script.only_when_idle.is_some()
+            && self.idle_known
+            && self.idle_at(script).is_none_or(|at| at > now)
     }
 
-    fn set_mounts(&mut self, mounts: &str) {
-        let mounts = parse_mounts(mounts);
+    // when the user will have been idle for long enough, `None` while the user is active
+    fn idle_at(&self, script: &Script) -> Option<DateTime<Utc>> {
+        Some(self.idle_since? + script.only_when_idle?)
+    }
 
-        for mount in &self.mounts {
-            if !mounts.contains(mount) {
-                log::debug!("`{}` has been unmounted", mount.display());
-            }
+    fn decide(
+        &self,
+        settings: &Settings,
+        script: &Script,
+        script_name: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Option<Decision> {
+        if !script.enabled {
+            return None;
         }
 
-        for mount in &mounts {
-            if !self.mounts.contains(mount) {
-                log::debug!("`{}` has been mounted", mount.display());
-            }
+        let manual = script_name.is_some_and(|name| name == script.name);
+        let scheduled = script_name.is_none()
+            && next_backup(now, script) <= now
+            && settings.paused_until(now).is_none()
+            && self.startup_at(script).is_none_or(|at| at <= now);
+
+        if !manual && !scheduled {
+            return None;
         }
 
-        self.mounts = mounts;
+        let paths = self.missing_paths(script);
+        let power = self.waiting_for_power(script).filter(|_| scheduled);
+        if scheduled && self.waiting_for_user() {
+            Some(Decision::WaitForUser(
+                settings.primary_user.clone().unwrap_or_default(),
+            ))
+        } else if let Some(requirement) = power {
+            Some(Decision::WaitForPower(requirement))
+        } else if scheduled && self.waiting_for_idle(script, now) {
+            Some(Decision::WaitForIdle)
+        } else if paths.is_empty() {
+            Some(Decision::Run)
+        } else {
+            Some(Decision::WaitForPaths(paths))
+        }
     }
 
-    fn run(
-        &mut self,
-        script_name: Option<&str>,
-        handle: &impl TrayHandle<Tray>,
-    ) -> anyhow::Result<()> {
+    // decides like `run` without running any scripts, all runs are assumed to be successful
+    pub fn dry_run(&mut self) -> Vec<(String, Decision)> {
         let settings = self.settings.load_full();
+        let now = self.clock.now();
 
+        let mut decisions = Vec::new();
         for script in &settings.scripts {
-            let now = self.clock.now();
-
-            if script_name.is_some_and(|name| name == script.name)
-                || (script_name.is_none() && next_backup(now, script) <= now)
-            {
-                if script
-                    .mount_paths
-                    .iter()
-                    .all(|path| self.mounts.contains(path))
-                {
-                    log::info!("running backup script `{}`", script.name);
-
+            let Some(decision) = self.decide(&settings, script, None, now) else {
+                continue;
+            };
+
+            match &decision {
+                Decision::WaitForUser(user) => {
+                    self.states.insert(
+                        script.name.clone(),
+                        ScriptState::WaitingForUser(user.clone()),
+                    );
+                }
+                Decision::WaitForPower(requirement) => {
+                    self.states.insert(
+                        script.name.clone(),
+                        ScriptState::WaitingForPower(requirement.clone()),
+                    );
+                }
+                Decision::WaitForIdle => {
                     self.states
-                        .insert(script.name.clone(), ScriptState::Running);
-
-                    let mut notification_handle = Notification::new()
-                        .appname(&settings.title)
-                        .summary(&format!("Running {}", script.name))
-                        .icon(&settings.icon_name)
-                        .hint(Hint::Resident(true))
-                        .timeout(Timeout::Never)
-                        .show()?;
+                        .insert(script.name.clone(), ScriptState::WaitingForIdle);
+                }
+                Decision::Run => {
+                    let mut settings = Settings::clone(&self.settings.load());
+                    if let Some(script) =
+                        settings.scripts.iter_mut().find(|s| s.name == script.name)
+                    {
+                        script.last_backup = Some(now);
+                    }
+                    self.settings.store(Arc::new(settings));
+                    self.states
+                        .insert(script.name.clone(), ScriptState::WaitingForTime);
+                }
+                Decision::WaitForPaths(paths) => {
+                    self.states.insert(
+                        script.name.clone(),
+                        ScriptState::WaitingForPaths(paths.clone()),
+                    );
+                }
+            }
 
-                    handle.update(TrayData {
-                        status: Some(ksni::Status::Active),
-                        tooltip: Some(self.tooltip()),
-                        ..Default::default()
-                    });
+            decisions.push((script.name.clone(), decision));
+        }
 
-                    let tmp = write_script(&script.backup_script)?;
+        decisions
+    }
 
-                    let start = Instant::now();
+    fn script_next_backup(
+        &self,
+        settings: &Settings,
+        script: &Script,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if !script.enabled {
+            return None;
+        }
 
-                    let state;
-                    let summary;
-                    let body;
-                    match Command::new(tmp.path()).status() {
-                        Ok(status) => {
-                            if status.success() {
-                                let (run_duration, _) = round_duration(
-                                    Duration::from_std(start.elapsed())?,
-                                    RoundAccuracy::Seconds,
-                                    RoundDirection::Down,
-                                );
-                                summary = format!("{} finished", script.name);
-                                body = format!(
-                                    "Backup took {}",
-                                    humantime::format_duration(run_duration.to_std()?)
-                                );
-                                state = ScriptState::WaitingForTime;
+        let next_backup = match self.script_state(script) {
+            ScriptState::WaitingForTime => next_backup(now, script),
+            ScriptState::WaitingForPaths(_)
+            | ScriptState::WaitingForUser(_)
+            | ScriptState::WaitingForPower(_)
+            | ScriptState::Running => return None,
+            // wakes up once the user has been idle for long enough
+            ScriptState::WaitingForIdle => self.idle_at(script)?,
+            ScriptState::Failed(ts, _) | ScriptState::TargetUnusable(ts, _) => {
+                within_allowed_time(now, retry_at(ts, script), script)
+            }
+        };
+        let next_backup = self
+            .startup_at(script)
+            .map_or(next_backup, |at| next_backup.max(at));
+        Some(
+            settings
+                .paused_until(now)
+                .map_or(next_backup, |until| next_backup.max(until)),
+        )
+    }
 
-                                // get latest settings
-                                let mut settings = Arc::unwrap_or_clone(self.settings.load_full());
+    pub fn failures(&self, script_name: &str) -> u32 {
+        self.failures.get(script_name).copied().unwrap_or_default()
+    }
 
-                                // find script and update `last_backup`
-                                if let Some(script) =
-                                    settings.scripts.iter_mut().find(|s| s.name == script.name)
-                                {
-                                    script.last_backup = Some(self.clock.now());
-                                }
+    pub fn next_backup_of(&self, script_name: &str) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+        let script = settings
+            .scripts
+            .iter()
+            .find(|script| script.name == script_name)?;
+        self.script_next_backup(&settings, script, now)
+    }
 
-                                // save new settings
-                                settings.save()?;
-                            } else if let Some(code) = status.code() {
-                                summary = format!("{} failed with exit code {code}", script.name);
-                                body = String::new();
-                                state = ScriptState::Failed(self.clock.now(), summary.clone());
-                            } else {
-                                summary = format!("{} failed", script.name);
-                                body = String::new();
-                                state = ScriptState::Failed(self.clock.now(), summary.clone());
-                            }
-                        }
-                        Err(error) => {
-                            summary = format!("{} failed with error", script.name);
-                            body = error.to_string();
-                            state = ScriptState::Failed(self.clock.now(), error.to_string());
-                        }
-                    };
-
-                    self.states.insert(script.name.clone(), state);
+    // what keeps the script from running now, shown by `explain` and `next`
+    fn blockers(&self, settings: &Settings, script: &Script, now: DateTime<Utc>) -> Vec<String> {
+        let mut blockers = Vec::new();
+        if !script.enabled {
+            blockers.push("The script is disabled".to_string());
+        }
+        if let Some(paused_until) = settings.paused_until(now) {
+            blockers.push(format!(
+                "Backups are paused until {}",
+                format_time(paused_until)
+            ));
+        }
+        for path in self.missing_paths(script) {
+            blockers.push(format!("\"{}\" is not mounted", path.display()));
+        }
+        if let (true, Some(primary_user), Some(active_user)) = (
+            self.waiting_for_user(),
+            &settings.primary_user,
+            &self.active_user,
+        ) {
+            blockers.push(format!(
+                "{primary_user} is not the active user ({active_user} is)"
+            ));
+        }
+        if let Some(requirement) = self.waiting_for_power(script) {
+            blockers.push(format!("Waiting for {requirement}"));
+        }
+        if let (true, Some(threshold)) = (self.waiting_for_idle(script, now), script.only_when_idle)
+        {
+            blockers.push(match self.idle_since {
+                Some(since) => format!(
+                    "The user has been idle for {} of {}",
+                    format_duration(now - since),
+                    humantime::format_duration(threshold)
+                ),
+                None => "The user is active".to_string(),
+            });
+        }
+        if let Some(startup_at) = self.startup_at(script).filter(|at| *at > now) {
+            blockers.push(format!("Startup delay until {}", format_time(startup_at)));
+        }
+        if let Some(allowed) = waiting_for_allowed_time(now, script) {
+            blockers.push(format!(
+                "Outside of the allowed hours and days until {}",
+                format_time(allowed)
+            ));
+        }
+        match self.script_state(script) {
+            ScriptState::Running => blockers.push("The script is already running".to_string()),
+            ScriptState::Failed(ts, message) if retry_at(ts, script) > now => {
+                blockers.push(format!(
+                    "Failure cooldown until {} ({message})",
+                    format_time(retry_at(ts, script))
+                ));
+            }
+            ScriptState::TargetUnusable(ts, message) if retry_at(ts, script) > now => {
+                blockers.push(format!(
+                    "Target not usable, checked again at {} ({message})",
+                    format_time(retry_at(ts, script))
+                ));
+            }
+            _ => {}
+        }
 
-                    for action in &script.post_backup_actions {
-                        notification_handle.action(&action.label, &action.label);
-                    }
-                    notification_handle.summary(&summary);
-                    notification_handle.body(&body);
-                    notification_handle.timeout(Timeout::Milliseconds(6_000));
-                    notification_handle.update();
-                    notification_handle.wait_for_action(|action_label| {
-                        if let Some(action) = script
-                            .post_backup_actions
-                            .iter()
-                            .find(|action| action.label == action_label)
-                        {
-                            log::info!("running post backup script `{}`", action.label);
-
-                            let tmp = write_script(&action.script).unwrap();
-
-                            let summary;
-                            let body;
-                            match Command::new(tmp.path()).status() {
-                                Ok(status) => {
-                                    if status.success() {
-                                        summary = format!("{} finished", action.label);
-                                        body = String::new();
-                                    } else {
-                                        summary = format!("{} failed", action.label);
-                                        body = String::new();
-                                    }
-                                }
-                                Err(error) => {
-                                    summary = format!("{} failed with error", action.label);
-                                    body = error.to_string();
-                                }
-                            };
+        blockers
+    }
 
-                            Notification::new()
-                                .appname(&settings.title)
-                                .summary(&summary)
-                                .body(&body)
-                                .icon(&settings.icon_name)
-                                .timeout(Timeout::Milliseconds(6_000))
-                                .show()
-                                .unwrap();
-                        }
-                    });
-                } else {
-                    let paths = script
-                        .mount_paths
-                        .iter()
-                        .filter(|path| !self.mounts.contains(*path))
-                        .cloned()
-                        .collect::<Vec<_>>();
+    // the next run of each script, without running anything
+    pub fn next_runs(&self) -> Vec<NextRun> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+        settings
+            .scripts
+            .iter()
+            .map(|script| NextRun {
+                name: script.name.clone(),
+                next_run: self.script_next_backup(&settings, script, now),
+                blocked_by: self.blockers(&settings, script, now),
+            })
+            .collect()
+    }
 
-                    log::debug!(
-                        "waiting for folders {} to be mounted",
-                        paths
-                            .iter()
-                            .map(|path| format!("`{}`", path.display()))
-                            .join(", ")
+    // records the disk usage of the mount paths and warns if one is projected to be full soon
+    fn check_disk_usage(&mut self, settings: &Settings, script: &Script, trigger: &Trigger) {
+        let now = self.clock.now();
+        for path in &script.mount_paths {
+            // an unmounted path would report the file system it is on
+            let Some(mounted_at) = self.mounted_at(path) else {
+                continue;
+            };
+            let usage = match disk_usage(&mounted_at) {
+                Ok(usage) => usage,
+                Err(error) => {
+                    log::warn!(
+                        "failed to get the disk usage of `{}`: {error}",
+                        path.display()
                     );
-
-                    self.states
-                        .insert(script.name.clone(), ScriptState::WaitingForPaths(paths));
+                    continue;
                 }
+            };
+            history::record(
+                Entry {
+                    time: now,
+                    script: Some(script.name.clone()),
+                    action: Action::DiskUsage {
+                        path: path.clone(),
+                        used: usage.used,
+                        free: usage.free,
+                    },
+                    trigger: trigger.clone(),
+                },
+                settings.audit_journal,
+            );
+
+            let Some(horizon) = settings.disk_full_warning else {
+                continue;
+            };
+            // once a day per mount path
+            if self
+                .disk_full_warnings
+                .get(path)
+                .is_some_and(|ts| now - *ts < DISK_FULL_WARNING_INTERVAL)
+            {
+                continue;
             }
 
-            handle.update(TrayData {
-                tooltip: Some(self.tooltip()),
-                ..Default::default()
-            });
+            let samples = history::read()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|entry| now - entry.time <= DISK_USAGE_TREND_PERIOD)
+                .filter_map(|entry| match entry.action {
+                    Action::DiskUsage {
+                        path: sample_path,
+                        used,
+                        ..
+                    } if sample_path == *path => Some((entry.time, used)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let Some(full) = projected_full(&samples, usage.used + usage.free).filter(|full| {
+                *full - now <= Duration::from_std(horizon).unwrap_or(Duration::max_value())
+            }) else {
+                continue;
+            };
+
+            let summary = format!("{} is running out of space", path.display());
+            let body = format!(
+                "At the current rate it is full {}, {} are free. Prune old backups or use a \
+                 bigger disk.",
+                if full <= now {
+                    "already".to_string()
+                } else {
+                    format!("in about {}", format_duration(full - now))
+                },
+                format_bytes(usage.free)
+            );
+            log::warn!("{summary}: {body}");
+            if let Err(error) = notification::show(
+                Notification::new()
+                    .appname(&settings.title)
+                    .summary(&summary)
+                    .body(&body)
+                    .icon("dialog-warning"),
+            ) {
+                log::warn!("failed to show the disk full warning: {error}");
+            }
+            self.disk_full_warnings.insert(path.clone(), now);
         }
+    }
 
-        Ok(())
+    // the first problem of the mount paths, checked before the script is run so it doesn't fail
+    // halfway through
+    fn check_targets(&self, script: &Script) -> Option<String> {
+        script
+            .mount_paths
+            .iter()
+            .filter_map(|path| self.mounted_at(path))
+            .find_map(|path| target_problem(&path, script.min_free_space))
     }
-}
 
-fn parse_mounts(mounts: &str) -> HashSet<PathBuf> {
-    mounts
-        .lines()
-        .filter_map(
-            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
-                [_, mount, ..] => Some(PathBuf::from(mount)),
-                _ => None,
-            },
-        )
-        .collect()
-}
+    // when the script failed, `None` if it hasn't failed or the failure has been dismissed, by
+    // the user or after `clear-failures-after`
+    fn shown_failure(&self, settings: &Settings, script_name: &str) -> Option<DateTime<Utc>> {
+        let ts = match self.states.get(script_name)? {
+            ScriptState::Failed(ts, _) | ScriptState::TargetUnusable(ts, _) => *ts,
+            _ => return None,
+        };
+        let cleared = settings
+            .clear_failures_after
+            .is_some_and(|after| self.clock.now() >= ts + after);
+        (!cleared && self.dismissed_failures.get(script_name) != Some(&ts)).then_some(ts)
+    }
 
-fn write_script(script: &str) -> Result<NamedTempFile, anyhow::Error> {
-    let mut tmp = NamedTempFile::new()?;
-    tmp.write_all(script.as_bytes())?;
+    // the failure message is hidden once the failure has been dismissed or after
+    // `failure-tooltip-duration`, the retry is still scheduled like after the failure
+    fn tooltip_state(&self, settings: &Settings, script: &Script) -> ScriptState {
+        let state = self
+            .states
+            .get(&script.name)
+            .cloned()
+            .unwrap_or(ScriptState::WaitingForTime);
+        if !matches!(
+            state,
+            ScriptState::Failed(..) | ScriptState::TargetUnusable(..)
+        ) {
+            return state;
+        }
 
-    let metadata = tmp.as_file().metadata()?;
-    let mut permissions = metadata.permissions();
-    permissions.set_mode(0o700);
-    tmp.as_file().set_permissions(permissions)?;
+        let now = self.clock.now();
+        let hidden = self.shown_failure(settings, &script.name).is_none_or(|ts| {
+            settings
+                .failure_tooltip_duration
+                .is_some_and(|duration| now >= ts + duration)
+        });
+        if hidden {
+            ScriptState::WaitingForTime
+        } else {
+            state
+        }
+    }
 
-    Ok(tmp)
-}
+    // the disk usage of the mounted mount paths, by the path they are mounted at
+    fn target_usage(&self, script: &Script) -> Vec<(PathBuf, DiskUsage)> {
+        script
+            .mount_paths
+            .iter()
+            .filter_map(|path| {
+                let mounted_at = self.mounted_at(path)?;
+                let usage = disk_usage(&mounted_at).ok()?;
+                Some((mounted_at, usage))
+            })
+            .collect()
+    }
 
-fn next_backup(now: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
-    script
-        .last_backup
-        .as_ref()
-        .map_or(now, |last_backup| *last_backup + script.interval)
+    fn reminder_enabled(&self, script: &Script) -> bool {
+        script.enabled
+            && self.script_state(script) != ScriptState::Running
+            && (script.remind_when_target_missing || self.missing_paths(script).is_empty())
+    }
 }
 
-fn next_ui_update(now: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
-    let (_, next_backup_remainder) = round_duration(
-        next_backup(now, script).max(now) - now,
-        RoundAccuracy::Minutes,
-        RoundDirection::Down,
-    );
+impl Manager for ScriptManager {
+    fn next_backup(&self) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
 
-    let last_backup_remainder = script.last_backup.map(|last_backup| {
-        let (_, remainder) = round_duration(
-            now - last_backup.min(now),
-            RoundAccuracy::Minutes,
-            RoundDirection::Up,
-        );
-        remainder
-    });
+        settings
+            .scripts
+            .iter()
+            .filter_map(|script| self.script_next_backup(&settings, script, now))
+            .min()
+    }
 
-    let remainder =
-        last_backup_remainder.map_or(next_backup_remainder, |r| r.min(next_backup_remainder));
+    fn next_reminder(&self) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
 
-    now + remainder + Duration::milliseconds(1)
-}
+        settings
+            .scripts
+            .iter()
+            .filter(|script| self.reminder_enabled(script))
+            .filter_map(|script| next_reminder(now, script))
+            .min()
+            .map(|ts| settings.paused_until(now).map_or(ts, |until| ts.max(until)))
+    }
 
-fn next_reminder(now: DateTime<Utc>, script: &Script) -> Option<DateTime<Utc>> {
-    let reminder = script.reminder?;
-    let next_reminder = script
-        .last_backup
-        .as_ref()
-        .map_or(now, |last_backup| *last_backup + reminder);
-    Some(next_reminder)
-}
+    fn next_ui_update(&self) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
 
-fn tooltip(clock: &Clock, script: &Script, state: &ScriptState) -> String {
-    let last_backup = if let Some(last_backup) = script.last_backup {
-        let now = clock.now();
-        let (last_backup, _) = round_duration(
-            now - last_backup.min(now),
-            RoundAccuracy::Minutes,
-            RoundDirection::Down,
-        );
-        format!(
-            "Last backup was {} ago",
-            humantime::format_duration(last_backup.to_std().unwrap())
-        )
-    } else {
-        "Never backed up before".to_string()
-    };
+        // when failures disappear from the tooltip or are cleared
+        let failure_updates = settings
+            .scripts
+            .iter()
+            .filter_map(|script| self.shown_failure(&settings, &script.name))
+            .flat_map(|ts| {
+                [
+                    settings.failure_tooltip_duration,
+                    settings.clear_failures_after,
+                ]
+                .into_iter()
+                .flatten()
+                .map(move |duration| ts + duration)
+            })
+            .filter(|ts| *ts > now);
 
-    let status = match state {
-        ScriptState::WaitingForTime => {
-            let now = clock.now();
-            let (next_backup, _) = round_duration(
-                next_backup(now, script).max(now) - now,
-                RoundAccuracy::Minutes,
-                RoundDirection::Down,
-            );
-            format!(
-                "Next backup in {}",
-                humantime::format_duration(next_backup.to_std().unwrap())
-            )
+        settings
+            .scripts
+            .iter()
+            .filter(|script| {
+                script.enabled && self.script_state(script) == ScriptState::WaitingForTime
+            })
+            .map(|script| next_ui_update(now, script))
+            .chain(failure_updates)
+            .min()
+    }
+
+    fn tooltip(&self) -> String {
+        let mut items = Vec::new();
+
+        let settings = self.settings.load();
+
+        if let Some(paused_until) = settings.paused_until(self.clock.now()) {
+            items.push(format!(
+                "Backups are paused until {}",
+                paused_until.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            ));
         }
-        ScriptState::WaitingForPaths(paths) => {
-            format!(
-                "Waiting for folders {} to be mounted",
-                paths
-                    .iter()
-                    .map(|path| format!("\"{}\"", path.display()))
-                    .join(", ")
-            )
+        if let Some(startup_until) = self.startup_until.filter(|until| *until > self.clock.now()) {
+            items.push(format!(
+                "Scheduled backups start after {}",
+                startup_until.with_timezone(&Local).format("%H:%M")
+            ));
         }
-        ScriptState::Running => "Running".to_string(),
-        ScriptState::Failed(_, message) => format!("Failed: {message}",),
-    };
 
-    format!("{last_backup}\n{status}")
-}
+        if settings.scripts.is_empty()
+            && settings.timers.is_empty()
+            && settings.snapshots.is_empty()
+        {
+            let mut item = "No backup scripts configured".to_string();
+            if let Ok(path) = settings_file_path() {
+                item.push_str(&format!("\nAdd them to {}", path.display()));
+            }
+            item.push_str(&format!(
+                "\nSee {} for instructions",
+                env!("CARGO_PKG_HOMEPAGE")
+            ));
+            items.push(item);
+        } else {
+            for script in &settings.scripts {
+                let mut item = format!(
+                    "{}:\n{}",
+                    script.name,
+                    tooltip(
+                        &self.clock,
+                        script,
+                        &self.tooltip_state(&settings, script),
+                        self.progress.as_ref()
+                    )
+                );
+                for (path, usage) in self.target_usage(script) {
+                    item.push_str(&format!(
+                        "\n{}",
+                        format_usage(&path, usage, settings.low_space_warning)
+                    ));
+                }
+                for warning in settings
+                    .lint_warnings
+                    .get(&script.name)
+                    .into_iter()
+                    .flatten()
+                {
+                    item.push_str(&format!("\nWarning: {warning}"));
+                }
+                items.push(item);
+            }
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use fake::{Fake, Faker};
-    use indoc::indoc;
-    use serde::Deserialize;
-    use std::{cmp::max, fs::File, time::Duration};
+        items.join("\n\n")
+    }
 
-    #[derive(Debug, Deserialize)]
-    struct ScheduleTestScript {
-        pub mount_paths: Vec<PathBuf>,
+    fn low_space(&self) -> bool {
+        let settings = self.settings.load();
+        let Some(low_space_warning) = settings.low_space_warning else {
+            return false;
+        };
 
-        #[serde(with = "humantime_serde")]
-        pub interval: Duration,
+        settings
+            .scripts
+            .iter()
+            .filter(|script| script.enabled)
+            .flat_map(|script| self.target_usage(script))
+            .any(|(_, usage)| usage.free < low_space_warning)
+    }
 
-        #[serde(default, with = "humantime_serde")]
-        pub reminder: Option<Duration>,
+    fn overdue(&self) -> Vec<Overdue> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
 
-        #[serde(default, with = "humantime_serde")]
-        pub last_backup: Option<Duration>,
+        if settings.paused_until(now).is_some() {
+            return Vec::new();
+        }
 
-        pub state: Option<String>,
+        settings
+            .scripts
+            .iter()
+            .filter(|script| self.reminder_enabled(script))
+            .filter_map(|script| {
+                let next_reminder = next_reminder(now, script).filter(|ts| *ts <= now)?;
+                Some(Overdue {
+                    name: script.name.clone(),
+                    overdue: script.last_backup.map(|_| now - next_reminder),
+                    missing_paths: if script.plug_in_reminder {
+                        self.missing_paths(script)
+                    } else {
+                        Vec::new()
+                    },
+                    target_missing_for: self.target_missing_for(&settings, script, now),
+                })
+            })
+            .collect()
     }
 
-    impl ScheduleTestScript {
-        fn into_script(self, clock: &Clock) -> Script {
-            Script {
-                name: Faker.fake(),
-                icon_name: None,
-                backup_script: "#!/bin/bash".to_string(),
-                mount_paths: self.mount_paths,
-                interval: self.interval,
-                reminder: self.reminder,
-                post_backup_actions: Vec::new(),
-                last_backup: self.last_backup.map(|delta| clock.now() - delta),
+    fn script_status(&self, script_name: &str) -> ScriptStatus {
+        let settings = self.settings.load();
+        let disabled = settings
+            .scripts
+            .iter()
+            .any(|script| script.name == script_name && !script.enabled);
+        match self.states.get(script_name) {
+            _ if disabled => ScriptStatus::Disabled,
+            Some(ScriptState::Running) => ScriptStatus::Running,
+            Some(ScriptState::Failed(_, _) | ScriptState::TargetUnusable(_, _))
+                if self.shown_failure(&settings, script_name).is_some() =>
+            {
+                ScriptStatus::Failed
             }
+            _ if self.overdue().iter().any(|item| item.name == script_name) => {
+                ScriptStatus::Overdue
+            }
+            _ => ScriptStatus::Ok,
         }
     }
 
-    #[derive(Debug, Deserialize)]
-    struct ScheduleTestCase {
-        scripts: Vec<ScheduleTestScript>,
+    fn explain(&self, script_name: &str) -> Option<String> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+        let script = settings
+            .scripts
+            .iter()
+            .find(|script| script.name == script_name)?;
+        let state = self.script_state(script);
+        let blockers = self.blockers(&settings, script, now);
+
+        let last_backup = script.last_backup.map_or("never".to_string(), |ts| {
+            format!(
+                "{} ({} ago)",
+                format_time(ts),
+                format_duration(now - ts.min(now))
+            )
+        });
+        let reminder = match script.reminder {
+            Some(reminder) => humantime::format_duration(reminder).to_string(),
+            None => "none".to_string(),
+        };
+        let state = match state {
+            ScriptState::WaitingForTime => "waiting for time".to_string(),
+            ScriptState::WaitingForPaths(_) => "waiting for folders to be mounted".to_string(),
+            ScriptState::WaitingForUser(user) => {
+                format!("waiting for {user} to be the active user")
+            }
+            ScriptState::WaitingForPower(requirement) => format!("waiting for {requirement}"),
+            ScriptState::WaitingForIdle => "waiting for the user to be idle".to_string(),
+            ScriptState::Running => "running".to_string(),
+            ScriptState::Failed(ts, message) => format!("failed at {}: {message}", format_time(ts)),
+            ScriptState::TargetUnusable(ts, message) => {
+                format!("target not usable at {}: {message}", format_time(ts))
+            }
+        };
+        let next_run = match self.script_next_backup(&settings, script, now) {
+            Some(ts) if ts <= now => "now".to_string(),
+            Some(ts) => format!("{} (in {})", format_time(ts), format_duration(ts - now)),
+            None => "as soon as it is no longer blocked".to_string(),
+        };
+        let next_reminder = match next_reminder(now, script) {
+            Some(ts) if self.reminder_enabled(script) => format_time(ts.max(now)),
+            Some(_) | None => "none".to_string(),
+        };
+
+        let mut lines = vec![
+            format!("{}:", script.name),
+            format!("Last backup: {last_backup}"),
+            match (&script.schedule, script.interval) {
+                (Some(schedule), _) => format!("Schedule: {schedule}"),
+                (None, Some(interval)) => {
+                    format!("Interval: {}", humantime::format_duration(interval))
+                }
+                (None, None) => "Interval: none".to_string(),
+            },
+            format!("Reminder: {reminder}"),
+            format!("State: {state}"),
+            format!("Next run: {next_run}"),
+            format!("Next reminder: {next_reminder}"),
+        ];
+        if blockers.is_empty() {
+            lines.push("Blocked by: nothing".to_string());
+        } else {
+            lines.push("Blocked by:".to_string());
+            lines.extend(blockers.into_iter().map(|blocker| format!("  - {blocker}")));
+        }
+
+        Some(lines.join("\n"))
+    }
+
+    fn set_mounts(&mut self, mounts: &str) {
+        let device_mounts = device_mounts(mounts);
+        let (mounts, automounts) = parse_mounts(mounts);
+
+        let now = self.clock.now();
+        let settings = self.settings.load();
+        for script in &settings.scripts {
+            let was_mounted =
+                !script.mount_paths.is_empty() && self.missing_paths(script).is_empty();
+            let unmounted = script
+                .mount_paths
+                .iter()
+                .any(|path| mounted_at(path, &mounts, &device_mounts).is_none());
+            if was_mounted && unmounted {
+                self.targets_seen.insert(script.name.clone(), now);
+                if let Err(error) = record_targets_seen(&script.name, now) {
+                    log::error!("failed to save state: {error:#}");
+                }
+            }
+        }
+
+        for mount in &self.mounts {
+            if !mounts.contains(mount) {
+                log::debug!("`{}` has been unmounted", mount.display());
+            }
+        }
+
+        for mount in &mounts {
+            if !self.mounts.contains(mount) {
+                log::debug!("`{}` has been mounted", mount.display());
+            }
+        }
+
+        self.mounts = mounts;
+        self.automounts = automounts;
+        self.device_mounts = device_mounts;
+    }
+
+    fn set_active_user(&mut self, user: Option<String>) {
+        self.active_user = user;
+    }
+
+    fn set_power(&mut self, power: Option<Power>) {
+        self.power = power;
+    }
+
+    fn set_idle_since(&mut self, idle_since: Option<DateTime<Utc>>) {
+        self.idle_since = idle_since;
+        self.idle_known = true;
+    }
+
+    fn dismiss_failure(&mut self, script_name: &str) {
+        if let Some(ScriptState::Failed(ts, _) | ScriptState::TargetUnusable(ts, _)) =
+            self.states.get(script_name)
+        {
+            self.dismissed_failures.insert(script_name.to_string(), *ts);
+            self.close_notifications(script_name);
+        }
+    }
+
+    fn run(
+        &mut self,
+        script_name: Option<&str>,
+        trigger: &Trigger,
+        handle: &impl TrayHandle<Tray>,
+    ) -> anyhow::Result<Vec<(String, RunOutcome)>> {
+        let settings = self.settings.load_full();
+
+        let mut outcomes = Vec::new();
+        // summaries and bodies of the success notifications, shown at once after all scripts
+        let mut finished = Vec::new();
+        // UUIDs of the file systems to unmount after all scripts, others may use them too
+        let mut unmount_after = HashSet::new();
+        for script in &settings.scripts {
+            let now = self.clock.now();
+
+            match self.decide(&settings, script, script_name, now) {
+                None => {}
+                Some(Decision::WaitForUser(user)) => {
+                    log::debug!("waiting for `{user}` to be the active user");
+
+                    outcomes.push((
+                        script.name.clone(),
+                        RunOutcome::WaitingForUser(user.clone()),
+                    ));
+
+                    self.states
+                        .insert(script.name.clone(), ScriptState::WaitingForUser(user));
+                }
+                Some(Decision::WaitForPower(requirement)) => {
+                    log::debug!("waiting for {requirement}");
+
+                    outcomes.push((
+                        script.name.clone(),
+                        RunOutcome::WaitingForPower(requirement.clone()),
+                    ));
+
+                    self.states.insert(
+                        script.name.clone(),
+                        ScriptState::WaitingForPower(requirement),
+                    );
+                }
+                Some(Decision::WaitForIdle) => {
+                    let threshold = script.only_when_idle.unwrap_or_default();
+
+                    log::debug!(
+                        "waiting for the user to be idle for {}",
+                        humantime::format_duration(threshold)
+                    );
+
+                    outcomes.push((script.name.clone(), RunOutcome::WaitingForIdle(threshold)));
+
+                    self.states
+                        .insert(script.name.clone(), ScriptState::WaitingForIdle);
+                }
+                Some(Decision::Run) => {
+                    if let Some(message) = self.check_targets(script) {
+                        log::warn!("{} wasn't started: {message}", script.name);
+                        outcomes.push((script.name.clone(), RunOutcome::Failed(message.clone())));
+                        if let Err(error) = notification::show(
+                            Notification::new()
+                                .appname(&settings.title)
+                                .summary(&format!("{} wasn't started", script.name))
+                                .body(&message)
+                                .icon("dialog-warning"),
+                        ) {
+                            log::warn!("failed to show notification: {error}");
+                        }
+                        self.states.insert(
+                            script.name.clone(),
+                            ScriptState::TargetUnusable(self.clock.now(), message),
+                        );
+                        handle.update(TrayData {
+                            tooltip: Some(self.tooltip()),
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+
+                    if let Some(message) = self.preflight(&settings, script, trigger) {
+                        log::warn!("{message}");
+                        outcomes.push((script.name.clone(), RunOutcome::Failed(message.clone())));
+                        // retried after the cooldown, which runs the checks again
+                        self.states.insert(
+                            script.name.clone(),
+                            ScriptState::Failed(self.clock.now(), message),
+                        );
+                        handle.update(TrayData {
+                            tooltip: Some(self.tooltip()),
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+
+                    let run_id = new_run_id();
+                    log::info!("running backup script `{}` ({run_id})", script.name);
+
+                    // released when the run is over
+                    let _sleep_lock = if script.inhibit_sleep {
+                        inhibit_sleep(&settings.title, &format!("Running {}", script.name))
+                            .inspect_err(|error| {
+                                log::warn!("failed to keep the computer awake: {error}")
+                            })
+                            .ok()
+                    } else {
+                        None
+                    };
+                    let _running = RunningScript::new(&script.name);
+                    if let Some(auto_mount) = script.auto_mount.as_ref().filter(|auto| auto.unmount)
+                    {
+                        unmount_after.insert(auto_mount.uuid.clone());
+                    }
+
+                    self.states
+                        .insert(script.name.clone(), ScriptState::Running);
+
+                    // discard requests made while the script wasn't running
+                    take_cancel_request(&script.name);
+
+                    if let Some(dbus_service) = &self.dbus_service {
+                        dbus_service.backup_started(&script.name);
+                    }
+                    call_webhooks(
+                        &settings.webhooks,
+                        WebhookEvent::Started,
+                        script,
+                        &format!("Running {}", script.name),
+                        now,
+                    );
+
+                    let mut notification_handle = notification::show(
+                        Notification::new()
+                            .appname(&settings.title)
+                            .summary(&format!("Running {}", script.name))
+                            .icon(&settings.icon_name)
+                            .hint(Hint::Resident(true))
+                            .timeout(Timeout::Never),
+                    )?;
+
+                    handle.update(TrayData {
+                        status: Some(ksni::Status::Active),
+                        tooltip: Some(self.tooltip()),
+                        ..Default::default()
+                    });
+
+                    let dir = run_dir()?;
+                    let tmp = write_script(
+                        dir.path(),
+                        &render_script(
+                            &settings.backup_script(script),
+                            &script_variables(script, self.clock.now()),
+                        ),
+                    )?;
+
+                    let progress_file = NamedTempFile::new_in(dir.path())?;
+                    let chain_file = NamedTempFile::new_in(dir.path())?;
+                    let work_dir = work_dir(&script.name)?;
+
+                    // e.g. where UDisks2 has mounted a file system selected by its label
+                    let mount_paths = script
+                        .mount_paths
+                        .iter()
+                        .filter_map(|path| self.mounted_at(path))
+                        .collect::<Vec<_>>();
+
+                    let mut command = script_command(&tmp, script.privileged)?;
+                    if let Some(target) = mount_paths.first() {
+                        command.env("BM_TARGET", target);
+                    }
+                    // without a log file the output goes to the log of the backup monitor
+                    let logged = match create_log_file(&script.name, &run_id) {
+                        Ok(log_file) => {
+                            command.stdout(log_file.try_clone()?).stderr(log_file);
+                            true
+                        }
+                        Err(error) => {
+                            log::warn!("failed to create log file: {error}");
+                            false
+                        }
+                    };
+
+                    let start = Instant::now();
+                    // to compare with modification times
+                    let started = SystemTime::now();
+
+                    let state;
+                    let summary;
+                    let body;
+                    let mut exit_code = None;
+                    match command
+                        .env("BM_PROGRESS_FILE", progress_file.path())
+                        .env("BM_WORK_DIR", work_dir.path())
+                        .env("BM_CHAIN_FILE", chain_file.path())
+                        .env("BM_RUN_ID", &run_id)
+                        .process_group(0)
+                        .spawn()
+                        .and_then(|child| {
+                            self.wait_with_progress(
+                                &script.name,
+                                child,
+                                progress_file.path(),
+                                script.work_dir_limit.map(|limit| {
+                                    (work_dir.path(), limit.saturating_mul(1024 * 1024))
+                                }),
+                                handle,
+                                notification_handle.as_mut(),
+                                start,
+                            )
+                        }) {
+                        Ok(Wait::Cancelled) => {
+                            summary = format!("{} was cancelled", script.name);
+                            body = String::new();
+                            state = ScriptState::Failed(self.clock.now(), summary.clone());
+                        }
+                        Ok(Wait::WorkDirFull) => {
+                            summary = format!("{} filled its work dir", script.name);
+                            body = format!(
+                                "The work dir grew larger than {} MiB",
+                                script.work_dir_limit.unwrap_or_default()
+                            );
+                            state = ScriptState::Failed(self.clock.now(), summary.clone());
+                        }
+                        Ok(Wait::Exited(status)) => {
+                            exit_code = status.code();
+                            let unmet = match &script.success_when {
+                                Some(success_when) if status.success() => {
+                                    // the log of the last run
+                                    let output = logged
+                                        .then(|| {
+                                            fs::read_to_string(log_file_path(&script.name).ok()?)
+                                                .ok()
+                                        })
+                                        .flatten();
+                                    unmet_conditions(success_when, started, output.as_deref())
+                                }
+                                _ => Vec::new(),
+                            };
+                            if !unmet.is_empty() {
+                                summary =
+                                    format!("{} didn't meet its success criteria", script.name);
+                                body = unmet.join("\n");
+                                state = ScriptState::Failed(self.clock.now(), summary.clone());
+                            } else if status.success() {
+                                let (run_duration, _) = round_duration(
+                                    Duration::from_std(start.elapsed())?,
+                                    RoundAccuracy::Seconds,
+                                    RoundDirection::Down,
+                                );
+                                let (failed_steps, mandatory_failed) = run_post_backup_steps(
+                                    &settings,
+                                    script,
+                                    dir.path(),
+                                    self.clock.now(),
+                                );
+
+                                if mandatory_failed
+                                    && script.commit_last_backup
+                                        == CommitLastBackup::AfterMandatorySteps
+                                {
+                                    summary = failed_steps.last().cloned().unwrap_or_default();
+                                    body = failed_steps.join("\n");
+                                    state = ScriptState::Failed(self.clock.now(), summary.clone());
+                                } else {
+                                    summary = format!("{} finished", script.name);
+                                    body = [format!(
+                                        "Backup took {}",
+                                        humantime::format_duration(run_duration.to_std()?)
+                                    )]
+                                    .into_iter()
+                                    .chain(failed_steps)
+                                    .join("\n");
+                                    state = ScriptState::WaitingForTime;
+
+                                    // find script and update `last_backup`
+                                    let now = self.clock.now();
+                                    let message = format!(
+                                        "last-backup of `{}` updated by run {run_id}",
+                                        script.name
+                                    );
+                                    update_settings(&self.settings, &message, |settings| {
+                                        if let Some(s) = settings
+                                            .scripts
+                                            .iter_mut()
+                                            .find(|s| s.name == script.name)
+                                        {
+                                            s.last_backup = Some(now);
+                                        }
+                                    });
+                                }
+                            } else if script.privileged
+                                && not_authorized(
+                                    status.code(),
+                                    &fs::read_to_string(status_file_path(&tmp)).unwrap_or_default(),
+                                )
+                            {
+                                summary = format!("{} was not authorized", script.name);
+                                body = String::new();
+                                state = ScriptState::Failed(self.clock.now(), summary.clone());
+                            } else if let Some(code) = status.code() {
+                                summary = format!("{} failed with exit code {code}", script.name);
+                                body = String::new();
+                                state = ScriptState::Failed(self.clock.now(), summary.clone());
+                            } else {
+                                summary = format!("{} failed", script.name);
+                                body = String::new();
+                                state = ScriptState::Failed(self.clock.now(), summary.clone());
+                            }
+                        }
+                        Err(error) => {
+                            summary = format!("{} failed with error", script.name);
+                            body = error.to_string();
+                            state = ScriptState::Failed(self.clock.now(), error.to_string());
+                        }
+                    };
+
+                    let chain = fs::read_to_string(chain_file.path())
+                        .ok()
+                        .and_then(|content| parse_chain(&content));
+
+                    // the script may contain secrets
+                    drop(dir);
+
+                    keep_work_dir(
+                        &script.name,
+                        work_dir,
+                        matches!(state, ScriptState::Failed(..)),
+                    );
+
+                    let (summary, body) = match (&state, &script.failure_message) {
+                        (ScriptState::Failed(_, message), Some(template)) => {
+                            let now = self.clock.now();
+                            let overdue = next_reminder(now, script)
+                                .filter(|ts| *ts <= now)
+                                .map(|ts| now - ts);
+                            let mut values = script_values(script, overdue);
+                            values.push(("error", message.clone()));
+                            (render(template, &values), message.clone())
+                        }
+                        _ => (summary, body),
+                    };
+                    // to find the log and history entry of the run
+                    let body = if body.is_empty() {
+                        format!("Run {run_id}")
+                    } else {
+                        format!("{body}\nRun {run_id}")
+                    };
+
+                    history::record(
+                        Entry {
+                            time: self.clock.now(),
+                            script: Some(script.name.clone()),
+                            action: Action::Run {
+                                success: state == ScriptState::WaitingForTime,
+                                message: summary.clone(),
+                                run_id: Some(run_id.clone()),
+                            },
+                            trigger: trigger.clone(),
+                        },
+                        settings.audit_journal,
+                    );
+                    self.check_disk_usage(&settings, script, trigger);
+
+                    let failed = state != ScriptState::WaitingForTime;
+                    let outcome = match &state {
+                        ScriptState::Failed(_, message) => RunOutcome::Failed(message.clone()),
+                        _ => RunOutcome::Succeeded,
+                    };
+                    let error = match &outcome {
+                        RunOutcome::Failed(message) => Some(message.as_str()),
+                        _ => None,
+                    };
+                    if let Err(error) = record_result(&script.name, error) {
+                        log::error!("failed to save state: {error:#}");
+                    }
+                    if error.is_none() && !script.mount_paths.is_empty() {
+                        let now = self.clock.now();
+                        self.targets_seen.insert(script.name.clone(), now);
+                        if let Err(error) = record_targets_seen(&script.name, now) {
+                            log::error!("failed to save state: {error:#}");
+                        }
+                        record_targets(&script.name, &mount_paths, now);
+                    }
+                    if let Some(chain) = chain.filter(|_| error.is_none()) {
+                        check_chain(&settings, script, &chain);
+                    }
+                    outcomes.push((script.name.clone(), outcome));
+                    self.states.insert(script.name.clone(), state);
+
+                    if let Some(dbus_service) = &self.dbus_service {
+                        dbus_service.backup_finished(&script.name, !failed);
+                    }
+                    let event = if failed {
+                        WebhookEvent::Failed
+                    } else {
+                        WebhookEvent::Succeeded
+                    };
+                    call_webhooks(
+                        &settings.webhooks,
+                        event,
+                        script,
+                        &summary,
+                        self.clock.now(),
+                    );
+
+                    if failed {
+                        if settings.broadcast_failures {
+                            broadcast(&settings.title, &summary, &body);
+                        }
+                        let failures = self.failures.entry(script.name.clone()).or_default();
+                        *failures += 1;
+                        // once per series of failures
+                        if let Some(email) = settings
+                            .email
+                            .as_ref()
+                            .filter(|email| email.after_failures == *failures)
+                        {
+                            send_failure_email(email, &script.name, *failures, &summary);
+                        }
+                    } else {
+                        self.failures.remove(&script.name);
+                    }
+
+                    let message =
+                        format!("last-attempt of `{}` updated by run {run_id}", script.name);
+                    update_settings(&self.settings, &message, |settings| {
+                        if let Some(s) = settings.scripts.iter_mut().find(|s| s.name == script.name)
+                        {
+                            s.last_attempt = Some(now);
+                        }
+                    });
+
+                    log::info!("{summary}");
+
+                    if !failed {
+                        self.close_notifications(&script.name);
+                    }
+
+                    // the actions need a notification of their own
+                    let notification_handle = match notification_handle {
+                        Some(notification_handle)
+                            if !failed && script.post_backup_actions.is_empty() =>
+                        {
+                            notification_handle.close();
+                            finished.push((summary.clone(), body.clone()));
+                            None
+                        }
+                        notification_handle => notification_handle,
+                    };
+
+                    if let Some(mut notification_handle) = notification_handle {
+                        let details = failure_details(
+                            &summary,
+                            &body,
+                            exit_code,
+                            log_file_path(&script.name).ok().as_deref(),
+                        );
+                        if failed {
+                            notification_handle.action(COPY_DETAILS_ACTION, "Copy details");
+                        }
+                        for action in &script.post_backup_actions {
+                            notification_handle.action(&action.label, &action.label);
+                        }
+                        notification_handle.summary(&summary);
+                        notification_handle.body(&body);
+                        notification_handle.timeout(Timeout::Milliseconds(6_000));
+                        notification_handle.update();
+                        if failed {
+                            self.replace_notifications(
+                                std::slice::from_ref(&script.name),
+                                notification_handle.id(),
+                            );
+                        }
+                        let clock = self.clock;
+                        notification_handle.wait_for_action(|action_label| {
+                            if action_label == COPY_DETAILS_ACTION {
+                                if let Err(error) = clipboard::copy(&details) {
+                                    log::warn!("failed to copy the details: {error:#}");
+                                }
+                                return;
+                            }
+
+                            if let Some(action) = script
+                                .post_backup_actions
+                                .iter()
+                                .find(|action| action.label == action_label)
+                            {
+                                log::info!("running post backup script `{}`", action.label);
+
+                                let result = run_action(
+                                    &settings,
+                                    &action.label,
+                                    &render_script(
+                                        &settings.script_with_defaults(&action.script),
+                                        &script_variables(script, clock.now()),
+                                    ),
+                                );
+                                if let Err(error) = result {
+                                    log::warn!(
+                                        "failed to run post backup script `{}`: {error:#}",
+                                        action.label
+                                    );
+                                }
+                            }
+                        });
+                    }
+                }
+                Some(Decision::WaitForPaths(paths)) => {
+                    log::debug!(
+                        "waiting for folders {} to be mounted",
+                        paths
+                            .iter()
+                            .map(|path| format!("`{}`", path.display()))
+                            .join(", ")
+                    );
+
+                    self.trigger_automounts(&paths);
+                    if let Some(auto_mount) = &script.auto_mount {
+                        request_mount(auto_mount);
+                    }
+
+                    outcomes.push((
+                        script.name.clone(),
+                        RunOutcome::WaitingForPaths(paths.clone()),
+                    ));
+                    self.states
+                        .insert(script.name.clone(), ScriptState::WaitingForPaths(paths));
+                }
+            }
+
+            handle.update(TrayData {
+                tooltip: Some(self.tooltip()),
+                ..Default::default()
+            });
+        }
+
+        unmount(&unmount_after);
+
+        if let Some((summary, body)) = merge_finished(finished) {
+            notification::show(
+                Notification::new()
+                    .appname(&settings.title)
+                    .summary(&summary)
+                    .body(&body)
+                    .icon(&settings.icon_name)
+                    .timeout(Timeout::Milliseconds(6_000)),
+            )?;
+        }
+
+        Ok(outcomes)
+    }
+}
+
+// many scripts may finish at once (e.g. after a long time offline), they share a notification
+// instead of showing a wall of them
+fn merge_finished(mut finished: Vec<(String, String)>) -> Option<(String, String)> {
+    if finished.len() <= 1 {
+        return finished.pop();
+    }
+
+    let summary = format!("{} backups finished", finished.len());
+    let body = finished
+        .into_iter()
+        .map(|(summary, body)| {
+            if body.is_empty() {
+                summary
+            } else {
+                format!("{summary}: {}", body.replace('\n', ", "))
+            }
+        })
+        .join("\n");
+    Some((summary, body))
+}
+
+// returns the mounted file systems and the autofs mount points, which are listed in addition to
+// the file systems mounted on them
+fn parse_mounts(mounts: &str) -> (HashSet<PathBuf>, HashSet<PathBuf>) {
+    let mut file_systems = HashSet::new();
+    let mut automounts = HashSet::new();
+
+    for line in mounts.lines() {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [_, mount, "autofs", ..] => {
+                automounts.insert(unescape_mount_path(mount));
+            }
+            [_, mount, ..] => {
+                file_systems.insert(unescape_mount_path(mount));
+            }
+            _ => {}
+        }
+    }
+
+    (file_systems, automounts)
+}
+
+// spaces, tabs, newlines and backslashes are escaped as octal numbers, e.g. `\040` for a space
+pub fn unescape_mount_path(mount: &str) -> PathBuf {
+    let bytes = mount.as_bytes();
+    let mut path = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let code = bytes
+            .get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        if let Some(code) = code {
+            path.push(code);
+            i += 4;
+        } else {
+            path.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    normalize_path(Path::new(OsStr::from_bytes(&path)))
+}
+
+// mount paths selecting a file system by UUID or label are resolved to where it is mounted
+fn mounted_at(
+    path: &Path,
+    mounts: &HashSet<PathBuf>,
+    device_mounts: &HashMap<PathBuf, PathBuf>,
+) -> Option<PathBuf> {
+    match Selector::parse(path) {
+        Some(_) => resolve_mount_path(path, device_mounts),
+        None => mounts
+            .contains(&canonical_path(path))
+            .then(|| path.to_path_buf()),
+    }
+}
+
+// symlinks are resolved, paths which don't exist are only normalized
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| normalize_path(path))
+}
+
+// removes trailing slashes and `.` components
+fn normalize_path(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+// e.g. a reminder which is out of date
+fn close_notification(id: u32) {
+    let result = Connection::session().and_then(|connection| {
+        connection.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "CloseNotification",
+            &(id,),
+        )
+    });
+    if let Err(error) = result {
+        log::warn!("failed to close notification: {error}");
+    }
+}
+
+// exit codes of pkexec if the authentication dialog was dismissed or the user is not authorized
+const PKEXEC_NOT_AUTHORIZED: [i32; 2] = [126, 127];
+
+// polkit asks for authentication and runs `backup-monitor exec-privileged` as root, which runs the
+// script and records its exit code in the status file next to it
+fn script_command(path: &Path, privileged: bool) -> anyhow::Result<Command> {
+    if !privileged {
+        return Ok(Command::new(path));
+    }
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(status_file_path(path))?;
+
+    let mut command = Command::new("pkexec");
+    command
+        .arg(std::env::current_exe()?)
+        .arg(privileged::EXEC_PRIVILEGED)
+        .arg(status_file_path(path))
+        .arg(path)
+        // to stop the script
+        .stdin(Stdio::piped());
+    Ok(command)
+}
+
+// the script runs in its own process group, which includes its child processes. Privileged scripts
+// run as root and can't be signalled, `exec-privileged` stops them when asked on its stdin.
+fn stop_script(child: &mut Child) -> anyhow::Result<()> {
+    match child.stdin.as_mut() {
+        Some(stdin) => writeln!(stdin, "{}", privileged::STOP)?,
+        None => killpg(Pid::from_raw(child.id() as i32), Signal::SIGTERM)?,
+    }
+    Ok(())
+}
+
+// e.g. `.tmpa1b2c3.status` next to the script in the run dir
+fn status_file_path(script_path: &Path) -> PathBuf {
+    let mut path = script_path.as_os_str().to_owned();
+    path.push(".status");
+    PathBuf::from(path)
+}
+
+// whether pkexec failed instead of the script, the status file is empty unless the script ran
+fn not_authorized(code: Option<i32>, recorded: &str) -> bool {
+    code.is_some_and(|code| PKEXEC_NOT_AUTHORIZED.contains(&code)) && recorded.trim().is_empty()
+}
+
+// private directory for the files of a single run, removed with its content when dropped
+fn run_dir() -> Result<TempDir, anyhow::Error> {
+    let base_dir = if system_mode() {
+        PathBuf::from("/run/backup-monitor")
+    } else {
+        let runtime_dir = dirs::runtime_dir().context("runtime dir not found")?;
+        runtime_dir.join("backup-monitor")
+    };
+    fs::create_dir_all(&base_dir)?;
+    if !system_mode() {
+        fs::set_permissions(&base_dir, fs::Permissions::from_mode(0o700))?;
+    }
+
+    let dir = tempfile::Builder::new()
+        .prefix("run-")
+        .tempdir_in(base_dir)?;
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700))?;
+
+    Ok(dir)
+}
+
+fn work_dir_base() -> anyhow::Result<PathBuf> {
+    if system_mode() {
+        Ok(PathBuf::from("/var/cache/backup-monitor/work"))
+    } else {
+        let cache_dir = dirs::cache_dir().context("cache dir not found")?;
+        Ok(cache_dir.join("backup-monitor").join("work"))
+    }
+}
+
+// scratch space for a single run, on disk instead of the runtime dir, which is usually in memory
+fn work_dir(script_name: &str) -> anyhow::Result<TempDir> {
+    let base_dir = work_dir_base()?;
+    fs::create_dir_all(&base_dir)?;
+    fs::set_permissions(&base_dir, fs::Permissions::from_mode(0o700))?;
+
+    let dir = tempfile::Builder::new()
+        .prefix(&format!("{}-", script_name.replace('/', "_")))
+        .tempdir_in(base_dir)?;
+    Ok(dir)
+}
+
+// the work dir of the last failed run is kept for debugging until the next run of the script
+fn failed_work_dir_path(script_name: &str) -> anyhow::Result<PathBuf> {
+    Ok(work_dir_base()?.join(format!("failed-{}", script_name.replace('/', "_"))))
+}
+
+fn keep_work_dir(script_name: &str, work_dir: TempDir, failed: bool) {
+    let result = failed_work_dir_path(script_name).and_then(|failed_path| {
+        if failed_path.exists() {
+            fs::remove_dir_all(&failed_path)?;
+        }
+        if failed {
+            fs::rename(work_dir.into_path(), &failed_path)?;
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        log::warn!("failed to clean up the work dir of {script_name}: {error:#}");
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map_or(0, |metadata| metadata.len()),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+// for pasting into an issue or chat, `body` ends with the run id
+fn failure_details(
+    summary: &str,
+    body: &str,
+    exit_code: Option<i32>,
+    log_path: Option<&Path>,
+) -> String {
+    let mut lines = vec![summary.to_string(), body.to_string()];
+    if let Some(exit_code) = exit_code {
+        lines.push(format!("Exit code: {exit_code}"));
+    }
+    if let Some(log_path) = log_path {
+        lines.push(format!("Log: {}", log_path.display()));
+    }
+    lines.join("\n")
+}
+
+// random UUID (version 4), e.g. `0b5c1a4e-7d2f-4c89-9a61-3f0e8b7d2c15`
+fn new_run_id() -> String {
+    let mut bytes = rand::random::<[u8; 16]>();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn log_dir() -> anyhow::Result<PathBuf> {
+    if system_mode() {
+        Ok(PathBuf::from("/var/lib/backup-monitor/logs"))
+    } else {
+        let state_dir = dirs::state_dir().context("state dir not found")?;
+        Ok(state_dir.join("backup-monitor").join("logs"))
+    }
+}
+
+// the output of the last run of the script, a symlink to the log of the run
+pub fn log_file_path(script_name: &str) -> anyhow::Result<PathBuf> {
+    Ok(log_dir()?.join(format!("{}.log", script_name.replace('/', "_"))))
+}
+
+// the logs of the runs of a script, named after the run id
+fn run_log_dir(script_name: &str) -> anyhow::Result<PathBuf> {
+    Ok(log_dir()?.join(script_name.replace('/', "_")))
+}
+
+// the output may contain secrets
+fn create_log_file(script_name: &str, run_id: &str) -> anyhow::Result<File> {
+    let dir = run_log_dir(script_name)?;
+    fs::create_dir_all(&dir)?;
+
+    let file_name = format!("{run_id}.log");
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(dir.join(&file_name))?;
+
+    let link = log_file_path(script_name)?;
+    if link.symlink_metadata().is_ok() {
+        fs::remove_file(&link)?;
+    }
+    let target = Path::new(dir.file_name().unwrap_or_default()).join(&file_name);
+    std::os::unix::fs::symlink(target, &link)?;
+
+    if let Err(error) = prune_logs(&dir) {
+        log::warn!("failed to remove old logs: {error}");
+    }
+
+    Ok(file)
+}
+
+// keeps the logs of the last runs
+fn prune_logs(dir: &Path) -> std::io::Result<()> {
+    let mut logs = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    logs.sort();
+
+    for (_, path) in logs.iter().rev().skip(LOG_RETENTION) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// returns the messages of the failed steps and whether a mandatory step failed, in which case the
+// remaining steps are skipped
+fn run_post_backup_steps(
+    settings: &Settings,
+    script: &Script,
+    dir: &Path,
+    now: DateTime<Utc>,
+) -> (Vec<String>, bool) {
+    let mut failed_steps = Vec::new();
+
+    for step in &script.post_backup_steps {
+        log::info!("running post backup step `{}`", step.label);
+
+        let result = write_script(
+            dir,
+            &render_script(
+                &settings.script_with_defaults(&step.script),
+                &script_variables(script, now),
+            ),
+        )
+        .and_then(|tmp| Ok(script_command(&tmp, script.privileged)?.status()?));
+        let message = match result {
+            Ok(status) if status.success() => continue,
+            Ok(status) => match status.code() {
+                Some(code) => format!("{} failed with exit code {code}", step.label),
+                None => format!("{} failed", step.label),
+            },
+            Err(error) => format!("{} failed with error: {error}", step.label),
+        };
+        log::warn!("{message}");
+        failed_steps.push(message);
+
+        if step.mandatory {
+            return (failed_steps, true);
+        }
+    }
+
+    (failed_steps, false)
+}
+
+// the first line of the output of a failed check is its warning
+fn run_preflight_checks(
+    settings: &Settings,
+    script: &Script,
+    dir: &Path,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for check in &script.preflight_checks {
+        log::info!("running pre-flight check `{}`", check.label);
+
+        let result = write_script(
+            dir,
+            &render_script(
+                &settings.script_with_defaults(&check.script),
+                &script_variables(script, now),
+            ),
+        )
+        .and_then(|tmp| Ok(script_command(&tmp, script.privileged)?.output()?));
+        let warning = match result {
+            Ok(output) if output.status.success() => continue,
+            Ok(output) => match String::from_utf8_lossy(&output.stdout).lines().next() {
+                Some(line) if !line.trim().is_empty() => {
+                    format!("{}: {}", check.label, line.trim())
+                }
+                _ => format!("{} failed", check.label),
+            },
+            Err(error) => format!("{} failed with error: {error}", check.label),
+        };
+        log::warn!("{warning}");
+        warnings.push(warning);
+    }
+
+    warnings
+}
+
+// "Run anyway" runs the script without checks, "Skip" leaves it to the retry after the cooldown
+fn ask_run_anyway(
+    settings: &Settings,
+    script: &Script,
+    warnings: &[String],
+    tx: EventSender,
+) -> anyhow::Result<()> {
+    let Some(notification_handle) = notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary(&format!("{} has pre-flight warnings", script.name))
+            .body(&warnings.join("\n"))
+            .icon("dialog-warning")
+            .action(RUN_ANYWAY_ACTION, "Run anyway")
+            .action(SKIP_ACTION, "Skip")
+            .timeout(Timeout::Never),
+    )?
+    else {
+        return Ok(());
+    };
+
+    // wait for actions in the background to keep the main loop responsive
+    let name = script.name.clone();
+    thread::spawn(move || {
+        notification_handle.wait_for_action(|action| {
+            if action == RUN_ANYWAY_ACTION {
+                let _ = tx.send(Event::ManualRun(name.clone(), Trigger::RunAnyway, None));
+            }
+        });
+    });
+
+    Ok(())
+}
+
+// remembers the base of full backups and warns about long or broken chains
+fn check_chain(settings: &Settings, script: &Script, chain: &Chain) {
+    let known_base = match chain_base(&script.name) {
+        Ok(known_base) => known_base,
+        Err(error) => {
+            log::warn!("failed to read the base of the backup chain: {error:#}");
+            None
+        }
+    };
+    let warnings = chain_warnings(chain, known_base.as_deref(), script.max_chain_length);
+
+    if chain.increment == 0 {
+        if let Err(error) = record_chain_base(&script.name, &chain.base) {
+            log::error!("failed to save state: {error:#}");
+        }
+    }
+
+    if warnings.is_empty() {
+        return;
+    }
+    for warning in &warnings {
+        log::warn!("script `{}`: {warning}", script.name);
+    }
+    if let Err(error) = notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary(&format!("{} needs a new full backup", script.name))
+            .body(&warnings.join("\n"))
+            .icon("dialog-warning"),
+    ) {
+        log::warn!("failed to show the backup chain warning: {error}");
+    }
+}
+
+// runs a post backup action or a tray command and shows the result in a notification
+pub fn run_action(settings: &Settings, label: &str, script: &str) -> anyhow::Result<()> {
+    let dir = run_dir()?;
+    let tmp = write_script(dir.path(), script)?;
+
+    let summary;
+    let body;
+    match Command::new(&tmp).status() {
+        Ok(status) => {
+            if status.success() {
+                summary = format!("{label} finished");
+                body = String::new();
+            } else {
+                summary = format!("{label} failed");
+                body = String::new();
+            }
+        }
+        Err(error) => {
+            summary = format!("{label} failed with error");
+            body = error.to_string();
+        }
+    };
+
+    notification::show(
+        Notification::new()
+            .appname(&settings.title)
+            .summary(&summary)
+            .body(&body)
+            .icon(&settings.icon_name)
+            .timeout(Timeout::Milliseconds(6_000)),
+    )?;
+    Ok(())
+}
+
+fn write_script(dir: &Path, script: &str) -> Result<TempPath, anyhow::Error> {
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(script.as_bytes())?;
+    tmp.as_file()
+        .set_permissions(fs::Permissions::from_mode(0o700))?;
+
+    // close the file, it can't be executed while it is open for writing
+    Ok(tmp.into_temp_path())
+}
+
+fn next_backup(now: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
+    within_allowed_time(now, due_backup(now, script), script)
+}
+
+// when the backup is due, regardless of the allowed hours and days
+fn due_backup(now: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
+    let last_run = match script.schedule_from {
+        ScheduleFrom::LastSuccess => script.last_backup,
+        ScheduleFrom::LastAttempt => script.last_backup.max(script.last_attempt),
+    };
+    last_run.map_or(now, |last_run| {
+        next_scheduled(last_run, script) + jitter(script, last_run)
+    })
+}
+
+// a random delay up to the jitter of the script, fixed for `seed` so the next backup doesn't move
+fn jitter(script: &Script, seed: DateTime<Utc>) -> Duration {
+    let Some(jitter) = script.jitter.filter(|jitter| !jitter.is_zero()) else {
+        return Duration::zero();
+    };
+    let mut hasher = DefaultHasher::new();
+    (&script.name, seed.timestamp_micros()).hash(&mut hasher);
+    let millis = hasher.finish() % jitter.as_millis().max(1) as u64;
+    Duration::milliseconds(millis as i64)
+}
+
+// a due backup is deferred to the start of the next allowed hours and days
+fn within_allowed_time(now: DateTime<Utc>, due: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
+    let from = due.max(now);
+    let allowed = next_allowed(
+        from,
+        script.allowed_hours.as_ref(),
+        &script.allowed_days,
+        &Local,
+    );
+    if allowed == from {
+        due
+    } else {
+        allowed
+    }
+}
+
+// the start of the allowed hours and days a due backup is waiting for
+fn waiting_for_allowed_time(now: DateTime<Utc>, script: &Script) -> Option<DateTime<Utc>> {
+    Some(next_backup(now, script)).filter(|next| due_backup(now, script) <= now && *next > now)
+}
+
+// the next run after `last_run` according to the schedule or the interval
+fn next_scheduled(last_run: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
+    match &script.schedule {
+        Some(schedule) => schedule
+            .next_after(last_run, &Local)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        None => last_run + script.interval.unwrap_or_default(),
+    }
+}
+
+// `None` if the file system of the path is writable and has at least `min_free_space` bytes free
+fn target_problem(path: &Path, min_free_space: Option<u64>) -> Option<String> {
+    let stat = match statvfs(path) {
+        Ok(stat) => stat,
+        Err(error) => return Some(format!("\"{}\" can't be checked: {error}", path.display())),
+    };
+    if stat.flags().contains(FsFlags::ST_RDONLY) {
+        return Some(format!("\"{}\" is mounted read-only", path.display()));
+    }
+
+    let free = stat.blocks_available() as u64 * stat.fragment_size() as u64;
+    let min_free_space = min_free_space.filter(|min_free_space| free < *min_free_space)?;
+    Some(format!(
+        "\"{}\" has only {} free, {} are needed",
+        path.display(),
+        format_bytes(free),
+        format_bytes(min_free_space)
+    ))
+}
+
+fn retry_at(failed: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
+    match script.schedule_from {
+        ScheduleFrom::LastSuccess => failed + RETRY_INTERVAL,
+        ScheduleFrom::LastAttempt => (failed + RETRY_INTERVAL).max(next_scheduled(failed, script)),
+    }
+}
+
+fn next_ui_update(now: DateTime<Utc>, script: &Script) -> DateTime<Utc> {
+    let (_, next_backup_remainder) = round_duration(
+        next_backup(now, script).max(now) - now,
+        RoundAccuracy::Minutes,
+        RoundDirection::Down,
+    );
+
+    let last_backup_remainder = script.last_backup.map(|last_backup| {
+        let (_, remainder) = round_duration(
+            now - last_backup.min(now),
+            RoundAccuracy::Minutes,
+            RoundDirection::Up,
+        );
+        remainder
+    });
+
+    let remainder =
+        last_backup_remainder.map_or(next_backup_remainder, |r| r.min(next_backup_remainder));
+
+    now + remainder + Duration::milliseconds(1)
+}
+
+fn next_reminder(now: DateTime<Utc>, script: &Script) -> Option<DateTime<Utc>> {
+    let reminder = script.reminder?;
+    let next_reminder = script
+        .last_backup
+        .as_ref()
+        .map_or(now, |last_backup| *last_backup + reminder);
+    Some(next_reminder)
+}
+
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let (duration, _) = round_duration(duration, RoundAccuracy::Minutes, RoundDirection::Down);
+    humantime::format_duration(duration.to_std().unwrap()).to_string()
+}
+
+// the last non-empty line counts, e.g. `42` or `42 copying photos`
+fn parse_progress(content: &str) -> Option<Progress> {
+    let line = content.lines().rev().find(|line| !line.trim().is_empty())?;
+    let (percent, stage) = match line.trim().split_once(char::is_whitespace) {
+        Some((percent, stage)) => (percent, Some(stage.trim().to_string())),
+        None => (line.trim(), None),
+    };
+    let percent = percent.trim_end_matches('%').parse::<f64>().ok()?;
+
+    Some(Progress {
+        percent: percent.clamp(0.0, 100.0) as u8,
+        stage,
+    })
+}
+
+// e.g. `/mnt/backup: 120.0 GiB free, 1843.2 GiB used`
+fn format_usage(path: &Path, usage: DiskUsage, low_space_warning: Option<u64>) -> String {
+    let low = low_space_warning.is_some_and(|low_space_warning| usage.free < low_space_warning);
+    format!(
+        "{}: {} free, {} used{}",
+        path.display(),
+        format_bytes(usage.free),
+        format_bytes(usage.used),
+        if low { " (running low)" } else { "" }
+    )
+}
+
+fn running_body(elapsed: std_time::Duration, progress: Option<&Progress>) -> String {
+    let elapsed =
+        humantime::format_duration(std_time::Duration::from_secs(elapsed.as_secs() / 60 * 60));
+    match progress {
+        Some(Progress {
+            percent,
+            stage: Some(stage),
+        }) => format!("Running for {elapsed} ({percent}%, {stage})"),
+        Some(Progress {
+            percent,
+            stage: None,
+        }) => format!("Running for {elapsed} ({percent}%)"),
+        None => format!("Running for {elapsed}"),
+    }
+}
+
+fn tooltip(
+    clock: &Clock,
+    script: &Script,
+    state: &ScriptState,
+    progress: Option<&Progress>,
+) -> String {
+    let last_backup = if let Some(last_backup) = script.last_backup {
+        let now = clock.now();
+        let (last_backup, _) = round_duration(
+            now - last_backup.min(now),
+            RoundAccuracy::Minutes,
+            RoundDirection::Down,
+        );
+        format!(
+            "Last backup was {} ago",
+            humantime::format_duration(last_backup.to_std().unwrap())
+        )
+    } else {
+        "Never backed up before".to_string()
+    };
+
+    let status = match state {
+        ScriptState::WaitingForTime if !script.enabled => "Disabled".to_string(),
+        ScriptState::WaitingForTime if waiting_for_allowed_time(clock.now(), script).is_some() => {
+            format!(
+                "Due, waiting for the allowed time at {}",
+                format_time(next_backup(clock.now(), script))
+            )
+        }
+        ScriptState::WaitingForTime => {
+            let now = clock.now();
+            let (next_backup, _) = round_duration(
+                next_backup(now, script).max(now) - now,
+                RoundAccuracy::Minutes,
+                RoundDirection::Down,
+            );
+            format!(
+                "Next backup in {}",
+                humantime::format_duration(next_backup.to_std().unwrap())
+            )
+        }
+        ScriptState::WaitingForPaths(paths) => {
+            format!(
+                "Waiting for folders {} to be mounted",
+                paths
+                    .iter()
+                    .map(|path| format!("\"{}\"", path.display()))
+                    .join(", ")
+            )
+        }
+        ScriptState::WaitingForUser(user) => format!("Waiting for {user} to be the active user"),
+        ScriptState::WaitingForPower(requirement) => format!("Waiting for {requirement}"),
+        ScriptState::WaitingForIdle => format!(
+            "Due, waiting for you to be away for {}",
+            humantime::format_duration(script.only_when_idle.unwrap_or_default())
+        ),
+        ScriptState::Running => match progress {
+            Some(Progress {
+                percent,
+                stage: Some(stage),
+            }) => format!("Running ({percent}%, {stage})"),
+            Some(Progress {
+                percent,
+                stage: None,
+            }) => format!("Running ({percent}%)"),
+            None => "Running".to_string(),
+        },
+        ScriptState::Failed(_, message) => format!("Failed: {message}",),
+        ScriptState::TargetUnusable(_, message) => format!("Not started: {message}"),
+    };
+
+    format!("{last_backup}\n{status}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control::StatusPublisher,
+        settings::{PostBackupStep, PreflightCheck},
+    };
+    use chrono::{Datelike, NaiveTime};
+    use fake::{Fake, Faker};
+    use indoc::indoc;
+    use serde::Deserialize;
+    use std::{cmp::max, time::Duration};
+
+    #[derive(Debug, Default, Deserialize)]
+    struct ScheduleTestScript {
+        pub mount_paths: Vec<PathBuf>,
+
+        #[serde(with = "humantime_serde")]
+        pub interval: Duration,
+
+        #[serde(default, with = "humantime_serde")]
+        pub reminder: Option<Duration>,
+
+        #[serde(default, with = "humantime_serde")]
+        pub last_backup: Option<Duration>,
+
+        #[serde(default)]
+        pub remind_when_target_missing: Option<bool>,
+
+        pub enabled: Option<bool>,
+
+        #[serde(default)]
+        pub require_ac_power: bool,
+
+        pub min_battery_percent: Option<u8>,
+
+        #[serde(default, with = "humantime_serde")]
+        pub only_when_idle: Option<Duration>,
+
+        pub state: Option<String>,
+    }
+
+    impl ScheduleTestScript {
+        fn into_script(self, clock: &Clock) -> Script {
+            Script {
+                name: Faker.fake(),
+                enabled: self.enabled.unwrap_or(true),
+                icon_name: None,
+                backup_script: "#!/bin/bash".to_string(),
+                uses: Vec::new(),
+                mount_paths: self.mount_paths,
+                auto_mount: None,
+                min_free_space: None,
+                interval: Some(self.interval),
+                schedule: None,
+                schedule_from: ScheduleFrom::LastSuccess,
+                allowed_hours: None,
+                allowed_days: Vec::new(),
+                jitter: None,
+                require_ac_power: self.require_ac_power,
+                min_battery_percent: self.min_battery_percent,
+                only_when_idle: self.only_when_idle,
+                reminder: self.reminder,
+                ignore_schedule_warnings: false,
+                remind_when_target_missing: self.remind_when_target_missing.unwrap_or(true),
+                plug_in_reminder: false,
+                reminder_message: None,
+                reminder_summary: None,
+                reminder_repeat: None,
+                reminder_urgency: None,
+                failure_message: None,
+                privileged: false,
+                inhibit_sleep: true,
+                allowed_users: Vec::new(),
+                work_dir_limit: None,
+                max_chain_length: None,
+                preflight_checks: Vec::new(),
+                preflight_policy: PreflightPolicy::Ask,
+                success_when: None,
+                post_backup_steps: Vec::new(),
+                commit_last_backup: CommitLastBackup::AfterScript,
+                post_backup_actions: Vec::new(),
+                last_backup: self.last_backup.map(|delta| clock.now() - delta),
+                last_attempt: None,
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct ScheduleTestCase {
+        scripts: Vec<ScheduleTestScript>,
+
+        primary_user: Option<String>,
+
+        active_user: Option<String>,
+
+        #[serde(default)]
+        on_battery: bool,
+
+        battery_percent: Option<u8>,
+
+        // active if not set
+        #[serde(default, with = "humantime_serde")]
+        idle_for: Option<Duration>,
+
+        #[serde(default, with = "humantime_serde")]
+        paused_for: Option<Duration>,
+
+        #[serde(default, with = "humantime_serde")]
+        next_backup: Option<Duration>,
+
+        #[serde(default, with = "humantime_serde")]
+        next_reminder: Option<Duration>,
+
+        #[serde(default, with = "humantime_serde")]
+        next_ui_update: Option<Duration>,
+    }
+
+    #[rstest::rstest]
+    #[case("empty")]
+    #[case("never_backed_up")]
+    #[case("blocked_by_last_backup_1")]
+    #[case("blocked_by_last_backup_2")]
+    #[case("blocked_by_last_backup_3")]
+    #[case("waiting_for_path")]
+    #[case("waiting_for_path_without_reminder")]
+    #[case("running")]
+    #[case("failed_with_cooldown")]
+    #[case("failed_without_cooldown")]
+    #[case("target_unusable")]
+    #[case("paused")]
+    #[case("waiting_for_user")]
+    #[case("waiting_for_user_active")]
+    #[case("waiting_for_power")]
+    #[case("waiting_for_power_plugged_in")]
+    #[case("waiting_for_idle")]
+    #[case("waiting_for_idle_soon")]
+    #[case("disabled")]
+    fn schedule(#[case] name: &str) {
+        let test_case = serde_hjson::from_reader::<_, ScheduleTestCase>(
+            File::open(format!("./src/test_cases/manager/{name}.hjson")).unwrap(),
+        )
+        .unwrap();
+
+        let script_states = test_case
+            .scripts
+            .iter()
+            .map(|script| &script.state)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            scripts: test_case
+                .scripts
+                .into_iter()
+                .map(|script| script.into_script(&clock))
+                .collect(),
+            paused_until: test_case.paused_for.map(|delta| now + delta),
+            primary_user: test_case.primary_user,
+            ..Default::default()
+        }));
+        let mut manager = ScriptManager::new(clock, settings.clone(), "");
+        manager.set_active_user(test_case.active_user);
+        manager.set_power(Some(Power {
+            on_battery: test_case.on_battery,
+            battery_percent: test_case.battery_percent,
+        }));
+        manager.set_idle_since(test_case.idle_for.map(|delta| now - delta));
+
+        for (script, state) in settings.load().scripts.iter().zip(script_states) {
+            if let Some(state) = state {
+                let state = match state.split(':').collect::<Vec<_>>()[..] {
+                    ["WaitingForTime"] => ScriptState::WaitingForTime,
+                    ["WaitingForPath", path] => ScriptState::WaitingForPaths(vec![path.into()]),
+                    ["WaitingForUser", user] => ScriptState::WaitingForUser(user.to_string()),
+                    ["WaitingForPower", requirement] => {
+                        ScriptState::WaitingForPower(requirement.to_string())
+                    }
+                    ["WaitingForIdle"] => ScriptState::WaitingForIdle,
+                    ["Running"] => ScriptState::Running,
+                    ["Failed", ts, message] => ScriptState::Failed(
+                        now - humantime::parse_duration(ts).unwrap(),
+                        message.to_string(),
+                    ),
+                    ["TargetUnusable", ts, message] => ScriptState::TargetUnusable(
+                        now - humantime::parse_duration(ts).unwrap(),
+                        message.to_string(),
+                    ),
+                    _ => unimplemented!(),
+                };
+                manager.states.insert(script.name.clone(), state);
+            }
+        }
+
+        assert_eq!(
+            (
+                manager
+                    .next_backup()
+                    .map(|ts| (max(ts, now) - now).to_std().unwrap()),
+                manager
+                    .next_reminder()
+                    .map(|ts| (max(ts, now) - now).to_std().unwrap()),
+                manager
+                    .next_ui_update()
+                    .map(|ts| (max(ts, now) - now).to_std().unwrap()),
+            ),
+            (
+                test_case.next_backup,
+                test_case.next_reminder,
+                test_case.next_ui_update
+            ),
+            "{name}"
+        );
+    }
+
+    #[test]
+    fn set_mounts() {
+        let clock = Faker.fake::<Clock>();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings::default()));
+        let mut manager = ScriptManager::new(clock, settings, "");
+
+        manager.set_mounts(indoc! {"
+            /dev/nvme0n1p2 / btrfs rw,relatime,ssd,discard=async,space_cache=v2,subvolid=403,subvol=/@/.snapshots/138/snapshot 0 0
+            devtmpfs /dev devtmpfs rw,nosuid,size=4096k,nr_inodes=8192558,mode=755,inode64 0 0
+            tmpfs /dev/shm tmpfs rw,nosuid,nodev,inode64 0 0
+            systemd-1 /mnt/backup autofs rw,relatime,fd=45,pgrp=1,timeout=0,minproto=5,maxproto=5,direct 0 0
+        "});
+
+        assert!(manager.mounts.contains(&PathBuf::from("/")));
+        assert!(manager.mounts.contains(&PathBuf::from("/dev/shm")));
+        assert!(!manager.mounts.contains(&PathBuf::from("/does-not-exist")));
+        assert!(!manager.mounts.contains(&PathBuf::from("/mnt/backup")));
+        assert!(manager.automounts.contains(&PathBuf::from("/mnt/backup")));
+    }
+
+    #[test]
+    fn mount_path_normalization() {
+        assert_eq!(
+            unescape_mount_path(r"/run/media/alice/Backup\040Disk"),
+            PathBuf::from("/run/media/alice/Backup Disk")
+        );
+        assert_eq!(
+            unescape_mount_path(r"/mnt/tab\011back\134slash"),
+            PathBuf::from("/mnt/tab\tback\\slash")
+        );
+        assert_eq!(
+            unescape_mount_path(r"/mnt/not\escaped"),
+            PathBuf::from(r"/mnt/not\escaped")
+        );
+        assert_eq!(
+            canonical_path(Path::new("/does-not-exist/./backup/")),
+            PathBuf::from("/does-not-exist/backup")
+        );
+    }
+
+    #[test]
+    fn run_outcomes() {
+        let clock = Faker.fake::<Clock>();
+        let script = ScheduleTestScript {
+            mount_paths: vec![PathBuf::from("/does-not-exist")],
+            interval: Duration::from_secs(3600),
+            ..Default::default()
+        }
+        .into_script(&clock);
+        let name = script.name.clone();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            scripts: vec![script],
+            ..Default::default()
+        }));
+        let mut manager = ScriptManager::new(clock, settings, "");
+        let handle = StatusPublisher::<ksni::Handle<Tray>>::new(None, Default::default());
+
+        assert_eq!(
+            manager.run(None, &Trigger::Schedule, &handle).unwrap(),
+            vec![(
+                name,
+                RunOutcome::WaitingForPaths(vec![PathBuf::from("/does-not-exist")])
+            )]
+        );
+        assert_eq!(
+            manager
+                .run(Some("Unknown"), &Trigger::Schedule, &handle)
+                .unwrap(),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn cancel_script() {
+        let clock = Faker.fake::<Clock>();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings::default()));
+        let mut manager = ScriptManager::new(clock, settings, "");
+        let handle = StatusPublisher::<ksni::Handle<Tray>>::new(None, Default::default());
+        let dir = tempfile::tempdir().unwrap();
+
+        let child = Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        request_cancel("Cancelled backup");
+        request_cancel("Other backup");
+
+        let status = manager
+            .wait_with_progress(
+                "Cancelled backup",
+                child,
+                &dir.path().join("progress"),
+                None,
+                &handle,
+                None,
+                Instant::now(),
+            )
+            .unwrap();
+
+        assert!(matches!(status, Wait::Cancelled));
+        assert!(!take_cancel_request("Cancelled backup"));
+        assert!(take_cancel_request("Other backup"));
+    }
+
+    #[test]
+    fn cancel_privileged_script() {
+        let clock = Faker.fake::<Clock>();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings::default()));
+        let mut manager = ScriptManager::new(clock, settings, "");
+        let handle = StatusPublisher::<ksni::Handle<Tray>>::new(None, Default::default());
+        let dir = tempfile::tempdir().unwrap();
+
+        // stands in for `exec-privileged`, which can't be signalled
+        let child = Command::new("sh")
+            .args(["-c", "read line && [ \"$line\" = stop ]"])
+            .stdin(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+
+        request_cancel("Privileged backup");
+        let status = manager
+            .wait_with_progress(
+                "Privileged backup",
+                child,
+                &dir.path().join("progress"),
+                None,
+                &handle,
+                None,
+                Instant::now(),
+            )
+            .unwrap();
+
+        assert!(matches!(status, Wait::Cancelled));
+    }
+
+    #[test]
+    fn running_script() {
+        let running = RunningScript::new("Running backup");
+        assert!(running_scripts().contains(&"Running backup".to_string()));
+
+        drop(running);
+        assert!(!running_scripts().contains(&"Running backup".to_string()));
+    }
+
+    #[test]
+    fn full_work_dir() {
+        let clock = Faker.fake::<Clock>();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings::default()));
+        let mut manager = ScriptManager::new(clock, settings, "");
+        let handle = StatusPublisher::<ksni::Handle<Tray>>::new(None, Default::default());
+        let dir = tempfile::tempdir().unwrap();
+        let work_dir = dir.path().join("work");
+        fs::create_dir_all(work_dir.join("nested")).unwrap();
+        fs::write(work_dir.join("file"), [0; 600]).unwrap();
+        fs::write(work_dir.join("nested").join("file"), [0; 600]).unwrap();
+
+        assert_eq!(dir_size(&work_dir), 1200);
+
+        let child = Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+
+        // the size is checked right away
+        let start = Instant::now().checked_sub(WORK_DIR_CHECK_INTERVAL).unwrap();
+        let status = manager
+            .wait_with_progress(
+                "Backup",
+                child,
+                &dir.path().join("progress"),
+                Some((&work_dir, 1000)),
+                &handle,
+                None,
+                start,
+            )
+            .unwrap();
+
+        assert!(matches!(status, Wait::WorkDirFull));
+    }
+
+    #[test]
+    fn schedule_from_last_attempt() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let mut script = ScheduleTestScript {
+            interval: Duration::from_secs(24 * 60 * 60),
+            last_backup: Some(Duration::from_secs(3 * 24 * 60 * 60)),
+            ..Default::default()
+        }
+        .into_script(&clock);
+        script.last_attempt = Some(now - chrono::Duration::hours(2));
+        let failed = now - chrono::Duration::hours(2);
+
+        assert_eq!(next_backup(now, &script), now - chrono::Duration::days(2));
+        assert_eq!(retry_at(failed, &script), failed + RETRY_INTERVAL);
+
+        script.schedule_from = ScheduleFrom::LastAttempt;
+        assert_eq!(next_backup(now, &script), now + chrono::Duration::hours(22));
+        assert_eq!(retry_at(failed, &script), now + chrono::Duration::hours(22));
+    }
+
+    #[test]
+    fn jitter_is_fixed() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let mut script = ScheduleTestScript {
+            interval: Duration::from_secs(24 * 60 * 60),
+            last_backup: Some(Duration::from_secs(60 * 60)),
+            ..Default::default()
+        }
+        .into_script(&clock);
+        let due = now + chrono::Duration::hours(23);
+        assert_eq!(next_backup(now, &script), due);
+
+        script.jitter = Some(Duration::from_secs(30 * 60));
+        let next = next_backup(now, &script);
+        assert!(next >= due && next < due + chrono::Duration::minutes(30));
+        assert_eq!(next_backup(now + chrono::Duration::hours(1), &script), next);
+    }
+
+    #[test]
+    fn allowed_time() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let mut script = ScheduleTestScript {
+            interval: Duration::from_secs(24 * 60 * 60),
+            last_backup: Some(Duration::from_secs(3 * 24 * 60 * 60)),
+            ..Default::default()
+        }
+        .into_script(&clock);
+        assert_eq!(next_backup(now, &script), now - chrono::Duration::days(2));
+        assert_eq!(waiting_for_allowed_time(now, &script), None);
+
+        // every day but today
+        let today = now.with_timezone(&Local).date_naive();
+        script.allowed_days = (1..7)
+            .map(|day| (today + chrono::Duration::days(day)).weekday())
+            .collect();
+        let tomorrow = today
+            .succ_opt()
+            .unwrap()
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap()
+            .to_utc();
+        assert_eq!(next_backup(now, &script), tomorrow);
+        assert_eq!(waiting_for_allowed_time(now, &script), Some(tomorrow));
+    }
 
-        #[serde(default, with = "humantime_serde")]
-        next_backup: Option<Duration>,
+    #[test]
+    fn post_backup_steps() {
+        let clock = Faker.fake::<Clock>();
+        let dir = tempfile::tempdir().unwrap();
+        let skipped = dir.path().join("skipped");
+        let step = |label: &str, script: String, mandatory| PostBackupStep {
+            label: label.to_string(),
+            script: format!("#!/bin/sh\n{script}"),
+            mandatory,
+        };
+        let mut script = ScheduleTestScript {
+            interval: Duration::from_secs(3600),
+            ..Default::default()
+        }
+        .into_script(&clock);
+        script.post_backup_steps = vec![
+            step("Compact", "exit 3".to_string(), false),
+            step("Verify", "true".to_string(), true),
+            step("Prune", "exit 1".to_string(), true),
+            step("Notify", format!("touch {}", skipped.display()), false),
+        ];
 
-        #[serde(default, with = "humantime_serde")]
-        next_reminder: Option<Duration>,
+        assert_eq!(
+            run_post_backup_steps(&Settings::default(), &script, dir.path(), clock.now()),
+            (
+                vec![
+                    "Compact failed with exit code 3".to_string(),
+                    "Prune failed with exit code 1".to_string()
+                ],
+                true
+            )
+        );
+        assert!(!skipped.exists());
 
-        #[serde(default, with = "humantime_serde")]
-        next_ui_update: Option<Duration>,
+        script.post_backup_steps.truncate(2);
+        assert_eq!(
+            run_post_backup_steps(&Settings::default(), &script, dir.path(), clock.now()),
+            (vec!["Compact failed with exit code 3".to_string()], false)
+        );
     }
 
-    #[rstest::rstest]
-    #[case("empty")]
-    #[case("never_backed_up")]
-    #[case("blocked_by_last_backup_1")]
-    #[case("blocked_by_last_backup_2")]
-    #[case("blocked_by_last_backup_3")]
-    #[case("waiting_for_path")]
-    #[case("running")]
-    #[case("failed_with_cooldown")]
-    #[case("failed_without_cooldown")]
-    fn schedule(#[case] name: &str) {
-        let test_case = serde_hjson::from_reader::<_, ScheduleTestCase>(
-            File::open(format!("./src/test_cases/manager/{name}.hjson")).unwrap(),
-        )
-        .unwrap();
+    #[test]
+    fn preflight_checks() {
+        let clock = Faker.fake::<Clock>();
+        let check = |label: &str, script: &str| PreflightCheck {
+            label: label.to_string(),
+            script: format!("#!/bin/sh\n{script}"),
+        };
+        let mut script = ScheduleTestScript {
+            interval: Duration::from_secs(3600),
+            ..Default::default()
+        }
+        .into_script(&clock);
+        script.name = "Backup".to_string();
+        script.preflight_checks = vec![
+            check("Disk", "true"),
+            check("Free space", "echo less than 10 GB free; exit 1"),
+            check("SMART", "exit 2"),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let settings = Settings::default();
 
-        let script_states = test_case
-            .scripts
-            .iter()
-            .map(|script| &script.state)
-            .cloned()
-            .collect::<Vec<_>>();
+        assert_eq!(
+            run_preflight_checks(&settings, &script, dir.path(), clock.now()),
+            vec![
+                "Free space: less than 10 GB free".to_string(),
+                "SMART failed".to_string()
+            ]
+        );
+
+        // the checks aren't run again once the warnings have been accepted
+        let manager = ScriptManager::new(
+            clock,
+            Arc::new(ArcSwap::from_pointee(Settings::default())),
+            "",
+        );
+        assert_eq!(
+            manager.preflight(&settings, &script, &Trigger::RunAnyway),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_paths_resolve_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().canonicalize().unwrap().join("target");
+        let link = dir.path().join("link");
+        fs::create_dir(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let clock = Faker.fake::<Clock>();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings::default()));
+        let mounts = format!("/dev/sdb1 {} ext4 rw 0 0", target.display());
+        let manager = ScriptManager::new(clock, settings, &mounts);
+
+        let script = ScheduleTestScript {
+            mount_paths: vec![link, dir.path().join("missing")],
+            interval: Duration::from_secs(3600),
+            ..Default::default()
+        }
+        .into_script(&clock);
+
+        assert_eq!(
+            manager.missing_paths(&script),
+            vec![dir.path().join("missing")]
+        );
+
+        let script = Script {
+            mount_paths: vec![PathBuf::from("uuid:does-not-exist")],
+            ..script
+        };
+        assert_eq!(
+            manager.missing_paths(&script),
+            vec![PathBuf::from("uuid:does-not-exist")]
+        );
+    }
+
+    #[test]
+    fn script_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup");
+
+        let command = script_command(&path, false).unwrap();
+        assert_eq!(command.get_program(), path.as_os_str());
+        assert_eq!(command.get_args().count(), 0);
+        assert!(!status_file_path(&path).exists());
+
+        let command = script_command(&path, true).unwrap();
+        assert_eq!(command.get_program(), "pkexec");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            vec![
+                std::env::current_exe().unwrap().as_os_str(),
+                "exec-privileged".as_ref(),
+                dir.path().join("backup.status").as_os_str(),
+                path.as_os_str(),
+            ]
+        );
+        assert!(status_file_path(&path).exists());
+    }
+
+    #[test]
+    fn pkexec_exit_codes() {
+        // the script didn't run
+        assert!(not_authorized(Some(126), ""));
+        assert!(not_authorized(Some(127), ""));
+        // exit codes of the script
+        assert!(!not_authorized(Some(126), "126"));
+        assert!(!not_authorized(Some(127), "127"));
+        assert!(!not_authorized(Some(1), "1"));
+        assert!(!not_authorized(Some(1), ""));
+        assert!(!not_authorized(None, ""));
+    }
 
+    #[test]
+    fn dry_run() {
         let clock = Faker.fake::<Clock>();
         let now = clock.now();
         let settings = Arc::new(ArcSwap::from_pointee(Settings {
-            scripts: test_case
-                .scripts
-                .into_iter()
-                .map(|script| script.into_script(&clock))
-                .collect(),
+            scripts: vec![
+                ScheduleTestScript {
+                    interval: Duration::from_secs(3600),
+                    ..Default::default()
+                }
+                .into_script(&clock),
+                ScheduleTestScript {
+                    mount_paths: vec![PathBuf::from("/does-not-exist")],
+                    interval: Duration::from_secs(3600),
+                    ..Default::default()
+                }
+                .into_script(&clock),
+            ],
             ..Default::default()
         }));
         let mut manager = ScriptManager::new(clock, settings.clone(), "");
 
-        for (script, state) in settings.load().scripts.iter().zip(script_states) {
-            if let Some(state) = state {
-                let state = match state.split(':').collect::<Vec<_>>()[..] {
-                    ["WaitingForTime"] => ScriptState::WaitingForTime,
-                    ["WaitingForPath", path] => ScriptState::WaitingForPaths(vec![path.into()]),
-                    ["Running"] => ScriptState::Running,
-                    ["Failed", ts, message] => ScriptState::Failed(
-                        now - humantime::parse_duration(ts).unwrap(),
-                        message.to_string(),
-                    ),
-                    _ => unimplemented!(),
-                };
-                manager.states.insert(script.name.clone(), state);
-            }
+        let decisions = manager
+            .dry_run()
+            .into_iter()
+            .map(|(_, decision)| decision)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            decisions,
+            vec![
+                Decision::Run,
+                Decision::WaitForPaths(vec![PathBuf::from("/does-not-exist")])
+            ]
+        );
+        assert_eq!(settings.load().scripts[0].last_backup, Some(now));
+        assert_eq!(settings.load().scripts[1].last_backup, None);
+        assert_eq!(manager.dry_run().len(), 1);
+    }
+
+    #[test]
+    fn explain() {
+        let clock = Faker.fake::<Clock>();
+        let script = ScheduleTestScript {
+            mount_paths: vec![PathBuf::from("/does-not-exist")],
+            interval: Duration::from_secs(3600),
+            ..Default::default()
         }
+        .into_script(&clock);
+        let name = script.name.clone();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            scripts: vec![script],
+            ..Default::default()
+        }));
+        let manager = ScriptManager::new(clock, settings, "");
 
         assert_eq!(
-            (
-                manager
-                    .next_backup()
-                    .map(|ts| (max(ts, now) - now).to_std().unwrap()),
-                manager
-                    .next_reminder()
-                    .map(|ts| (max(ts, now) - now).to_std().unwrap()),
-                manager
-                    .next_ui_update()
-                    .map(|ts| (max(ts, now) - now).to_std().unwrap()),
-            ),
-            (
-                test_case.next_backup,
-                test_case.next_reminder,
-                test_case.next_ui_update
-            ),
-            "{name}"
+            manager.explain(&name).unwrap(),
+            format!(
+                indoc! {r#"
+                    {}:
+                    Last backup: never
+                    Interval: 1h
+                    Reminder: none
+                    State: waiting for time
+                    Next run: now
+                    Next reminder: none
+                    Blocked by:
+                      - "/does-not-exist" is not mounted"#},
+                name
+            )
         );
+        assert!(manager.explain("does-not-exist").is_none());
     }
 
     #[test]
-    fn set_mounts() {
+    fn next_runs() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let script = ScheduleTestScript {
+            mount_paths: vec![PathBuf::from("/does-not-exist")],
+            interval: Duration::from_secs(3600),
+            reminder: None,
+            last_backup: Some(Duration::from_secs(1800)),
+            remind_when_target_missing: None,
+            enabled: None,
+            require_ac_power: false,
+            min_battery_percent: None,
+            only_when_idle: None,
+            state: None,
+        }
+        .into_script(&clock);
+        let name = script.name.clone();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            scripts: vec![script],
+            ..Default::default()
+        }));
+        let manager = ScriptManager::new(clock, settings, "");
+
+        assert_eq!(
+            manager.next_runs(),
+            vec![NextRun {
+                name,
+                next_run: Some(now + chrono::Duration::minutes(30)),
+                blocked_by: vec!["\"/does-not-exist\" is not mounted".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn progress() {
+        assert_eq!(parse_progress(""), None);
+        assert_eq!(parse_progress("copying"), None);
+        assert_eq!(
+            parse_progress("10\n42 copying photos\n\n"),
+            Some(Progress {
+                percent: 42,
+                stage: Some("copying photos".to_string())
+            })
+        );
+        assert_eq!(
+            parse_progress("150%"),
+            Some(Progress {
+                percent: 100,
+                stage: None
+            })
+        );
+    }
+
+    #[test]
+    fn replace_notifications() {
         let clock = Faker.fake::<Clock>();
         let settings = Arc::new(ArcSwap::from_pointee(Settings::default()));
         let mut manager = ScriptManager::new(clock, settings, "");
 
-        manager.set_mounts(indoc! {"
-            /dev/nvme0n1p2 / btrfs rw,relatime,ssd,discard=async,space_cache=v2,subvolid=403,subvol=/@/.snapshots/138/snapshot 0 0
-            devtmpfs /dev devtmpfs rw,nosuid,size=4096k,nr_inodes=8192558,mode=755,inode64 0 0
-            tmpfs /dev/shm tmpfs rw,nosuid,nodev,inode64 0 0
-        "});
+        manager.notifications = HashMap::from([
+            ("Home".to_string(), HashSet::from([1, 2])),
+            ("System".to_string(), HashSet::from([2])),
+        ]);
 
-        assert!(manager.mounts.contains(&PathBuf::from("/")));
-        assert!(manager.mounts.contains(&PathBuf::from("/dev/shm")));
-        assert!(!manager.mounts.contains(&PathBuf::from("/does-not-exist")));
+        // the reminder about both scripts is kept until both have been backed up
+        assert_eq!(manager.forget_notifications("Home"), vec![1]);
+        assert_eq!(manager.forget_notifications("Home"), Vec::<u32>::new());
+        assert_eq!(manager.forget_notifications("System"), vec![2]);
+    }
+
+    #[test]
+    fn running_notification_body() {
+        assert_eq!(
+            running_body(std_time::Duration::from_secs(150), None),
+            "Running for 2m"
+        );
+        assert_eq!(
+            running_body(
+                std_time::Duration::from_secs(3720),
+                Some(&Progress {
+                    percent: 42,
+                    stage: Some("copying photos".to_string())
+                })
+            ),
+            "Running for 1h 2m (42%, copying photos)"
+        );
+    }
+
+    #[test]
+    fn target_problems() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(target_problem(dir.path(), None), None);
+        assert_eq!(target_problem(dir.path(), Some(1)), None);
+        assert!(target_problem(dir.path(), Some(u64::MAX))
+            .unwrap()
+            .ends_with("GiB are needed"));
+        assert!(target_problem(&dir.path().join("missing"), None)
+            .unwrap()
+            .contains("can't be checked"));
+    }
+
+    #[test]
+    fn script_status() {
+        let clock = Faker.fake::<Clock>();
+        let script = |reminder| {
+            ScheduleTestScript {
+                interval: Duration::from_secs(3600),
+                reminder,
+                ..Default::default()
+            }
+            .into_script(&clock)
+        };
+        let scripts = vec![
+            script(Some(Duration::from_secs(3600))),
+            script(None),
+            script(None),
+        ];
+        let names = scripts
+            .iter()
+            .map(|script| script.name.clone())
+            .collect::<Vec<_>>();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            scripts,
+            ..Default::default()
+        }));
+        let mut manager = ScriptManager::new(clock, settings, "");
+        manager.states.insert(
+            names[2].clone(),
+            ScriptState::Failed(clock.now(), "failed".to_string()),
+        );
+
+        assert_eq!(manager.script_status(&names[0]), ScriptStatus::Overdue);
+        assert_eq!(manager.script_status(&names[1]), ScriptStatus::Ok);
+        assert_eq!(manager.script_status(&names[2]), ScriptStatus::Failed);
+    }
+
+    #[test]
+    fn dismissed_failures() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let scripts = (0..3)
+            .map(|_| {
+                ScheduleTestScript {
+                    mount_paths: Vec::new(),
+                    interval: Duration::from_secs(3600),
+                    reminder: None,
+                    last_backup: None,
+                    remind_when_target_missing: None,
+                    enabled: None,
+                    require_ac_power: false,
+                    min_battery_percent: None,
+                    only_when_idle: None,
+                    state: None,
+                }
+                .into_script(&clock)
+            })
+            .collect::<Vec<_>>();
+        let names = scripts
+            .iter()
+            .map(|script| script.name.clone())
+            .collect::<Vec<_>>();
+        let settings = Settings {
+            scripts,
+            failure_tooltip_duration: Some(Duration::from_secs(60 * 60)),
+            clear_failures_after: Some(Duration::from_secs(24 * 60 * 60)),
+            ..Default::default()
+        };
+        let mut manager =
+            ScriptManager::new(clock, Arc::new(ArcSwap::from_pointee(settings.clone())), "");
+        let failed = |ago: chrono::Duration| ScriptState::Failed(now - ago, "failed".to_string());
+        manager
+            .states
+            .insert(names[0].clone(), failed(chrono::Duration::zero()));
+        manager
+            .states
+            .insert(names[1].clone(), failed(chrono::Duration::hours(2)));
+        manager
+            .states
+            .insert(names[2].clone(), failed(chrono::Duration::days(2)));
+
+        assert_eq!(manager.script_status(&names[0]), ScriptStatus::Failed);
+        assert_eq!(manager.script_status(&names[1]), ScriptStatus::Failed);
+        assert_eq!(manager.script_status(&names[2]), ScriptStatus::Ok);
+        assert_eq!(
+            manager.tooltip_state(&settings, &settings.scripts[0]),
+            failed(chrono::Duration::zero())
+        );
+        assert_eq!(
+            manager.tooltip_state(&settings, &settings.scripts[1]),
+            ScriptState::WaitingForTime
+        );
+        assert_eq!(
+            manager.next_ui_update(),
+            Some(now + chrono::Duration::hours(1))
+        );
+
+        manager.dismiss_failure(&names[0]);
+        assert_eq!(manager.script_status(&names[0]), ScriptStatus::Ok);
+        assert_eq!(
+            manager.tooltip_state(&settings, &settings.scripts[0]),
+            ScriptState::WaitingForTime
+        );
+
+        // the next failure is shown again
+        manager
+            .states
+            .insert(names[0].clone(), failed(chrono::Duration::minutes(1)));
+        assert_eq!(manager.script_status(&names[0]), ScriptStatus::Failed);
+    }
+
+    #[test]
+    fn low_space() {
+        let clock = Faker.fake::<Clock>();
+        let dir = tempfile::tempdir().unwrap();
+        let mount = fs::canonicalize(dir.path()).unwrap();
+        let mut script = ScheduleTestScript {
+            mount_paths: vec![mount.clone()],
+            interval: Duration::from_secs(3600),
+            reminder: None,
+            last_backup: None,
+            remind_when_target_missing: None,
+            enabled: None,
+            require_ac_power: false,
+            min_battery_percent: None,
+            only_when_idle: None,
+            state: None,
+        }
+        .into_script(&clock);
+        script.mount_paths.push("/mnt/does-not-exist".into());
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            scripts: vec![script.clone()],
+            low_space_warning: Some(u64::MAX),
+            ..Default::default()
+        }));
+        let mut manager = ScriptManager::new(clock, settings.clone(), "");
+
+        // not mounted
+        assert!(manager.target_usage(&script).is_empty());
+        assert!(!manager.low_space());
+
+        manager.set_mounts(&format!("tmpfs {} tmpfs rw 0 0", mount.display()));
+        assert_eq!(
+            manager
+                .target_usage(&script)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            vec![mount.clone()]
+        );
+        assert!(manager.low_space());
+        assert!(manager.tooltip().contains(" (running low)"));
+
+        settings.store(Arc::new(Settings {
+            low_space_warning: None,
+            ..(**settings.load()).clone()
+        }));
+        assert!(!manager.low_space());
+    }
+
+    #[test]
+    fn usage() {
+        let usage = DiskUsage {
+            used: 1024 * 1024 * 1024 * 1024,
+            free: 512 * 1024 * 1024,
+        };
+
+        assert_eq!(
+            format_usage(Path::new("/mnt/backup"), usage, None),
+            "/mnt/backup: 512 MiB free, 1024.0 GiB used"
+        );
+        assert_eq!(
+            format_usage(Path::new("/mnt/backup"), usage, Some(1024 * 1024 * 1024)),
+            "/mnt/backup: 512 MiB free, 1024.0 GiB used (running low)"
+        );
+    }
+
+    #[test]
+    fn merged_finished_notifications() {
+        let finished = |name: &str, body: &str| (format!("{name} finished"), body.to_string());
+
+        assert_eq!(merge_finished(Vec::new()), None);
+        assert_eq!(
+            merge_finished(vec![finished("Backup", "Backup took 3m")]),
+            Some(finished("Backup", "Backup took 3m"))
+        );
+        assert_eq!(
+            merge_finished(vec![
+                finished("Backup", "Backup took 3m\nVerify failed"),
+                finished("Photos", ""),
+            ]),
+            Some((
+                "2 backups finished".to_string(),
+                "Backup finished: Backup took 3m, Verify failed\nPhotos finished".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn run_ids() {
+        let run_id = new_run_id();
+
+        assert_eq!(run_id.len(), 36);
+        assert_eq!(
+            run_id.split('-').map(str::len).collect::<Vec<_>>(),
+            vec![8, 4, 4, 4, 12]
+        );
+        assert_eq!(&run_id[14..15], "4");
+        assert!("89ab".contains(&run_id[19..20]));
+        assert_ne!(run_id, new_run_id());
+    }
+
+    #[test]
+    fn pruned_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..LOG_RETENTION + 2 {
+            let path = dir.path().join(format!("{i}.log"));
+            fs::write(&path, "").unwrap();
+            let modified =
+                std_time::SystemTime::UNIX_EPOCH + std_time::Duration::from_secs(i as u64);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+
+        prune_logs(dir.path()).unwrap();
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), LOG_RETENTION);
+        assert!(!dir.path().join("0.log").exists());
+        assert!(!dir.path().join("1.log").exists());
+        assert!(dir.path().join("2.log").exists());
+    }
+
+    #[test]
+    fn copied_failure_details() {
+        assert_eq!(
+            failure_details(
+                "Backup failed with exit code 2",
+                "Run 0b5c1a4e-7d2f-4c89-9a61-3f0e8b7d2c15",
+                Some(2),
+                Some(Path::new(
+                    "/home/alice/.local/state/backup-monitor/logs/Backup.log"
+                )),
+            ),
+            "Backup failed with exit code 2\n\
+             Run 0b5c1a4e-7d2f-4c89-9a61-3f0e8b7d2c15\n\
+             Exit code: 2\n\
+             Log: /home/alice/.local/state/backup-monitor/logs/Backup.log"
+        );
     }
 }
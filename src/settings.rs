@@ -1,9 +1,29 @@
-use std::{collections::HashSet, fs::File, io::Write, path::PathBuf, time::Duration};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use anyhow::{ensure, Context};
-use chrono::{DateTime, Utc};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc, Weekday};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    cron::Cron,
+    git_history,
+    lint::{lint, lint_mount_paths, lint_schedule},
+    network::Location,
+    state::{self, State},
+    time_window::TimeWindow,
+};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct PostScriptAction {
@@ -12,28 +32,516 @@ pub struct PostScriptAction {
     pub script: String,
 }
 
+// run automatically after the backup script succeeded, e.g. to prune or verify the backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PostBackupStep {
+    pub label: String,
+
+    pub script: String,
+
+    // the remaining steps are skipped if a mandatory step fails
+    #[serde(default)]
+    pub mandatory: bool,
+}
+
+// run before the backup script, a failing check is a warning, e.g. about the wrong disk or low
+// space on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PreflightCheck {
+    pub label: String,
+
+    pub script: String,
+}
+
+// conditions a run must meet besides exiting with 0, for wrapper scripts which swallow exit codes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SuccessWhen {
+    // a file matching the pattern (`*` and `?` in the file name) was modified during the run, e.g.
+    // `/mnt/backup/latest-*`
+    pub newer_file: Option<PathBuf>,
+
+    // the output (stdout and stderr) contains the text
+    pub output_contains: Option<String>,
+}
+
+// what happens to a scheduled run when pre-flight checks produced warnings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreflightPolicy {
+    // a notification asks whether to run anyway, skipped if nobody can be asked
+    #[default]
+    Ask,
+    Run,
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitLastBackup {
+    // as soon as the backup script succeeded
+    #[default]
+    AfterScript,
+    // once the mandatory post backup steps succeeded too
+    AfterMandatorySteps,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScheduleFrom {
+    #[default]
+    LastSuccess,
+    // failed runs are retried after the interval instead of the retry interval
+    LastAttempt,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Script {
     pub name: String,
 
+    // disabled scripts are neither run nor reminded of, but keep their last backup
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
     pub icon_name: Option<String>,
 
     pub backup_script: String,
 
+    #[serde(default)]
+    pub uses: Vec<String>,
+
     #[serde(default)]
     pub mount_paths: Vec<PathBuf>,
 
-    #[serde(with = "humantime_serde")]
-    pub interval: Duration,
+    pub auto_mount: Option<AutoMount>,
+
+    // in bytes, the mount paths must have at least this much free space before the script is run
+    #[serde(default, deserialize_with = "byte_size::deserialize")]
+    pub min_free_space: Option<u64>,
+
+    // either an interval or a schedule is required
+    #[serde(default, with = "humantime_hint")]
+    pub interval: Option<Duration>,
+
+    // cron expression, e.g. `0 22 * * *`, takes precedence over the interval
+    pub schedule: Option<Cron>,
+
+    #[serde(default)]
+    pub schedule_from: ScheduleFrom,
+
+    // due backups wait for these hours and days, manual runs don't
+    pub allowed_hours: Option<TimeWindow>,
 
-    #[serde(default, with = "humantime_serde")]
+    #[serde(default)]
+    pub allowed_days: Vec<Weekday>,
+
+    // a random delay up to this duration is added to each scheduled run to stagger the scripts
+    #[serde(default, with = "humantime_hint")]
+    pub jitter: Option<Duration>,
+
+    // scheduled runs wait while the computer runs on battery
+    #[serde(default)]
+    pub require_ac_power: bool,
+
+    // scheduled runs wait while the computer runs on a battery below this level
+    pub min_battery_percent: Option<u8>,
+
+    // scheduled runs wait until the user has been idle for this long
+    #[serde(default, with = "humantime_hint")]
+    pub only_when_idle: Option<Duration>,
+
+    #[serde(default, with = "humantime_hint")]
     pub reminder: Option<Duration>,
 
+    // for intentionally short intervals or reminders, see `lint_schedule`
+    #[serde(default)]
+    pub ignore_schedule_warnings: bool,
+
+    #[serde(default = "default_true")]
+    pub remind_when_target_missing: bool,
+
+    #[serde(default)]
+    pub plug_in_reminder: bool,
+
+    pub reminder_message: Option<String>,
+
+    // used instead of the summary of `reminder-notifications` if only this script is overdue
+    pub reminder_summary: Option<String>,
+
+    // overrides the repetition of `reminder-notifications` while this script is overdue
+    #[serde(default, with = "humantime_hint")]
+    pub reminder_repeat: Option<Duration>,
+
+    pub reminder_urgency: Option<Urgency>,
+
+    pub failure_message: Option<String>,
+
+    #[serde(default)]
+    pub privileged: bool,
+
+    // keeps the computer from going to sleep while the script runs
+    #[serde(default = "default_true")]
+    pub inhibit_sleep: bool,
+
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+
+    // maximum size of `$BM_WORK_DIR` in MiB
+    pub work_dir_limit: Option<u64>,
+
+    // number of increments of a backup chain reported in `$BM_CHAIN_FILE` before a new full
+    // backup is due
+    pub max_chain_length: Option<u32>,
+
+    #[serde(default)]
+    pub preflight_checks: Vec<PreflightCheck>,
+
+    #[serde(default)]
+    pub preflight_policy: PreflightPolicy,
+
+    pub success_when: Option<SuccessWhen>,
+
+    #[serde(default)]
+    pub post_backup_steps: Vec<PostBackupStep>,
+
+    #[serde(default)]
+    pub commit_last_backup: CommitLastBackup,
+
     #[serde(default)]
     pub post_backup_actions: Vec<PostScriptAction>,
 
     pub last_backup: Option<DateTime<Utc>>,
+
+    // time of the last run, successful or not
+    pub last_attempt: Option<DateTime<Utc>>,
+}
+
+// a systemd timer running a backup, which is only monitored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Timer {
+    pub name: String,
+
+    // e.g. `borgmatic.timer`
+    pub unit: String,
+
+    // the timer belongs to the system instance of systemd instead of the user instance
+    #[serde(default)]
+    pub system: bool,
+
+    #[serde(default, with = "humantime_hint")]
+    pub reminder: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotTool {
+    Snapper,
+    Timeshift,
+}
+
+// snapshots created by snapper or Timeshift, which are only monitored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Snapshots {
+    pub name: String,
+
+    pub tool: SnapshotTool,
+
+    // snapper config, `root` if not set
+    pub config: Option<String>,
+
+    // directory of the Timeshift snapshots, `/timeshift/snapshots` if not set
+    pub path: Option<PathBuf>,
+
+    // maximum age of the newest snapshot
+    #[serde(with = "humantime_hint")]
+    pub reminder: Duration,
+}
+
+// a named subset of the backup scripts, e.g. to skip the NAS backups while travelling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Profile {
+    pub name: String,
+
+    // names of the backup scripts which are run in this profile, the others are disabled
+    pub scripts: Vec<String>,
+
+    // the profile is activated automatically on matching networks
+    pub auto_activate: Option<AutoActivate>,
+}
+
+// matches if any of the values matches, or on every network if none is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AutoActivate {
+    pub ssids: Vec<String>,
+
+    // MAC addresses of the default gateway, e.g. `a4:2b:b0:12:34:56`
+    pub gateway_macs: Vec<String>,
+
+    // search domains of the resolver
+    pub domains: Vec<String>,
+}
+
+impl AutoActivate {
+    pub fn matches(&self, location: &Location) -> bool {
+        if self.ssids.is_empty() && self.gateway_macs.is_empty() && self.domains.is_empty() {
+            return true;
+        }
+
+        location
+            .ssid
+            .as_ref()
+            .is_some_and(|ssid| self.ssids.contains(ssid))
+            || location.gateway_mac.as_ref().is_some_and(|mac| {
+                self.gateway_macs
+                    .iter()
+                    .any(|gateway_mac| gateway_mac.eq_ignore_ascii_case(mac))
+            })
+            || location.domains.iter().any(|domain| {
+                self.domains
+                    .iter()
+                    .any(|auto_domain| auto_domain.eq_ignore_ascii_case(domain))
+            })
+    }
+}
+
+// called on backup results, e.g. to forward them to Slack, Matrix or ntfy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Webhook {
+    pub url: String,
+
+    #[serde(default = "default_webhook_method")]
+    pub method: String,
+
+    // JSON with `{{variable}}` placeholders, a JSON object with the event, script name and
+    // message if not set
+    pub payload: Option<String>,
+
+    #[serde(default = "default_webhook_events")]
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    Started,
+    Succeeded,
+    Failed,
+}
+
+// sent over SMTP when a script keeps failing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Email {
+    pub smtp_server: String,
+
+    // the default port for STARTTLS if not set
+    pub port: Option<u16>,
+
+    pub username: Option<String>,
+
+    pub password: Option<String>,
+
+    pub from: String,
+
+    pub to: String,
+
+    // number of consecutive failures of a script after which an email is sent
+    #[serde(default = "default_email_after_failures")]
+    pub after_failures: u32,
+}
+
+// the states of the scripts are published with Home Assistant discovery configs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Mqtt {
+    pub host: String,
+
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+
+    pub username: Option<String>,
+
+    pub password: Option<String>,
+
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+
+    #[serde(default = "default_mqtt_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+// compares the settings file with a reference copy, e.g. in a dotfiles repo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DriftCheck {
+    pub reference: PathBuf,
+
+    // compares with the reference as committed in this git revision (e.g. `HEAD` or
+    // `origin/main`) instead of the file itself
+    pub revision: Option<String>,
+
+    #[serde(default = "default_drift_check_interval", with = "humantime_hint")]
+    pub interval: Duration,
+}
+
+// mounts a file system with UDisks2 when the script is due and waits for its mount paths
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AutoMount {
+    // of the LUKS container for encrypted file systems
+    pub uuid: String,
+
+    // prints the passphrase of an encrypted file system, e.g. from the keyring
+    pub passphrase_command: Option<String>,
+
+    // unmounts the file system after the run if it has been mounted by the backup monitor, and
+    // locks it again if it has been unlocked
+    #[serde(default)]
+    pub unmount: bool,
+}
+
+// copies the settings, state and history to the backup targets, so that a restored computer can
+// recover its backup configuration first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConfigBackup {
+    // copies into this directory instead of `.backup-monitor` in the mount paths of the scripts
+    pub path: Option<PathBuf>,
+
+    #[serde(default = "default_config_backup_interval", with = "humantime_hint")]
+    pub interval: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+// reminders of all overdue backups are combined into one notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ReminderNotifications {
+    // how often the reminder is repeated while backups are overdue
+    #[serde(with = "humantime_hint")]
+    pub repeat: Duration,
+
+    // `{names}` and `{count}` of the overdue backups are replaced
+    pub summary: Option<String>,
+
+    // one line per overdue backup script, the `reminder-message` of the script takes precedence
+    pub message: Option<String>,
+
+    pub urgency: Urgency,
+
+    // reminders of scripts whose mount paths have been missing this long ask to connect the backup
+    // disk instead
+    #[serde(with = "humantime_hint")]
+    pub missing_target_after: Option<Duration>,
+}
+
+impl Default for ReminderNotifications {
+    fn default() -> Self {
+        ReminderNotifications {
+            repeat: Duration::from_secs(4 * 60 * 60),
+            summary: None,
+            message: None,
+            urgency: Urgency::Normal,
+            missing_target_after: Some(Duration::from_secs(14 * 24 * 60 * 60)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ScriptDefaults {
+    pub strict_mode: bool,
+}
+
+// composition of the system tray menu
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Menu {
+    pub layout: MenuLayout,
+
+    // pinned to the top of the menu in this order
+    pub favorites: Vec<String>,
+
+    pub hide_exit: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatusMarkers {
+    #[default]
+    None,
+    Emoji,
+    // for fonts without emoji
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MenuLayout {
+    #[default]
+    Flat,
+    // a submenu per script with its actions and log
+    Grouped,
+}
+
+// chores which aren't scheduled, e.g. mounting the NAS, run from the tray menu like post backup
+// actions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TrayCommand {
+    pub label: String,
+
+    pub icon_name: Option<String>,
+
+    // an inline script, a single command line doesn't need a shebang
+    pub command: String,
+}
+
+impl TrayCommand {
+    pub fn script(&self, settings: &Settings) -> String {
+        let script = settings.script_with_defaults(&self.command);
+        if script.starts_with("#!") {
+            script
+        } else {
+            format!("#!/bin/sh\n{script}")
+        }
+    }
+}
+
+// the tooltip is updated at most this often, `None` to update it whenever the displayed time changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct UiUpdateAccuracy {
+    #[serde(with = "humantime_hint")]
+    pub on_ac: Option<Duration>,
+
+    #[serde(with = "humantime_hint")]
+    pub on_battery: Option<Duration>,
+}
+
+impl Default for UiUpdateAccuracy {
+    fn default() -> Self {
+        UiUpdateAccuracy {
+            on_ac: None,
+            on_battery: Some(Duration::from_secs(5 * 60)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,9 +551,122 @@ pub struct Settings {
 
     pub title: String,
 
+    pub snippets: BTreeMap<String, String>,
+
     pub scripts: Vec<Script>,
 
+    pub timers: Vec<Timer>,
+
+    pub snapshots: Vec<Snapshots>,
+
+    pub profiles: Vec<Profile>,
+
     pub autostart: bool,
+
+    pub paused_until: Option<DateTime<Utc>>,
+
+    // the first start of the backup monitor, the no backup alert counts from it until a script has
+    // run
+    pub monitored_since: Option<DateTime<Utc>>,
+
+    // switched from the system tray menu, kept in the state file like `paused_until`
+    pub active_profile: Option<String>,
+
+    pub primary_user: Option<String>,
+
+    // failure notifications are shown in the graphical sessions of all users
+    pub broadcast_failures: bool,
+
+    pub metrics_address: Option<String>,
+
+    #[serde(with = "humantime_hint")]
+    pub no_backup_alert: Option<Duration>,
+
+    // warn if a backup target is projected to be full within this duration
+    #[serde(with = "humantime_hint")]
+    pub disk_full_warning: Option<Duration>,
+
+    // in bytes, the tray icon needs attention while a mounted backup target has less space free
+    #[serde(deserialize_with = "byte_size::deserialize")]
+    pub low_space_warning: Option<u64>,
+
+    // the message of a failure is only shown in the tooltip this long after the failure
+    #[serde(with = "humantime_hint")]
+    pub failure_tooltip_duration: Option<Duration>,
+
+    // failures are dismissed automatically this long after they happened
+    #[serde(with = "humantime_hint")]
+    pub clear_failures_after: Option<Duration>,
+
+    pub audit_journal: bool,
+
+    pub rich_tooltip: bool,
+
+    // prefixed to the script names in the tooltip and the menu
+    pub status_markers: StatusMarkers,
+
+    pub script_defaults: ScriptDefaults,
+
+    #[serde(with = "humantime_hint")]
+    pub mount_poll_interval: Duration,
+
+    pub ui_update_accuracy: UiUpdateAccuracy,
+
+    // opens the output of the last run of a script, `xdg-open` if not set
+    pub log_viewer: Option<String>,
+
+    pub menu: Menu,
+
+    pub tray_commands: Vec<TrayCommand>,
+
+    // delivers reminders while the screen is locked (e.g. to ntfy or by email), they are shown
+    // once the screen is unlocked if not set
+    pub locked_reminder_command: Option<String>,
+
+    pub reminder_notifications: ReminderNotifications,
+
+    // also publishes the freshness of the backups as an extended attribute of the home directory
+    pub freshness_xattr: bool,
+
+    // file (or `-` for stdout) to which the status is written in waybar's custom module format,
+    // for bars without system tray support
+    pub status_output: Option<PathBuf>,
+
+    pub webhooks: Vec<Webhook>,
+
+    pub email: Option<Email>,
+
+    pub mqtt: Option<Mqtt>,
+
+    pub drift_check: Option<DriftCheck>,
+
+    pub config_backup: Option<ConfigBackup>,
+
+    // runs the overdue scripts once at login with `backup-monitor oneshot`, even if the tray isn't
+    // started automatically
+    pub catch_up_on_login: bool,
+
+    // scheduled runs wait this long after the start, e.g. while the session is still loading
+    #[serde(with = "humantime_hint")]
+    pub startup_delay: Option<Duration>,
+
+    // running scripts are stopped this long after a shutdown has been requested, logind
+    // only waits as long as `InhibitDelayMaxSec` allows
+    #[serde(with = "humantime_hint")]
+    pub shutdown_grace_period: Duration,
+
+    // wakes the computer from suspend for the next scheduled backup, needs root privileges
+    pub rtc_wake: bool,
+
+    // suspends the computer again after running the backups it has been woken up for
+    pub suspend_after_wake: bool,
+
+    // commits the settings and the state to a git repo next to the settings file on every change
+    pub git_history: bool,
+
+    // problems found in the backup scripts, by script name
+    #[serde(skip)]
+    pub lint_warnings: BTreeMap<String, Vec<String>>,
 }
 
 impl Default for Settings {
@@ -53,53 +674,597 @@ impl Default for Settings {
         Settings {
             icon_name: "backup".to_string(),
             title: "Backup".to_string(),
+            snippets: BTreeMap::new(),
             scripts: Vec::new(),
+            timers: Vec::new(),
+            snapshots: Vec::new(),
+            profiles: Vec::new(),
             autostart: false,
+            paused_until: None,
+            monitored_since: None,
+            active_profile: None,
+            primary_user: None,
+            broadcast_failures: false,
+            metrics_address: None,
+            no_backup_alert: Some(Duration::from_secs(14 * 24 * 60 * 60)),
+            disk_full_warning: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+            low_space_warning: None,
+            failure_tooltip_duration: None,
+            clear_failures_after: None,
+            audit_journal: false,
+            rich_tooltip: false,
+            status_markers: StatusMarkers::default(),
+            script_defaults: ScriptDefaults::default(),
+            mount_poll_interval: Duration::from_secs(10),
+            ui_update_accuracy: UiUpdateAccuracy::default(),
+            log_viewer: None,
+            menu: Menu::default(),
+            tray_commands: Vec::new(),
+            locked_reminder_command: None,
+            reminder_notifications: ReminderNotifications::default(),
+            freshness_xattr: false,
+            status_output: None,
+            webhooks: Vec::new(),
+            email: None,
+            mqtt: None,
+            drift_check: None,
+            config_backup: None,
+            catch_up_on_login: false,
+            startup_delay: None,
+            shutdown_grace_period: Duration::from_secs(5),
+            rtc_wake: false,
+            suspend_after_wake: false,
+            git_history: false,
+            lint_warnings: BTreeMap::new(),
         }
     }
 }
 
 impl Settings {
     pub fn load() -> anyhow::Result<Settings> {
-        let settings_file_path = settings_file_path()?;
+        let mut settings = Settings::load_from(&settings_file_path()?)?;
+
+        // without the state, the values in the settings file are used
+        match State::load() {
+            Ok(Some(state)) => state.apply(&mut settings),
+            Ok(None) => {}
+            Err(error) => log::warn!("failed to load state: {error:#}"),
+        }
+        settings.apply_profile();
+
+        Ok(settings)
+    }
 
-        if !settings_file_path.exists() {
+    fn load_from(path: &Path) -> anyhow::Result<Settings> {
+        if !path.exists() {
             let settings = Settings::default();
-            settings.save()?;
+            settings.save_to(path)?;
         }
 
-        let file = File::open(settings_file_path)?;
-        let settings = serde_yaml_ng::from_reader::<_, Settings>(&file)?;
+        let mut settings = parse::<Settings>(&fs::read_to_string(path)?)?;
+
+        for file in include_files(&include_dir_path(path))? {
+            let include = parse::<Include>(&fs::read_to_string(&file)?)
+                .with_context(|| format!("`{}`", file.display()))?;
+            settings.scripts.extend(include.scripts);
+        }
 
-        let script_names = settings
+        let names = settings
             .scripts
             .iter()
             .map(|script| &script.name)
+            .chain(settings.timers.iter().map(|timer| &timer.name))
+            .chain(settings.snapshots.iter().map(|snapshots| &snapshots.name))
             .collect::<HashSet<_>>();
         ensure!(
-            script_names.len() == settings.scripts.len(),
-            "script names must be unique"
+            names.len()
+                == settings.scripts.len() + settings.timers.len() + settings.snapshots.len(),
+            "script, timer and snapshot names must be unique"
         );
 
+        for profile in &settings.profiles {
+            for name in &profile.scripts {
+                ensure!(
+                    settings.scripts.iter().any(|script| script.name == *name),
+                    "profile `{}` contains unknown script `{name}`",
+                    profile.name
+                );
+            }
+        }
+
+        for name in &settings.menu.favorites {
+            ensure!(
+                settings.scripts.iter().any(|script| script.name == *name),
+                "menu favorites contain unknown script `{name}`"
+            );
+        }
+
+        for script in &settings.scripts {
+            ensure!(
+                script.interval.is_some() || script.schedule.is_some(),
+                "script `{}` needs an `interval` or a `schedule`",
+                script.name
+            );
+            ensure!(
+                script.auto_mount.is_none() || !script.mount_paths.is_empty(),
+                "`auto-mount` of script `{}` needs `mount-paths`",
+                script.name
+            );
+            ensure!(
+                script.min_free_space.is_none() || !script.mount_paths.is_empty(),
+                "`min-free-space` of script `{}` needs `mount-paths`",
+                script.name
+            );
+            ensure!(
+                script
+                    .min_battery_percent
+                    .is_none_or(|percent| percent <= 100),
+                "`min-battery-percent` of script `{}` must be at most 100",
+                script.name
+            );
+            for snippet in &script.uses {
+                ensure!(
+                    settings.snippets.contains_key(snippet),
+                    "script `{}` uses unknown snippet `{snippet}`",
+                    script.name
+                );
+            }
+        }
+
+        for script in &settings.scripts {
+            let mut warnings = lint(&settings.backup_script(script));
+            warnings.extend(lint_schedule(script));
+            warnings.extend(lint_mount_paths(script));
+            for warning in &warnings {
+                log::warn!("script `{}`: {warning}", script.name);
+            }
+            if !warnings.is_empty() {
+                settings.lint_warnings.insert(script.name.clone(), warnings);
+            }
+        }
+
         log::trace!("settings loaded: {settings:#?}");
 
         Ok(settings)
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
-        let settings_file_path = settings_file_path()?;
+    // the first profile whose rules match the location, `None` if no profile is activated
+    // automatically
+    pub fn auto_profile(&self, location: &Location) -> Option<Option<String>> {
+        let mut profiles = self
+            .profiles
+            .iter()
+            .filter_map(|profile| Some((profile, profile.auto_activate.as_ref()?)))
+            .peekable();
+        profiles.peek()?;
+
+        // all backup scripts are used if no profile matches
+        Some(
+            profiles
+                .find(|(_, auto_activate)| auto_activate.matches(location))
+                .map(|(profile, _)| profile.name.clone()),
+        )
+    }
+
+    // disables the backup scripts which aren't part of the active profile
+    fn apply_profile(&mut self) {
+        let Some(name) = &self.active_profile else {
+            return;
+        };
+        let Some(profile) = self.profiles.iter().find(|profile| profile.name == *name) else {
+            log::warn!("profile `{name}` not found, all backup scripts are used");
+            return;
+        };
+        for script in &mut self.scripts {
+            if !profile.scripts.contains(&script.name) {
+                script.enabled = false;
+            }
+        }
+    }
+
+    // inserts the snippets used by the script after its shebang
+    pub fn backup_script(&self, script: &Script) -> String {
+        let backup_script = self.script_with_defaults(&script.backup_script);
+        if script.uses.is_empty() {
+            return backup_script;
+        }
+
+        let (shebang, body) = match backup_script.split_once('\n') {
+            Some((first_line, rest)) if first_line.starts_with("#!") => (Some(first_line), rest),
+            _ => (None, backup_script.as_str()),
+        };
+
+        let mut output = String::new();
+        if let Some(shebang) = shebang {
+            output.push_str(shebang);
+            output.push('\n');
+        }
+        for snippet in script
+            .uses
+            .iter()
+            .filter_map(|name| self.snippets.get(name))
+        {
+            output.push_str(snippet);
+            if !snippet.ends_with('\n') {
+                output.push('\n');
+            }
+        }
+        output.push_str(body);
+        output
+    }
+
+    // prepends a bash shebang and strict mode to scripts without a shebang if enabled
+    pub fn script_with_defaults(&self, script: &str) -> String {
+        if self.script_defaults.strict_mode && !script.starts_with("#!") {
+            format!("#!/usr/bin/env bash\nset -euo pipefail\n{script}")
+        } else {
+            script.to_string()
+        }
+    }
+
+    pub fn paused_until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.paused_until.filter(|until| *until > now)
+    }
+
+    fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let content = format!(
+            "# see https://github.com/dfaust/backup-monitor/blob/master/README.md for instructions\n{}",
+            serde_yaml_ng::to_string(self)?
+        );
 
-        let mut file = File::create(settings_file_path)?;
-        file.write_all(b"# see https://github.com/dfaust/backup-monitor/blob/master/README.md for instructions\n")?;
-        serde_yaml_ng::to_writer(&file, self)?;
+        // remember what has been written to ignore the resulting change events
+        let mut write_token = WRITE_TOKEN.lock().unwrap();
+        fs::write(path, &content)?;
+        *write_token = Some(hash(&settings_content(path)?));
 
         log::trace!("settings saved");
 
         Ok(())
     }
+
+    // `false` if the settings file still contains what has been written by `save` and the
+    // included files didn't change since
+    pub fn changed_externally() -> anyhow::Result<bool> {
+        Settings::file_changed_externally(&settings_file_path()?)
+    }
+
+    fn file_changed_externally(path: &Path) -> anyhow::Result<bool> {
+        let content = settings_content(path)?;
+        Ok(*WRITE_TOKEN.lock().unwrap() != Some(hash(&content)))
+    }
+}
+
+// additional settings files with scripts, e.g. for per-machine scripts managed by a dotfile manager
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+struct Include {
+    scripts: Vec<Script>,
+}
+
+// e.g. `~/.config/backup-monitor.d` for `~/.config/backup-monitor.yaml`
+pub fn include_dir_path(settings_path: &Path) -> PathBuf {
+    settings_path.with_extension("d")
+}
+
+// the `*.yaml` files of the include directory, in the order of their names
+pub fn include_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|file| {
+        file.extension()
+            .is_some_and(|extension| extension == "yaml")
+    });
+    files.sort();
+    Ok(files)
+}
+
+// the settings file together with the included files
+fn settings_content(path: &Path) -> anyhow::Result<String> {
+    let mut content = fs::read_to_string(path)?;
+    for file in include_files(&include_dir_path(path))? {
+        content.push_str(&file.to_string_lossy());
+        content.push_str(&fs::read_to_string(&file)?);
+    }
+    Ok(content)
+}
+
+// changes the loaded settings and writes the runtime state (`last_backup`, `paused_until`, ...)
+// to the state file, the settings file is left alone, the message describes the change for the
+// git history
+pub fn update_settings(
+    settings: &ArcSwap<Settings>,
+    message: &str,
+    change: impl Fn(&mut Settings),
+) {
+    let _lock = UPDATE_LOCK.lock().unwrap();
+
+    let mut updated_settings = Settings::clone(&settings.load());
+    change(&mut updated_settings);
+    if let Err(error) = state::save_settings_state(&updated_settings) {
+        log::error!("failed to save state: {error:#}");
+    }
+    git_history::commit(&updated_settings, message);
+    settings.store(Arc::new(updated_settings));
+}
+
+static WRITE_TOKEN: Mutex<Option<u64>> = Mutex::new(None);
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+fn hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+const EXAMPLE_SCRIPT: &str = "
+# Uncomment and adapt the example backup script below, then save the file:
+# scripts:
+# - name: Home Backup
+#   backup-script: |
+#     #!/usr/bin/env bash
+#     set -o errexit
+#     rsync -ah --delete $HOME/ /mnt/backup/home/
+#   mount-paths: [\"/mnt/backup\"]
+#   interval: 1day
+#   reminder: 7days
+";
+
+// appends a commented out example script to the settings file, which doesn't have any scripts
+pub fn add_example_script() -> anyhow::Result<PathBuf> {
+    let path = settings_file_path()?;
+    let content = fs::read_to_string(&path)?;
+
+    if !content.contains(EXAMPLE_SCRIPT) {
+        let mut content = content
+            .lines()
+            .filter(|line| *line != "scripts: []")
+            .map(|line| format!("{line}\n"))
+            .collect::<String>();
+        content.push_str(EXAMPLE_SCRIPT);
+        fs::write(&path, content)?;
+    }
+
+    Ok(path)
+}
+
+// validates the settings file, or another file (e.g. in a dotfiles repo before deploying it),
+// `false` if warnings are denied and there are any
+pub fn check_config(path: Option<&Path>, deny_warnings: bool) -> anyhow::Result<bool> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => settings_file_path()?,
+    };
+    // a missing settings file would be created with the defaults
+    ensure!(path.exists(), "`{}` doesn't exist", path.display());
+    let settings = Settings::load_from(&path)?;
+
+    println!("`{}` is valid", path.display());
+
+    for (name, warnings) in &settings.lint_warnings {
+        println!("\n{name}:");
+        for warning in warnings {
+            println!("  warning: {warning}");
+        }
+    }
+
+    let count = settings.lint_warnings.values().map(Vec::len).sum::<usize>();
+    if count > 0 {
+        println!("\n{count} warning(s)");
+    }
+
+    Ok(count == 0 || !deny_warnings)
+}
+
+// the path of a serde error only has the index of a script, e.g. `scripts[1].interval`, so the
+// failing script (or timer, ...) is looked up to name it
+fn parse<T: for<'de> Deserialize<'de>>(content: &str) -> anyhow::Result<T> {
+    serde_yaml_ng::from_str::<T>(content).map_err(|error| {
+        let Ok(value) = serde_yaml_ng::from_str::<serde_yaml_ng::Value>(content) else {
+            return error.into();
+        };
+
+        let failing = failing_item::<Script>(&value, "scripts", "script")
+            .or_else(|| failing_item::<Timer>(&value, "timers", "timer"))
+            .or_else(|| failing_item::<Snapshots>(&value, "snapshots", "snapshots"));
+        match failing {
+            Some(item) => anyhow::anyhow!("{item}: {error}"),
+            None => error.into(),
+        }
+    })
+}
+
+// e.g. script `Backup`
+fn failing_item<T: for<'de> Deserialize<'de>>(
+    value: &serde_yaml_ng::Value,
+    key: &str,
+    kind: &str,
+) -> Option<String> {
+    value.get(key)?.as_sequence()?.iter().find_map(|item| {
+        serde_yaml_ng::from_value::<T>(item.clone()).err()?;
+        let name = item.get("name").and_then(|name| name.as_str())?;
+        Some(format!("{kind} `{name}`"))
+    })
+}
+
+// like `humantime_serde`, with examples of valid durations in errors. The error is raised by the
+// visitor, so the deserializer gives it the path of the field, e.g. `scripts[1].interval`.
+mod humantime_hint {
+    use std::{fmt, time::Duration};
+
+    use serde::{de, Deserialize, Deserializer};
+
+    pub use humantime_serde::serialize;
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Hint,
+    {
+        T::deserialize_hinted(deserializer)
+    }
+
+    pub trait Hint: Sized {
+        fn deserialize_hinted<'de, D: Deserializer<'de>>(deserializer: D)
+            -> Result<Self, D::Error>;
+    }
+
+    impl Hint for Duration {
+        fn deserialize_hinted<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Self, D::Error> {
+            Hinted::deserialize(deserializer).map(|Hinted(duration)| duration)
+        }
+    }
+
+    impl Hint for Option<Duration> {
+        fn deserialize_hinted<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Self, D::Error> {
+            Option::<Hinted>::deserialize(deserializer)
+                .map(|duration| duration.map(|Hinted(duration)| duration))
+        }
+    }
+
+    struct Hinted(Duration);
+
+    impl<'de> Deserialize<'de> for Hinted {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_str(HintedVisitor)
+        }
+    }
+
+    struct HintedVisitor;
+
+    impl de::Visitor<'_> for HintedVisitor {
+        type Value = Hinted;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a duration (use formats like `1day 12h`, `30min` or `2weeks`)")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Hinted, E> {
+            humantime::parse_duration(value)
+                .map(Hinted)
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+        }
+    }
+}
+
+mod byte_size {
+    use serde::{de, Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    // `50GB` or `1.5 TiB`, a plain number counts bytes
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<Size>::deserialize(deserializer)? {
+            Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+            Some(Size::Text(text)) => parse(&text).map(Some).ok_or_else(|| {
+                de::Error::custom(format!(
+                    "invalid size `{text}` (use formats like `50GB`, `500MiB` or `1.5TB`)"
+                ))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    // decimal units like `GB` and binary units like `GiB`
+    pub fn parse(text: &str) -> Option<u64> {
+        let text = text.trim();
+        let split = text
+            .find(|char: char| char.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number = number.trim().parse::<f64>().ok()?;
+        let factor = match unit.to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "mb" => 1000_u64.pow(2),
+            "gb" => 1000_u64.pow(3),
+            "tb" => 1000_u64.pow(4),
+            "kib" => 1024,
+            "mib" => 1024_u64.pow(2),
+            "gib" => 1024_u64.pow(3),
+            "tib" => 1024_u64.pow(4),
+            _ => return None,
+        };
+        (number >= 0.0).then(|| (number * factor as f64).round() as u64)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_email_after_failures() -> u32 {
+    3
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "backup-monitor".to_string()
+}
+
+fn default_mqtt_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_drift_check_interval() -> Duration {
+    Duration::from_secs(7 * 24 * 60 * 60)
+}
+
+fn default_config_backup_interval() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+fn default_webhook_method() -> String {
+    "POST".to_string()
+}
+
+fn default_webhook_events() -> Vec<WebhookEvent> {
+    vec![
+        WebhookEvent::Started,
+        WebhookEvent::Succeeded,
+        WebhookEvent::Failed,
+    ]
+}
+
+static SYSTEM_MODE: AtomicBool = AtomicBool::new(false);
+
+// use the system-wide settings in `/etc/backup-monitor`
+pub fn set_system_mode() {
+    SYSTEM_MODE.store(true, Ordering::Relaxed);
+}
+
+pub fn system_mode() -> bool {
+    SYSTEM_MODE.load(Ordering::Relaxed)
 }
 
 pub fn settings_file_path() -> anyhow::Result<PathBuf> {
+    if system_mode() {
+        return Ok(PathBuf::from("/etc/backup-monitor/backup-monitor.yaml"));
+    }
+
     let config_dir = dirs::config_dir().context("config dir not found")?;
     Ok(config_dir.join("backup-monitor.yaml"))
 }
@@ -137,16 +1302,60 @@ mod tests {
         let yaml = indoc! {"
             icon-name: backup
             title: Backup
+            snippets:
+              mount-check: |
+                mountpoint -q /mnt/backup
             scripts:
             - name: Backup
+              enabled: true
               icon-name: null
               backup-script: |
                 #!/usr/bin/env bash
                 set -o errexit
                 /usr/bin/backup.sh
+              uses: [mount-check]
               mount-paths: [\"/mnt/backup\"]
+              auto-mount:
+                uuid: 2f1e9c4a-7d3b-4e8f-9a61-0c5d2b7e8f13
+                passphrase-command: secret-tool lookup backup-disk usb
+                unmount: true
+              min-free-space: 50GB
               interval: 1day
+              schedule: 0 22 * * *
+              schedule-from: last-attempt
+              allowed-hours: 20:00-06:00
+              allowed-days: [mon, tue, wed, thu, fri]
+              jitter: 30m
+              require-ac-power: true
+              min-battery-percent: 40
+              only-when-idle: 10m
               reminder: 7days
+              ignore-schedule-warnings: true
+              remind-when-target-missing: false
+              plug-in-reminder: true
+              reminder-message: '{name} ist seit {overdue} überfällig (zuletzt {last_backup})'
+              reminder-summary: '{name} überfällig'
+              reminder-repeat: 1h
+              reminder-urgency: critical
+              failure-message: '{name} fehlgeschlagen'
+              privileged: true
+              inhibit-sleep: false
+              allowed-users: [bob]
+              work-dir-limit: 2048
+              max-chain-length: 30
+              preflight-checks:
+                - label: Free space
+                  script: |
+                    [ $(df --output=avail /mnt/backup | tail -1) -gt 10000000 ] || { echo less than 10 GB free; exit 1; }
+              preflight-policy: skip
+              success-when:
+                newer-file: /mnt/backup/latest-*
+                output-contains: snapshot saved
+              post-backup-steps:
+                - label: Verify backup
+                  script: borg check /mnt/backup/repo
+                  mandatory: true
+              commit-last-backup: after-mandatory-steps
               post-backup-actions:
                 - label: Unmount backup HDD
                   script: |
@@ -154,10 +1363,426 @@ mod tests {
                     set -o errexit
                     umount /mnt/backup
               last-backup: 2024-10-24T20:18:00.857399073Z
+              last-attempt: 2024-10-25T20:18:00.123456789Z
+            timers:
+            - name: Borgmatic
+              unit: borgmatic.timer
+              system: true
+              reminder: 3days
+            snapshots:
+            - name: Root snapshots
+              tool: snapper
+              config: root
+              path: null
+              reminder: 1day
+            profiles:
+            - name: Home
+              scripts: [Backup]
+              auto-activate:
+                ssids: [FRITZ!Box 7590]
+                gateway-macs: ['a4:2b:b0:12:34:56']
+                domains: [fritz.box]
+            - name: Travel
+              scripts: []
+              auto-activate: {}
             autostart: true
+            paused-until: 2024-11-02T08:00:00Z
+            monitored-since: 2024-10-01T08:00:00Z
+            active-profile: Travel
+            primary-user: alice
+            broadcast-failures: true
+            metrics-address: 127.0.0.1:9184
+            no-backup-alert: 30days
+            disk-full-warning: 14days
+            low-space-warning: 20GB
+            failure-tooltip-duration: 1day
+            clear-failures-after: 1week
+            audit-journal: true
+            rich-tooltip: true
+            status-markers: emoji
+            script-defaults:
+              strict-mode: true
+            mount-poll-interval: 30s
+            ui-update-accuracy:
+              on-ac: 1m
+              on-battery: 15m
+            log-viewer: kitty less
+            menu:
+              layout: grouped
+              favorites:
+                - Backup
+              hide-exit: true
+            tray-commands:
+              - label: Mount NAS
+                icon-name: drive-harddisk
+                command: mount /mnt/nas
+            locked-reminder-command: /usr/local/bin/push-reminder
+            reminder-notifications:
+              repeat: 2h
+              summary: '{count} Backups überfällig'
+              message: '{name}: {overdue}'
+              urgency: low
+              missing-target-after: 7days
+            freshness-xattr: true
+            status-output: /run/user/1000/backup-monitor.json
+            webhooks:
+            - url: https://ntfy.sh/backups
+              payload: '{\"topic\": \"backups\", \"message\": \"{{script.name}} {{event}}\"}'
+              events: [failed]
+            email:
+              smtp-server: smtp.example.com
+              port: 587
+              username: alice
+              password: secret
+              from: backup@example.com
+              to: alice@example.com
+              after-failures: 2
+            mqtt:
+              host: homeassistant.local
+              username: backup
+              password: secret
+            drift-check:
+              reference: /home/alice/dotfiles/backup-monitor.yaml
+              revision: origin/main
+            config-backup:
+              path: /srv/nas/config
+            git-history: true
+            catch-up-on-login: true
+            startup-delay: 2m
+            shutdown-grace-period: 2m
+            rtc-wake: true
+            suspend-after-wake: true
         "};
         let settings = serde_yaml_ng::from_str::<Settings>(yaml).unwrap();
 
         insta::assert_yaml_snapshot!(settings);
     }
+
+    #[test]
+    fn backup_script_with_snippets() {
+        let yaml = indoc! {"
+            snippets:
+              mount-check: |
+                mountpoint -q /mnt/backup
+              notify-phone: curl -d done https://ntfy.sh/backups
+            scripts:
+            - name: Backup
+              backup-script: |
+                #!/usr/bin/env bash
+                set -o errexit
+                /usr/bin/backup.sh
+              uses: [mount-check, notify-phone]
+              interval: 1day
+            - name: Without shebang
+              backup-script: /usr/bin/backup.sh
+              uses: [mount-check]
+              interval: 1day
+        "};
+        let settings = serde_yaml_ng::from_str::<Settings>(yaml).unwrap();
+
+        assert_eq!(
+            settings.backup_script(&settings.scripts[0]),
+            indoc! {"
+                #!/usr/bin/env bash
+                mountpoint -q /mnt/backup
+                curl -d done https://ntfy.sh/backups
+                set -o errexit
+                /usr/bin/backup.sh
+            "}
+        );
+        assert_eq!(
+            settings.backup_script(&settings.scripts[1]),
+            "mountpoint -q /mnt/backup\n/usr/bin/backup.sh"
+        );
+    }
+
+    #[test]
+    fn backup_script_with_strict_mode() {
+        let yaml = indoc! {"
+            snippets:
+              mount-check: |
+                mountpoint -q /mnt/backup
+            script-defaults:
+              strict-mode: true
+            scripts:
+            - name: Without shebang
+              backup-script: /usr/bin/backup.sh
+              uses: [mount-check]
+              interval: 1day
+            - name: With shebang
+              backup-script: |
+                #!/bin/sh
+                /usr/bin/backup.sh
+              interval: 1day
+        "};
+        let settings = serde_yaml_ng::from_str::<Settings>(yaml).unwrap();
+
+        assert_eq!(
+            settings.backup_script(&settings.scripts[0]),
+            indoc! {"
+                #!/usr/bin/env bash
+                mountpoint -q /mnt/backup
+                set -euo pipefail
+                /usr/bin/backup.sh"}
+        );
+        assert_eq!(
+            settings.backup_script(&settings.scripts[1]),
+            "#!/bin/sh\n/usr/bin/backup.sh\n"
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(byte_size::parse("50GB"), Some(50_000_000_000));
+        assert_eq!(byte_size::parse("1.5 TiB"), Some(1_649_267_441_664));
+        assert_eq!(byte_size::parse("500mib"), Some(524_288_000));
+        assert_eq!(byte_size::parse("4096"), Some(4096));
+        assert_eq!(byte_size::parse("50 GBs"), None);
+        assert_eq!(byte_size::parse("-1GB"), None);
+        assert_eq!(byte_size::parse("GB"), None);
+
+        let error = serde_yaml_ng::from_str::<Settings>(indoc! {"
+            scripts:
+            - name: Backup
+              backup-script: '#!/bin/sh'
+              mount-paths: [/mnt/backup]
+              min-free-space: 50 gigabytes
+              interval: 1day
+        "})
+        .unwrap_err();
+        assert!(error.to_string().contains("invalid size `50 gigabytes`"));
+    }
+
+    #[test]
+    fn tray_command_script() {
+        let yaml = indoc! {"
+            tray-commands:
+            - label: Mount NAS
+              command: mount /mnt/nas
+            - label: Backup drive usage
+              command: |
+                #!/usr/bin/env bash
+                notify-send \"$(df -h /mnt/backup)\"
+        "};
+        let settings = serde_yaml_ng::from_str::<Settings>(yaml).unwrap();
+
+        assert_eq!(
+            settings.tray_commands[0].script(&settings),
+            "#!/bin/sh\nmount /mnt/nas"
+        );
+        assert_eq!(
+            settings.tray_commands[1].script(&settings),
+            "#!/usr/bin/env bash\nnotify-send \"$(df -h /mnt/backup)\"\n"
+        );
+    }
+
+    #[test]
+    fn example_script() {
+        let yaml = EXAMPLE_SCRIPT
+            .lines()
+            .skip_while(|line| *line != "# scripts:")
+            .map(|line| line.strip_prefix("# ").unwrap_or(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let settings = serde_yaml_ng::from_str::<Settings>(&yaml).unwrap();
+
+        assert_eq!(settings.scripts.len(), 1);
+    }
+
+    #[test]
+    fn external_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup-monitor.yaml");
+
+        let settings = Settings::load_from(&path).unwrap();
+        assert_eq!(settings.title, Settings::default().title);
+        assert!(!Settings::file_changed_externally(&path).unwrap());
+
+        // edited in a text editor
+        fs::write(&path, "title: Edited\n").unwrap();
+        assert!(Settings::file_changed_externally(&path).unwrap());
+
+        let settings = Settings::load_from(&path).unwrap();
+        assert_eq!(settings.title, "Edited");
+
+        Settings::default().save_to(&path).unwrap();
+        assert!(!Settings::file_changed_externally(&path).unwrap());
+
+        // a file added to the include directory
+        fs::create_dir(dir.path().join("backup-monitor.d")).unwrap();
+        fs::write(dir.path().join("backup-monitor.d/laptop.yaml"), "").unwrap();
+        assert!(Settings::file_changed_externally(&path).unwrap());
+    }
+
+    #[test]
+    fn included_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup-monitor.yaml");
+        let include_dir = dir.path().join("backup-monitor.d");
+        fs::create_dir(&include_dir).unwrap();
+
+        let script = |name: &str| {
+            format!("- name: {name}\n  backup-script: '#!/bin/bash'\n  interval: 1day\n")
+        };
+        fs::write(&path, format!("scripts:\n{}", script("Home"))).unwrap();
+        fs::write(
+            include_dir.join("b-photos.yaml"),
+            format!("scripts:\n{}", script("Photos")),
+        )
+        .unwrap();
+        fs::write(
+            include_dir.join("a-laptop.yaml"),
+            format!("scripts:\n{}{}", script("Documents"), script("Music")),
+        )
+        .unwrap();
+        fs::write(include_dir.join("notes.txt"), "not a settings file").unwrap();
+
+        let settings = Settings::load_from(&path).unwrap();
+        let names = settings
+            .scripts
+            .iter()
+            .map(|script| script.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Home", "Documents", "Music", "Photos"]);
+
+        // names are unique across all files
+        fs::write(
+            include_dir.join("c-home.yaml"),
+            format!("scripts:\n{}", script("Home")),
+        )
+        .unwrap();
+        assert!(Settings::load_from(&path).is_err());
+
+        // only scripts can be included
+        fs::write(include_dir.join("c-home.yaml"), "title: Laptop\n").unwrap();
+        let error = format!("{:#}", Settings::load_from(&path).unwrap_err());
+        assert!(error.contains("c-home.yaml"), "{error}");
+    }
+
+    #[test]
+    fn profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup-monitor.yaml");
+        fs::write(
+            &path,
+            indoc! {"
+                scripts:
+                - name: Home
+                  backup-script: '#!/bin/bash'
+                  interval: 1day
+                - name: NAS
+                  backup-script: '#!/bin/bash'
+                  interval: 1day
+                profiles:
+                - name: Travel
+                  scripts: [Home]
+            "},
+        )
+        .unwrap();
+
+        let enabled = |settings: &Settings| {
+            settings
+                .scripts
+                .iter()
+                .filter(|script| script.enabled)
+                .map(|script| script.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut settings = Settings::load_from(&path).unwrap();
+        settings.apply_profile();
+        assert_eq!(enabled(&settings), "Home, NAS");
+
+        settings.active_profile = Some("Travel".to_string());
+        settings.apply_profile();
+        assert_eq!(enabled(&settings), "Home");
+
+        // unknown profiles are ignored
+        let mut settings = Settings::load_from(&path).unwrap();
+        settings.active_profile = Some("Office".to_string());
+        settings.apply_profile();
+        assert_eq!(enabled(&settings), "Home, NAS");
+
+        let yaml = fs::read_to_string(&path).unwrap();
+        fs::write(&path, yaml.replace("[Home]", "[Home, Photos]")).unwrap();
+        let error = format!("{:#}", Settings::load_from(&path).unwrap_err());
+        assert_eq!(error, "profile `Travel` contains unknown script `Photos`");
+    }
+
+    #[test]
+    fn auto_profiles() {
+        let mut settings = parse::<Settings>(indoc! {"
+            profiles:
+            - name: Home
+              scripts: []
+              auto-activate:
+                ssids: [Home]
+                gateway-macs: ['A4:2B:B0:12:34:56']
+            - name: Office
+              scripts: []
+              auto-activate:
+                domains: [corp.example.com]
+            - name: Manual
+              scripts: []
+        "})
+        .unwrap();
+        let location =
+            |ssid: Option<&str>, gateway_mac: Option<&str>, domain: Option<&str>| Location {
+                ssid: ssid.map(str::to_string),
+                gateway_mac: gateway_mac.map(str::to_string),
+                domains: domain.map(str::to_string).into_iter().collect(),
+            };
+
+        assert_eq!(
+            settings.auto_profile(&location(Some("Home"), None, None)),
+            Some(Some("Home".to_string()))
+        );
+        // wired
+        assert_eq!(
+            settings.auto_profile(&location(None, Some("a4:2b:b0:12:34:56"), None)),
+            Some(Some("Home".to_string()))
+        );
+        assert_eq!(
+            settings.auto_profile(&location(Some("Guest"), None, Some("corp.example.com"))),
+            Some(Some("Office".to_string()))
+        );
+        assert_eq!(
+            settings.auto_profile(&location(Some("Hotel"), None, None)),
+            Some(None)
+        );
+
+        // a profile without rules matches everywhere
+        settings.profiles[2].auto_activate = Some(AutoActivate::default());
+        assert_eq!(
+            settings.auto_profile(&location(Some("Hotel"), None, None)),
+            Some(Some("Manual".to_string()))
+        );
+
+        // nothing is switched automatically without rules
+        for profile in &mut settings.profiles {
+            profile.auto_activate = None;
+        }
+        assert_eq!(settings.auto_profile(&Location::default()), None);
+    }
+
+    #[test]
+    fn invalid_duration() {
+        let yaml = indoc! {"
+            scripts:
+            - name: Backup
+              backup-script: '#!/bin/bash'
+              interval: 1day
+            - name: Photos
+              backup-script: '#!/bin/bash'
+              interval: 1 dya
+        "};
+        let error = format!("{:#}", parse::<Settings>(yaml).unwrap_err());
+
+        assert!(
+            error.starts_with("script `Photos`: scripts[1].interval: "),
+            "{error}"
+        );
+        assert!(error.contains("use formats like `1day 12h`"), "{error}");
+    }
 }
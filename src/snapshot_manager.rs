@@ -0,0 +1,371 @@
+use std::{collections::HashMap, fs, path::Path, process::Command, sync::Arc};
+
+use anyhow::ensure;
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone, Utc};
+
+use crate::{
+    clock::Clock,
+    history::Trigger,
+    manager::{Manager, Overdue, RunOutcome, ScriptStatus},
+    script_manager::{format_duration, format_time},
+    settings::{Settings, SnapshotTool, Snapshots},
+    tray::Tray,
+    tray_handle::TrayHandle,
+    upower::Power,
+};
+
+const SNAPSHOT_POLL_INTERVAL: Duration = Duration::minutes(15);
+
+// monitors the age of snapshots created by snapper or Timeshift
+pub struct SnapshotManager {
+    clock: Clock,
+    settings: Arc<ArcSwap<Settings>>,
+    // time of the newest snapshot, `Ok(None)` if there are no snapshots, `Err` if the snapshots
+    // couldn't be read
+    newest: HashMap<String, Result<Option<DateTime<Utc>>, String>>,
+    refreshed: Option<DateTime<Utc>>,
+}
+
+impl SnapshotManager {
+    pub fn new(clock: Clock, settings: Arc<ArcSwap<Settings>>) -> SnapshotManager {
+        SnapshotManager {
+            clock,
+            settings,
+            newest: HashMap::new(),
+            refreshed: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let settings = self.settings.load_full();
+
+        self.newest.clear();
+        for snapshots in &settings.snapshots {
+            let newest = newest_snapshot(snapshots).map_err(|error| format!("{error:#}"));
+            if let Err(error) = &newest {
+                log::warn!("failed to read snapshots of `{}`: {error}", snapshots.name);
+            }
+
+            self.newest.insert(snapshots.name.clone(), newest);
+        }
+
+        self.refreshed = Some(self.clock.now());
+    }
+
+    fn reminder_at(&self, snapshots: &Snapshots) -> Option<DateTime<Utc>> {
+        let Some(Ok(newest)) = self.newest.get(&snapshots.name) else {
+            return None;
+        };
+
+        Some(newest.map_or(self.clock.now(), |newest| newest + snapshots.reminder))
+    }
+}
+
+impl Manager for SnapshotManager {
+    fn next_backup(&self) -> Option<DateTime<Utc>> {
+        let settings = self.settings.load();
+
+        if settings.snapshots.is_empty() {
+            return None;
+        }
+
+        // snapshots which have been added since the last refresh are read right away
+        match self.refreshed {
+            Some(refreshed)
+                if settings
+                    .snapshots
+                    .iter()
+                    .all(|snapshots| self.newest.contains_key(&snapshots.name)) =>
+            {
+                Some(refreshed + SNAPSHOT_POLL_INTERVAL)
+            }
+            _ => Some(self.clock.now()),
+        }
+    }
+
+    fn next_reminder(&self) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+
+        settings
+            .snapshots
+            .iter()
+            .filter_map(|snapshots| self.reminder_at(snapshots))
+            .min()
+            .map(|ts| settings.paused_until(now).map_or(ts, |until| ts.max(until)))
+    }
+
+    fn next_ui_update(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    fn tooltip(&self) -> String {
+        let settings = self.settings.load();
+
+        settings
+            .snapshots
+            .iter()
+            .map(|snapshots| {
+                let status = match self.newest.get(&snapshots.name) {
+                    Some(Ok(Some(newest))) => {
+                        format!("Newest snapshot at {}", format_time(*newest))
+                    }
+                    Some(Ok(None)) => "No snapshots".to_string(),
+                    Some(Err(error)) => format!("Failed to read snapshots: {error}"),
+                    None => "Reading snapshots".to_string(),
+                };
+                format!("{}:\n{status}", snapshots.name)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn low_space(&self) -> bool {
+        false
+    }
+
+    fn overdue(&self) -> Vec<Overdue> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+
+        if settings.paused_until(now).is_some() {
+            return Vec::new();
+        }
+
+        settings
+            .snapshots
+            .iter()
+            .filter_map(|snapshots| {
+                let next_reminder = self.reminder_at(snapshots).filter(|ts| *ts <= now)?;
+                let newest = match self.newest.get(&snapshots.name) {
+                    Some(Ok(newest)) => *newest,
+                    _ => None,
+                };
+                Some(Overdue {
+                    name: snapshots.name.clone(),
+                    overdue: newest.map(|_| now - next_reminder),
+                    missing_paths: Vec::new(),
+                    target_missing_for: None,
+                })
+            })
+            .collect()
+    }
+
+    fn script_status(&self, script_name: &str) -> ScriptStatus {
+        match self.newest.get(script_name) {
+            Some(Err(_)) => ScriptStatus::Failed,
+            _ if self.overdue().iter().any(|item| item.name == script_name) => {
+                ScriptStatus::Overdue
+            }
+            _ => ScriptStatus::Ok,
+        }
+    }
+
+    fn explain(&self, script_name: &str) -> Option<String> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+        let snapshots = settings
+            .snapshots
+            .iter()
+            .find(|snapshots| snapshots.name == script_name)?;
+
+        let source = match snapshots.tool {
+            SnapshotTool::Snapper => format!("snapper config `{}`", snapper_config(snapshots)),
+            SnapshotTool::Timeshift => {
+                format!(
+                    "Timeshift snapshots in `{}`",
+                    timeshift_path(snapshots).display()
+                )
+            }
+        };
+        let newest = match self.newest.get(&snapshots.name) {
+            Some(Ok(Some(newest))) => format!(
+                "{} ({} ago)",
+                format_time(*newest),
+                format_duration(now - (*newest).min(now))
+            ),
+            Some(Ok(None)) => "none".to_string(),
+            Some(Err(error)) => format!("failed to read the snapshots: {error}"),
+            None => "not read yet".to_string(),
+        };
+        let next_reminder = match self.reminder_at(snapshots) {
+            Some(ts) => format_time(ts.max(now)),
+            None => "none".to_string(),
+        };
+
+        let lines = [
+            format!("{}:", snapshots.name),
+            format!("Snapshots: {source}"),
+            format!("Newest snapshot: {newest}"),
+            format!(
+                "Reminder: {}",
+                humantime::format_duration(snapshots.reminder)
+            ),
+            format!("Next reminder: {next_reminder}"),
+            "The snapshots are created by another tool".to_string(),
+        ];
+
+        Some(lines.join("\n"))
+    }
+
+    fn set_mounts(&mut self, _mounts: &str) {}
+
+    fn set_active_user(&mut self, _user: Option<String>) {}
+
+    fn set_power(&mut self, _power: Option<Power>) {}
+
+    fn set_idle_since(&mut self, _idle_since: Option<DateTime<Utc>>) {}
+
+    fn dismiss_failure(&mut self, _script_name: &str) {}
+
+    fn run(
+        &mut self,
+        _script_name: Option<&str>,
+        _trigger: &Trigger,
+        _handle: &impl TrayHandle<Tray>,
+    ) -> anyhow::Result<Vec<(String, RunOutcome)>> {
+        self.refresh();
+        Ok(Vec::new())
+    }
+}
+
+fn snapper_config(snapshots: &Snapshots) -> &str {
+    snapshots.config.as_deref().unwrap_or("root")
+}
+
+fn timeshift_path(snapshots: &Snapshots) -> &Path {
+    snapshots
+        .path
+        .as_deref()
+        .unwrap_or(Path::new("/timeshift/snapshots"))
+}
+
+fn newest_snapshot(snapshots: &Snapshots) -> anyhow::Result<Option<DateTime<Utc>>> {
+    match snapshots.tool {
+        SnapshotTool::Snapper => {
+            let output = Command::new("snapper")
+                .args(["--csvout", "--no-headers", "--config"])
+                .arg(snapper_config(snapshots))
+                .args(["list", "--columns", "date"])
+                .output()?;
+            ensure!(
+                output.status.success(),
+                "snapper failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            Ok(newest_snapper_snapshot(&String::from_utf8_lossy(
+                &output.stdout,
+            )))
+        }
+        SnapshotTool::Timeshift => {
+            let names = fs::read_dir(timeshift_path(snapshots))?
+                .filter_map(|entry| Some(entry.ok()?.file_name().to_str()?.to_string()))
+                .collect::<Vec<_>>();
+            Ok(newest_timeshift_snapshot(&names))
+        }
+    }
+}
+
+// one date per line in local time, e.g. `2024-10-24 20:18:00`, the current system (snapshot 0)
+// has no date
+fn newest_snapper_snapshot(output: &str) -> Option<DateTime<Utc>> {
+    output
+        .lines()
+        .filter_map(|line| parse_local_time(line.trim(), "%Y-%m-%d %H:%M:%S"))
+        .max()
+}
+
+// the snapshot directories are named after the local time, e.g. `2024-10-24_20-18-01`
+fn newest_timeshift_snapshot(names: &[String]) -> Option<DateTime<Utc>> {
+    names
+        .iter()
+        .filter_map(|name| parse_local_time(name, "%Y-%m-%d_%H-%M-%S"))
+        .max()
+}
+
+fn parse_local_time(time: &str, format: &str) -> Option<DateTime<Utc>> {
+    let time = NaiveDateTime::parse_from_str(time, format).ok()?;
+    Some(Local.from_local_datetime(&time).earliest()?.to_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+
+    fn local_time(time: &str) -> DateTime<Utc> {
+        parse_local_time(time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn newest_snapshots() {
+        // the current system comes first and has no date
+        let output = "\n2024-10-23 20:00:01\n2024-10-24 20:18:00\n2024-10-24 08:00:00\n";
+        assert_eq!(
+            newest_snapper_snapshot(output),
+            Some(local_time("2024-10-24 20:18:00"))
+        );
+        assert_eq!(newest_snapper_snapshot(""), None);
+
+        let names =
+            ["2024-10-24_20-18-01", "2024-10-23_20-00-00", "lost+found"].map(ToString::to_string);
+        assert_eq!(
+            newest_timeshift_snapshot(&names),
+            Some(local_time("2024-10-24 20:18:01"))
+        );
+    }
+
+    #[test]
+    fn overdue_snapshots() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            snapshots: vec![Snapshots {
+                name: "Root snapshots".to_string(),
+                tool: SnapshotTool::Snapper,
+                config: None,
+                path: None,
+                reminder: std::time::Duration::from_secs(24 * 60 * 60),
+            }],
+            ..Default::default()
+        }));
+        let mut manager = SnapshotManager::new(clock, settings);
+
+        // not read yet
+        assert_eq!(manager.next_backup(), Some(now));
+        assert_eq!(manager.next_reminder(), None);
+
+        manager.refreshed = Some(now);
+        manager.newest.insert(
+            "Root snapshots".to_string(),
+            Ok(Some(now - Duration::hours(36))),
+        );
+
+        assert_eq!(manager.next_backup(), Some(now + SNAPSHOT_POLL_INTERVAL));
+        assert_eq!(manager.next_reminder(), Some(now - Duration::hours(12)));
+        assert_eq!(
+            manager.overdue(),
+            vec![Overdue {
+                name: "Root snapshots".to_string(),
+                overdue: Some(Duration::hours(12)),
+                missing_paths: Vec::new(),
+                target_missing_for: None,
+            }]
+        );
+        assert_eq!(
+            manager.script_status("Root snapshots"),
+            ScriptStatus::Overdue
+        );
+
+        manager.newest.insert(
+            "Root snapshots".to_string(),
+            Err("permission denied".to_string()),
+        );
+        assert_eq!(manager.next_reminder(), None);
+        assert_eq!(
+            manager.script_status("Root snapshots"),
+            ScriptStatus::Failed
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    settings::{system_mode, Settings},
+    targets::Target,
+};
+
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+// runtime state, kept out of the settings file so that it isn't rewritten on every run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct State {
+    pub paused_until: Option<DateTime<Utc>>,
+    pub monitored_since: Option<DateTime<Utc>>,
+    pub active_profile: Option<String>,
+    // by script name
+    pub scripts: BTreeMap<String, ScriptRecord>,
+    // the file systems the backup scripts have written to, by UUID
+    pub targets: BTreeMap<String, TargetRecord>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ScriptRecord {
+    pub last_backup: Option<DateTime<Utc>>,
+    pub last_attempt: Option<DateTime<Utc>>,
+    // consecutive failures since the last success
+    pub failures: u32,
+    pub last_error: Option<String>,
+    // base of the last full backup of an incremental backup chain
+    pub chain_base: Option<String>,
+    // when the mount paths of the script were last seen mounted, recorded when they are unmounted
+    // and after successful runs
+    pub targets_seen: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct TargetRecord {
+    pub label: Option<String>,
+    // in bytes
+    pub size: Option<u64>,
+    // by script name
+    pub last_used: BTreeMap<String, DateTime<Utc>>,
+}
+
+pub fn state_file_path() -> anyhow::Result<PathBuf> {
+    if system_mode() {
+        Ok(PathBuf::from("/var/lib/backup-monitor/state.yaml"))
+    } else {
+        let state_dir = dirs::state_dir().context("state dir not found")?;
+        Ok(state_dir.join("backup-monitor").join("state.yaml"))
+    }
+}
+
+impl State {
+    // `None` if nothing has been saved yet
+    pub fn load() -> anyhow::Result<Option<State>> {
+        State::load_from(&state_file_path()?)
+    }
+
+    fn load_from(path: &Path) -> anyhow::Result<Option<State>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let state = serde_yaml_ng::from_str(&fs::read_to_string(path)?)
+            .with_context(|| format!("failed to read `{}`", path.display()))?;
+        Ok(Some(state))
+    }
+
+    fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // written to a temporary file first, so the state isn't lost if writing fails
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, serde_yaml_ng::to_string(self)?)?;
+        fs::rename(&temp_path, path)?;
+
+        log::trace!("state saved");
+
+        Ok(())
+    }
+
+    // the values in the settings file are only used until the state has been saved for the first
+    // time, e.g. after upgrading from a version which kept them in the settings file
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.paused_until = self.paused_until;
+        settings.monitored_since = self.monitored_since.or(settings.monitored_since);
+        settings.active_profile.clone_from(&self.active_profile);
+        for script in &mut settings.scripts {
+            if let Some(record) = self.scripts.get(&script.name) {
+                script.last_backup = record.last_backup;
+                script.last_attempt = record.last_attempt;
+            }
+        }
+    }
+
+    // records of removed scripts are dropped
+    fn take_from(&mut self, settings: &Settings) {
+        self.paused_until = settings.paused_until;
+        self.monitored_since = settings.monitored_since;
+        self.active_profile.clone_from(&settings.active_profile);
+        self.scripts
+            .retain(|name, _| settings.scripts.iter().any(|script| script.name == *name));
+        for script in &settings.scripts {
+            let record = self.scripts.entry(script.name.clone()).or_default();
+            record.last_backup = script.last_backup;
+            record.last_attempt = script.last_attempt;
+        }
+    }
+}
+
+// reads the state file again before applying the change, the state file has more than one writer
+fn update_file(path: &Path, change: impl FnOnce(&mut State)) -> anyhow::Result<()> {
+    let _lock = UPDATE_LOCK.lock().unwrap();
+
+    let mut state = State::load_from(path)?.unwrap_or_default();
+    change(&mut state);
+    state.save_to(path)
+}
+
+// saves the runtime state of the settings (`last_backup`, `paused_until`, ...)
+pub fn save_settings_state(settings: &Settings) -> anyhow::Result<()> {
+    update_file(&state_file_path()?, |state| state.take_from(settings))
+}
+
+// `error` is `None` if the run succeeded
+pub fn record_result(script_name: &str, error: Option<&str>) -> anyhow::Result<()> {
+    update_file(&state_file_path()?, |state| {
+        let record = state.scripts.entry(script_name.to_string()).or_default();
+        match error {
+            Some(error) => {
+                record.failures += 1;
+                record.last_error = Some(error.to_string());
+            }
+            None => {
+                record.failures = 0;
+                record.last_error = None;
+            }
+        }
+    })
+}
+
+pub fn chain_base(script_name: &str) -> anyhow::Result<Option<String>> {
+    let state = State::load()?.unwrap_or_default();
+    Ok(state
+        .scripts
+        .get(script_name)
+        .and_then(|record| record.chain_base.clone()))
+}
+
+pub fn record_chain_base(script_name: &str, base: &str) -> anyhow::Result<()> {
+    update_file(&state_file_path()?, |state| {
+        let record = state.scripts.entry(script_name.to_string()).or_default();
+        record.chain_base = Some(base.to_string());
+    })
+}
+
+pub fn record_targets_seen(script_name: &str, time: DateTime<Utc>) -> anyhow::Result<()> {
+    update_file(&state_file_path()?, |state| {
+        let record = state.scripts.entry(script_name.to_string()).or_default();
+        record.targets_seen = Some(time);
+    })
+}
+
+// the label and size are updated, e.g. after the disk has been reformatted
+pub fn record_target(
+    target: &Target,
+    script_name: &str,
+    time: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    update_file(&state_file_path()?, |state| {
+        let record = state.targets.entry(target.uuid.clone()).or_default();
+        record.label = target.label.clone();
+        record.size = target.size;
+        record.last_used.insert(script_name.to_string(), time);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use fake::{Fake, Faker};
+    use indoc::indoc;
+
+    #[test]
+    fn state_file() {
+        let now = Faker.fake::<Clock>().now();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.yaml");
+
+        assert_eq!(State::load_from(&path).unwrap(), None);
+
+        let mut settings = serde_yaml_ng::from_str::<Settings>(indoc! {"
+            scripts:
+            - name: Backup
+              backup-script: '#!/bin/bash'
+              interval: 1day
+        "})
+        .unwrap();
+        settings.scripts[0].last_backup = Some(now);
+        settings.paused_until = Some(now);
+        settings.monitored_since = Some(now);
+
+        update_file(&path, |state| {
+            state.scripts.insert(
+                "Removed".to_string(),
+                ScriptRecord {
+                    failures: 1,
+                    ..Default::default()
+                },
+            );
+        })
+        .unwrap();
+        update_file(&path, |state| state.take_from(&settings)).unwrap();
+        update_file(&path, |state| {
+            state.scripts.get_mut("Backup").unwrap().failures = 2;
+        })
+        .unwrap();
+        // keeps the failures written by other writers
+        update_file(&path, |state| state.take_from(&settings)).unwrap();
+
+        let state = State::load_from(&path).unwrap().unwrap();
+        assert_eq!(
+            state,
+            State {
+                paused_until: Some(now),
+                monitored_since: Some(now),
+                active_profile: None,
+                scripts: BTreeMap::from([(
+                    "Backup".to_string(),
+                    ScriptRecord {
+                        last_backup: Some(now),
+                        last_attempt: None,
+                        failures: 2,
+                        last_error: None,
+                        chain_base: None,
+                        targets_seen: None,
+                    }
+                )]),
+                targets: BTreeMap::new(),
+            }
+        );
+
+        // the state takes precedence over the settings file
+        settings.scripts[0].last_backup = None;
+        settings.paused_until = None;
+        settings.monitored_since = None;
+        state.apply(&mut settings);
+        assert_eq!(settings.scripts[0].last_backup, Some(now));
+        assert_eq!(settings.paused_until, Some(now));
+        assert_eq!(settings.monitored_since, Some(now));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{manager::ScriptStatus, settings::Settings, tray_handle::TrayData};
+
+// waybar's custom module format (`return-type: json`), the scripts are ignored by waybar but
+// can be used by other bars
+#[derive(Debug, Serialize)]
+struct StatusLine {
+    text: ScriptStatus,
+    alt: ScriptStatus,
+    class: ScriptStatus,
+    tooltip: String,
+    scripts: Vec<ScriptLine>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ScriptLine {
+    name: String,
+    status: ScriptStatus,
+    last_backup: Option<DateTime<Utc>>,
+    next_backup: Option<DateTime<Utc>>,
+}
+
+// a single line of JSON
+pub fn status_line(
+    tray_data: &TrayData,
+    settings: &Settings,
+    next_backup: impl Fn(&str) -> Option<DateTime<Utc>>,
+) -> anyhow::Result<String> {
+    let scripts = tray_data
+        .scripts
+        .iter()
+        .flatten()
+        .map(|(name, _, status)| ScriptLine {
+            name: name.clone(),
+            status: *status,
+            last_backup: settings
+                .scripts
+                .iter()
+                .find(|script| script.name == *name)
+                .and_then(|script| script.last_backup),
+            next_backup: next_backup(name),
+        })
+        .collect::<Vec<_>>();
+
+    // the most important status of all scripts
+    let status = [
+        ScriptStatus::Failed,
+        ScriptStatus::Running,
+        ScriptStatus::Overdue,
+    ]
+    .into_iter()
+    .find(|status| scripts.iter().any(|script| script.status == *status))
+    .unwrap_or(ScriptStatus::Ok);
+
+    let line = StatusLine {
+        text: status,
+        alt: status,
+        class: status,
+        tooltip: tray_data.tooltip.clone().unwrap_or_default(),
+        scripts,
+    };
+    Ok(serde_json::to_string(&line)?)
+}
+
+// `-` writes to stdout, files are replaced, so readers never see a partial line
+pub fn write_status_line(output: &Path, line: &str) -> anyhow::Result<()> {
+    if output == Path::new("-") {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{line}")?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    let temp_path = output.with_extension("tmp");
+    fs::write(&temp_path, format!("{line}\n"))?;
+    fs::rename(&temp_path, output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use chrono::Duration;
+    use fake::{Fake, Faker};
+    use indoc::indoc;
+
+    #[test]
+    fn waybar_status_line() {
+        let now = Faker.fake::<Clock>().now();
+        let last_backup = now - Duration::days(2);
+        let next_backup = now + Duration::hours(3);
+        let settings = Settings {
+            scripts: vec![crate::settings::Script {
+                last_backup: Some(last_backup),
+                ..serde_yaml_ng::from_str(indoc! {"
+                    name: Backup
+                    backup-script: '#!/bin/bash'
+                    interval: 1day
+                "})
+                .unwrap()
+            }],
+            ..Default::default()
+        };
+        let tray_data = TrayData {
+            tooltip: Some("Backup:\nLast backup two days ago".to_string()),
+            scripts: Some(vec![
+                ("Backup".to_string(), None, ScriptStatus::Overdue),
+                ("Photos".to_string(), None, ScriptStatus::Ok),
+            ]),
+            ..Default::default()
+        };
+
+        let line = status_line(&tray_data, &settings, |name| {
+            (name == "Backup").then_some(next_backup)
+        })
+        .unwrap();
+
+        assert!(!line.contains('\n'));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            serde_json::json!({
+                "text": "overdue",
+                "alt": "overdue",
+                "class": "overdue",
+                "tooltip": "Backup:\nLast backup two days ago",
+                "scripts": [
+                    {
+                        "name": "Backup",
+                        "status": "overdue",
+                        "last-backup": last_backup,
+                        "next-backup": next_backup,
+                    },
+                    {
+                        "name": "Photos",
+                        "status": "ok",
+                        "last-backup": null,
+                        "next-backup": null,
+                    },
+                ],
+            })
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fs, path::Path, time::SystemTime};
+
+use crate::settings::SuccessWhen;
+
+// the conditions the run which started at `start` doesn't meet, `output` is `None` if it wasn't
+// captured
+pub fn unmet_conditions(
+    success_when: &SuccessWhen,
+    start: SystemTime,
+    output: Option<&str>,
+) -> Vec<String> {
+    let mut unmet = Vec::new();
+
+    if let Some(pattern) = &success_when.newer_file {
+        if !newer_file_exists(pattern, start) {
+            unmet.push(format!(
+                "No file matching \"{}\" was written",
+                pattern.display()
+            ));
+        }
+    }
+
+    if let Some(text) = &success_when.output_contains {
+        match output {
+            Some(output) if output.contains(text.as_str()) => {}
+            Some(_) => unmet.push(format!("The output doesn't contain \"{text}\"")),
+            None => unmet.push(format!(
+                "The output couldn't be checked for \"{text}\", it wasn't logged"
+            )),
+        }
+    }
+
+    unmet
+}
+
+fn newer_file_exists(pattern: &Path, start: SystemTime) -> bool {
+    let (Some(dir), Some(file_name)) = (pattern.parent(), pattern.file_name()) else {
+        return false;
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+
+    let file_name = file_name.to_string_lossy();
+    entries.flatten().any(|entry| {
+        matches(&file_name, &entry.file_name().to_string_lossy())
+            && entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified >= start)
+    })
+}
+
+// `*` matches any number of characters and `?` a single character
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // the positions in the name after the pattern so far
+    let mut positions = vec![0];
+    for char in pattern {
+        positions = match char {
+            '*' => (positions[0]..=name.len()).collect(),
+            '?' => positions
+                .into_iter()
+                .filter(|position| *position < name.len())
+                .map(|position| position + 1)
+                .collect(),
+            char => positions
+                .into_iter()
+                .filter(|position| name.get(*position) == Some(&char))
+                .map(|position| position + 1)
+                .collect(),
+        };
+        if positions.is_empty() {
+            return false;
+        }
+    }
+
+    positions.contains(&name.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("latest-*", "latest-2024-10-24.tar"));
+        assert!(matches("latest-*", "latest-"));
+        assert!(matches("*.tar", "backup.tar"));
+        assert!(matches("backup-?.tar", "backup-1.tar"));
+        assert!(matches("*-*.tar", "a-b-c.tar"));
+        assert!(!matches("latest-*", "oldest-2024-10-24.tar"));
+        assert!(!matches("backup-?.tar", "backup-12.tar"));
+        assert!(!matches("*.tar", "backup.tar.gz"));
+    }
+
+    #[test]
+    fn conditions() {
+        let dir = tempfile::tempdir().unwrap();
+        let start = SystemTime::now() - Duration::from_secs(60);
+        fs::write(dir.path().join("latest-1.tar"), "").unwrap();
+
+        let success_when = SuccessWhen {
+            newer_file: Some(dir.path().join("latest-*")),
+            output_contains: Some("snapshot saved".to_string()),
+        };
+        assert!(unmet_conditions(&success_when, start, Some("ok\nsnapshot saved\n")).is_empty());
+
+        assert_eq!(
+            unmet_conditions(
+                &success_when,
+                SystemTime::now() + Duration::from_secs(60),
+                Some("error")
+            ),
+            vec![
+                format!(
+                    "No file matching \"{}\" was written",
+                    dir.path().join("latest-*").display()
+                ),
+                "The output doesn't contain \"snapshot saved\"".to_string(),
+            ]
+        );
+        assert_eq!(
+            unmet_conditions(&success_when, start, None),
+            vec!["The output couldn't be checked for \"snapshot saved\", it wasn't logged"]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use zbus::{blocking::Connection, proxy, zvariant::OwnedObjectPath};
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait Systemd {
+    fn load_unit(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Unit",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Unit {
+    #[zbus(property)]
+    fn load_state(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn active_state(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn sub_state(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Timer",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Timer {
+    #[zbus(property)]
+    fn unit(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "LastTriggerUSec")]
+    fn last_trigger(&self) -> zbus::Result<u64>;
+
+    #[zbus(property, name = "NextElapseUSecRealtime")]
+    fn next_elapse(&self) -> zbus::Result<u64>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Service",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Service {
+    #[zbus(property)]
+    fn result(&self) -> zbus::Result<String>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimerState {
+    pub last_trigger: Option<DateTime<Utc>>,
+    pub next_elapse: Option<DateTime<Utc>>,
+    // whether the service started by the timer is running
+    pub running: bool,
+    // result of the last run of the service, e.g. `success` or `exit-code`
+    pub result: String,
+}
+
+impl TimerState {
+    pub fn failed(&self) -> bool {
+        self.result != "success"
+    }
+}
+
+// reads the timer and the service it starts from the user or system instance of systemd
+pub fn timer_state(connection: &Connection, unit: &str) -> zbus::Result<TimerState> {
+    let systemd = SystemdProxyBlocking::new(connection)?;
+
+    let timer_path = systemd.load_unit(unit)?;
+    let timer_unit = UnitProxyBlocking::builder(connection)
+        .path(timer_path.clone())?
+        .build()?;
+    if timer_unit.load_state()? != "loaded" {
+        return Err(zbus::Error::Failure(format!("unit `{unit}` not found")));
+    }
+    let timer = TimerProxyBlocking::builder(connection)
+        .path(timer_path)?
+        .build()?;
+
+    let service_path = systemd.load_unit(&timer.unit()?)?;
+    let service_unit = UnitProxyBlocking::builder(connection)
+        .path(service_path.clone())?
+        .build()?;
+    let service = ServiceProxyBlocking::builder(connection)
+        .path(service_path)?
+        .build()?;
+
+    Ok(TimerState {
+        last_trigger: timestamp(timer.last_trigger()?),
+        next_elapse: timestamp(timer.next_elapse()?),
+        running: matches!(
+            service_unit.active_state()?.as_str(),
+            "activating" | "deactivating"
+        ) || service_unit.sub_state()? == "running",
+        result: service.result()?,
+    })
+}
+
+// systemd uses 0 and `u64::MAX` for timestamps which are not set
+fn timestamp(usec: u64) -> Option<DateTime<Utc>> {
+    if usec == 0 {
+        return None;
+    }
+    DateTime::from_timestamp_micros(i64::try_from(usec).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0), None);
+        assert_eq!(timestamp(u64::MAX), None);
+        assert_eq!(
+            timestamp(1_700_000_000_000_000),
+            DateTime::from_timestamp(1_700_000_000, 0)
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+use nix::sys::statvfs::statvfs;
+use notify_rust::{Notification, Timeout};
+
+use crate::{
+    disk_usage::format_bytes,
+    notification,
+    script_manager::{format_time, unescape_mount_path},
+    state::{record_target, State, TargetRecord},
+};
+
+const BY_UUID: &str = "/dev/disk/by-uuid";
+const BY_LABEL: &str = "/dev/disk/by-label";
+
+// a file system a backup script has written to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub uuid: String,
+    pub label: Option<String>,
+    pub size: Option<u64>,
+}
+
+// a mount path like `uuid:2f1e9c4a-...` or `label:Backup`, which matches the file system wherever
+// it is mounted, e.g. in `/run/media/<user>/<label>` by UDisks2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    Uuid(String),
+    Label(String),
+}
+
+impl Selector {
+    pub fn parse(path: &Path) -> Option<Selector> {
+        let path = path.to_str()?;
+        if let Some(uuid) = path.strip_prefix("uuid:") {
+            Some(Selector::Uuid(uuid.to_string()))
+        } else {
+            path.strip_prefix("label:")
+                .map(|label| Selector::Label(label.to_string()))
+        }
+    }
+
+    // the canonical path of the device, `None` if it isn't connected
+    fn device(&self) -> Option<PathBuf> {
+        let link = match self {
+            Selector::Uuid(uuid) => Path::new(BY_UUID).join(uuid),
+            Selector::Label(label) => fs::read_dir(BY_LABEL)
+                .ok()?
+                .flatten()
+                .find(|entry| unescape_label(&entry.file_name().to_string_lossy()) == *label)?
+                .path(),
+        };
+        fs::canonicalize(link).ok()
+    }
+}
+
+// where the devices are mounted, by their canonical path
+pub fn device_mounts(mounts: &str) -> HashMap<PathBuf, PathBuf> {
+    mounts
+        .lines()
+        .filter_map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [source, mount, ..] if source.starts_with('/') => {
+                    let source = unescape_mount_path(source);
+                    Some((
+                        fs::canonicalize(&source).unwrap_or(source),
+                        unescape_mount_path(mount),
+                    ))
+                }
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+// the path the selector's file system is mounted at, other mount paths are returned as they are
+pub fn resolve_mount_path(
+    path: &Path,
+    device_mounts: &HashMap<PathBuf, PathBuf>,
+) -> Option<PathBuf> {
+    match Selector::parse(path) {
+        Some(selector) => device_mounts.get(&selector.device()?).cloned(),
+        None => Some(path.to_path_buf()),
+    }
+}
+
+// like `resolve_mount_path`, reading the current mounts
+pub fn resolve_mount_path_now(path: &Path) -> Option<PathBuf> {
+    if Selector::parse(path).is_none() {
+        return Some(path.to_path_buf());
+    }
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    resolve_mount_path(path, &device_mounts(&mounts))
+}
+
+// the file system `path` is on, `None` if it has no UUID, e.g. a network share
+pub fn identify(path: &Path) -> Option<Target> {
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    let path = fs::canonicalize(path).ok()?;
+    let device = fs::canonicalize(mount_source(&mounts, &path)?).ok()?;
+
+    Some(Target {
+        uuid: link_to(Path::new(BY_UUID), &device)?,
+        label: link_to(Path::new(BY_LABEL), &device).map(|label| unescape_label(&label)),
+        size: size(&path),
+    })
+}
+
+// remembers the file systems the resolved mount paths of a script are on, after a successful run
+pub fn record_targets(script_name: &str, mount_paths: &[PathBuf], time: DateTime<Utc>) {
+    for path in mount_paths {
+        let Some(target) = identify(path) else {
+            continue;
+        };
+        if let Err(error) = record_target(&target, script_name, time) {
+            log::error!("failed to save state: {error:#}");
+        }
+    }
+}
+
+// the device of the innermost mount containing `path`
+fn mount_source(mounts: &str, path: &Path) -> Option<PathBuf> {
+    mounts
+        .lines()
+        .filter_map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [source, mount, ..] => {
+                    Some((unescape_mount_path(source), unescape_mount_path(mount)))
+                }
+                _ => None,
+            },
+        )
+        .filter(|(_, mount)| path.starts_with(mount))
+        .max_by_key(|(_, mount)| mount.components().count())
+        .map(|(source, _)| source)
+}
+
+// the name of the link in `dir` pointing to `device`
+fn size(path: &Path) -> Option<u64> {
+    let stat = statvfs(path).ok()?;
+    Some(stat.blocks() as u64 * stat.fragment_size() as u64)
+}
+
+fn link_to(dir: &Path, device: &Path) -> Option<String> {
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .find(|entry| fs::canonicalize(entry.path()).is_ok_and(|target| target == device))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+// udev escapes spaces and other unsafe characters as hex numbers, e.g. `\x20` for a space
+fn unescape_label(label: &str) -> String {
+    let mut bytes = Vec::with_capacity(label.len());
+
+    let mut rest = label.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let code = tail
+            .strip_prefix(b"x")
+            .and_then(|tail| tail.get(..2))
+            .filter(|_| byte == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        match code {
+            Some(code) => {
+                bytes.push(code);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// the UUIDs of the file systems which are currently connected, mounted or not
+fn connected() -> HashSet<String> {
+    fs::read_dir(BY_UUID)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// the connected disks first, so that a disk which has just been plugged in can be recognized
+pub fn format_inventory(
+    targets: &BTreeMap<String, TargetRecord>,
+    connected: &HashSet<String>,
+) -> String {
+    if targets.is_empty() {
+        return "No backup disks have been used yet".to_string();
+    }
+
+    let mut targets = targets.iter().collect::<Vec<_>>();
+    targets.sort_by_key(|(uuid, _)| !connected.contains(*uuid));
+
+    targets
+        .into_iter()
+        .map(|(uuid, target)| {
+            let mut lines = vec![match &target.label {
+                Some(label) => format!("{label} ({uuid})"),
+                None => uuid.clone(),
+            }];
+            let mut details = Vec::new();
+            if connected.contains(uuid) {
+                details.push("connected".to_string());
+            }
+            if let Some(size) = target.size {
+                details.push(format_bytes(size));
+            }
+            if !details.is_empty() {
+                lines[0] += &format!(", {}", details.join(", "));
+            }
+            lines.extend(
+                target
+                    .last_used
+                    .iter()
+                    .map(|(script, time)| format!("  {script}: last used {}", format_time(*time))),
+            );
+            lines.join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+pub fn inventory() -> anyhow::Result<String> {
+    let state = State::load()?.unwrap_or_default();
+    Ok(format_inventory(&state.targets, &connected()))
+}
+
+pub fn print_inventory() -> anyhow::Result<()> {
+    println!("{}", inventory()?);
+    Ok(())
+}
+
+pub fn show(title: String, icon_name: String) {
+    thread::spawn(move || {
+        let result = inventory().and_then(|inventory| {
+            notification::show(
+                Notification::new()
+                    .appname(&title)
+                    .summary("Backup disks")
+                    .body(&inventory)
+                    .icon(&icon_name)
+                    .timeout(Timeout::Milliseconds(30_000)),
+            )?;
+            Ok(())
+        });
+        if let Err(error) = result {
+            log::warn!("failed to show the backup disks: {error}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use fake::{Fake, Faker};
+
+    #[test]
+    fn source() {
+        let mounts = "/dev/sda1 / ext4 rw 0 0\n\
+                      /dev/sdb1 /mnt/backup\\040disk ext4 rw 0 0\n\
+                      tmpfs /tmp tmpfs rw 0 0\n";
+
+        assert_eq!(
+            mount_source(mounts, Path::new("/mnt/backup disk/home")),
+            Some(PathBuf::from("/dev/sdb1"))
+        );
+        assert_eq!(
+            mount_source(mounts, Path::new("/mnt/backup")),
+            Some(PathBuf::from("/dev/sda1"))
+        );
+    }
+
+    #[test]
+    fn selectors() {
+        assert_eq!(
+            Selector::parse(Path::new("uuid:2f1e9c4a")),
+            Some(Selector::Uuid("2f1e9c4a".to_string()))
+        );
+        assert_eq!(
+            Selector::parse(Path::new("label:Backup A")),
+            Some(Selector::Label("Backup A".to_string()))
+        );
+        assert_eq!(Selector::parse(Path::new("/mnt/backup")), None);
+
+        let device_mounts = device_mounts(
+            "/dev/does-not-exist /run/media/alice/Backup\\040A ext4 rw 0 0\n\
+             tmpfs /tmp tmpfs rw 0 0\n",
+        );
+        assert_eq!(
+            device_mounts,
+            HashMap::from([(
+                PathBuf::from("/dev/does-not-exist"),
+                PathBuf::from("/run/media/alice/Backup A")
+            )])
+        );
+        assert_eq!(
+            resolve_mount_path(Path::new("/mnt/backup"), &device_mounts),
+            Some(PathBuf::from("/mnt/backup"))
+        );
+        assert_eq!(
+            resolve_mount_path(Path::new("uuid:does-not-exist"), &device_mounts),
+            None
+        );
+    }
+
+    #[test]
+    fn label() {
+        assert_eq!(unescape_label("Backup\\x20A"), "Backup A");
+        assert_eq!(unescape_label("backup\\x2"), "backup\\x2");
+        assert_eq!(unescape_label("backup"), "backup");
+    }
+
+    #[test]
+    fn listing() {
+        let now = Faker.fake::<Clock>().now();
+        let targets = BTreeMap::from([
+            (
+                "1111-AAAA".to_string(),
+                TargetRecord {
+                    label: Some("Backup".to_string()),
+                    size: Some(2000 * 1024 * 1024 * 1024),
+                    last_used: BTreeMap::from([("Home".to_string(), now)]),
+                },
+            ),
+            (
+                "2222-BBBB".to_string(),
+                TargetRecord {
+                    label: Some("Backup".to_string()),
+                    size: None,
+                    last_used: BTreeMap::from([
+                        ("Home".to_string(), now),
+                        ("Photos".to_string(), now),
+                    ]),
+                },
+            ),
+        ]);
+
+        assert_eq!(
+            format_inventory(&targets, &HashSet::from(["2222-BBBB".to_string()])),
+            format!(
+                "Backup (2222-BBBB), connected\n  \
+                 Home: last used {time}\n  \
+                 Photos: last used {time}\n\n\
+                 Backup (1111-AAAA), 2000.0 GiB\n  \
+                 Home: last used {time}",
+                time = format_time(now)
+            )
+        );
+        assert_eq!(
+            format_inventory(&BTreeMap::new(), &HashSet::new()),
+            "No backup disks have been used yet"
+        );
+    }
+}
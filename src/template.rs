@@ -0,0 +1,213 @@
+use std::borrow::Cow;
+
+use chrono::{DateTime, Duration, Local, Utc};
+use nix::unistd::gethostname;
+
+use crate::{settings::Script, targets::resolve_mount_path_now};
+
+// replaces `{key}` placeholders, unknown placeholders are kept as they are
+pub fn render(template: &str, values: &[(&str, String)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+
+        let key = &rest[1..end];
+        match values.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) => output.push_str(value),
+            None => output.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+// replaces `{{key}}` variables in script bodies, unknown variables are kept as they are. The values
+// are quoted for the shell, mount paths may contain spaces (e.g. the label of a drive).
+pub fn render_script(script: &str, variables: &[(&str, String)]) -> String {
+    render_variables(script, variables, shell_quote)
+}
+
+// replaces `{{key}}` variables, the values are escaped for the context of the template (e.g. JSON)
+pub fn render_variables(
+    template: &str,
+    variables: &[(&str, String)],
+    escape: fn(&str) -> Cow<'_, str>,
+) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find("}}") else {
+            break;
+        };
+
+        let key = rest[2..end].trim();
+        match variables.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) => output.push_str(&escape(value)),
+            None => output.push_str(&rest[..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+// e.g. `'/run/media/alice/My Passport'`, values without special characters are kept as they are
+fn shell_quote(value: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("'{}'", value.replace('\'', r"'\''")))
+    }
+}
+
+pub fn script_variables(script: &Script, now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "target",
+            script
+                .mount_paths
+                .first()
+                .map(|path| {
+                    resolve_mount_path_now(path)
+                        .unwrap_or_else(|| path.clone())
+                        .display()
+                        .to_string()
+                })
+                .unwrap_or_default(),
+        ),
+        (
+            "date",
+            now.with_timezone(&Local).format("%Y-%m-%d").to_string(),
+        ),
+        (
+            "hostname",
+            gethostname()
+                .map(|hostname| hostname.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        ),
+        ("script.name", script.name.clone()),
+    ]
+}
+
+pub fn script_values(script: &Script, overdue: Option<Duration>) -> Vec<(&'static str, String)> {
+    vec![
+        ("name", script.name.clone()),
+        (
+            "overdue",
+            match (script.last_backup, overdue) {
+                (None, _) => "never backed up".to_string(),
+                (Some(_), Some(overdue)) => format_overdue(overdue),
+                (Some(_), None) => "not overdue".to_string(),
+            },
+        ),
+        ("last_backup", format_last_backup(script.last_backup)),
+    ]
+}
+
+fn format_last_backup(last_backup: Option<DateTime<Utc>>) -> String {
+    last_backup.map_or_else(
+        || "never".to_string(),
+        |ts| {
+            ts.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        },
+    )
+}
+
+pub fn format_overdue(overdue: Duration) -> String {
+    fn plural(count: i64, unit: &str) -> String {
+        if count == 1 {
+            format!("{count} {unit}")
+        } else {
+            format!("{count} {unit}s")
+        }
+    }
+
+    if overdue >= Duration::days(1) {
+        plural(overdue.num_days(), "day")
+    } else if overdue >= Duration::hours(1) {
+        plural(overdue.num_hours(), "hour")
+    } else {
+        plural(overdue.num_minutes().max(1), "minute")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_placeholders() {
+        let values = [
+            ("name", "Photos".to_string()),
+            ("overdue", "9 days".to_string()),
+        ];
+
+        assert_eq!(
+            render("{name}: {overdue} overdue", &values),
+            "Photos: 9 days overdue"
+        );
+        assert_eq!(
+            render("Sicherung {name} überfällig", &values),
+            "Sicherung Photos überfällig"
+        );
+        assert_eq!(render("{unknown} {name}", &values), "{unknown} Photos");
+        assert_eq!(render("{name} {", &values), "Photos {");
+        assert_eq!(render("no placeholders", &values), "no placeholders");
+    }
+
+    #[test]
+    fn render_script_variables() {
+        let variables = [
+            ("target", "/mnt/backup".to_string()),
+            ("script.name", "Photos".to_string()),
+        ];
+
+        assert_eq!(
+            render_script(
+                "rsync -a ~/Pictures/ {{target}}/{{ script.name }}/",
+                &variables
+            ),
+            "rsync -a ~/Pictures/ /mnt/backup/Photos/"
+        );
+        assert_eq!(
+            render_script("echo ${HOME} {{unknown}} {{target", &variables),
+            "echo ${HOME} {{unknown}} {{target"
+        );
+
+        let variables = [
+            ("target", "/run/media/alice/My Passport".to_string()),
+            ("script.name", "$(reboot)'s".to_string()),
+        ];
+        assert_eq!(
+            render_script("cd {{target}} && echo {{script.name}}", &variables),
+            r"cd '/run/media/alice/My Passport' && echo '$(reboot)'\''s'"
+        );
+    }
+
+    #[test]
+    fn format_overdue_durations() {
+        assert_eq!(format_overdue(Duration::seconds(5)), "1 minute");
+        assert_eq!(format_overdue(Duration::minutes(42)), "42 minutes");
+        assert_eq!(format_overdue(Duration::hours(1)), "1 hour");
+        assert_eq!(format_overdue(Duration::hours(23)), "23 hours");
+        assert_eq!(format_overdue(Duration::days(1)), "1 day");
+        assert_eq!(format_overdue(Duration::hours(9 * 24 + 5)), "9 days");
+    }
+}
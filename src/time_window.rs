@@ -0,0 +1,176 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{ensure, Context};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+// hours of the day, e.g. `20:00-06:00`, the end is on the next day if it isn't after the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl FromStr for TimeWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (start, end) = value
+            .split_once('-')
+            .context("expected a start and an end, e.g. `20:00-06:00`")?;
+        let time = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .with_context(|| format!("`{}` is not a time, e.g. `20:00`", time.trim()))
+        };
+        let window = TimeWindow {
+            start: time(start)?,
+            end: time(end)?,
+        };
+        ensure!(
+            window.start != window.end || window.start == NaiveTime::MIN,
+            "the window is empty, use `00:00-00:00` for the whole day"
+        );
+        Ok(window)
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(window: TimeWindow) -> Self {
+        window.to_string()
+    }
+}
+
+// `ts` if it is inside the hours on one of the days, otherwise the next start of the hours on one
+// of the days; a window belongs to the day it starts on and no days means every day
+pub fn next_allowed<Tz: TimeZone>(
+    ts: DateTime<Utc>,
+    hours: Option<&TimeWindow>,
+    days: &[Weekday],
+    tz: &Tz,
+) -> DateTime<Utc> {
+    let hours = hours.copied().unwrap_or(TimeWindow {
+        start: NaiveTime::MIN,
+        end: NaiveTime::MIN,
+    });
+    let date = ts.with_timezone(tz).date_naive();
+
+    // the window of the previous day may reach into this day
+    for day in -1..=7 {
+        let date = date + Duration::days(day);
+        if !days.is_empty() && !days.contains(&date.weekday()) {
+            continue;
+        }
+        // windows starting in a gap of daylight saving time are skipped
+        let Some((start, end)) = window(date, &hours, tz) else {
+            continue;
+        };
+        if start <= ts && ts < end {
+            return ts;
+        }
+        if start > ts {
+            return start;
+        }
+    }
+
+    ts
+}
+
+fn window<Tz: TimeZone>(
+    date: NaiveDate,
+    hours: &TimeWindow,
+    tz: &Tz,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let end_date = if hours.end <= hours.start {
+        date.succ_opt()?
+    } else {
+        date
+    };
+    let start = tz
+        .from_local_datetime(&date.and_time(hours.start))
+        .earliest()?;
+    let end = tz
+        .from_local_datetime(&end_date.and_time(hours.end))
+        .latest()?;
+    Some((start.with_timezone(&Utc), end.with_timezone(&Utc)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().to_utc()
+    }
+
+    #[test]
+    fn next_allowed_times() {
+        let nights = "20:00-06:00".parse::<TimeWindow>().unwrap();
+        let weekdays = [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ];
+
+        // a wednesday
+        let afternoon = ts("2024-10-23T15:00:00Z");
+        assert_eq!(
+            next_allowed(afternoon, Some(&nights), &[], &Utc),
+            ts("2024-10-23T20:00:00Z")
+        );
+        // the window of tuesday
+        let morning = ts("2024-10-23T05:00:00Z");
+        assert_eq!(next_allowed(morning, Some(&nights), &[], &Utc), morning);
+        assert_eq!(next_allowed(afternoon, None, &weekdays, &Utc), afternoon);
+
+        // a saturday
+        let saturday = ts("2024-10-26T10:00:00Z");
+        assert_eq!(
+            next_allowed(saturday, None, &weekdays, &Utc),
+            ts("2024-10-28T00:00:00Z")
+        );
+        // the window of friday reaches into saturday
+        let early_saturday = ts("2024-10-26T03:00:00Z");
+        assert_eq!(
+            next_allowed(early_saturday, Some(&nights), &weekdays, &Utc),
+            early_saturday
+        );
+        assert_eq!(
+            next_allowed(saturday, Some(&nights), &weekdays, &Utc),
+            ts("2024-10-28T20:00:00Z")
+        );
+    }
+
+    #[test]
+    fn parse_time_windows() {
+        assert_eq!(
+            "08:30-17:00".parse::<TimeWindow>().unwrap().to_string(),
+            "08:30-17:00"
+        );
+        assert!("00:00-00:00".parse::<TimeWindow>().is_ok());
+        assert!("20:00-20:00".parse::<TimeWindow>().is_err());
+        assert!("20:00".parse::<TimeWindow>().is_err());
+        assert!("8pm-6am".parse::<TimeWindow>().is_err());
+    }
+}
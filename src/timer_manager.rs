@@ -0,0 +1,367 @@
+use std::{collections::HashMap, sync::Arc};
+
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Duration, Utc};
+use zbus::blocking::Connection;
+
+use crate::{
+    clock::Clock,
+    history::Trigger,
+    manager::{Manager, Overdue, RunOutcome, ScriptStatus},
+    script_manager::format_time,
+    settings::{Settings, Timer},
+    systemd::{timer_state, TimerState},
+    tray::Tray,
+    tray_handle::TrayHandle,
+    upower::Power,
+    RETRY_INTERVAL,
+};
+
+// the result is only known once the service has finished
+const RUNNING_POLL_INTERVAL: Duration = Duration::minutes(1);
+
+// monitors backups run by systemd timers, without running them
+pub struct TimerManager {
+    clock: Clock,
+    settings: Arc<ArcSwap<Settings>>,
+    // `Err` if the timer couldn't be read
+    states: HashMap<String, Result<TimerState, String>>,
+    refreshed: Option<DateTime<Utc>>,
+}
+
+impl TimerManager {
+    pub fn new(clock: Clock, settings: Arc<ArcSwap<Settings>>) -> TimerManager {
+        TimerManager {
+            clock,
+            settings,
+            states: HashMap::new(),
+            refreshed: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let settings = self.settings.load_full();
+
+        let mut session = None;
+        let mut system = None;
+
+        self.states.clear();
+        for timer in &settings.timers {
+            let connection = if timer.system {
+                system.get_or_insert_with(Connection::system)
+            } else {
+                session.get_or_insert_with(Connection::session)
+            };
+
+            let state = connection
+                .as_ref()
+                .map_err(ToString::to_string)
+                .and_then(|connection| {
+                    timer_state(connection, &timer.unit).map_err(|error| error.to_string())
+                });
+            if let Err(error) = &state {
+                log::warn!("failed to read timer `{}`: {error}", timer.unit);
+            }
+
+            self.states.insert(timer.name.clone(), state);
+        }
+
+        self.refreshed = Some(self.clock.now());
+    }
+
+    fn next_refresh(&self, timer: &Timer) -> DateTime<Utc> {
+        let Some(refreshed) = self.refreshed else {
+            return self.clock.now();
+        };
+
+        match self.states.get(&timer.name) {
+            Some(Ok(state)) if state.running => refreshed + RUNNING_POLL_INTERVAL,
+            // give the service some time to start
+            Some(Ok(state)) => state.next_elapse.map_or(refreshed + RETRY_INTERVAL, |ts| {
+                ts.max(refreshed) + RUNNING_POLL_INTERVAL
+            }),
+            Some(Err(_)) => refreshed + RETRY_INTERVAL,
+            // added since the last refresh
+            None => self.clock.now(),
+        }
+    }
+
+    fn reminder_at(&self, timer: &Timer) -> Option<DateTime<Utc>> {
+        let reminder = timer.reminder?;
+        let Some(Ok(state)) = self.states.get(&timer.name) else {
+            return None;
+        };
+        if state.running {
+            return None;
+        }
+
+        Some(
+            state
+                .last_trigger
+                .map_or(self.clock.now(), |last_trigger| last_trigger + reminder),
+        )
+    }
+}
+
+impl Manager for TimerManager {
+    fn next_backup(&self) -> Option<DateTime<Utc>> {
+        let settings = self.settings.load();
+
+        settings
+            .timers
+            .iter()
+            .map(|timer| self.next_refresh(timer))
+            .min()
+    }
+
+    fn next_reminder(&self) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+
+        settings
+            .timers
+            .iter()
+            .filter_map(|timer| self.reminder_at(timer))
+            .min()
+            .map(|ts| settings.paused_until(now).map_or(ts, |until| ts.max(until)))
+    }
+
+    fn next_ui_update(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    fn tooltip(&self) -> String {
+        let settings = self.settings.load();
+
+        settings
+            .timers
+            .iter()
+            .map(|timer| {
+                let status = match self.states.get(&timer.name) {
+                    Some(Ok(state)) => tooltip(state),
+                    Some(Err(error)) => format!("Failed to read {}: {error}", timer.unit),
+                    None => format!("Reading {}", timer.unit),
+                };
+                format!("{}:\n{status}", timer.name)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn low_space(&self) -> bool {
+        false
+    }
+
+    fn overdue(&self) -> Vec<Overdue> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+
+        if settings.paused_until(now).is_some() {
+            return Vec::new();
+        }
+
+        settings
+            .timers
+            .iter()
+            .filter_map(|timer| {
+                let next_reminder = self.reminder_at(timer).filter(|ts| *ts <= now)?;
+                let last_trigger = match self.states.get(&timer.name) {
+                    Some(Ok(state)) => state.last_trigger,
+                    _ => None,
+                };
+                Some(Overdue {
+                    name: timer.name.clone(),
+                    overdue: last_trigger.map(|_| now - next_reminder),
+                    missing_paths: Vec::new(),
+                    target_missing_for: None,
+                })
+            })
+            .collect()
+    }
+
+    fn script_status(&self, script_name: &str) -> ScriptStatus {
+        match self.states.get(script_name) {
+            Some(Ok(state)) if state.running => ScriptStatus::Running,
+            Some(Ok(state)) if state.failed() => ScriptStatus::Failed,
+            Some(Err(_)) => ScriptStatus::Failed,
+            _ if self.overdue().iter().any(|item| item.name == script_name) => {
+                ScriptStatus::Overdue
+            }
+            _ => ScriptStatus::Ok,
+        }
+    }
+
+    fn explain(&self, script_name: &str) -> Option<String> {
+        let now = self.clock.now();
+        let settings = self.settings.load();
+        let timer = settings
+            .timers
+            .iter()
+            .find(|timer| timer.name == script_name)?;
+
+        let instance = if timer.system { "system" } else { "user" };
+        let reminder = match timer.reminder {
+            Some(reminder) => humantime::format_duration(reminder).to_string(),
+            None => "none".to_string(),
+        };
+        let next_reminder = match self.reminder_at(timer) {
+            Some(ts) => format_time(ts.max(now)),
+            None => "none".to_string(),
+        };
+
+        let mut lines = vec![
+            format!("{}:", timer.name),
+            format!("Timer: {} ({instance})", timer.unit),
+        ];
+        match self.states.get(&timer.name) {
+            Some(Ok(state)) => {
+                let last_run = state.last_trigger.map_or("never".to_string(), format_time);
+                let next_run = state.next_elapse.map_or("none".to_string(), format_time);
+                let state = if state.running {
+                    "running".to_string()
+                } else {
+                    format!("finished ({})", state.result)
+                };
+                lines.extend([
+                    format!("Last run: {last_run}"),
+                    format!("State: {state}"),
+                    format!("Next run: {next_run}"),
+                ]);
+            }
+            Some(Err(error)) => lines.push(format!("State: failed to read the timer: {error}")),
+            None => lines.push("State: not read yet".to_string()),
+        }
+        lines.extend([
+            format!("Reminder: {reminder}"),
+            format!("Next reminder: {next_reminder}"),
+            "The backup is run by systemd".to_string(),
+        ]);
+
+        Some(lines.join("\n"))
+    }
+
+    fn set_mounts(&mut self, _mounts: &str) {}
+
+    fn set_active_user(&mut self, _user: Option<String>) {}
+
+    fn set_power(&mut self, _power: Option<Power>) {}
+
+    fn set_idle_since(&mut self, _idle_since: Option<DateTime<Utc>>) {}
+
+    fn dismiss_failure(&mut self, _script_name: &str) {}
+
+    fn run(
+        &mut self,
+        _script_name: Option<&str>,
+        _trigger: &Trigger,
+        _handle: &impl TrayHandle<Tray>,
+    ) -> anyhow::Result<Vec<(String, RunOutcome)>> {
+        self.refresh();
+        Ok(Vec::new())
+    }
+}
+
+fn tooltip(state: &TimerState) -> String {
+    let last_run = match state.last_trigger {
+        Some(last_trigger) if state.failed() => format!(
+            "Last run at {} failed ({})",
+            format_time(last_trigger),
+            state.result
+        ),
+        Some(last_trigger) => format!("Last run at {}", format_time(last_trigger)),
+        None => "Never run before".to_string(),
+    };
+    let status = match state.next_elapse {
+        _ if state.running => "Running".to_string(),
+        Some(next_elapse) => format!("Next run at {}", format_time(next_elapse)),
+        None => "Not scheduled".to_string(),
+    };
+
+    format!("{last_run}\n{status}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+
+    fn timer(reminder: Option<std::time::Duration>) -> Timer {
+        Timer {
+            name: "Borgmatic".to_string(),
+            unit: "borgmatic.timer".to_string(),
+            system: false,
+            reminder,
+        }
+    }
+
+    #[test]
+    fn overdue_timer() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            timers: vec![timer(Some(std::time::Duration::from_secs(24 * 60 * 60)))],
+            ..Default::default()
+        }));
+        let mut manager = TimerManager::new(clock, settings);
+
+        // not read yet
+        assert_eq!(manager.next_backup(), Some(now));
+        assert_eq!(manager.next_reminder(), None);
+
+        let next_elapse = now + Duration::hours(2);
+        manager.refreshed = Some(now);
+        manager.states.insert(
+            "Borgmatic".to_string(),
+            Ok(TimerState {
+                last_trigger: Some(now - Duration::days(2)),
+                next_elapse: Some(next_elapse),
+                running: false,
+                result: "exit-code".to_string(),
+            }),
+        );
+
+        assert_eq!(
+            manager.next_backup(),
+            Some(next_elapse + RUNNING_POLL_INTERVAL)
+        );
+        assert_eq!(manager.next_reminder(), Some(now - Duration::days(1)));
+        assert_eq!(
+            manager.overdue(),
+            vec![Overdue {
+                name: "Borgmatic".to_string(),
+                overdue: Some(Duration::days(1)),
+                missing_paths: Vec::new(),
+                target_missing_for: None,
+            }]
+        );
+        assert_eq!(manager.script_status("Borgmatic"), ScriptStatus::Failed);
+        assert_eq!(manager.script_status("Backup"), ScriptStatus::Ok);
+        assert!(manager.explain("Backup").is_none());
+    }
+
+    #[test]
+    fn running_timer() {
+        let clock = Faker.fake::<Clock>();
+        let now = clock.now();
+        let settings = Arc::new(ArcSwap::from_pointee(Settings {
+            timers: vec![timer(Some(std::time::Duration::from_secs(60)))],
+            ..Default::default()
+        }));
+        let mut manager = TimerManager::new(clock, settings);
+
+        manager.refreshed = Some(now);
+        manager.states.insert(
+            "Borgmatic".to_string(),
+            Ok(TimerState {
+                last_trigger: Some(now - Duration::hours(1)),
+                next_elapse: Some(now + Duration::days(1)),
+                running: true,
+                result: "success".to_string(),
+            }),
+        );
+
+        assert_eq!(manager.next_backup(), Some(now + RUNNING_POLL_INTERVAL));
+        assert_eq!(manager.next_reminder(), None);
+        assert_eq!(manager.script_status("Borgmatic"), ScriptStatus::Running);
+    }
+}
@@ -1,8 +1,19 @@
-use std::{process::Command, sync::mpsc::Sender};
+use std::{path::Path, process::Command, thread, time::Duration};
+
+use notify_rust::{Notification, Timeout};
 
 use crate::{
-    settings::{settings_file_path, Settings},
-    Event,
+    diagnostics,
+    event::EventSender,
+    history::Trigger,
+    manager::ScriptStatus,
+    notification,
+    script_manager::log_file_path,
+    settings::{
+        add_example_script, settings_file_path, Menu, MenuLayout, Settings, StatusMarkers,
+        TrayCommand,
+    },
+    targets, Event,
 };
 
 pub struct Tray {
@@ -10,22 +21,51 @@ pub struct Tray {
     title: String,
     status: ksni::Status,
     tooltip: String,
-    scripts: Vec<(String, Option<String>)>,
-    tx: Sender<Event>,
+    scripts: Vec<(String, Option<String>, ScriptStatus)>,
+    paused: bool,
+    profiles: Vec<String>,
+    active_profile: Option<String>,
+    overdue: Vec<String>,
+    // use the HTML subset supported by hosts like Plasma, read once like the icon and the title
+    rich_tooltip: bool,
+    status_markers: StatusMarkers,
+    log_viewer: Option<String>,
+    menu: Menu,
+    tray_commands: Vec<TrayCommand>,
+    // shows the status of the system-wide daemon
+    remote: bool,
+    tx: EventSender,
 }
 
 impl Tray {
-    pub fn new(settings: &Settings, tx: Sender<Event>) -> Tray {
+    pub fn new(settings: &Settings, tx: EventSender) -> Tray {
         Tray {
             icon_name: settings.icon_name.clone(),
             title: settings.title.clone(),
             status: ksni::Status::Passive,
             tooltip: String::new(),
             scripts: Vec::new(),
+            paused: false,
+            profiles: Vec::new(),
+            active_profile: None,
+            overdue: Vec::new(),
+            rich_tooltip: settings.rich_tooltip,
+            status_markers: settings.status_markers,
+            log_viewer: settings.log_viewer.clone(),
+            menu: settings.menu.clone(),
+            tray_commands: settings.tray_commands.clone(),
+            remote: false,
             tx,
         }
     }
 
+    pub fn remote(settings: &Settings, tx: EventSender) -> Tray {
+        Tray {
+            remote: true,
+            ..Tray::new(settings, tx)
+        }
+    }
+
     pub fn set_status(&mut self, status: ksni::Status) {
         self.status = status;
     }
@@ -34,9 +74,133 @@ impl Tray {
         self.tooltip = tooltip;
     }
 
-    pub fn set_scripts(&mut self, scripts: Vec<(String, Option<String>)>) {
+    pub fn set_scripts(&mut self, scripts: Vec<(String, Option<String>, ScriptStatus)>) {
         self.scripts = scripts;
     }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn set_profiles(&mut self, profiles: Vec<String>, active_profile: Option<String>) {
+        self.profiles = profiles;
+        self.active_profile = active_profile;
+    }
+
+    pub fn set_overdue(&mut self, overdue: Vec<String>) {
+        self.overdue = overdue;
+    }
+
+    // `None` for other items of the tooltip, e.g. of timers
+    fn marker(&self, script_name: &str) -> Option<&'static str> {
+        let (_, _, status) = self
+            .scripts
+            .iter()
+            .find(|(name, _, _)| name == script_name)?;
+        status_marker(self.status_markers, *status)
+    }
+
+    // the marker replaces the suffix of `status_icon`
+    fn script_label(&self, label: &str, status: ScriptStatus, suffix: &str) -> String {
+        match status_marker(self.status_markers, status) {
+            Some(marker) => format!("{marker} {label}"),
+            None => format!("{label}{suffix}"),
+        }
+    }
+
+    // `Run` and `Cancel` of a script, without its name inside of its submenu
+    fn script_items(
+        &self,
+        script_name: &str,
+        icon_name: Option<&str>,
+        status: ScriptStatus,
+        grouped: bool,
+    ) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::*;
+
+        let (icon_name, suffix) = status_icon(icon_name, status);
+
+        // greyed out, so it isn't run by accident
+        if status == ScriptStatus::Disabled {
+            return vec![StandardItem {
+                label: if grouped {
+                    "Disabled".to_string()
+                } else {
+                    self.script_label(script_name, status, suffix)
+                },
+                icon_name: icon_name.to_string(),
+                enabled: false,
+                ..Default::default()
+            }
+            .into()];
+        }
+
+        let mut items = Vec::new();
+
+        let tx = self.tx.clone();
+        let name = script_name.to_string();
+        items.push(
+            StandardItem {
+                label: if grouped {
+                    "Run now".to_string()
+                } else {
+                    self.script_label(&format!("Run {script_name} now"), status, suffix)
+                },
+                icon_name: icon_name.to_string(),
+                activate: Box::new(move |_| {
+                    let _ = tx.send(Event::ManualRun(name.clone(), Trigger::Tray, None));
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        // the system-wide daemon doesn't accept cancel requests
+        if status == ScriptStatus::Running && !self.remote {
+            let tx = self.tx.clone();
+            let name = script_name.to_string();
+
+            items.push(
+                StandardItem {
+                    label: if grouped {
+                        "Cancel".to_string()
+                    } else {
+                        format!("Cancel {script_name}")
+                    },
+                    icon_name: "process-stop".to_string(),
+                    activate: Box::new(move |_| {
+                        let _ = tx.send(Event::Cancel(name.clone()));
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        // nor requests to dismiss failures
+        if status == ScriptStatus::Failed && !self.remote {
+            let tx = self.tx.clone();
+            let name = script_name.to_string();
+
+            items.push(
+                StandardItem {
+                    label: if grouped {
+                        "Dismiss failure".to_string()
+                    } else {
+                        format!("Dismiss failure of {script_name}")
+                    },
+                    icon_name: "edit-clear".to_string(),
+                    activate: Box::new(move |_| {
+                        let _ = tx.send(Event::DismissFailure(name.clone()));
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        items
+    }
 }
 
 impl ksni::Tray for Tray {
@@ -61,10 +225,21 @@ impl ksni::Tray for Tray {
     }
 
     fn tool_tip(&self) -> ksni::ToolTip {
+        let (icon_name, state) = match (self.status, self.paused) {
+            (ksni::Status::Active, _) => ("media-playback-start", "running"),
+            (ksni::Status::NeedsAttention, _) => ("dialog-warning", "needs attention"),
+            (ksni::Status::Passive, true) => ("media-playback-pause", "paused"),
+            (ksni::Status::Passive, false) => (self.icon_name.as_str(), "up to date"),
+        };
+
         ksni::ToolTip {
-            icon_name: "backup".to_string(),
-            title: "Backup Monitor".to_string(),
-            description: self.tooltip.clone(),
+            icon_name: icon_name.to_string(),
+            title: format!("{} ({state})", self.title),
+            description: if self.rich_tooltip {
+                rich_tooltip(&self.tooltip, &self.overdue, |name| self.marker(name))
+            } else {
+                mark_tooltip(&self.tooltip, |name| self.marker(name))
+            },
             ..Default::default()
         }
     }
@@ -74,16 +249,118 @@ impl ksni::Tray for Tray {
 
         let mut items = Vec::new();
 
-        for (script_name, icon_name) in &self.scripts {
-            let tx = self.tx.clone();
-            let name = script_name.clone();
+        let grouped = self.menu.layout == MenuLayout::Grouped;
+
+        // the logs of the system-wide daemon are only readable by root
+        let logs = if self.remote {
+            Vec::new()
+        } else {
+            self.scripts
+                .iter()
+                .filter_map(|(script_name, _, _)| {
+                    let path = log_file_path(script_name)
+                        .ok()
+                        .filter(|path| path.exists())?;
+                    Some((script_name.clone(), path))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (script_name, icon_name, status) in pinned_first(&self.scripts, &self.menu.favorites) {
+            let script_items =
+                self.script_items(script_name, icon_name.as_deref(), *status, grouped);
+            if !grouped {
+                items.extend(script_items);
+                continue;
+            }
+
+            let mut submenu = script_items;
+            if let Some((_, path)) = logs.iter().find(|(name, _)| name == script_name) {
+                let log_viewer = self.log_viewer.clone();
+                let path = path.clone();
+                submenu.push(
+                    StandardItem {
+                        label: "View last log".to_string(),
+                        icon_name: "document-open".to_string(),
+                        activate: Box::new(move |_| {
+                            open_log(log_viewer.as_deref(), &path);
+                        }),
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            }
+            let (icon_name, suffix) = status_icon(icon_name.as_deref(), *status);
+            items.push(
+                SubMenu {
+                    label: self.script_label(script_name, *status, suffix),
+                    icon_name: icon_name.to_string(),
+                    submenu,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        if !logs.is_empty() && !grouped {
+            items.push(
+                SubMenu {
+                    label: "View last log".to_string(),
+                    icon_name: "document-open".to_string(),
+                    submenu: logs
+                        .into_iter()
+                        .map(|(script_name, path)| {
+                            let log_viewer = self.log_viewer.clone();
+                            StandardItem {
+                                label: script_name,
+                                activate: Box::new(move |_| {
+                                    open_log(log_viewer.as_deref(), &path);
+                                }),
+                                ..Default::default()
+                            }
+                            .into()
+                        })
+                        .collect(),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        // inserts an example into the settings file of new users
+        if self.scripts.is_empty() && !self.remote {
+            items.push(
+                StandardItem {
+                    label: "Add your first backup…".to_string(),
+                    icon_name: "list-add".to_string(),
+                    activate: Box::new(|_| match add_example_script() {
+                        Ok(path) => {
+                            let _ = Command::new("xdg-open").arg(path).spawn();
+                        }
+                        Err(error) => log::warn!("failed to add example script: {error}"),
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        if !items.is_empty() {
+            items.push(MenuItem::Separator);
+        }
 
+        for command in &self.tray_commands {
+            let tx = self.tx.clone();
+            let label = command.label.clone();
             items.push(
                 StandardItem {
-                    label: format!("Run {script_name} now"),
-                    icon_name: icon_name.as_deref().unwrap_or("system-run").to_string(),
+                    label: command.label.clone(),
+                    icon_name: command
+                        .icon_name
+                        .clone()
+                        .unwrap_or_else(|| "system-run".to_string()),
                     activate: Box::new(move |_| {
-                        let _ = tx.send(Event::ManualRun(name.clone()));
+                        let _ = tx.send(Event::TrayCommand(label.clone()));
                     }),
                     ..Default::default()
                 }
@@ -91,30 +368,164 @@ impl ksni::Tray for Tray {
             );
         }
 
-        items.push(MenuItem::Separator);
+        // the system-wide daemon can only be configured by root
+        if !self.remote {
+            if self.paused {
+                let tx = self.tx.clone();
+                items.push(
+                    StandardItem {
+                        label: "Resume backups".to_string(),
+                        icon_name: "media-playback-start".to_string(),
+                        activate: Box::new(move |_| {
+                            let _ = tx.send(Event::Resume);
+                        }),
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            } else {
+                let durations = [
+                    ("For 1 day", Duration::from_secs(24 * 60 * 60)),
+                    ("For 1 week", Duration::from_secs(7 * 24 * 60 * 60)),
+                    ("For 2 weeks", Duration::from_secs(14 * 24 * 60 * 60)),
+                ];
+                items.push(
+                    SubMenu {
+                        label: "Pause backups".to_string(),
+                        icon_name: "media-playback-pause".to_string(),
+                        submenu: durations
+                            .into_iter()
+                            .map(|(label, duration)| {
+                                let tx = self.tx.clone();
+                                StandardItem {
+                                    label: label.to_string(),
+                                    activate: Box::new(move |_| {
+                                        let _ = tx.send(Event::Pause(duration));
+                                    }),
+                                    ..Default::default()
+                                }
+                                .into()
+                            })
+                            .collect(),
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            }
 
-        items.push(
-            StandardItem {
-                label: "Settings".to_string(),
-                icon_name: "settings-configure".to_string(),
-                activate: Box::new(|_| {
-                    let settings_file_path = settings_file_path()
-                        .unwrap()
-                        .to_str()
-                        .map(str::to_string)
-                        .unwrap();
-                    let _ = Command::new("xdg-open").arg(settings_file_path).spawn();
-                }),
-                ..Default::default()
+            if !self.profiles.is_empty() {
+                let selected = self
+                    .profiles
+                    .iter()
+                    .position(|profile| self.active_profile.as_ref() == Some(profile))
+                    .map_or(0, |index| index + 1);
+                items.push(
+                    SubMenu {
+                        label: "Profile".to_string(),
+                        icon_name: "view-filter".to_string(),
+                        submenu: vec![RadioGroup {
+                            selected,
+                            select: Box::new(|tray: &mut Tray, index| {
+                                // the first option uses all backup scripts
+                                let profile = index
+                                    .checked_sub(1)
+                                    .and_then(|index| tray.profiles.get(index))
+                                    .cloned();
+                                let _ = tray.tx.send(Event::SwitchProfile(profile));
+                            }),
+                            options: ["All backups".to_string()]
+                                .into_iter()
+                                .chain(self.profiles.iter().cloned())
+                                .map(|label| RadioItem {
+                                    label,
+                                    ..Default::default()
+                                })
+                                .collect(),
+                        }
+                        .into()],
+                        ..Default::default()
+                    }
+                    .into(),
+                );
             }
-            .into(),
-        );
 
+            items.push(
+                StandardItem {
+                    label: "Settings".to_string(),
+                    icon_name: "settings-configure".to_string(),
+                    activate: Box::new(|_| {
+                        let settings_file_path = settings_file_path()
+                            .unwrap()
+                            .to_str()
+                            .map(str::to_string)
+                            .unwrap();
+                        let _ = Command::new("xdg-open").arg(settings_file_path).spawn();
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+
+            let title = self.title.clone();
+            let icon_name = self.icon_name.clone();
+            items.push(
+                StandardItem {
+                    label: "Backup Disks".to_string(),
+                    icon_name: "drive-harddisk".to_string(),
+                    activate: Box::new(move |_| targets::show(title.clone(), icon_name.clone())),
+                    ..Default::default()
+                }
+                .into(),
+            );
+
+            let title = self.title.clone();
+            let icon_name = self.icon_name.clone();
+            items.push(
+                StandardItem {
+                    label: "About / Diagnostics".to_string(),
+                    icon_name: "help-about".to_string(),
+                    activate: Box::new(move |_| {
+                        let title = title.clone();
+                        let icon_name = icon_name.clone();
+                        // waits for the action of the notification
+                        thread::spawn(move || {
+                            if let Err(error) = diagnostics::show(&title, &icon_name) {
+                                log::warn!("failed to show diagnostics: {error}");
+                            }
+                        });
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        // e.g. if the session restarts the tray anyway
+        if self.menu.hide_exit {
+            return items;
+        }
+
+        let title = self.title.clone();
+        let icon_name = self.icon_name.clone();
+        let summary = exit_summary(&self.scripts);
         items.push(
             StandardItem {
                 label: "Exit".to_string(),
                 icon_name: "application-exit".to_string(),
-                activate: Box::new(|_| std::process::exit(0)),
+                activate: Box::new(move |_| {
+                    // closing the tray shouldn't hide problems
+                    if let Some((summary, body)) = &summary {
+                        let _ = notification::show(
+                            Notification::new()
+                                .appname(&title)
+                                .summary(summary)
+                                .body(body)
+                                .icon(&icon_name)
+                                .timeout(Timeout::Never),
+                        );
+                    }
+                    std::process::exit(0)
+                }),
                 ..Default::default()
             }
             .into(),
@@ -123,3 +534,428 @@ impl ksni::Tray for Tray {
         items
     }
 }
+
+// the configured icon is only shown while the script doesn't need attention
+fn status_icon(icon_name: Option<&str>, status: ScriptStatus) -> (&str, &'static str) {
+    match status {
+        ScriptStatus::Ok => (icon_name.unwrap_or("system-run"), ""),
+        ScriptStatus::Disabled => (icon_name.unwrap_or("system-run"), " (disabled)"),
+        ScriptStatus::Overdue => ("dialog-warning", " (overdue)"),
+        ScriptStatus::Failed => ("dialog-error", " (failed)"),
+        ScriptStatus::Running => ("process-working", " (running)"),
+    }
+}
+
+fn status_marker(markers: StatusMarkers, status: ScriptStatus) -> Option<&'static str> {
+    match (markers, status) {
+        (StatusMarkers::None, _) | (_, ScriptStatus::Disabled) => None,
+        (StatusMarkers::Emoji, ScriptStatus::Ok) => Some("✅"),
+        (StatusMarkers::Emoji, ScriptStatus::Overdue) => Some("⚠️"),
+        (StatusMarkers::Emoji, ScriptStatus::Failed) => Some("❌"),
+        (StatusMarkers::Emoji, ScriptStatus::Running) => Some("⏳"),
+        (StatusMarkers::Text, ScriptStatus::Ok) => Some("[ok]"),
+        (StatusMarkers::Text, ScriptStatus::Overdue) => Some("[overdue]"),
+        (StatusMarkers::Text, ScriptStatus::Failed) => Some("[failed]"),
+        (StatusMarkers::Text, ScriptStatus::Running) => Some("[running]"),
+    }
+}
+
+// puts the markers in front of the script names, which start the items of the tooltip
+fn mark_tooltip(tooltip: &str, marker: impl Fn(&str) -> Option<&'static str>) -> String {
+    tooltip
+        .split("\n\n")
+        .map(
+            |item| match item.split_once(":\n").and_then(|(name, _)| marker(name)) {
+                Some(marker) => format!("{marker} {item}"),
+                None => item.to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// the favorites in their configured order, followed by the other scripts
+fn pinned_first<'a, T>(
+    scripts: &'a [(String, Option<String>, T)],
+    favorites: &[String],
+) -> Vec<&'a (String, Option<String>, T)> {
+    favorites
+        .iter()
+        .filter_map(|favorite| scripts.iter().find(|(name, _, _)| name == favorite))
+        .chain(
+            scripts
+                .iter()
+                .filter(|(name, _, _)| !favorites.contains(name)),
+        )
+        .collect()
+}
+
+// makes the script names bold and marks overdue scripts
+// the viewer may include arguments, e.g. `kitty less`
+fn open_log(log_viewer: Option<&str>, path: &Path) {
+    let mut words = log_viewer.unwrap_or("xdg-open").split_whitespace();
+    let Some(program) = words.next() else {
+        return;
+    };
+
+    if let Err(error) = Command::new(program).args(words).arg(path).spawn() {
+        log::warn!("failed to open log file: {error}");
+    }
+}
+
+// summary and body of the notification shown on exit, `None` if no script failed or is overdue
+fn exit_summary(scripts: &[(String, Option<String>, ScriptStatus)]) -> Option<(String, String)> {
+    let problems = scripts
+        .iter()
+        .filter_map(|(name, _, status)| match status {
+            ScriptStatus::Failed => Some(format!("{name} failed")),
+            ScriptStatus::Overdue => Some(format!("{name} is overdue")),
+            ScriptStatus::Ok | ScriptStatus::Running | ScriptStatus::Disabled => None,
+        })
+        .collect::<Vec<_>>();
+    if problems.is_empty() {
+        return None;
+    }
+
+    Some((
+        "Backups need attention".to_string(),
+        format!(
+            "{}\nBackup Monitor has exited and won't remind you.",
+            problems.join("\n")
+        ),
+    ))
+}
+
+fn rich_tooltip(
+    tooltip: &str,
+    overdue: &[String],
+    marker: impl Fn(&str) -> Option<&'static str>,
+) -> String {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    tooltip
+        .split("\n\n")
+        .map(|item| match item.split_once(":\n") {
+            Some((name, status)) => {
+                let overdue_marker = if overdue.iter().any(|overdue| overdue == name) {
+                    " <font color=\"#da4453\">(overdue)</font>"
+                } else {
+                    ""
+                };
+                let status_marker =
+                    marker(name).map_or(String::new(), |marker| format!("{marker} "));
+                format!(
+                    "{status_marker}<b>{}</b>{overdue_marker}<br/>{}",
+                    escape(name),
+                    escape(status).replace('\n', "<br/>")
+                )
+            }
+            None => escape(item).replace('\n', "<br/>"),
+        })
+        .collect::<Vec<_>>()
+        .join("<br/><br/>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::channel,
+        mock_tray_host::MockTrayHost,
+        tray_handle::{TrayData, TrayHandle},
+    };
+    use std::sync::mpsc::Receiver;
+
+    // the names are unlikely to have logs on the machine running the tests, which add menu items
+    fn host(settings: Settings) -> (MockTrayHost, Receiver<Event>) {
+        let (tx, rx) = channel();
+        let host = MockTrayHost::new(Tray::new(&settings, tx));
+        host.update(TrayData {
+            scripts: Some(vec![
+                ("Menu Test Home".to_string(), None, ScriptStatus::Ok),
+                ("Menu Test Photos".to_string(), None, ScriptStatus::Running),
+                ("Menu Test Music".to_string(), None, ScriptStatus::Disabled),
+            ]),
+            profiles: Some((vec!["Work".to_string()], None)),
+            ..Default::default()
+        });
+        (host, rx)
+    }
+
+    fn settings(layout: MenuLayout) -> Settings {
+        Settings {
+            menu: Menu {
+                layout,
+                favorites: vec!["Menu Test Photos".to_string()],
+                hide_exit: false,
+            },
+            tray_commands: vec![TrayCommand {
+                label: "Mount NAS".to_string(),
+                icon_name: None,
+                command: "mount /srv/nas".to_string(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flat_menu() {
+        let (host, _rx) = host(settings(MenuLayout::Flat));
+
+        assert_eq!(
+            host.menu(),
+            [
+                "Run Menu Test Photos now (running)",
+                "Cancel Menu Test Photos",
+                "Run Menu Test Home now",
+                "Menu Test Music (disabled) (greyed out)",
+                "---",
+                "Mount NAS",
+                "Pause backups >",
+                "  For 1 day",
+                "  For 1 week",
+                "  For 2 weeks",
+                "Profile >",
+                "  (x) All backups",
+                "  ( ) Work",
+                "Settings",
+                "Backup Disks",
+                "About / Diagnostics",
+                "Exit",
+            ]
+        );
+    }
+
+    #[test]
+    fn grouped_menu() {
+        let (host, _rx) = host(Settings {
+            menu: Menu {
+                hide_exit: true,
+                ..settings(MenuLayout::Grouped).menu
+            },
+            ..settings(MenuLayout::Grouped)
+        });
+        host.update(TrayData {
+            paused: Some(true),
+            profiles: Some((vec!["Work".to_string()], Some("Work".to_string()))),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            host.menu(),
+            [
+                "Menu Test Photos (running) >",
+                "  Run now",
+                "  Cancel",
+                "Menu Test Home >",
+                "  Run now",
+                "Menu Test Music (disabled) >",
+                "  Disabled (greyed out)",
+                "---",
+                "Mount NAS",
+                "Resume backups",
+                "Profile >",
+                "  ( ) All backups",
+                "  (x) Work",
+                "Settings",
+                "Backup Disks",
+                "About / Diagnostics",
+            ]
+        );
+    }
+
+    #[test]
+    fn remote_menu() {
+        let (tx, _rx) = channel();
+        let host = MockTrayHost::new(Tray::remote(&settings(MenuLayout::Flat), tx));
+        host.update(TrayData {
+            status: Some(ksni::Status::Active),
+            scripts: Some(vec![(
+                "Menu Test System".to_string(),
+                None,
+                ScriptStatus::Running,
+            )]),
+            ..Default::default()
+        });
+
+        // the system-wide daemon can't be configured or cancelled from the tray
+        assert_eq!(
+            host.menu(),
+            [
+                "Run Menu Test System now (running)",
+                "---",
+                "Mount NAS",
+                "Exit"
+            ]
+        );
+        assert_eq!(host.status(), ksni::Status::Active);
+        assert_eq!(host.tool_tip().icon_name, "media-playback-start");
+    }
+
+    #[test]
+    fn menu_events() {
+        let (host, rx) = host(settings(MenuLayout::Flat));
+
+        host.activate(&["Run Menu Test Home now"]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::ManualRun(name, Trigger::Tray, None)) if name == "Menu Test Home"
+        ));
+
+        host.activate(&["Cancel Menu Test Photos"]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::Cancel(name)) if name == "Menu Test Photos"
+        ));
+
+        host.update(TrayData {
+            scripts: Some(vec![(
+                "Menu Test Home".to_string(),
+                None,
+                ScriptStatus::Failed,
+            )]),
+            ..Default::default()
+        });
+        host.activate(&["Dismiss failure of Menu Test Home"]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::DismissFailure(name)) if name == "Menu Test Home"
+        ));
+
+        host.activate(&["Mount NAS"]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::TrayCommand(label)) if label == "Mount NAS"
+        ));
+
+        host.activate(&["Pause backups", "For 1 week"]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::Pause(duration)) if duration == Duration::from_secs(7 * 24 * 60 * 60)
+        ));
+
+        host.activate(&["Profile", "Work"]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::SwitchProfile(Some(profile))) if profile == "Work"
+        ));
+        host.activate(&["Profile", "All backups"]);
+        assert!(matches!(rx.try_recv(), Ok(Event::SwitchProfile(None))));
+
+        host.update(TrayData {
+            paused: Some(true),
+            ..Default::default()
+        });
+        host.activate(&["Resume backups"]);
+        assert!(matches!(rx.try_recv(), Ok(Event::Resume)));
+
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn markers() {
+        let (emoji, _rx) = host(Settings {
+            status_markers: StatusMarkers::Emoji,
+            ..settings(MenuLayout::Flat)
+        });
+        emoji.update(TrayData {
+            tooltip: Some(
+                "Menu Test Home:\nNext backup in 1h\n\nMenu Test Photos:\nRunning\n\n\
+                 Menu Test Music:\nDisabled\n\nborgmatic.timer:\nNext backup in 2h"
+                    .to_string(),
+            ),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            emoji.menu()[..4],
+            [
+                "⏳ Run Menu Test Photos now",
+                "Cancel Menu Test Photos",
+                "✅ Run Menu Test Home now",
+                "Menu Test Music (disabled) (greyed out)",
+            ]
+        );
+        assert_eq!(
+            emoji.tool_tip().description,
+            "✅ Menu Test Home:\nNext backup in 1h\n\n⏳ Menu Test Photos:\nRunning\n\n\
+             Menu Test Music:\nDisabled\n\nborgmatic.timer:\nNext backup in 2h"
+        );
+
+        let (text, _rx) = host(Settings {
+            status_markers: StatusMarkers::Text,
+            ..settings(MenuLayout::Grouped)
+        });
+        assert_eq!(text.menu()[0], "[running] Menu Test Photos >");
+    }
+
+    #[test]
+    #[should_panic(expected = "greyed out")]
+    fn disabled_script() {
+        let (host, _rx) = host(settings(MenuLayout::Flat));
+
+        host.activate(&["Menu Test Music (disabled)"]);
+    }
+
+    #[test]
+    fn exit_summaries() {
+        assert_eq!(
+            exit_summary(&[
+                ("Home".to_string(), None, ScriptStatus::Ok),
+                ("Photos".to_string(), None, ScriptStatus::Running),
+            ]),
+            None
+        );
+
+        assert_eq!(
+            exit_summary(&[
+                ("Home".to_string(), None, ScriptStatus::Failed),
+                ("Photos".to_string(), None, ScriptStatus::Ok),
+                ("Music".to_string(), None, ScriptStatus::Overdue),
+            ]),
+            Some((
+                "Backups need attention".to_string(),
+                "Home failed\nMusic is overdue\nBackup Monitor has exited and won't remind you."
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn favorites_first() {
+        let scripts =
+            ["Home", "Photos", "Music"].map(|name| (name.to_string(), None, ScriptStatus::Ok));
+
+        let names = |favorites: &[&str]| {
+            let favorites = favorites
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>();
+            pinned_first(&scripts, &favorites)
+                .into_iter()
+                .map(|(name, _, _)| name.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(&[]), ["Home", "Photos", "Music"]);
+        assert_eq!(names(&["Music", "Photos"]), ["Music", "Photos", "Home"]);
+        // removed from the settings
+        assert_eq!(names(&["Videos", "Photos"]), ["Photos", "Home", "Music"]);
+    }
+
+    #[test]
+    fn rich_tooltip_formatting() {
+        let tooltip = "Backups are paused until 2024-11-02 08:00\n\nHome:\nLast backup was 3days ago\nNext backup in 0s\n\nPhotos & Videos:\nRunning";
+
+        assert_eq!(
+            rich_tooltip(tooltip, &["Home".to_string()], |_| None),
+            "Backups are paused until 2024-11-02 08:00<br/><br/>\
+             <b>Home</b> <font color=\"#da4453\">(overdue)</font><br/>Last backup was 3days ago<br/>Next backup in 0s<br/><br/>\
+             <b>Photos &amp; Videos</b><br/>Running"
+        );
+    }
+}
@@ -1,13 +1,20 @@
+use std::cell::RefCell;
+
 use serde::{Deserialize, Deserializer};
 
-use crate::tray::Tray;
+use crate::{manager::ScriptStatus, tray::Tray};
 
-#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
 pub struct TrayData {
     #[serde(deserialize_with = "deserialize_status")]
     pub status: Option<ksni::Status>,
     pub tooltip: Option<String>,
-    pub scripts: Option<Vec<(String, Option<String>)>>,
+    pub scripts: Option<Vec<(String, Option<String>, ScriptStatus)>>,
+    pub paused: Option<bool>,
+    // names of the profiles and the active one
+    pub profiles: Option<(Vec<String>, Option<String>)>,
+    // names of the overdue scripts
+    pub overdue: Option<Vec<String>>,
 }
 
 pub fn deserialize_status<'de, D>(deserializer: D) -> Result<Option<ksni::Status>, D::Error>
@@ -16,32 +23,225 @@ where
 {
     let opt: Option<String> = Option::deserialize(deserializer)?;
     match opt {
-        Some(s) => match s.to_lowercase().as_str() {
-            "passive" => Ok(Some(ksni::Status::Passive)),
-            "active" => Ok(Some(ksni::Status::Active)),
-            "needsattention" => Ok(Some(ksni::Status::NeedsAttention)),
-            _ => Err(serde::de::Error::custom(format!("Invalid status: {s}"))),
-        },
+        Some(s) => parse_status(&s)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid status: {s}"))),
         None => Ok(None),
     }
 }
 
+pub fn parse_status(s: &str) -> Option<ksni::Status> {
+    match s.to_lowercase().as_str() {
+        "passive" => Some(ksni::Status::Passive),
+        "active" => Some(ksni::Status::Active),
+        "needsattention" => Some(ksni::Status::NeedsAttention),
+        _ => None,
+    }
+}
+
+pub fn status_name(status: ksni::Status) -> &'static str {
+    match status {
+        ksni::Status::Passive => "passive",
+        ksni::Status::Active => "active",
+        ksni::Status::NeedsAttention => "needsattention",
+    }
+}
+
 pub trait TrayHandle<T: ksni::Tray> {
     fn update(&self, data: TrayData);
 }
 
+// changes the tooltip of the updates, e.g. to add the tooltips of the other managers to the updates
+// of a running script
+pub struct MapTooltip<'a, H, F> {
+    handle: &'a H,
+    map: F,
+}
+
+impl<'a, H, F> MapTooltip<'a, H, F> {
+    pub fn new(handle: &'a H, map: F) -> Self {
+        MapTooltip { handle, map }
+    }
+}
+
+impl<H: TrayHandle<Tray>, F: Fn(String) -> String> TrayHandle<Tray> for MapTooltip<'_, H, F> {
+    fn update(&self, mut data: TrayData) {
+        data.tooltip = data.tooltip.map(&self.map);
+        self.handle.update(data);
+    }
+}
+
+// sets the fields which are given
+pub fn apply(tray: &mut Tray, data: TrayData) {
+    if let Some(status) = data.status {
+        tray.set_status(status);
+    }
+    if let Some(tooltip) = data.tooltip {
+        tray.set_tooltip(tooltip);
+    }
+    if let Some(scripts) = data.scripts {
+        tray.set_scripts(scripts);
+    }
+    if let Some(paused) = data.paused {
+        tray.set_paused(paused);
+    }
+    if let Some((profiles, active_profile)) = data.profiles {
+        tray.set_profiles(profiles, active_profile);
+    }
+    if let Some(overdue) = data.overdue {
+        tray.set_overdue(overdue);
+    }
+}
+
 impl TrayHandle<Tray> for ksni::Handle<Tray> {
     fn update(&self, data: TrayData) {
-        self.update(|tray| {
-            if let Some(status) = data.status {
-                tray.set_status(status);
-            }
-            if let Some(tooltip) = data.tooltip {
-                tray.set_tooltip(tooltip);
-            }
-            if let Some(scripts) = data.scripts {
-                tray.set_scripts(scripts);
-            }
+        self.update(|tray| apply(tray, data));
+    }
+}
+
+// forwards only the fields which changed since the last update, every update of the ksni handle
+// is sent over D-Bus and makes the menu flicker
+pub struct ChangeFilter<H> {
+    handle: H,
+    last: RefCell<TrayData>,
+}
+
+impl<H> ChangeFilter<H> {
+    pub fn new(handle: H) -> Self {
+        ChangeFilter {
+            handle,
+            last: RefCell::new(TrayData::default()),
+        }
+    }
+}
+
+impl<T: ksni::Tray, H: TrayHandle<T>> TrayHandle<T> for ChangeFilter<H> {
+    fn update(&self, data: TrayData) {
+        let mut last = self.last.borrow_mut();
+
+        let delta = TrayData {
+            status: data.status.filter(|status| last.status != Some(*status)),
+            tooltip: data
+                .tooltip
+                .filter(|tooltip| last.tooltip.as_ref() != Some(tooltip)),
+            scripts: data
+                .scripts
+                .filter(|scripts| last.scripts.as_ref() != Some(scripts)),
+            paused: data.paused.filter(|paused| last.paused != Some(*paused)),
+            profiles: data
+                .profiles
+                .filter(|profiles| last.profiles.as_ref() != Some(profiles)),
+            overdue: data
+                .overdue
+                .filter(|overdue| last.overdue.as_ref() != Some(overdue)),
+        };
+        if delta == TrayData::default() {
+            return;
+        }
+
+        if delta.status.is_some() {
+            last.status = delta.status;
+        }
+        if delta.tooltip.is_some() {
+            last.tooltip.clone_from(&delta.tooltip);
+        }
+        if delta.scripts.is_some() {
+            last.scripts.clone_from(&delta.scripts);
+        }
+        if delta.paused.is_some() {
+            last.paused = delta.paused;
+        }
+        if delta.profiles.is_some() {
+            last.profiles.clone_from(&delta.profiles);
+        }
+        if delta.overdue.is_some() {
+            last.overdue.clone_from(&delta.overdue);
+        }
+
+        self.handle.update(delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{history::Trigger, manager::Manager, mock_manager::MockManager};
+
+    #[derive(Default)]
+    struct RecordingHandle(RefCell<Vec<TrayData>>);
+
+    impl TrayHandle<Tray> for RecordingHandle {
+        fn update(&self, data: TrayData) {
+            self.0.borrow_mut().push(data);
+        }
+    }
+
+    #[test]
+    fn running_tooltip() {
+        let handle = RecordingHandle::default();
+        let mut manager = (
+            MockManager {
+                tooltip: "Backup:\nRunning".to_string(),
+                ..Default::default()
+            },
+            MockManager {
+                tooltip: "Timer:\nLast backup 1h ago".to_string(),
+                ..Default::default()
+            },
+        );
+
+        manager
+            .run(
+                None,
+                &Trigger::Schedule,
+                &MapTooltip::new(&handle, |tooltip| format!("Invalid settings\n\n{tooltip}")),
+            )
+            .unwrap();
+
+        let tooltip = "Invalid settings\n\nBackup:\nRunning\n\nTimer:\nLast backup 1h ago";
+        assert_eq!(
+            handle.0.into_inner(),
+            vec![
+                TrayData {
+                    tooltip: Some(tooltip.to_string()),
+                    ..Default::default()
+                };
+                2
+            ]
+        );
+    }
+
+    #[test]
+    fn change_filter() {
+        let handle = ChangeFilter::new(RecordingHandle::default());
+        let data = TrayData {
+            status: Some(ksni::Status::Passive),
+            tooltip: Some("Backup:\nLast backup 1h ago".to_string()),
+            paused: Some(false),
+            ..Default::default()
+        };
+
+        handle.update(data.clone());
+        handle.update(data.clone());
+        handle.update(TrayData {
+            tooltip: Some("Backup:\nLast backup 2h ago".to_string()),
+            ..data
         });
+
+        assert_eq!(
+            handle.handle.0.into_inner(),
+            vec![
+                TrayData {
+                    status: Some(ksni::Status::Passive),
+                    tooltip: Some("Backup:\nLast backup 1h ago".to_string()),
+                    paused: Some(false),
+                    ..Default::default()
+                },
+                TrayData {
+                    tooltip: Some("Backup:\nLast backup 2h ago".to_string()),
+                    ..Default::default()
+                },
+            ]
+        );
     }
 }
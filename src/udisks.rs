@@ -0,0 +1,281 @@
+use std::{
+    collections::{HashMap, HashSet},
+    process::{Command, Stdio},
+    sync::Mutex,
+    thread,
+};
+
+use anyhow::ensure;
+
+use zbus::{
+    blocking::{Connection, MessageIterator},
+    message, proxy,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+    MatchRule,
+};
+
+use crate::{event::EventSender, settings::AutoMount, Event};
+
+const SERVICE: &str = "org.freedesktop.UDisks2";
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Manager",
+    default_service = "org.freedesktop.UDisks2",
+    default_path = "/org/freedesktop/UDisks2/Manager"
+)]
+trait Manager {
+    // the block devices matching the spec, e.g. `{"uuid": "..."}`
+    fn resolve_device(
+        &self,
+        devspec: HashMap<&str, Value<'_>>,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Filesystem",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait Filesystem {
+    // mounts at the mount point of `/etc/fstab` if there is an entry, otherwise in `/run/media`
+    fn mount(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<String>;
+
+    fn unmount(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Block",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait Block {
+    // e.g. `ext4` or `crypto_LUKS`
+    #[zbus(property)]
+    fn id_type(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Encrypted",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait Encrypted {
+    // returns the unlocked block device
+    fn unlock(
+        &self,
+        passphrase: &str,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<OwnedObjectPath>;
+
+    fn lock(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn cleartext_device(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+// UUIDs of the file systems which are being mounted, so they are only requested once
+static PENDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// UUIDs of the file systems which have been mounted by the backup monitor, only those are
+// unmounted again, and whether their encrypted containers have been unlocked too
+static MOUNTED: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+
+// the block device with the UUID, `None` if it isn't connected
+fn device(connection: &Connection, uuid: &str) -> zbus::Result<Option<OwnedObjectPath>> {
+    let devices = ManagerProxyBlocking::new(connection)?
+        .resolve_device(HashMap::from([("uuid", uuid.into())]), HashMap::new())?;
+    Ok(devices.into_iter().next())
+}
+
+fn filesystem(
+    connection: &Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<FilesystemProxyBlocking<'static>> {
+    FilesystemProxyBlocking::builder(connection)
+        .path(path)?
+        .build()
+}
+
+fn encrypted(
+    connection: &Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<EncryptedProxyBlocking<'static>> {
+    EncryptedProxyBlocking::builder(connection)
+        .path(path)?
+        .build()
+}
+
+fn is_encrypted(connection: &Connection, path: &OwnedObjectPath) -> zbus::Result<bool> {
+    let block = BlockProxyBlocking::builder(connection)
+        .path(path.clone())?
+        .build()?;
+    Ok(block.id_type()? == "crypto_LUKS")
+}
+
+// the output of the passphrase command, empty without one, which makes UDisks2 use the
+// passphrase of `/etc/crypttab`
+fn passphrase(command: Option<&str>) -> anyhow::Result<String> {
+    let Some(command) = command else {
+        return Ok(String::new());
+    };
+
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .stderr(Stdio::inherit())
+        .output()?;
+    ensure!(
+        output.status.success(),
+        "the passphrase command failed with {}",
+        output.status
+    );
+    let passphrase = String::from_utf8(output.stdout)?;
+    Ok(passphrase
+        .strip_suffix('\n')
+        .unwrap_or(&passphrase)
+        .to_string())
+}
+
+// unlocks the encrypted container first if needed, returns whether it has been unlocked
+fn mount(connection: &Connection, auto_mount: &AutoMount) -> anyhow::Result<Option<bool>> {
+    let Some(path) = device(connection, &auto_mount.uuid)? else {
+        log::debug!("file system {} isn't connected", auto_mount.uuid);
+        return Ok(None);
+    };
+
+    let (path, unlocked) = if is_encrypted(connection, &path)? {
+        let encrypted = encrypted(connection, path)?;
+        let cleartext = encrypted.cleartext_device()?;
+        // `/` while the container is locked
+        if cleartext.as_str() != "/" {
+            (cleartext, false)
+        } else {
+            let passphrase = passphrase(auto_mount.passphrase_command.as_deref())?;
+            let cleartext = encrypted.unlock(&passphrase, HashMap::new())?;
+            log::info!("unlocked encrypted file system {}", auto_mount.uuid);
+            (cleartext, true)
+        }
+    } else {
+        (path, false)
+    };
+
+    let mount_path = filesystem(connection, path)?.mount(HashMap::new())?;
+    log::info!("mounted file system {} at `{mount_path}`", auto_mount.uuid);
+
+    Ok(Some(unlocked))
+}
+
+// mounts the file system in the background, polkit may ask for authentication, the mount watcher
+// notices when it has been mounted
+pub fn request_mount(auto_mount: &AutoMount) {
+    {
+        let mut pending = PENDING.lock().unwrap();
+        if pending.contains(&auto_mount.uuid) {
+            return;
+        }
+        pending.push(auto_mount.uuid.clone());
+    }
+
+    let auto_mount = auto_mount.clone();
+    thread::spawn(move || {
+        let result = Connection::system()
+            .map_err(anyhow::Error::from)
+            .and_then(|connection| mount(&connection, &auto_mount));
+        match result {
+            Ok(Some(unlocked)) => MOUNTED
+                .lock()
+                .unwrap()
+                .push((auto_mount.uuid.clone(), unlocked)),
+            Ok(None) => {}
+            Err(error) => log::warn!("failed to mount file system {}: {error:#}", auto_mount.uuid),
+        }
+
+        PENDING
+            .lock()
+            .unwrap()
+            .retain(|pending| *pending != auto_mount.uuid);
+    });
+}
+
+// locks the encrypted container again if it has been unlocked
+fn unmount_device(connection: &Connection, uuid: &str, unlocked: bool) -> zbus::Result<()> {
+    let Some(path) = device(connection, uuid)? else {
+        return Ok(());
+    };
+
+    if !is_encrypted(connection, &path)? {
+        return filesystem(connection, path)?.unmount(HashMap::new());
+    }
+
+    let encrypted = encrypted(connection, path)?;
+    let cleartext = encrypted.cleartext_device()?;
+    if cleartext.as_str() != "/" {
+        filesystem(connection, cleartext)?.unmount(HashMap::new())?;
+        if unlocked {
+            encrypted.lock(HashMap::new())?;
+            log::info!("locked encrypted file system {uuid}");
+        }
+    }
+    Ok(())
+}
+
+// unmounts the file systems if they have been mounted by `request_mount`
+pub fn unmount(uuids: &HashSet<String>) {
+    let mounted = {
+        let mut mounted = MOUNTED.lock().unwrap();
+        let (unmount, keep): (Vec<_>, Vec<_>) = mounted
+            .drain(..)
+            .partition(|(uuid, _)| uuids.contains(uuid));
+        *mounted = keep;
+        unmount
+    };
+
+    for (uuid, unlocked) in mounted {
+        let result = Connection::system()
+            .and_then(|connection| unmount_device(&connection, &uuid, unlocked));
+        match result {
+            Ok(()) => log::info!("unmounted file system {uuid}"),
+            Err(error) => log::warn!("failed to unmount file system {uuid}: {error}"),
+        }
+    }
+}
+
+// a drive has been plugged in, scripts which wait for it to be mounted may mount it now
+pub fn watch_devices(tx: &EventSender) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+
+    let rule = MatchRule::builder()
+        .msg_type(message::Type::Signal)
+        .sender(SERVICE)?
+        .interface("org.freedesktop.DBus.ObjectManager")?
+        .member("InterfacesAdded")?
+        .build();
+    for message in MessageIterator::for_match_rule(rule, &connection, None)? {
+        let (path, interfaces) = message?.body().deserialize::<(
+            OwnedObjectPath,
+            HashMap<String, HashMap<String, OwnedValue>>,
+        )>()?;
+        if !interfaces.contains_key("org.freedesktop.UDisks2.Filesystem") {
+            continue;
+        }
+
+        log::debug!("file system `{}` has been connected", path.as_str());
+
+        let _ = tx.send(Event::DeviceAdded);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passphrase_command() {
+        assert_eq!(passphrase(None).unwrap(), "");
+        assert_eq!(
+            passphrase(Some("printf 'pass phrase\\n'")).unwrap(),
+            "pass phrase"
+        );
+        assert_eq!(passphrase(Some("printf 'secret'")).unwrap(), "secret");
+        assert!(passphrase(Some("exit 1")).is_err());
+    }
+}
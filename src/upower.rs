@@ -0,0 +1,80 @@
+use zbus::{
+    blocking::{Connection, MessageIterator},
+    message, proxy, MatchRule,
+};
+
+use crate::{event::EventSender, Event};
+
+const SERVICE: &str = "org.freedesktop.UPower";
+
+#[proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    #[zbus(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+// the composite of all batteries
+#[proxy(
+    interface = "org.freedesktop.UPower.Device",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower/devices/DisplayDevice"
+)]
+trait Device {
+    #[zbus(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Power {
+    pub on_battery: bool,
+    // `None` if the computer has no battery
+    pub battery_percent: Option<u8>,
+}
+
+pub fn power(connection: &Connection) -> zbus::Result<Power> {
+    let device = DeviceProxyBlocking::new(connection)?;
+    Ok(Power {
+        on_battery: UPowerProxyBlocking::new(connection)?.on_battery()?,
+        battery_percent: if device.is_present()? {
+            Some(device.percentage()?.round() as u8)
+        } else {
+            None
+        },
+    })
+}
+
+pub fn watch_power(tx: &EventSender) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+
+    let mut last_power = power(&connection)?;
+    let _ = tx.send(Event::PowerChanged(last_power));
+
+    // the properties of the daemon and of the batteries
+    let rule = MatchRule::builder()
+        .msg_type(message::Type::Signal)
+        .sender(SERVICE)?
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .build();
+    for _ in MessageIterator::for_match_rule(rule, &connection, None)? {
+        // the percentage changes in fractions, which aren't worth an event
+        let power = power(&connection)?;
+        if power == last_power {
+            continue;
+        }
+
+        log::debug!("power has changed to {power:?}");
+
+        let _ = tx.send(Event::PowerChanged(power));
+        last_power = power;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    process::{Command, Output, Stdio},
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    settings::{Script, Webhook, WebhookEvent},
+    template::{render_variables, script_variables},
+};
+
+impl WebhookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            WebhookEvent::Started => "started",
+            WebhookEvent::Succeeded => "succeeded",
+            WebhookEvent::Failed => "failed",
+        }
+    }
+}
+
+// called in the background with curl, so slow servers don't hold up the backups
+pub fn call_webhooks(
+    webhooks: &[Webhook],
+    event: WebhookEvent,
+    script: &Script,
+    message: &str,
+    now: DateTime<Utc>,
+) {
+    for webhook in webhooks
+        .iter()
+        .filter(|webhook| webhook.events.contains(&event))
+    {
+        let payload = payload(webhook, event, script, message, now);
+        let mut command = Command::new("curl");
+        command
+            .args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+            .args(["--request", &webhook.method])
+            .args(["--header", "Content-Type: application/json"])
+            .args(["--data-binary", &payload])
+            // the URL often contains a token, on stdin it doesn't show up in the process list
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let config = format!("url = {}\n", curl_quote(&webhook.url));
+
+        let url = webhook.url.clone();
+        thread::spawn(move || match run_curl(command, &config) {
+            Ok(output) if !output.status.success() => {
+                log::warn!(
+                    "webhook `{url}` failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            Ok(_) => log::debug!("called webhook `{url}`"),
+            Err(error) => log::warn!("failed to run curl for webhook `{url}`: {error}"),
+        });
+    }
+}
+
+fn run_curl(mut command: Command, config: &str) -> io::Result<Output> {
+    let mut child = command.spawn()?;
+    // curl reads the config until stdin is closed
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(config.as_bytes())?;
+    }
+    child.wait_with_output()
+}
+
+// a quoted string of the curl config file format
+fn curl_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// the values are escaped, so they can be placed inside JSON strings
+fn payload(
+    webhook: &Webhook,
+    event: WebhookEvent,
+    script: &Script,
+    message: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let Some(template) = &webhook.payload else {
+        return serde_json::json!({
+            "event": event.name(),
+            "script": script.name,
+            "message": message,
+        })
+        .to_string();
+    };
+
+    let mut variables = script_variables(script, now);
+    variables.extend([
+        ("event", event.name().to_string()),
+        ("message", message.to_string()),
+    ]);
+
+    render_variables(template, &variables, json_escape)
+}
+
+fn json_escape(value: &str) -> Cow<'_, str> {
+    let quoted = serde_json::Value::from(value).to_string();
+    Cow::Owned(quoted[1..quoted.len() - 1].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use fake::{Fake, Faker};
+    use indoc::indoc;
+
+    #[test]
+    fn webhook_payload() {
+        let now = Faker.fake::<Clock>().now();
+        let script = serde_yaml_ng::from_str::<Script>(indoc! {"
+            name: Backup
+            backup-script: '#!/bin/bash'
+            interval: 1day
+        "})
+        .unwrap();
+        let mut webhook = Webhook {
+            url: "https://ntfy.sh/backups".to_string(),
+            method: "POST".to_string(),
+            payload: None,
+            events: vec![WebhookEvent::Failed],
+        };
+
+        let message = "Backup failed with \"exit code 2\"";
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&payload(
+                &webhook,
+                WebhookEvent::Failed,
+                &script,
+                message,
+                now
+            ))
+            .unwrap(),
+            serde_json::json!({
+                "event": "failed",
+                "script": "Backup",
+                "message": message,
+            })
+        );
+
+        webhook.payload = Some(r#"{"text": "{{script.name}} {{event}}: {{message}}"}"#.to_string());
+        assert_eq!(
+            payload(&webhook, WebhookEvent::Failed, &script, message, now),
+            r#"{"text": "Backup failed: Backup failed with \"exit code 2\""}"#
+        );
+    }
+
+    #[test]
+    fn curl_config() {
+        assert_eq!(
+            curl_quote("https://ntfy.sh/backups?auth=a\"b\\c"),
+            r#""https://ntfy.sh/backups?auth=a\"b\\c""#
+        );
+        assert_eq!(curl_quote("https://ntfy.sh/\n"), r#""https://ntfy.sh/\n""#);
+    }
+}